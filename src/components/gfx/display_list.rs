@@ -15,14 +15,18 @@
 /// low-level drawing primitives.
 
 use color::Color;
+use font::FontMetrics;
 use servo_util::geometry::Au;
 use style::computed_values::border_style;
 use render_context::RenderContext;
 use text::TextRun;
+use text::util::CharIndexMap;
 
+use azure::AzFloat;
 use std::cast::transmute_region;
 use std::vec::VecIterator;
 use geom::{Point2D, Rect, Size2D, SideOffsets2D};
+use geom::matrix2d::Matrix2D;
 use servo_net::image::base::Image;
 use servo_util::range::Range;
 use extra::arc::Arc;
@@ -58,6 +62,13 @@ impl<E> DisplayList<E> {
         debug!("Ending display list.")
     }
 
+    /// Appends the given items to the children of the last clip item of this list, looking into
+    /// groups and transforms, where the clip items of boxes with an opacity or a transform are.
+    /// Returns false if there is no clip item.
+    pub fn append_to_last_clip(&mut self, items: ~[DisplayItem<E>]) -> bool {
+        append_to_last_clip(&mut self.list, items).is_none()
+    }
+
     /// Returns a preorder iterator over the given display list.
     pub fn iter<'a>(&'a self) -> DisplayItemIterator<'a,E> {
         ParentDisplayItemIterator(self.list.iter())
@@ -70,7 +81,12 @@ pub enum DisplayItem<E> {
     TextDisplayItemClass(~TextDisplayItem<E>),
     ImageDisplayItemClass(~ImageDisplayItem<E>),
     BorderDisplayItemClass(~BorderDisplayItem<E>),
-    ClipDisplayItemClass(~ClipDisplayItem<E>)
+    GradientDisplayItemClass(~GradientDisplayItem<E>),
+    BoxShadowDisplayItemClass(~BoxShadowDisplayItem<E>),
+    TextShadowDisplayItemClass(~TextShadowDisplayItem<E>),
+    ClipDisplayItemClass(~ClipDisplayItem<E>),
+    GroupDisplayItemClass(~GroupDisplayItem<E>),
+    TransformDisplayItemClass(~TransformDisplayItem<E>),
 }
 
 /// Information common to all display items.
@@ -84,10 +100,43 @@ pub struct BaseDisplayItem<E> {
     extra: E,
 }
 
-/// Renders a solid color.
+/// The radii of the corners of a rectangle, as horizontal and vertical radii in each size.
+#[deriving(Clone)]
+pub struct BorderRadii<T> {
+    top_left: Size2D<T>,
+    top_right: Size2D<T>,
+    bottom_right: Size2D<T>,
+    bottom_left: Size2D<T>,
+}
+
+impl BorderRadii<Au> {
+    /// Returns the radii of a rectangle with square corners.
+    pub fn zero() -> BorderRadii<Au> {
+        let zero = Size2D(Au(0), Au(0));
+        BorderRadii {
+            top_left: zero,
+            top_right: zero,
+            bottom_right: zero,
+            bottom_left: zero,
+        }
+    }
+
+    /// Returns true if no corner is rounded. A corner with a zero radius in either direction is
+    /// square.
+    pub fn is_square(&self) -> bool {
+        let is_square = |corner: &Size2D<Au>| corner.width == Au(0) || corner.height == Au(0);
+        is_square(&self.top_left) && is_square(&self.top_right) &&
+            is_square(&self.bottom_right) && is_square(&self.bottom_left)
+    }
+}
+
+/// Renders a solid color, possibly with rounded corners.
 pub struct SolidColorDisplayItem<E> {
     base: BaseDisplayItem<E>,
     color: Color,
+
+    /// The radii of the corners.
+    radii: BorderRadii<Au>,
 }
 
 /// Renders text.
@@ -106,6 +155,39 @@ pub struct TextDisplayItem<E> {
 
     /// A bitfield of flags for text display items.
     flags: TextDisplayItemFlags,
+
+    /// Maps the characters of the text run that came from the DOM text node of this item back to
+    /// character offsets in that node.
+    char_map: Arc<CharIndexMap>,
+
+    /// The index in the text run of the first character that came from the DOM text node of this
+    /// item.
+    node_start: uint,
+}
+
+impl<E> TextDisplayItem<E> {
+    /// Returns the character offset in the DOM text node of this item of the character drawn at
+    /// the given horizontal position, for hit testing.
+    pub fn node_offset_at(&self, x: Au) -> uint {
+        let text_run = self.text_run.get();
+        let bounds = &self.base.bounds;
+        let mut distance = if text_run.rtl {
+            bounds.origin.x + bounds.size.width - x
+        } else {
+            x - bounds.origin.x
+        };
+
+        let mut run_index = self.range.begin();
+        while run_index + 1 < self.range.end() {
+            let advance = text_run.metrics_for_range(&Range::new(run_index, 1)).advance_width;
+            if distance < advance {
+                break
+            }
+            distance = distance - advance;
+            run_index += 1;
+        }
+        self.char_map.get().original_index(run_index - self.node_start)
+    }
 }
 
 /// Flags for text display items.
@@ -124,10 +206,22 @@ bitfield!(TextDisplayItemFlags, override_overline, set_override_overline, 0x02)
 // Whether line-through is forced on.
 bitfield!(TextDisplayItemFlags, override_line_through, set_override_line_through, 0x04)
 
-/// Renders an image.
+/// Renders an image, possibly tiled. The copies of the image are clipped to the bounds.
 pub struct ImageDisplayItem<E> {
     base: BaseDisplayItem<E>,
     image: Arc<~Image>,
+
+    /// The size at which each copy of the image is drawn.
+    stretch_size: Size2D<Au>,
+
+    /// The position of one copy of the image. The others, if any, are placed next to it.
+    tile_origin: Point2D<Au>,
+
+    /// Whether the image is repeated horizontally to fill the bounds.
+    repeat_x: bool,
+
+    /// Whether the image is repeated vertically to fill the bounds.
+    repeat_y: bool,
 }
 
 /// Renders a border.
@@ -141,13 +235,149 @@ pub struct BorderDisplayItem<E> {
     color: SideOffsets2D<Color>,
 
     /// The border styles.
-    style: SideOffsets2D<border_style::T>
+    style: SideOffsets2D<border_style::T>,
+
+    /// The radii of the outer corners of the border.
+    radii: BorderRadii<Au>,
+}
+
+/// Renders a linear or radial gradient, clipped to the bounds.
+pub struct GradientDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+
+    /// Where the gradient is drawn, in the same coordinates as the bounds.
+    shape: GradientShape,
+
+    /// The color stops, sorted by offset.
+    stops: ~[GradientStop],
+
+    /// Whether the stops repeat beyond offsets 0 and 1. Otherwise, the colors at the ends extend.
+    repeating: bool,
+}
+
+/// The geometry of a gradient.
+#[deriving(Clone)]
+pub enum GradientShape {
+    /// A linear gradient, from the point at offset 0 to the point at offset 1.
+    LinearGradientShape(Point2D<Au>, Point2D<Au>),
+
+    /// A radial gradient around the given center, from the horizontal radius at offset 0 to the
+    /// horizontal radius at offset 1. Vertical radii are the horizontal ones scaled by the given
+    /// factor, which makes ellipses.
+    RadialGradientShape(Point2D<Au>, Au, Au, f64),
+}
+
+/// A color of a gradient, at an offset between 0 and 1 along the gradient.
+#[deriving(Clone)]
+pub struct GradientStop {
+    offset: f32,
+    color: Color,
+}
+
+/// Renders the shadow of a box, possibly blurred. Outer shadows are painted outside of the box
+/// only, and inset shadows inside of it only. The bounds enclose everything that is painted.
+pub struct BoxShadowDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+
+    /// The border box of the box for outer shadows, or its padding box for inset shadows.
+    box_bounds: Rect<Au>,
+
+    /// The radii of the corners of `box_bounds`. The shadow has the same corners, grown by the
+    /// spread.
+    radii: BorderRadii<Au>,
+
+    /// The offset of the shadow from the box.
+    offset: Point2D<Au>,
+
+    color: Color,
+
+    /// The blur radius, which is twice the standard deviation of the Gaussian blur.
+    blur_radius: Au,
+
+    /// How much the shadow is bigger than the box, or smaller for inset shadows.
+    spread_radius: Au,
+
+    inset: bool,
+}
+
+/// Renders the shadow of text, possibly blurred. The bounds enclose everything that is painted.
+pub struct TextShadowDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+
+    text_run: Arc<~TextRun>,
+
+    range: Range,
+
+    /// The top left corner of the shadow of the text box, which is offset from the text box.
+    origin: Point2D<Au>,
+
+    color: Color,
+
+    /// The blur radius, which is twice the standard deviation of the Gaussian blur.
+    blur_radius: Au,
+}
+
+/// Returns how far beyond a shape a blur with the given radius paints. The Gaussian is negligible
+/// beyond three standard deviations.
+pub fn blur_extent(blur_radius: Au) -> Au {
+    blur_radius.scale_by(1.5)
 }
 
 pub struct ClipDisplayItem<E> {
     base: BaseDisplayItem<E>,
     child_list: ~[DisplayItem<E>],
-    need_clip: bool
+    need_clip: bool,
+
+    /// The radii of the corners of the clip.
+    radii: BorderRadii<Au>,
+}
+
+/// Renders items as a group, which is composited at once with an opacity. The bounds are those of
+/// the box that creates the group.
+pub struct GroupDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+    child_list: ~[DisplayItem<E>],
+
+    /// The opacity of the group, between 0 and 1. Nothing is painted if it is 0.
+    opacity: f32,
+}
+
+/// Renders items with a transform. The bounds are those of the box that creates the item, once
+/// transformed; the bounds of the children are not transformed.
+pub struct TransformDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+    child_list: ~[DisplayItem<E>],
+
+    /// The transform, which maps page pixels and already accounts for the transform origin.
+    transform: Matrix2D<AzFloat>,
+}
+
+/// Returns the items back if there is no clip item in the list.
+fn append_to_last_clip<E>(list: &mut ~[DisplayItem<E>], items: ~[DisplayItem<E>])
+                          -> Option<~[DisplayItem<E>]> {
+    let mut items = items;
+    for item in list.mut_rev_iter() {
+        match *item {
+            ClipDisplayItemClass(ref mut clip) => {
+                clip.child_list.push_all_move(items);
+                return None
+            }
+            GroupDisplayItemClass(ref mut group) => {
+                match append_to_last_clip(&mut group.child_list, items) {
+                    None => return None,
+                    Some(rest) => items = rest,
+                }
+            }
+            TransformDisplayItemClass(ref mut transform) => {
+                match append_to_last_clip(&mut transform.child_list, items) {
+                    None => return None,
+                    Some(rest) => items = rest,
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(items)
 }
 
 pub enum DisplayItemIterator<'self,E> {
@@ -165,17 +395,35 @@ impl<'self,E> Iterator<&'self DisplayItem<E>> for DisplayItemIterator<'self,E> {
     }
 }
 
+/// Returns where the first copy of an image starts and where the copies end along one axis, so
+/// that they cover the range from `start` to `start + length`. Copies are `tile_length` long, and
+/// one of them starts at `tile_origin`. Without repetition, there is only that copy.
+fn tile_range(start: Au, length: Au, tile_origin: Au, tile_length: Au, repeat: bool)
+              -> (Au, Au) {
+    if !repeat {
+        return (tile_origin, tile_origin + tile_length)
+    }
+    let tiles_before = ((*start - *tile_origin) as f64 / *tile_length as f64).floor() as i32;
+    (tile_origin + Au(tiles_before * *tile_length), start + length)
+}
+
 impl<E> DisplayItem<E> {
     /// Renders this display item into the given render context.
     fn draw_into_context(&self, render_context: &mut RenderContext) {
         match *self {
             SolidColorDisplayItemClass(ref solid_color) => {
-                render_context.draw_solid_color(&solid_color.base.bounds, solid_color.color)
+                if solid_color.radii.is_square() {
+                    render_context.draw_solid_color(&solid_color.base.bounds, solid_color.color)
+                } else {
+                    render_context.draw_rounded_solid_color(&solid_color.base.bounds,
+                                                            &solid_color.radii,
+                                                            solid_color.color)
+                }
             }
 
             ClipDisplayItemClass(ref clip) => {
                 if clip.need_clip {
-                    render_context.draw_push_clip(&clip.base.bounds);
+                    render_context.draw_push_clip(&clip.base.bounds, &clip.radii);
                 }
                 for item in clip.child_list.iter() {
                     (*item).draw_into_context(render_context);
@@ -185,25 +433,33 @@ impl<E> DisplayItem<E> {
                 }
             }
 
+            GroupDisplayItemClass(ref group) => {
+                if group.opacity <= 0.0 {
+                    return
+                }
+                do render_context.draw_group(group.opacity) |render_context| {
+                    for item in group.child_list.iter() {
+                        item.draw_into_context(render_context);
+                    }
+                }
+            }
+
+            TransformDisplayItemClass(ref transform) => {
+                do render_context.draw_transformed(&transform.transform) |render_context| {
+                    for item in transform.child_list.iter() {
+                        item.draw_into_context(render_context);
+                    }
+                }
+            }
+
             TextDisplayItemClass(ref text) => {
                 debug!("Drawing text at {:?}.", text.base.bounds);
 
-                // FIXME(pcwalton): Allocating? Why?
                 let text_run = text.text_run.get();
-                let font = render_context.font_ctx.get_font_by_descriptor(&text_run.font_descriptor).unwrap();
-
-                let font_metrics = font.with_borrow( |font| {
-                    font.metrics.clone()
-                });
                 let origin = text.base.bounds.origin;
+                let font_metrics = draw_text_run(render_context, &text.text_run, &text.range,
+                                                 origin, text.color);
                 let baseline_origin = Point2D(origin.x, origin.y + font_metrics.ascent);
-                font.with_mut_borrow( |font| {
-                    font.draw_text_into_context(render_context,
-                                                text.text_run.get(),
-                                                &text.range,
-                                                baseline_origin,
-                                                text.color);
-                });
                 let width = text.base.bounds.size.width;
                 let underline_size = font_metrics.underline_size;
                 let underline_offset = font_metrics.underline_offset;
@@ -232,15 +488,97 @@ impl<E> DisplayItem<E> {
             ImageDisplayItemClass(ref image_item) => {
                 debug!("Drawing image at {:?}.", image_item.base.bounds);
 
-                render_context.draw_image(image_item.base.bounds, image_item.image.clone())
+                let bounds = image_item.base.bounds;
+                let size = image_item.stretch_size;
+                if size.width <= Au(0) || size.height <= Au(0) {
+                    return
+                }
+                // Repeated copies of the image are only drawn where this tile shows them. Copies
+                // smaller than a pixel are drawn a pixel large, which bounds their number.
+                let area = match render_context.visible_rect() {
+                    Some(visible_rect) => bounds.intersection(&visible_rect),
+                    None => None,
+                };
+                let area = match area {
+                    Some(area) => area,
+                    None => return,
+                };
+                let min_tile_length = Au::from_px(1);
+                let size = Size2D(if image_item.repeat_x {
+                                      Au::max(size.width, min_tile_length)
+                                  } else {
+                                      size.width
+                                  },
+                                  if image_item.repeat_y {
+                                      Au::max(size.height, min_tile_length)
+                                  } else {
+                                      size.height
+                                  });
+                let (start_x, end_x) = tile_range(area.origin.x, area.size.width,
+                                                  image_item.tile_origin.x, size.width,
+                                                  image_item.repeat_x);
+                let (start_y, end_y) = tile_range(area.origin.y, area.size.height,
+                                                  image_item.tile_origin.y, size.height,
+                                                  image_item.repeat_y);
+
+                render_context.draw_push_clip(&bounds, &BorderRadii::zero());
+                let mut y = start_y;
+                while y < end_y {
+                    let mut x = start_x;
+                    while x < end_x {
+                        render_context.draw_image(Rect(Point2D(x, y), size),
+                                                  image_item.image.clone());
+                        x = x + size.width;
+                    }
+                    y = y + size.height;
+                }
+                render_context.draw_pop_clip();
             }
 
             BorderDisplayItemClass(ref border) => {
                 render_context.draw_border(&border.base.bounds,
                                            border.border,
+                                           &border.radii,
                                            border.color,
                                            border.style)
             }
+
+            BoxShadowDisplayItemClass(ref shadow) => {
+                debug!("Drawing box shadow at {:?}.", shadow.base.bounds);
+
+                render_context.draw_box_shadow(&shadow.base.bounds,
+                                               &shadow.box_bounds,
+                                               &shadow.radii,
+                                               shadow.offset,
+                                               shadow.color,
+                                               shadow.blur_radius,
+                                               shadow.spread_radius,
+                                               shadow.inset)
+            }
+
+            TextShadowDisplayItemClass(ref shadow) => {
+                debug!("Drawing text shadow at {:?}.", shadow.base.bounds);
+
+                if shadow.blur_radius == Au(0) {
+                    draw_text_run(render_context, &shadow.text_run, &shadow.range,
+                                  shadow.origin, shadow.color);
+                } else {
+                    do render_context.draw_blurred(&shadow.base.bounds,
+                                                   shadow.blur_radius) |render_context| {
+                        draw_text_run(render_context, &shadow.text_run, &shadow.range,
+                                      shadow.origin, shadow.color);
+                    }
+                }
+            }
+
+            GradientDisplayItemClass(ref gradient) => {
+                debug!("Drawing gradient at {:?}.", gradient.base.bounds);
+
+                render_context.draw_gradient(&gradient.base.bounds,
+                                             &gradient.shape,
+                                             gradient.stops,
+                                             gradient.repeating)
+            }
         }
     }
 
@@ -252,7 +590,12 @@ impl<E> DisplayItem<E> {
                 TextDisplayItemClass(ref text) => transmute_region(&text.base),
                 ImageDisplayItemClass(ref image_item) => transmute_region(&image_item.base),
                 BorderDisplayItemClass(ref border) => transmute_region(&border.base),
+                GradientDisplayItemClass(ref gradient) => transmute_region(&gradient.base),
+                BoxShadowDisplayItemClass(ref shadow) => transmute_region(&shadow.base),
+                TextShadowDisplayItemClass(ref shadow) => transmute_region(&shadow.base),
                 ClipDisplayItemClass(ref clip) => transmute_region(&clip.base),
+                GroupDisplayItemClass(ref group) => transmute_region(&group.base),
+                TransformDisplayItemClass(ref transform) => transmute_region(&transform.base),
             }
         }
     }
@@ -264,10 +607,27 @@ impl<E> DisplayItem<E> {
     pub fn children<'a>(&'a self) -> DisplayItemIterator<'a,E> {
         match *self {
             ClipDisplayItemClass(ref clip) => ParentDisplayItemIterator(clip.child_list.iter()),
+            GroupDisplayItemClass(ref group) => {
+                ParentDisplayItemIterator(group.child_list.iter())
+            }
+            TransformDisplayItemClass(ref transform) => {
+                ParentDisplayItemIterator(transform.child_list.iter())
+            }
             SolidColorDisplayItemClass(*) |
             TextDisplayItemClass(*) |
             ImageDisplayItemClass(*) |
-            BorderDisplayItemClass(*) => EmptyDisplayItemIterator,
+            BorderDisplayItemClass(*) |
+            GradientDisplayItemClass(*) |
+            BoxShadowDisplayItemClass(*) |
+            TextShadowDisplayItemClass(*) => EmptyDisplayItemIterator,
+        }
+    }
+
+    /// Returns true if this item paints a shadow, whose bounds are not those of any box.
+    pub fn is_shadow(&self) -> bool {
+        match *self {
+            BoxShadowDisplayItemClass(*) | TextShadowDisplayItemClass(*) => true,
+            _ => false,
         }
     }
 
@@ -277,7 +637,12 @@ impl<E> DisplayItem<E> {
             TextDisplayItemClass(_) => "Text",
             ImageDisplayItemClass(_) => "Image",
             BorderDisplayItemClass(_) => "Border",
+            GradientDisplayItemClass(_) => "Gradient",
+            BoxShadowDisplayItemClass(_) => "BoxShadow",
+            TextShadowDisplayItemClass(_) => "TextShadow",
             ClipDisplayItemClass(_) => "Clip",
+            GroupDisplayItemClass(_) => "Group",
+            TransformDisplayItemClass(_) => "Transform",
         };
         let mut string = format!("{} @ {:?}", class, self.base().bounds);
         for child in self.children() {
@@ -287,3 +652,28 @@ impl<E> DisplayItem<E> {
     }
 }
 
+/// Draws a range of a text run, whose box has its top left corner at the given origin. Returns
+/// the metrics of the font.
+fn draw_text_run(render_context: &mut RenderContext,
+                 text_run: &Arc<~TextRun>,
+                 range: &Range,
+                 origin: Point2D<Au>,
+                 color: Color)
+                 -> FontMetrics {
+    // FIXME(pcwalton): Allocating? Why?
+    let text_run = text_run.get();
+    let font = render_context.font_ctx.get_font_by_descriptor(&text_run.font_descriptor).unwrap();
+
+    let font_metrics = font.with_borrow( |font| {
+        font.metrics.clone()
+    });
+    let baseline_origin = Point2D(origin.x, origin.y + font_metrics.ascent);
+    font.with_mut_borrow( |font| {
+        font.draw_text_into_context(render_context,
+                                    text_run,
+                                    range,
+                                    baseline_origin,
+                                    color);
+    });
+    font_metrics
+}
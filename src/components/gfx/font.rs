@@ -7,7 +7,9 @@ use azure::azure_hl::{BackendType, ColorPattern};
 use azure::scaled_font::ScaledFont;
 use extra::arc::Arc;
 use geom::{Point2D, Rect, Size2D};
+use geom::matrix2d::Matrix2D;
 use std::cast;
+use std::comm::Port;
 use std::ptr;
 use std::str;
 use std::vec;
@@ -15,11 +17,12 @@ use std::rc::RcMut;
 use servo_util::cache::{Cache, HashCache};
 use servo_util::range::Range;
 use servo_util::time::ProfilerChan;
-use style::computed_values::{text_decoration, font_weight, font_style};
+use style::computed_values::{font_weight, font_style, font_stretch};
 
 use color::Color;
+use font_cache_task::{FontCacheTask, ShapedTextKey};
 use font_context::FontContext;
-use servo_util::geometry::Au;
+use servo_util::geometry::{Au, to_frac_px};
 use platform::font_context::FontContextHandle;
 use platform::font::{FontHandle, FontTable};
 use render_context::RenderContext;
@@ -33,7 +36,7 @@ use text::{Shaper, TextRun};
 // resources needed by the graphics layer to draw glyphs.
 
 pub trait FontHandleMethods {
-    fn new_from_buffer(fctx: &FontContextHandle, buf: ~[u8], style: &SpecifiedFontStyle)
+    fn new_from_buffer(fctx: &FontContextHandle, buf: Arc<~[u8]>, style: &SpecifiedFontStyle)
                     -> Result<Self,()>;
 
     // an identifier usable by FontContextHandle to recreate this FontHandle.
@@ -42,6 +45,7 @@ pub trait FontHandleMethods {
     fn face_name(&self) -> ~str;
     fn is_italic(&self) -> bool;
     fn boldness(&self) -> font_weight::T;
+    fn stretchiness(&self) -> font_stretch::T;
 
     fn clone_with_style(&self, fctx: &FontContextHandle, style: &UsedFontStyle)
                      -> Result<FontHandle, ()>;
@@ -101,8 +105,9 @@ pub struct FontStyle {
     pt_size: f64,
     weight: font_weight::T,
     style: font_style::T,
+    stretch: font_stretch::T,
     families: ~[~str],
-    // TODO(Issue #198): font-stretch, text-decoration, font-variant, size-adjust
+    // TODO(Issue #198): text-decoration, font-variant, size-adjust
 }
 
 pub type SpecifiedFontStyle = FontStyle;
@@ -139,6 +144,25 @@ impl FontDescriptor {
 #[deriving(Clone, Eq)]
 pub enum FontSelector {
     SelectorPlatformIdentifier(~str),
+    SelectorWebFont(WebFontData),
+}
+
+/// The data of a font loaded for an `@font-face` rule. The layout and render tasks each create
+/// their own font from the same shared buffer.
+#[deriving(Clone)]
+pub struct WebFontData {
+    /// The URL the font was loaded from, or `local(...)` with the name of the system font it was
+    /// made from, which identifies it.
+    url: ~str,
+    data: Arc<~[u8]>,
+    /// The inclusive ranges of code points the font is used for. Empty means all of them.
+    unicode_range: ~[(u32, u32)],
+}
+
+impl Eq for WebFontData {
+    fn eq(&self, other: &WebFontData) -> bool {
+        self.url == other.url
+    }
 }
 
 // This struct is the result of mapping a specified FontStyle into the
@@ -169,13 +193,9 @@ impl FontGroup {
         self.fonts = ~[];
     }
 
-    pub fn create_textrun(&self, text: ~str, decoration: text_decoration::T) -> TextRun {
-        assert!(self.fonts.len() > 0);
-
-        // TODO(Issue #177): Actually fall back through the FontGroup when a font is unsuitable.
-        self.fonts[0].with_mut_borrow(|font| {
-            TextRun::new(font, text.clone(), decoration)
-        })
+    /// Returns the index of the first font of the group that can render the given character.
+    pub fn font_index_for_char(&self, codepoint: char) -> Option<uint> {
+        self.fonts.iter().position(|font| font.with_borrow(|font| font.covers(codepoint)))
     }
 }
 
@@ -207,6 +227,26 @@ impl RunMetrics {
     }
 }
 
+/// What a font draws itself because its face lacks it: a face lighter than the requested bold is
+/// drawn twice with an offset, and an upright face for an italic or oblique style is skewed.
+#[deriving(Clone, Eq)]
+pub struct FontSynthesis {
+    bold: bool,
+    oblique: bool,
+}
+
+impl FontSynthesis {
+    pub fn new(handle: &FontHandle, style: &UsedFontStyle) -> FontSynthesis {
+        FontSynthesis {
+            bold: style.weight.is_bold() && !handle.boldness().is_bold(),
+            oblique: style.style != font_style::normal && !handle.is_italic(),
+        }
+    }
+}
+
+/// The horizontal shear of synthesized oblique text, about tan(14deg).
+static OBLIQUE_SKEW: AzFloat = 0.25;
+
 /**
 A font instance. Layout can use this to calculate glyph metrics
 and the renderer can use it to render text.
@@ -217,15 +257,24 @@ pub struct Font {
     priv shaper: Option<Shaper>,
     style: UsedFontStyle,
     metrics: FontMetrics,
+    synthesis: FontSynthesis,
     backend: BackendType,
     profiler_chan: ProfilerChan,
-    shape_cache: HashCache<~str, Arc<GlyphStore>>,
+    /// The glyphs of texts shaped with this font, by text and whether it is right-to-left.
+    shape_cache: HashCache<(~str, bool), Arc<GlyphStore>>,
     glyph_advance_cache: HashCache<u32, FractionalPixel>,
+    /// The `@font-face` data this font was created from, if any.
+    priv web_font: Option<WebFontData>,
+    /// Shares the text this font shapes with the fonts of the other pipelines.
+    priv font_cache_task: FontCacheTask,
+    /// The texts that the fonts of all pipelines shaped with this font, until they arrive from
+    /// the font cache task.
+    priv shared_shaped_texts: Option<Port<~[(ShapedTextKey, Arc<GlyphStore>)]>>,
 }
 
 impl<'self> Font {
     pub fn new_from_buffer(ctx: &FontContext,
-                       buffer: ~[u8],
+                       buffer: Arc<~[u8]>,
                        style: &SpecifiedFontStyle,
                        backend: BackendType,
                        profiler_chan: ProfilerChan)
@@ -238,6 +287,7 @@ impl<'self> Font {
         };
 
         let metrics = handle.get_metrics();
+        let synthesis = FontSynthesis::new(&handle, style);
         // TODO(Issue #179): convert between specified and used font style here?
 
         return Ok(RcMut::new(Font {
@@ -246,17 +296,36 @@ impl<'self> Font {
             shaper: None,
             style: (*style).clone(),
             metrics: metrics,
+            synthesis: synthesis,
             backend: backend,
             profiler_chan: profiler_chan,
             shape_cache: HashCache::new(),
             glyph_advance_cache: HashCache::new(),
+            web_font: None,
+            font_cache_task: ctx.font_cache_task.clone(),
+            shared_shaped_texts: None,
         }));
     }
 
-    pub fn new_from_adopted_handle(_fctx: &FontContext, handle: FontHandle,
+    pub fn new_from_web_font(ctx: &FontContext,
+                             web_font: &WebFontData,
+                             style: &SpecifiedFontStyle,
+                             backend: BackendType,
+                             profiler_chan: ProfilerChan)
+            -> Result<RcMut<Font>, ()> {
+        let result = Font::new_from_buffer(ctx, web_font.data.clone(), style, backend,
+                                           profiler_chan);
+        for font in result.iter() {
+            font.with_mut_borrow(|font| font.web_font = Some(web_font.clone()));
+        }
+        result
+    }
+
+    pub fn new_from_adopted_handle(fctx: &FontContext, handle: FontHandle,
                                style: &SpecifiedFontStyle, backend: BackendType,
                                profiler_chan: ProfilerChan) -> Font {
         let metrics = handle.get_metrics();
+        let synthesis = FontSynthesis::new(&handle, style);
 
         Font {
             handle: handle,
@@ -264,10 +333,14 @@ impl<'self> Font {
             shaper: None,
             style: (*style).clone(),
             metrics: metrics,
+            synthesis: synthesis,
             backend: backend,
             profiler_chan: profiler_chan,
             shape_cache: HashCache::new(),
             glyph_advance_cache: HashCache::new(),
+            web_font: None,
+            font_cache_task: fctx.font_cache_task.clone(),
+            shared_shaped_texts: None,
         }
     }
 
@@ -374,14 +447,33 @@ impl Font {
             fields: 0x0200 as uint16_t
         };
 
-        let mut origin = baseline_origin.clone();
+        // The glyphs of right-to-left text are in logical order, so the pen starts at the right
+        // edge of the text and moves leftward.
+        let mut origin = if run.rtl {
+            let advance = run.metrics_for_range(range).advance_width;
+            Point2D(baseline_origin.x + advance, baseline_origin.y)
+        } else {
+            baseline_origin.clone()
+        };
         let mut azglyphs = ~[];
         azglyphs.reserve(range.length());
 
         for (glyphs, _offset, slice_range) in run.iter_slices_for_range(range) {
-            for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
-                let glyph_advance = glyph.advance();
+            let spacing = run.spacing_for_char(glyphs);
+            let spacing = if run.rtl { -spacing } else { spacing };
+            let mut last_char_index = None;
+            for (i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
+                // The spacing goes after the last glyph of each character.
+                if last_char_index.is_some() && last_char_index != Some(i) {
+                    origin = Point2D(origin.x + spacing, origin.y);
+                }
+                last_char_index = Some(i);
+
+                let glyph_advance = if run.rtl { -glyph.advance() } else { glyph.advance() };
                 let glyph_offset = glyph.offset().unwrap_or(Au::zero_point());
+                if run.rtl {
+                    origin = Point2D(origin.x + glyph_advance, origin.y);
+                }
 
                 let azglyph = struct__AzGlyph {
                     mIndex: glyph.index() as uint32_t,
@@ -390,9 +482,14 @@ impl Font {
                         y: (origin.y + glyph_offset.y).to_nearest_px() as AzFloat
                     }
                 };
-                origin = Point2D(origin.x + glyph_advance, origin.y);
+                if !run.rtl {
+                    origin = Point2D(origin.x + glyph_advance, origin.y);
+                }
                 azglyphs.push(azglyph)
             };
+            if last_char_index.is_some() {
+                origin = Point2D(origin.x + spacing, origin.y);
+            }
         }
 
         let azglyph_buf_len = azglyphs.len();
@@ -403,6 +500,14 @@ impl Font {
             mNumGlyphs: azglyph_buf_len as uint32_t
         };
 
+        // Synthesized oblique glyphs lean right about the baseline.
+        let old_transform = target.get_transform();
+        if self.synthesis.oblique {
+            let baseline = baseline_origin.y.to_nearest_px() as AzFloat;
+            let skew = Matrix2D::new(1.0, 0.0, -OBLIQUE_SKEW, 1.0, OBLIQUE_SKEW * baseline, 0.0);
+            target.set_transform(&old_transform.mul(&skew));
+        }
+
         unsafe {
             // TODO(Issue #64): this call needs to move into azure_hl.rs
             AzDrawTargetFillGlyphs(target.azure_draw_target,
@@ -412,13 +517,41 @@ impl Font {
                                    ptr::to_unsafe_ptr(&options),
                                    ptr::null());
         }
+
+        // Synthesized bold glyphs are filled again, shifted by the extra advance they were given.
+        if self.synthesis.bold {
+            let offset = self.synthetic_bold_offset() as AzFloat;
+            for azglyph in azglyphs.mut_iter() {
+                azglyph.mPosition.x += offset;
+            }
+            let glyphbuf = struct__AzGlyphBuffer {
+                mGlyphs: vec::raw::to_ptr(azglyphs),
+                mNumGlyphs: azglyph_buf_len as uint32_t
+            };
+            unsafe {
+                AzDrawTargetFillGlyphs(target.azure_draw_target,
+                                       azfontref,
+                                       ptr::to_unsafe_ptr(&glyphbuf),
+                                       azure_pattern,
+                                       ptr::to_unsafe_ptr(&options),
+                                       ptr::null());
+            }
+        }
+
+        if self.synthesis.oblique {
+            target.set_transform(&old_transform);
+        }
+    }
+
+    /// How far synthesized bold glyphs are smeared, in pixels.
+    fn synthetic_bold_offset(&self) -> FractionalPixel {
+        (self.style.pt_size / 24.0).max(&1.0)
     }
 
     pub fn measure_text(&self, run: &TextRun, range: &Range) -> RunMetrics {
-        // TODO(Issue #199): alter advance direction for RTL
-        // TODO(Issue #98): using inter-char and inter-word spacing settings  when measuring text
         let mut advance = Au(0);
         for (glyphs, _offset, slice_range) in run.iter_slices_for_range(range) {
+            advance = advance + run.spacing_for_slice(glyphs, &slice_range);
             for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
                 advance = advance + glyph.advance();
             }
@@ -437,31 +570,94 @@ impl Font {
         RunMetrics::new(advance, self.metrics.ascent, self.metrics.descent)
     }
 
-    pub fn shape_text(&mut self, text: ~str, is_whitespace: bool) -> Arc<GlyphStore> {
+    /// Asks the font cache task for the texts that the fonts of all pipelines shaped with this
+    /// font. They are added to the cache of this font when they arrive, which is usually before
+    /// it shapes much text.
+    pub fn request_shared_shaped_texts(&mut self) {
+        let port = self.font_cache_task.find_shaped_texts(self.get_descriptor());
+        self.shared_shaped_texts = Some(port)
+    }
+
+    /// Adds the texts shaped by the fonts of other pipelines to the cache of this font, if they
+    /// have arrived.
+    fn receive_shared_shaped_texts(&mut self) {
+        let arrived = match self.shared_shaped_texts {
+            Some(ref port) => port.peek(),
+            None => false,
+        };
+        if !arrived {
+            return
+        }
+        let shaped_texts = self.shared_shaped_texts.take_unwrap().recv();
+        for (key, glyphs) in shaped_texts.move_iter() {
+            self.shape_cache.insert((key.text, key.rtl), glyphs)
+        }
+    }
+
+    /// Shapes the given text, or finds it shaped by this font or by the same font of another
+    /// pipeline. Text that isn't found is shaped here rather than waiting for the font cache
+    /// task, and the result is shared with the other pipelines.
+    pub fn shape_text(&mut self, text: ~str, is_whitespace: bool, rtl: bool) -> Arc<GlyphStore> {
+        self.receive_shared_shaped_texts();
+        match self.shape_cache.find(&(text.clone(), rtl)) {
+            Some(glyphs) => return glyphs,
+            None => (),
+        }
 
         //FIXME (ksh8281)
         self.make_shaper();
-        do self.shape_cache.find_or_create(&text) |txt| {
-            let mut glyphs = GlyphStore::new(text.char_len(), is_whitespace);
-            self.shaper.get_ref().shape_text(*txt, &mut glyphs);
-            Arc::new(glyphs)
-        }
+        let mut glyphs = GlyphStore::new(text.char_len(), is_whitespace);
+        self.shaper.get_ref().shape_text(text.as_slice(), rtl, &mut glyphs);
+        let glyphs = Arc::new(glyphs);
+        let key = ShapedTextKey {
+            font: self.get_descriptor(),
+            text: text.clone(),
+            rtl: rtl,
+        };
+        self.font_cache_task.add_shaped_text(key, glyphs.clone());
+        self.shape_cache.insert((text, rtl), glyphs.clone());
+        glyphs
     }
 
     pub fn get_descriptor(&self) -> FontDescriptor {
-        FontDescriptor::new(self.style.clone(), SelectorPlatformIdentifier(self.handle.face_identifier()))
+        let selector = match self.web_font {
+            Some(ref web_font) => SelectorWebFont(web_font.clone()),
+            None => SelectorPlatformIdentifier(self.handle.face_identifier()),
+        };
+        FontDescriptor::new(self.style.clone(), selector)
     }
 
     pub fn glyph_index(&self, codepoint: char) -> Option<GlyphIndex> {
         self.handle.glyph_index(codepoint)
     }
 
+    /// Whether this font has a glyph for the given character and, for a web font, whether its
+    /// `unicode-range` includes it.
+    pub fn covers(&self, codepoint: char) -> bool {
+        let in_range = match self.web_font {
+            Some(ref web_font) => {
+                web_font.unicode_range.is_empty() ||
+                    web_font.unicode_range.iter().any(|&(start, end)| {
+                        start <= codepoint as u32 && codepoint as u32 <= end
+                    })
+            }
+            None => true,
+        };
+        in_range && self.glyph_index(codepoint).is_some()
+    }
+
     pub fn glyph_h_advance(&mut self, glyph: GlyphIndex) -> FractionalPixel {
+        let bold_offset = if self.synthesis.bold { self.synthetic_bold_offset() } else { 0.0 };
         do self.glyph_advance_cache.find_or_create(&glyph) |glyph| {
-            match self.handle.glyph_h_advance(*glyph) {
+            let advance = match self.handle.glyph_h_advance(*glyph) {
                 Some(adv) => adv,
-                None => /* FIXME: Need fallback strategy */ 10f64 as FractionalPixel
-            }
+                // Draw the glyph as the font's missing-glyph box, or as half an em without one.
+                None => match self.handle.glyph_h_advance(0) {
+                    Some(adv) => adv,
+                    None => to_frac_px(self.metrics.em_size) / 2.0,
+                }
+            };
+            advance + bold_offset
         }
     }
 }
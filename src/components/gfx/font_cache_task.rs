@@ -0,0 +1,302 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The task that holds what the fonts of all pipelines share: the list of system font families,
+//! the data of the font files, and the glyphs of recently shaped text.
+
+use font::{FontDescriptor, FontSelector, SelectorPlatformIdentifier, SelectorWebFont};
+use font::SpecifiedFontStyle;
+use font_list::FontList;
+use platform::font_context::FontContextHandle;
+use platform::font_list::FontListHandle;
+use text::glyph::GlyphStore;
+
+use extra::arc::Arc;
+use servo_util::cache::HashLRUCache;
+use servo_util::io::result;
+use servo_util::time::{FontCacheFileDataMemory, FontCacheShapedTextMemory, MemoryMsg};
+use servo_util::time::ProfilerChan;
+use std::comm::{Chan, Port, SharedChan, stream};
+use std::rt::io::file;
+use std::rt::io::{EndOfFile, Open, Read, Reader, ignore_io_error};
+use std::task::spawn_with;
+use std::to_bytes::Cb;
+
+/// The number of shaped texts kept for all pipelines together.
+static SHAPED_TEXT_CACHE_SIZE: uint = 1000;
+
+/// The number of font files whose data is kept. Fonts keep the data of their own file alive, so
+/// evicting it only means that the next font made from the file reads it again.
+static FONT_DATA_CACHE_SIZE: uint = 32;
+
+static READ_SIZE: uint = 4096;
+
+/// A text shaped with a font, left-to-right or right-to-left.
+#[deriving(Clone, Eq)]
+pub struct ShapedTextKey {
+    font: FontDescriptor,
+    text: ~str,
+    rtl: bool,
+}
+
+impl IterBytes for ShapedTextKey {
+    fn iter_bytes(&self, lsb0: bool, f: Cb) -> bool {
+        // Keys for the same face at different sizes or styles hash alike and are told apart by
+        // `Eq`.
+        let face = match self.font.selector {
+            SelectorPlatformIdentifier(ref identifier) => identifier.as_slice(),
+            SelectorWebFont(ref web_font) => web_font.url.as_slice(),
+        };
+        self.text.iter_bytes(lsb0, |x| f(x)) &&
+            self.rtl.iter_bytes(lsb0, |x| f(x)) &&
+            face.iter_bytes(lsb0, |x| f(x))
+    }
+}
+
+pub enum Msg {
+    /// Finds the faces of a system family to use for a style.
+    FindFontsInFamilyMsg(~str, SpecifiedFontStyle, Chan<~[FontSelector]>),
+    /// Finds a system face with a glyph for a character.
+    FindFallbackFontMsg(char, SpecifiedFontStyle, Chan<Option<FontSelector>>),
+    /// Gets the data of the font file a face identifier names, if it names one.
+    GetFontDataMsg(~str, Chan<Option<Arc<~[u8]>>>),
+    /// Finds the texts shaped with a font.
+    FindShapedTextsMsg(FontDescriptor, Chan<~[(ShapedTextKey, Arc<GlyphStore>)]>),
+    AddShapedTextMsg(ShapedTextKey, Arc<GlyphStore>),
+    ExitMsg(Chan<()>),
+}
+
+#[deriving(Clone)]
+pub struct FontCacheTask {
+    chan: SharedChan<Msg>,
+}
+
+impl FontCacheTask {
+    pub fn new(profiler_chan: ProfilerChan) -> FontCacheTask {
+        let (port, chan) = stream();
+        do spawn_with((port, profiler_chan)) |(port, profiler_chan)| {
+            let handle = FontContextHandle::new();
+            let mut cache = FontCache {
+                port: port,
+                font_list: FontList::new(&handle, profiler_chan.clone()),
+                font_data: HashLRUCache::new(FONT_DATA_CACHE_SIZE),
+                font_data_size: 0,
+                shaped_text: HashLRUCache::new(SHAPED_TEXT_CACHE_SIZE),
+                shaped_text_size: 0,
+                profiler_chan: profiler_chan,
+            };
+            cache.run();
+        }
+        FontCacheTask {
+            chan: SharedChan::new(chan),
+        }
+    }
+
+    pub fn find_fonts_in_family(&self, family_name: ~str, style: &SpecifiedFontStyle)
+                                -> ~[FontSelector] {
+        let (port, chan) = stream();
+        self.chan.send(FindFontsInFamilyMsg(family_name, style.clone(), chan));
+        port.recv()
+    }
+
+    pub fn find_fallback_font(&self, codepoint: char, style: &SpecifiedFontStyle)
+                              -> Option<FontSelector> {
+        let (port, chan) = stream();
+        self.chan.send(FindFallbackFontMsg(codepoint, style.clone(), chan));
+        port.recv()
+    }
+
+    /// Returns the data of the file of the given face, loaded once for all fonts of the process,
+    /// or `None` if the face isn't identified by a file that can be read.
+    pub fn get_font_data(&self, identifier: ~str) -> Option<Arc<~[u8]>> {
+        let (port, chan) = stream();
+        self.chan.send(GetFontDataMsg(identifier, chan));
+        port.recv()
+    }
+
+    /// Asks for the texts that the fonts of all pipelines shaped with the given font. The answer
+    /// arrives on the returned port, so that the font can go on shaping text without waiting.
+    pub fn find_shaped_texts(&self, font: FontDescriptor)
+                             -> Port<~[(ShapedTextKey, Arc<GlyphStore>)]> {
+        let (port, chan) = stream();
+        self.chan.send(FindShapedTextsMsg(font, chan));
+        port
+    }
+
+    pub fn add_shaped_text(&self, key: ShapedTextKey, glyphs: Arc<GlyphStore>) {
+        self.chan.send(AddShapedTextMsg(key, glyphs));
+    }
+
+    pub fn exit(&self) {
+        let (port, chan) = stream();
+        self.chan.send(ExitMsg(chan));
+        port.recv();
+    }
+}
+
+struct FontCache {
+    port: Port<Msg>,
+    /// The system fonts. Web fonts belong to the document that loads them, so they stay in the
+    /// font context of its layout task.
+    font_list: FontList,
+    /// The data of recently used font files, or `None` for the identifiers that name no
+    /// readable file.
+    font_data: HashLRUCache<~str, Option<Arc<~[u8]>>>,
+    /// The number of bytes of font file data in `font_data`.
+    font_data_size: uint,
+    shaped_text: HashLRUCache<ShapedTextKey, Arc<GlyphStore>>,
+    /// The number of bytes the entries of `shaped_text` use.
+    shaped_text_size: uint,
+    profiler_chan: ProfilerChan,
+}
+
+impl FontCache {
+    fn run(&mut self) {
+        loop {
+            match self.port.recv() {
+                FindFontsInFamilyMsg(family_name, style, response) => {
+                    response.send(self.font_list.find_fonts_in_family(&family_name, &style))
+                }
+                FindFallbackFontMsg(codepoint, style, response) => {
+                    response.send(self.font_list.find_fallback_font(codepoint, &style))
+                }
+                GetFontDataMsg(identifier, response) => {
+                    response.send(self.get_font_data(identifier))
+                }
+                FindShapedTextsMsg(font, response) => {
+                    response.send(self.find_shaped_texts(&font))
+                }
+                AddShapedTextMsg(key, glyphs) => self.add_shaped_text(key, glyphs),
+                ExitMsg(response) => {
+                    response.send(());
+                    break
+                }
+            }
+        }
+    }
+
+    fn get_font_data(&mut self, identifier: ~str) -> Option<Arc<~[u8]>> {
+        match self.font_data.find(&identifier) {
+            Some(data) => return data,
+            None => (),
+        }
+        let data = match FontListHandle::file_for_identifier(identifier.as_slice()) {
+            Some(path) => read_font_file(path.as_slice()).map(|data| Arc::new(data)),
+            None => None,
+        };
+        self.font_data_size += font_data_size(&data);
+        match self.font_data.insert_and_evict(identifier, data.clone()) {
+            Some((_, evicted)) => self.font_data_size -= font_data_size(&evicted),
+            None => (),
+        }
+        self.profiler_chan.send_deferred(MemoryMsg(FontCacheFileDataMemory, self.font_data_size));
+        data
+    }
+
+    /// Returns the texts shaped with the given font. Finding them doesn't count as a use, since
+    /// fonts ask for them before they need any.
+    fn find_shaped_texts(&self, font: &FontDescriptor) -> ~[(ShapedTextKey, Arc<GlyphStore>)] {
+        let mut shaped_texts = ~[];
+        do self.shaped_text.each_entry |key, glyphs| {
+            if key.font == *font {
+                shaped_texts.push((key.clone(), glyphs.clone()))
+            }
+        }
+        shaped_texts
+    }
+
+    fn add_shaped_text(&mut self, key: ShapedTextKey, glyphs: Arc<GlyphStore>) {
+        // Layout tasks that missed the same text at once all shape it. The last copy replaces
+        // the others.
+        self.shaped_text_size += shaped_text_size(&key, &glyphs);
+        match self.shaped_text.insert_and_evict(key, glyphs) {
+            Some((key, glyphs)) => self.shaped_text_size -= shaped_text_size(&key, &glyphs),
+            None => (),
+        }
+        self.profiler_chan.send_deferred(MemoryMsg(FontCacheShapedTextMemory,
+                                                   self.shaped_text_size));
+    }
+}
+
+fn font_data_size(data: &Option<Arc<~[u8]>>) -> uint {
+    match *data {
+        Some(ref data) => data.get().len(),
+        None => 0,
+    }
+}
+
+fn shaped_text_size(key: &ShapedTextKey, glyphs: &Arc<GlyphStore>) -> uint {
+    key.text.len() + glyphs.get().memory_usage()
+}
+
+/// Reads a whole font file, or returns `None` if it can't be read.
+fn read_font_file(path: &str) -> Option<~[u8]> {
+    // ignore_io_error causes us to get None instead of a task failure.
+    let mut reader = match ignore_io_error(|| file::open(&path, Open, Read)) {
+        Some(reader) => reader,
+        None => return None,
+    };
+    let mut data = ~[];
+    loop {
+        match result(|| reader.read_bytes(READ_SIZE)) {
+            Ok(bytes) => data.push_all_move(bytes),
+            Err(e) => match e.kind {
+                EndOfFile => return Some(data),
+                _ => {
+                    debug!("(font cache) couldn't read `{:s}`", path);
+                    return None
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+fn shaped_text_key_for_test(text: &str, rtl: bool) -> ShapedTextKey {
+    use font::FontStyle;
+    use style::computed_values::{font_stretch, font_style, font_weight};
+
+    let style = FontStyle {
+        pt_size: 16.0,
+        weight: font_weight::Weight400,
+        style: font_style::normal,
+        stretch: font_stretch::normal,
+        families: ~[~"serif"],
+    };
+    ShapedTextKey {
+        font: FontDescriptor::new(style, SelectorPlatformIdentifier(~"test-face")),
+        text: text.to_owned(),
+        rtl: rtl,
+    }
+}
+
+#[test]
+fn test_find_and_add_shaped_text() {
+    let (_profiler_port, profiler_chan) = stream();
+    let font_cache_task = FontCacheTask::new(ProfilerChan::new(profiler_chan));
+    let font = shaped_text_key_for_test("", false).font;
+    assert!(font_cache_task.find_shaped_texts(font.clone()).recv().is_empty());
+
+    font_cache_task.add_shaped_text(shaped_text_key_for_test("hello", false),
+                                    Arc::new(GlyphStore::new(5, false)));
+    font_cache_task.add_shaped_text(shaped_text_key_for_test("hello", true),
+                                    Arc::new(GlyphStore::new(5, false)));
+    let shaped_texts = font_cache_task.find_shaped_texts(font.clone()).recv();
+    assert_eq!(shaped_texts.len(), 2);
+    for &(ref key, ref glyphs) in shaped_texts.iter() {
+        assert_eq!(key.text, ~"hello");
+        assert_eq!(glyphs.get().char_len(), 5);
+    }
+
+    // Only the texts of the same font are found.
+    let mut other_font = font;
+    other_font.style.pt_size = 12.0;
+    assert!(font_cache_task.find_shaped_texts(other_font).recv().is_empty());
+    font_cache_task.exit();
+}
+
+#[test]
+fn test_read_missing_font_file() {
+    assert!(read_font_file("/nonexistent/servo/font-cache-test.ttf").is_none());
+}
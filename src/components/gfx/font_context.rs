@@ -2,30 +2,42 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use font::{Font, FontDescriptor, FontGroup, FontHandleMethods,
-           SelectorPlatformIdentifier};
+use font::{Font, FontDescriptor, FontGroup, FontHandleMethods, FontSelector,
+           SelectorPlatformIdentifier, SelectorWebFont};
 use font::{SpecifiedFontStyle, UsedFontStyle};
-use font_list::FontList;
+use font_cache_task::FontCacheTask;
+use font_list::{FontList, WebFont};
+use servo_util::geometry::Au;
 use servo_util::cache::{Cache, LRUCache};
 use servo_util::time::ProfilerChan;
 
 use platform::font::FontHandle;
 use platform::font_context::FontContextHandle;
+use text::TextRun;
 
 use azure::azure_hl::BackendType;
+use extra::arc::Arc;
 use std::hashmap::HashMap;
+use std::util;
+use style::computed_values::{tab_size, text_decoration};
 
 use std::rc::RcMut;
 
 
 pub trait FontContextHandleMethods {
     fn create_font_from_identifier(&self, ~str, UsedFontStyle) -> Result<FontHandle, ()>;
+    /// Creates the face the identifier names from the data of its file.
+    fn create_font_from_data(&self, ~str, Arc<~[u8]>, UsedFontStyle) -> Result<FontHandle, ()>;
 }
 
 pub struct FontContext {
     instance_cache: LRUCache<FontDescriptor, RcMut<Font>>,
-    font_list: Option<FontList>, // only needed by layout
+    font_list: Option<FontList>, // web fonts, only needed by layout
+    font_cache_task: FontCacheTask,
     group_cache: LRUCache<SpecifiedFontStyle, RcMut<FontGroup>>,
+    /// The data of the font files that the font cache task sent, by face identifier, so that it
+    /// is asked once for each face. Documents use few faces, so the data is kept.
+    font_data: HashMap<~str, Option<Arc<~[u8]>>>,
     handle: FontContextHandle,
     backend: BackendType,
     generic_fonts: HashMap<~str,~str>,
@@ -35,11 +47,12 @@ pub struct FontContext {
 impl<'self> FontContext {
     pub fn new(backend: BackendType,
            needs_font_list: bool,
+           font_cache_task: FontCacheTask,
            profiler_chan: ProfilerChan)
            -> FontContext {
         let handle = FontContextHandle::new();
         let font_list = if needs_font_list {
-                            Some(FontList::new(&handle, profiler_chan.clone())) }
+                            Some(FontList::new_empty(&handle, profiler_chan.clone())) }
                         else { None };
 
         // TODO: Allow users to specify these.
@@ -53,7 +66,9 @@ impl<'self> FontContext {
         FontContext {
             instance_cache: LRUCache::new(10),
             font_list: font_list,
+            font_cache_task: font_cache_task,
             group_cache: LRUCache::new(10),
+            font_data: HashMap::new(),
             handle: handle,
             backend: backend,
             generic_fonts: generic_fonts,
@@ -65,6 +80,22 @@ impl<'self> FontContext {
         self.font_list.get_ref()
     }
 
+    /// Makes a font loaded for an `@font-face` rule available to the font groups created from now
+    /// on. The face is created once, here, and shared by every font group that uses it. The
+    /// cached font groups are dropped, since the new font may belong in them.
+    pub fn add_web_font(&mut self, font: &WebFont) {
+        let result = match self.font_list {
+            Some(ref mut font_list) => font_list.add_web_font(&self.handle, font),
+            None => fail!("web fonts are only used by layout"),
+        };
+        match result {
+            Ok(()) => self.group_cache.evict_all(),
+            Err(()) => {
+                debug!("(add web font) couldn't create a font from `{:s}`", font.data.url);
+            }
+        }
+    }
+
     pub fn get_resolved_font_for_style(&mut self, style: &SpecifiedFontStyle) -> RcMut<FontGroup> {
         match self.group_cache.find(style) {
             Some(fg) => {
@@ -91,6 +122,7 @@ impl<'self> FontContext {
                 let result = self.create_font_instance(desc);
                 match result.clone() {
                     Ok(ref font) => {
+                        font.with_mut_borrow(|font| font.request_shared_shaped_texts());
                         self.instance_cache.insert(desc.clone(), font.clone());
                     }, _ => {}
                 };
@@ -99,6 +131,92 @@ impl<'self> FontContext {
         }
     }
 
+    /// Shapes the given text into text runs, splitting it wherever the font changes. Each character
+    /// is rendered by the first font of the group for the style that has a glyph for it, or else
+    /// by a system font that does. Whitespace stays in the run it follows. Characters that no font
+    /// can render use the first font of the group.
+    pub fn create_textruns(&mut self,
+                           style: &SpecifiedFontStyle,
+                           text: &str,
+                           rtl: bool,
+                           decoration: text_decoration::T,
+                           letter_spacing: Au,
+                           word_spacing: Au,
+                           tab_size: tab_size::T)
+                           -> ~[TextRun] {
+        let fontgroup = self.get_resolved_font_for_style(style);
+        let mut fonts = fontgroup.with_borrow(|fg| fg.fonts.clone());
+        assert!(fonts.len() > 0);
+
+        let segments = do split_text_by_font(text) |ch| {
+            match fontgroup.with_borrow(|fg| fg.font_index_for_char(ch)) {
+                Some(index) => index,
+                None => self.fallback_font_index(style, ch, &mut fonts),
+            }
+        };
+        segments.move_iter().map(|(index, segment_text)| {
+            do fonts[index].with_mut_borrow |font| {
+                TextRun::new(font, segment_text.clone(), rtl, decoration, letter_spacing,
+                             word_spacing, tab_size)
+            }
+        }).collect()
+    }
+
+    /// Shapes the given text into a single text run, with the font that renders its first
+    /// character that isn't whitespace. This is meant for text that `create_textruns` already put
+    /// in one run.
+    pub fn create_textrun(&mut self,
+                          style: &SpecifiedFontStyle,
+                          text: ~str,
+                          rtl: bool,
+                          decoration: text_decoration::T,
+                          letter_spacing: Au,
+                          word_spacing: Au,
+                          tab_size: tab_size::T)
+                          -> TextRun {
+        let fontgroup = self.get_resolved_font_for_style(style);
+        let mut fonts = fontgroup.with_borrow(|fg| fg.fonts.clone());
+        assert!(fonts.len() > 0);
+
+        let index = match text.iter().find(|ch| !ch.is_whitespace()) {
+            Some(ch) => match fontgroup.with_borrow(|fg| fg.font_index_for_char(ch)) {
+                Some(index) => index,
+                None => self.fallback_font_index(style, ch, &mut fonts),
+            },
+            None => 0,
+        };
+        do fonts[index].with_mut_borrow |font| {
+            TextRun::new(font, text.clone(), rtl, decoration, letter_spacing, word_spacing,
+                         tab_size)
+        }
+    }
+
+    /// Returns the index in `fonts` of a system font that can render the given character, adding
+    /// it if needed, or 0 if there is none.
+    fn fallback_font_index(&mut self, style: &SpecifiedFontStyle, codepoint: char,
+                           fonts: &mut ~[RcMut<Font>]) -> uint {
+        let font_desc = match self.font_cache_task.find_fallback_font(codepoint, style) {
+            Some(selector) => FontDescriptor::new((*style).clone(), selector),
+            None => {
+                debug!("(fallback font) no font can render `{}`", codepoint);
+                return 0
+            }
+        };
+        let position = fonts.iter().position(|font| {
+            font.with_borrow(|font| font.get_descriptor() == font_desc)
+        });
+        match position {
+            Some(index) => index,
+            None => match self.get_font_by_descriptor(&font_desc) {
+                Ok(font) => {
+                    fonts.push(font);
+                    fonts.len() - 1
+                }
+                Err(()) => 0,
+            },
+        }
+    }
+
     fn transform_family(&self, family: &~str) -> ~str {
         debug!("(transform family) searching for `{:s}`", family.as_slice());
         match self.generic_fonts.find(family) {
@@ -118,32 +236,14 @@ impl<'self> FontContext {
             debug!("(create font group) transformed family is `{:s}`", transformed_family_name);
             let mut found = false;
 
-            let result = match self.font_list {
-                Some(ref mut fl) => {
-                    let font_in_family = fl.find_font_in_family(&transformed_family_name, style);
-                    match font_in_family {
-                        Some(font_entry) => {
-                            let font_id =
-                                SelectorPlatformIdentifier(font_entry.handle.face_identifier());
-                            let font_desc = FontDescriptor::new((*style).clone(), font_id);
-                            Some(font_desc)
-                        },
-                        None => {
-                            None
-                        }
-                    }
-                }
-                None => None,
-            };
+            let selectors = self.find_fonts_in_family(&transformed_family_name, style);
 
-            match result {
-                Some(ref result) => {
-                    found = true;
-                    let instance = self.get_font_by_descriptor(result);
+            for selector in selectors.move_iter() {
+                found = true;
+                let font_desc = FontDescriptor::new((*style).clone(), selector);
+                let instance = self.get_font_by_descriptor(&font_desc);
 
-                    for font in instance.iter() { fonts.push(font.clone()); }
-                },
-                _ => {}
+                for font in instance.iter() { fonts.push(font.clone()); }
             }
 
             if !found {
@@ -154,38 +254,15 @@ impl<'self> FontContext {
         if fonts.len() == 0 {
             let last_resort = FontList::get_last_resort_font_families();
             for family in last_resort.iter() {
-                let font_desc = match self.font_list {
-                    Some(ref mut font_list) => {
-                        let font_desc = {
-                            let font_entry = font_list.find_font_in_family(family, style);
-                            match font_entry {
-                                Some(v) => {
-                                    let font_id =
-                                        SelectorPlatformIdentifier(v.handle.face_identifier());
-                                    Some(FontDescriptor::new((*style).clone(), font_id))
-                                },
-                                None => {
-                                    None
-                                }
-                            }
-                        };
-                        font_desc
-                    },
-                    None => {
-                        None
-                    }
-                };
-
-                match font_desc {
-                    Some(ref fd) => {
-                        let instance = self.get_font_by_descriptor(fd);
+                let selectors = self.font_cache_task.find_fonts_in_family(family.clone(), style);
+                for selector in selectors.move_iter() {
+                    let font_desc = FontDescriptor::new((*style).clone(), selector);
+                    let instance = self.get_font_by_descriptor(&font_desc);
 
-                        for font in instance.iter() {
-                            fonts.push(font.clone());
-                        }
-                    },
-                    None => { }
-                };
+                    for font in instance.iter() {
+                        fonts.push(font.clone());
+                    }
+                }
             }
         }
         assert!(fonts.len() > 0);
@@ -197,12 +274,43 @@ impl<'self> FontContext {
         unsafe { RcMut::new_unchecked(FontGroup::new(style.families.clone(), &used_style, fonts)) }
     }
 
-    fn create_font_instance(&self, desc: &FontDescriptor) -> Result<RcMut<Font>, ()> {
+    /// Returns the selectors of the faces of the family to use for the given style, from the web
+    /// fonts of the document first and then from the system fonts.
+    fn find_fonts_in_family(&mut self, family_name: &~str, style: &SpecifiedFontStyle)
+                            -> ~[FontSelector] {
+        let selectors = match self.font_list {
+            Some(ref mut fl) => fl.find_fonts_in_family(family_name, style),
+            None => ~[],
+        };
+        if !selectors.is_empty() {
+            return selectors
+        }
+        self.font_cache_task.find_fonts_in_family(family_name.clone(), style)
+    }
+
+    /// Returns the data of the file of the given face, asking the font cache task for it the first
+    /// time only.
+    fn get_font_data(&mut self, identifier: &~str) -> Option<Arc<~[u8]>> {
+        match self.font_data.find(identifier) {
+            Some(data) => return data.clone(),
+            None => (),
+        }
+        let data = self.font_cache_task.get_font_data(identifier.clone());
+        self.font_data.insert(identifier.clone(), data.clone());
+        data
+    }
+
+    fn create_font_instance(&mut self, desc: &FontDescriptor) -> Result<RcMut<Font>, ()> {
         return match &desc.selector {
             // TODO(Issue #174): implement by-platform-name font selectors.
             &SelectorPlatformIdentifier(ref identifier) => {
-                let result_handle = self.handle.create_font_from_identifier((*identifier).clone(),
-                                                                            desc.style.clone());
+                // Faces made from files share the data the font cache task loaded.
+                let result_handle = match self.get_font_data(identifier) {
+                    Some(data) => self.handle.create_font_from_data(identifier.clone(), data,
+                                                                   desc.style.clone()),
+                    None => self.handle.create_font_from_identifier(identifier.clone(),
+                                                                    desc.style.clone()),
+                };
                 do result_handle.and_then |handle| {
                     Ok(RcMut::new(Font::new_from_adopted_handle(self,
                                                      handle,
@@ -211,6 +319,87 @@ impl<'self> FontContext {
                                                      self.profiler_chan.clone())))
                 }
             }
+            &SelectorWebFont(ref web_font) => {
+                Font::new_from_web_font(self, web_font, &desc.style, self.backend,
+                                        self.profiler_chan.clone())
+            }
         };
     }
 }
+
+/// Splits the given text into runs, as `(font index, text)` pairs, wherever the index of the font
+/// that renders its characters changes. Whitespace stays in the run it follows.
+fn split_text_by_font(text: &str, font_index_for_char: &fn(char) -> uint) -> ~[(uint, ~str)] {
+    let mut segments: ~[(uint, ~str)] = ~[];
+    let mut segment_index = None;
+    let mut segment_text = ~"";
+    for ch in text.iter() {
+        if segment_index.is_some() && ch.is_whitespace() {
+            segment_text.push_char(ch);
+            continue
+        }
+        let index = font_index_for_char(ch);
+        if segment_index != Some(index) {
+            for &last_index in segment_index.iter() {
+                segments.push((last_index, util::replace(&mut segment_text, ~"")));
+            }
+            segment_index = Some(index);
+        }
+        segment_text.push_char(ch);
+    }
+    for &last_index in segment_index.iter() {
+        segments.push((last_index, util::replace(&mut segment_text, ~"")));
+    }
+    segments
+}
+
+#[cfg(test)]
+fn test_font_index_for_char(ch: char) -> uint {
+    match ch {
+        '\u0370'..'\u03ff' => 1,
+        '\u4e00'..'\u9fff' => 2,
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_split_mixed_script_text() {
+    let segments = split_text_by_font("abc \u03b1\u03b2  \u4e2d\u6587 d", test_font_index_for_char);
+    assert_eq!(segments, ~[(0, ~"abc "),
+                           (1, ~"\u03b1\u03b2  "),
+                           (2, ~"\u4e2d\u6587 "),
+                           (0, ~"d")]);
+
+    // Leading whitespace starts the first run.
+    let segments = split_text_by_font(" \u03b1", test_font_index_for_char);
+    assert_eq!(segments, ~[(0, ~" "), (1, ~"\u03b1")]);
+    assert_eq!(split_text_by_font("", test_font_index_for_char), ~[]);
+}
+
+#[test]
+fn test_slice_char_map_for_mixed_script_runs() {
+    use text::util::{CharIndexMap, CompressWhitespaceNewline, transform_text};
+
+    // The layout text scanner slices the map of the transformed text at each run boundary.
+    let mut char_map = CharIndexMap::new();
+    let (text, _) = transform_text("ab  \u03b1\n\u03b2 \u4e2d", CompressWhitespaceNewline, false,
+                                   &mut char_map);
+    assert_eq!(text, ~"ab \u03b1 \u03b2 \u4e2d");
+
+    let segments = split_text_by_font(text.as_slice(), test_font_index_for_char);
+    let lengths = segments.map(|&(_, ref segment_text)| segment_text.char_len());
+    assert_eq!(lengths, ~[3, 4, 1]);
+
+    let mut run_start = 0;
+    let mut run_maps = ~[];
+    for &length in lengths.iter() {
+        run_maps.push(char_map.slice(run_start, run_start + length));
+        run_start += length;
+    }
+    assert_eq!(run_maps.map(|map| map.len()), ~[3, 4, 1]);
+    assert_eq!(run_maps[0].original_index(2), 2);
+    assert_eq!(run_maps[1].original_index(0), 4);
+    assert_eq!(run_maps[1].original_index(2), 6);
+    assert_eq!(run_maps[2].original_index(0), 8);
+    assert_eq!(run_maps[2].original_index(1), 9);
+}
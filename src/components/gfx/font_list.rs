@@ -2,7 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use font::SpecifiedFontStyle;
+use font::{SpecifiedFontStyle, FontStyle, FontSelector, SelectorPlatformIdentifier};
+use font::{SelectorWebFont, WebFontData};
 use gfx_font::FontHandleMethods;
 use platform::font::FontHandle;
 use platform::font_context::FontContextHandle;
@@ -10,7 +11,7 @@ use platform::font_list::FontListHandle;
 use servo_util::time;
 use servo_util::time::profile;
 use servo_util::time::ProfilerChan;
-use style::computed_values::{font_weight, font_style};
+use style::computed_values::{font_weight, font_style, font_stretch};
 
 use std::hashmap::HashMap;
 
@@ -20,29 +21,42 @@ trait FontListHandleMethods {
     fn get_available_families(&self, fctx: &FontContextHandle) -> FontFamilyMap;
     fn load_variations_for_family(&self, family: &mut FontFamily);
     fn get_last_resort_font_families() -> ~[~str];
+    fn fallback_family_for_char(&self, codepoint: char) -> Option<~str>;
+    fn file_for_identifier(identifier: &str) -> Option<~str>;
 }
 
+/// The number of code points, aligned on a multiple of it, that share a cached fallback family.
+static FALLBACK_BLOCK_SIZE: u32 = 128;
+
 /// The platform-independent font list abstraction.
 pub struct FontList {
     family_map: FontFamilyMap,
     handle: FontListHandle,
     prof_chan: ProfilerChan,
+    /// The fallback family last found for each block of code points, or `None` if none was.
+    fallback_families: HashMap<u32, Option<~str>>,
 }
 
 impl<'self> FontList {
     pub fn new(fctx: &FontContextHandle,
            prof_chan: ProfilerChan)
            -> FontList {
-        let handle = FontListHandle::new(fctx);
-        let mut list = FontList {
-            handle: handle,
-            family_map: HashMap::new(),
-            prof_chan: prof_chan.clone(),
-        };
+        let mut list = FontList::new_empty(fctx, prof_chan);
         list.refresh(fctx);
         list
     }
 
+    /// Creates a list without the system fonts, for the web fonts of a document. The font cache
+    /// task holds the system fonts for all documents.
+    pub fn new_empty(fctx: &FontContextHandle, prof_chan: ProfilerChan) -> FontList {
+        FontList {
+            handle: FontListHandle::new(fctx),
+            family_map: HashMap::new(),
+            prof_chan: prof_chan,
+            fallback_families: HashMap::new(),
+        }
+    }
+
     fn refresh(&mut self, _: &FontContextHandle) {
         // TODO(Issue #186): don't refresh unless something actually
         // changed.  Does OSX have a notification for this event?
@@ -76,6 +90,106 @@ impl<'self> FontList {
         }
     }
 
+    /// Returns the selectors of the faces of the family to use for the given style. That is the
+    /// face that matches the style best, and, when it is a web font, every other web font of the
+    /// family that matches it too, since those usually split the family by `unicode-range`.
+    pub fn find_fonts_in_family(&mut self, family_name: &~str, style: &SpecifiedFontStyle)
+                                -> ~[FontSelector] {
+        let (first, descriptors) = match self.find_font_in_family(family_name, style) {
+            Some(entry) if entry.web_font.is_some() => (entry.selector(), entry.descriptors()),
+            Some(entry) => return ~[entry.selector()],
+            None => return ~[],
+        };
+        let mut selectors = ~[first];
+        let family = self.family_map.get(family_name);
+        for entry in family.entries.iter() {
+            if entry.web_font.is_some() && entry.descriptors() == descriptors {
+                let selector = entry.selector();
+                if !selectors.contains(&selector) {
+                    selectors.push(selector)
+                }
+            }
+        }
+        selectors
+    }
+
+    /// Returns a system face with a glyph for the given character, in the given style if the family
+    /// has one. The family is found by the platform's coverage query and cached for the whole
+    /// block of code points around the character, so that text in one script keeps using the
+    /// same fallback family. A block for which no family was found is cached too, so that text
+    /// no font can render does not query the platform for every character.
+    pub fn find_fallback_font(&mut self, codepoint: char, style: &SpecifiedFontStyle)
+                              -> Option<FontSelector> {
+        let block = codepoint as u32 / FALLBACK_BLOCK_SIZE;
+        let cached_family = match self.fallback_families.find(&block) {
+            Some(&None) => return None,
+            Some(&Some(ref family_name)) => Some(family_name.clone()),
+            None => None,
+        };
+        for family_name in cached_family.iter() {
+            match self.find_font_covering(family_name, codepoint, style) {
+                Some(selector) => return Some(selector),
+                None => (),
+            }
+        }
+
+        let result = match self.handle.fallback_family_for_char(codepoint) {
+            Some(family_name) => {
+                let result = self.find_font_covering(&family_name, codepoint, style);
+                if result.is_some() {
+                    debug!("FontList: falling back to `{:s}` for block {}", family_name, block);
+                    self.fallback_families.insert(block, Some(family_name));
+                }
+                result
+            }
+            None => None,
+        };
+        if result.is_none() && cached_family.is_none() {
+            debug!("FontList: no fallback family for block {}", block);
+            self.fallback_families.insert(block, None);
+        }
+        result
+    }
+
+    fn find_font_covering(&mut self, family_name: &~str, codepoint: char,
+                          style: &SpecifiedFontStyle) -> Option<FontSelector> {
+        match self.find_font_in_family(family_name, style) {
+            Some(entry) if entry.handle.glyph_index(codepoint).is_some() => {
+                return Some(entry.selector())
+            }
+            _ => (),
+        }
+
+        // Better a face in another style than no glyph at all.
+        match self.family_map.find(family_name) {
+            Some(family) => {
+                family.entries.iter().find(|entry| entry.handle.glyph_index(codepoint).is_some())
+                                     .map(|entry| entry.selector())
+            }
+            None => None,
+        }
+    }
+
+    /// Makes the given `@font-face` font available under its family name. The first web font of a
+    /// family hides any system family with the same name.
+    pub fn add_web_font(&mut self, fctx: &FontContextHandle, font: &WebFont) -> Result<(), ()> {
+        let handle: FontHandle = match FontHandleMethods::new_from_buffer(
+                fctx, font.data.data.clone(), &font.font_style()) {
+            Ok(handle) => handle,
+            Err(()) => return Err(()),
+        };
+        let is_web_font_family = match self.family_map.find(&font.family_name) {
+            Some(family) => family.entries.iter().any(|entry| entry.web_font.is_some()),
+            None => false,
+        };
+        if !is_web_font_family {
+            self.family_map.insert(font.family_name.clone(), FontFamily::new(font.family_name));
+        }
+        let family = self.family_map.get_mut(&font.family_name);
+        family.entries.push(FontEntry::new_web_font(handle, font));
+        Ok(())
+    }
+
     pub fn get_last_resort_font_families() -> ~[~str] {
         let last_resort = FontListHandle::get_last_resort_font_families();
         last_resort
@@ -104,25 +218,106 @@ impl<'self> FontFamily {
         assert!(self.entries.len() > 0)
     }
 
+    /// Returns the face of the family that best matches the given style, following the font
+    /// matching algorithm of CSS Fonts 3 (section 5.2). Any face is better than none, so this only
+    /// fails for an empty family. The font synthesizes what the face lacks.
     pub fn find_font_for_style(&'self mut self, list: &FontListHandle, style: &SpecifiedFontStyle)
                             -> Option<&'self FontEntry> {
         self.load_family_variations(list);
+        let descriptors = self.entries.map(|entry| entry.descriptors());
+        match best_descriptors(style.weight, style.style, style.stretch, descriptors) {
+            Some(index) => Some(&self.entries[index]),
+            None => None,
+        }
+    }
+}
 
-        // TODO(Issue #189): optimize lookup for
-        // regular/bold/italic/bolditalic with fixed offsets and a
-        // static decision table for fallback between these values.
+/// Returns the index of the weight, style and stretch triple that best matches the given ones:
+/// the triples are narrowed down by stretch, then by style, then by weight. Returns `None` only
+/// if there are no triples.
+fn best_descriptors(weight: font_weight::T,
+                    style: font_style::T,
+                    stretch: font_stretch::T,
+                    available: &[(font_weight::T, font_style::T, font_stretch::T)])
+                    -> Option<uint> {
+    if available.is_empty() {
+        return None
+    }
 
-        // TODO(Issue #190): if not in the fast path above, do
-        // expensive matching of weights, etc.
-        for entry in self.entries.iter() {
-            if (style.weight.is_bold() == entry.is_bold()) &&
-               ((style.style == font_style::italic) == entry.is_italic()) {
+    let widths = available.map(|&(_, _, stretch)| stretch_width(stretch));
+    let styles = available.map(|&(_, style, _)| style);
+    let weights = available.map(|&(weight, _, _)| weight_value(weight));
+    let mut candidates: ~[uint] = range(0, available.len()).collect();
 
-                return Some(entry);
-            }
-        }
+    // Narrower widths are preferred for condensed and normal stretches, and wider widths for
+    // expanded ones.
+    let desired_width = stretch_width(stretch);
+    let width = nearest_value(desired_width, candidates.map(|&i| widths[i]), desired_width <= 5);
+    candidates = candidates.move_iter().filter(|&i| widths[i] == width).collect();
+
+    // Italic and oblique faces stand in for each other before normal faces do.
+    let style_order = match style {
+        font_style::italic => [font_style::italic, font_style::oblique, font_style::normal],
+        font_style::oblique => [font_style::oblique, font_style::italic, font_style::normal],
+        font_style::normal => [font_style::normal, font_style::oblique, font_style::italic],
+    };
+    let face_style = *style_order.iter().find(|&face_style| {
+        candidates.iter().any(|&i| styles[i] == *face_style)
+    }).unwrap();
+    candidates = candidates.move_iter().filter(|&i| styles[i] == face_style).collect();
+
+    // Lighter weights are preferred below 500, and 400 tries 500 first; heavier weights are
+    // preferred from 500 up.
+    let desired_weight = weight_value(weight);
+    let candidate_weights = candidates.map(|&i| weights[i]);
+    let weight = if desired_weight == 400 && !candidate_weights.contains(&400) &&
+            candidate_weights.contains(&500) {
+        500
+    } else {
+        nearest_value(desired_weight, candidate_weights, desired_weight <= 500)
+    };
+    candidates.move_iter().find(|&i| weights[i] == weight)
+}
+
+/// Returns the available value nearest to the desired one, looking below it first if
+/// `prefer_lower` is set and above it first otherwise. There must be some value available.
+fn nearest_value(desired: uint, available: &[uint], prefer_lower: bool) -> uint {
+    if available.contains(&desired) {
+        return desired
+    }
+    let below = available.iter().map(|&value| value).filter(|&value| value < desired).max();
+    let above = available.iter().map(|&value| value).filter(|&value| value > desired).min();
+    if prefer_lower { below.or(above).unwrap() } else { above.or(below).unwrap() }
+}
+
+/// The numeric value of a weight, from 100 to 900.
+pub fn weight_value(weight: font_weight::T) -> uint {
+    match weight {
+        font_weight::Weight100 => 100,
+        font_weight::Weight200 => 200,
+        font_weight::Weight300 => 300,
+        font_weight::Weight400 => 400,
+        font_weight::Weight500 => 500,
+        font_weight::Weight600 => 600,
+        font_weight::Weight700 => 700,
+        font_weight::Weight800 => 800,
+        font_weight::Weight900 => 900,
+    }
+}
 
-        None
+/// The width class of a stretch, from 1 for `ultra-condensed` to 9 for `ultra-expanded`, as in
+/// the OpenType `OS/2` table.
+pub fn stretch_width(stretch: font_stretch::T) -> uint {
+    match stretch {
+        font_stretch::ultra_condensed => 1,
+        font_stretch::extra_condensed => 2,
+        font_stretch::condensed => 3,
+        font_stretch::semi_condensed => 4,
+        font_stretch::normal => 5,
+        font_stretch::semi_expanded => 6,
+        font_stretch::expanded => 7,
+        font_stretch::extra_expanded => 8,
+        font_stretch::ultra_expanded => 9,
     }
 }
 
@@ -134,18 +329,53 @@ impl<'self> FontFamily {
 pub struct FontEntry {
     face_name: ~str,
     priv weight: font_weight::T,
-    priv italic: bool,
+    priv style: font_style::T,
+    priv stretch: font_stretch::T,
     handle: FontHandle,
+    /// The data of the face, if it comes from an `@font-face` rule rather than from the system.
+    web_font: Option<WebFontData>,
     // TODO: array of OpenType features, etc.
 }
 
 impl FontEntry {
     pub fn new(handle: FontHandle) -> FontEntry {
+        // Platforms only flag slanted faces; the name tells oblique ones from true italics.
+        let face_name = handle.face_name();
+        let style = if !handle.is_italic() {
+            font_style::normal
+        } else if face_name.contains("Oblique") {
+            font_style::oblique
+        } else {
+            font_style::italic
+        };
         FontEntry {
-            face_name: handle.face_name(),
+            face_name: face_name,
             weight: handle.boldness(),
-            italic: handle.is_italic(),
-            handle: handle
+            style: style,
+            stretch: handle.stretchiness(),
+            handle: handle,
+            web_font: None,
+        }
+    }
+
+    /// Creates the entry of an `@font-face` font. Its weight, style and stretch are the
+    /// descriptors of the rule, not the ones the font data declares.
+    pub fn new_web_font(handle: FontHandle, font: &WebFont) -> FontEntry {
+        FontEntry {
+            face_name: handle.face_name(),
+            weight: font.weight,
+            style: font.style,
+            stretch: font.stretch,
+            handle: handle,
+            web_font: Some(font.data.clone()),
+        }
+    }
+
+    /// Returns the selector that recreates this face, in this task or in another one.
+    pub fn selector(&self) -> FontSelector {
+        match self.web_font {
+            Some(ref data) => SelectorWebFont(data.clone()),
+            None => SelectorPlatformIdentifier(self.handle.face_identifier()),
         }
     }
 
@@ -154,7 +384,147 @@ impl FontEntry {
     }
 
     pub fn is_italic(&self) -> bool {
-        self.italic
+        self.style != font_style::normal
+    }
+
+    /// The weight, style and stretch of the face.
+    pub fn descriptors(&self) -> (font_weight::T, font_style::T, font_stretch::T) {
+        (self.weight, self.style, self.stretch)
+    }
+}
+
+
+/// A font loaded for an `@font-face` rule, with the descriptors of the rule. Layout sends these
+/// from the tasks that load them to its font context.
+#[deriving(Clone)]
+pub struct WebFont {
+    family_name: ~str,
+    weight: font_weight::T,
+    style: font_style::T,
+    stretch: font_stretch::T,
+    data: WebFontData,
+}
+
+impl WebFont {
+    fn font_style(&self) -> SpecifiedFontStyle {
+        FontStyle {
+            pt_size: 16.0,
+            weight: self.weight,
+            style: self.style,
+            stretch: self.stretch,
+            families: ~[self.family_name.clone()],
+        }
     }
+
+    /// Returns whether the platform can make a font from the data, which may not be a font at
+    /// all. The tasks that load `@font-face` sources check it to fall back to the next source.
+    pub fn is_usable(&self) -> bool {
+        let handle = FontContextHandle::new();
+        let font: Result<FontHandle, ()> =
+            FontHandleMethods::new_from_buffer(&handle, self.data.data.clone(), &self.font_style());
+        font.is_ok()
+    }
+}
+
+#[test]
+fn test_nearest_value() {
+    assert_eq!(nearest_value(5, [3, 5, 7], true), 5);
+    assert_eq!(nearest_value(5, [3, 4, 6, 7], true), 4);
+    assert_eq!(nearest_value(5, [3, 4, 6, 7], false), 6);
+    assert_eq!(nearest_value(5, [6, 7], true), 6);
+    assert_eq!(nearest_value(5, [3, 4], false), 4);
+}
+
+#[cfg(test)]
+fn test_best_weight(weight: font_weight::T, available: &[font_weight::T]) -> font_weight::T {
+    let descriptors = available.map(|&weight| (weight, font_style::normal, font_stretch::normal));
+    let index = best_descriptors(weight, font_style::normal, font_stretch::normal, descriptors);
+    available[index.unwrap()]
+}
+
+#[test]
+fn test_best_descriptors_weight() {
+    use style::computed_values::font_weight::{Weight100, Weight300, Weight400, Weight500};
+    use style::computed_values::font_weight::{Weight600, Weight700, Weight800};
+
+    // 400 tries 500 first, then lighter weights.
+    assert_eq!(test_best_weight(Weight400, [Weight300, Weight500, Weight700]), Weight500);
+    assert_eq!(test_best_weight(Weight400, [Weight300, Weight700]), Weight300);
+    assert_eq!(test_best_weight(Weight400, [Weight600, Weight700]), Weight600);
+
+    // Below 400, lighter weights come first.
+    assert_eq!(test_best_weight(Weight300, [Weight100, Weight400]), Weight100);
+    assert_eq!(test_best_weight(Weight300, [Weight400, Weight500]), Weight400);
+
+    // 500 tries lighter weights first too.
+    assert_eq!(test_best_weight(Weight500, [Weight400, Weight600]), Weight400);
+
+    // Above 500, heavier weights come first.
+    assert_eq!(test_best_weight(Weight600, [Weight500, Weight800]), Weight800);
+    assert_eq!(test_best_weight(Weight600, [Weight100, Weight500]), Weight500);
+    assert_eq!(test_best_weight(Weight700, [Weight700, Weight800]), Weight700);
 }
 
+#[cfg(test)]
+fn test_best_style(style: font_style::T, available: &[font_style::T]) -> font_style::T {
+    let descriptors = available.map(|&style| {
+        (font_weight::Weight400, style, font_stretch::normal)
+    });
+    let index = best_descriptors(font_weight::Weight400, style, font_stretch::normal,
+                                 descriptors);
+    available[index.unwrap()]
+}
+
+#[test]
+fn test_best_descriptors_style() {
+    use style::computed_values::font_style::{italic, normal, oblique};
+
+    assert_eq!(test_best_style(italic, [normal, oblique, italic]), italic);
+    assert_eq!(test_best_style(italic, [normal, oblique]), oblique);
+    assert_eq!(test_best_style(italic, [normal]), normal);
+    assert_eq!(test_best_style(oblique, [normal, italic]), italic);
+    assert_eq!(test_best_style(oblique, [normal]), normal);
+    assert_eq!(test_best_style(normal, [italic, oblique]), oblique);
+    assert_eq!(test_best_style(normal, [italic]), italic);
+}
+
+#[cfg(test)]
+fn test_best_stretch(stretch: font_stretch::T, available: &[font_stretch::T])
+                     -> font_stretch::T {
+    let descriptors = available.map(|&stretch| {
+        (font_weight::Weight400, font_style::normal, stretch)
+    });
+    let index = best_descriptors(font_weight::Weight400, font_style::normal, stretch,
+                                 descriptors);
+    available[index.unwrap()]
+}
+
+#[test]
+fn test_best_descriptors_stretch() {
+    use style::computed_values::font_stretch::{condensed, expanded, extra_condensed};
+    use style::computed_values::font_stretch::{extra_expanded, normal, semi_condensed};
+    use style::computed_values::font_stretch::{semi_expanded};
+
+    // Narrower widths come first up to `normal`, and wider ones after it.
+    assert_eq!(test_best_stretch(condensed, [extra_condensed, semi_condensed]), extra_condensed);
+    assert_eq!(test_best_stretch(normal, [semi_condensed, semi_expanded]), semi_condensed);
+    assert_eq!(test_best_stretch(normal, [semi_expanded, expanded]), semi_expanded);
+    assert_eq!(test_best_stretch(expanded, [semi_expanded, extra_expanded]), extra_expanded);
+    assert_eq!(test_best_stretch(expanded, [condensed, semi_expanded]), semi_expanded);
+}
+
+#[test]
+fn test_best_descriptors_order() {
+    use style::computed_values::font_weight::{Weight400, Weight700};
+
+    // Stretch is matched before style, and style before weight.
+    let available = [(Weight400, font_style::italic, font_stretch::normal),
+                     (Weight400, font_style::normal, font_stretch::condensed)];
+    assert_eq!(best_descriptors(Weight400, font_style::italic, font_stretch::condensed,
+                                available), Some(1));
+    let available = [(Weight400, font_style::italic, font_stretch::normal),
+                     (Weight700, font_style::normal, font_stretch::normal)];
+    assert_eq!(best_descriptors(Weight700, font_style::italic, font_stretch::normal,
+                                available), Some(0));
+    assert_eq!(best_descriptors(Weight400, font_style::normal, font_stretch::normal, []), None);
+}
@@ -40,6 +40,10 @@ pub struct Opts {
     output_file: Option<~str>,
     headless: bool,
     hard_fail: bool,
+
+    /// True to print the CSS errors of each page, such as dropped declarations, as the script
+    /// tasks report them (`--css-errors`).
+    css_errors: bool,
 }
 
 fn print_usage(app: &str, opts: &[groups::OptGroup]) {
@@ -61,6 +65,7 @@ pub fn from_cmdline_args(args: &[~str]) -> Opts {
         groups::optflag("x", "exit", "Exit after load flag"),
         groups::optflag("z", "headless", "Headless mode"),
         groups::optflag("f", "hard-fail", "Exit on task failure instead of displaying about:failure"),
+        groups::optflag("", "css-errors", "Print the CSS errors of each page"),
         groups::optflag("h", "help", "Print this message")
     ];
 
@@ -130,5 +135,6 @@ pub fn from_cmdline_args(args: &[~str]) -> Opts {
         output_file: opt_match.opt_str("o"),
         headless: opt_match.opt_present("z"),
         hard_fail: opt_match.opt_present("f"),
+        css_errors: opt_match.opt_present("css-errors"),
     }
 }
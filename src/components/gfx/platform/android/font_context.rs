@@ -10,6 +10,7 @@ use platform::font_list::path_from_identifier;
 use freetype::freetype::{FTErrorMethods, FT_Library};
 use freetype::freetype::{FT_Done_FreeType, FT_Init_FreeType};
 
+use extra::arc::Arc;
 use std::ptr;
 use std::rc::Rc;
 
@@ -49,10 +50,19 @@ impl FontContextHandleMethods for FontContextHandle {
     fn create_font_from_identifier(&self, name: ~str, style: UsedFontStyle)
                                 -> Result<FontHandle, ()> {
         debug!("Creating font handle for {:s}", name);
+        // Faces found in the font list are identified by their file, and others by their family.
+        if name.starts_with("/") {
+            return FontHandle::new_from_file(self, name, &style)
+        }
         do path_from_identifier(name, &style).and_then |file_name| {
             debug!("Opening font face {:s}", file_name);
             FontHandle::new_from_file(self, file_name.to_owned(), &style)
         }
     }
+
+    fn create_font_from_data(&self, identifier: ~str, data: Arc<~[u8]>, style: UsedFontStyle)
+                             -> Result<FontHandle, ()> {
+        FontHandle::new_from_file_data(self, identifier, data, &style)
+    }
 }
 
@@ -11,7 +11,7 @@ use servo_util::geometry;
 use platform::font_context::FontContextHandle;
 use text::glyph::GlyphIndex;
 use text::util::{float_to_fixed, fixed_to_float};
-use style::computed_values::font_weight;
+use style::computed_values::{font_stretch, font_weight};
 
 use freetype::freetype::{FT_Get_Char_Index, FT_Get_Postscript_Name};
 use freetype::freetype::{FT_Load_Glyph, FT_Set_Char_Size};
@@ -24,6 +24,7 @@ use freetype::freetype::{FT_SizeRec, FT_UInt, FT_Size_Metrics};
 use freetype::freetype::{ft_sfnt_os2};
 use freetype::tt_os2::TT_OS2;
 
+use extra::arc::Arc;
 use std::cast;
 use std::ptr;
 use std::str;
@@ -47,8 +48,10 @@ impl FontTableMethods for FontTable {
 }
 
 enum FontSource {
-    FontSourceMem(~[u8]),
-    FontSourceFile(~str)
+    FontSourceMem(Arc<~[u8]>),
+    FontSourceFile(~str),
+    /// A file whose data the font cache task loaded, shared by all the fonts made from it.
+    FontSourceFileData(~str, Arc<~[u8]>),
 }
 
 pub struct FontHandle {
@@ -74,57 +77,28 @@ impl Drop for FontHandle {
 
 impl FontHandleMethods for FontHandle {
     fn new_from_buffer(fctx: &FontContextHandle,
-                           buf: ~[u8],
+                           buf: Arc<~[u8]>,
                            style: &SpecifiedFontStyle)
                         -> Result<FontHandle, ()> {
-        let ft_ctx: FT_Library = fctx.ctx.borrow().ctx;
-        if ft_ctx.is_null() { return Err(()); }
-
-        let face_result = do buf.as_imm_buf |bytes: *u8, len: uint| {
-            create_face_from_buffer(ft_ctx, bytes, len, style.pt_size)
-        };
-
-        // TODO: this could be more simply written as result::chain
-        // and moving buf into the struct ctor, but cant' move out of
-        // captured binding.
-        return match face_result {
-            Ok(face) => {
-              let handle = FontHandle {
-                  face: face,
-                  source: FontSourceMem(buf),
-                  handle: fctx.clone()
-              };
-              Ok(handle)
-            }
-            Err(()) => Err(())
-        };
-
-        #[fixed_stack_segment]
-         fn create_face_from_buffer(lib: FT_Library, cbuf: *u8, cbuflen: uint, pt_size: f64)
-                                    -> Result<FT_Face, ()> {
-             unsafe {
-                 let mut face: FT_Face = ptr::null();
-                 let face_index = 0 as FT_Long;
-                 let result = FT_New_Memory_Face(lib, cbuf, cbuflen as FT_Long,
-                                                 face_index, ptr::to_mut_unsafe_ptr(&mut face));
-                 
-                 if !result.succeeded() || face.is_null() {
-                     return Err(());
-                 }
-                 if FontHandle::set_char_size(face, pt_size).is_ok() {
-                     Ok(face)
-                 } else {
-                     Err(())
-                 }
-             }
-         }
+        do FontHandle::create_face_from_buffer(fctx, &buf, style.pt_size).and_then |face| {
+            Ok(FontHandle {
+                face: face,
+                source: FontSourceMem(buf.clone()),
+                handle: fctx.clone()
+            })
+        }
     }
 
     // an identifier usable by FontContextHandle to recreate this FontHandle.
     fn face_identifier(&self) -> ~str {
-        /* FT_Get_Postscript_Name seems like a better choice here, but it
-           doesn't give usable results for fontconfig when deserializing. */
-        unsafe { str::raw::from_c_str((*self.face).family_name) }
+        match self.source {
+            // The file identifies the face exactly, whatever fontconfig would match for its
+            // family name.
+            FontSourceFile(ref file) | FontSourceFileData(ref file, _) => file.clone(),
+            /* FT_Get_Postscript_Name seems like a better choice here, but it
+               doesn't give usable results for fontconfig when deserializing. */
+            FontSourceMem(_) => unsafe { str::raw::from_c_str((*self.face).family_name) },
+        }
     }
     fn family_name(&self) -> ~str {
         unsafe { str::raw::from_c_str((*self.face).family_name) }
@@ -138,30 +112,53 @@ impl FontHandleMethods for FontHandle {
     }
     #[fixed_stack_segment]
     fn boldness(&self) -> font_weight::T {
-        let default_weight = font_weight::Weight400;
-        if unsafe { (*self.face).style_flags & FT_STYLE_FLAG_BOLD == 0 } {
-            default_weight
+        // Without an OS/2 table, the style flags only tell bold from regular.
+        let default_weight = if unsafe { (*self.face).style_flags & FT_STYLE_FLAG_BOLD == 0 } {
+            font_weight::Weight400
         } else {
-            unsafe {
-                let os2 = FT_Get_Sfnt_Table(self.face, ft_sfnt_os2) as *TT_OS2;
-                let valid = os2.is_not_null() && (*os2).version != 0xffff;
-                if valid {
-                    let weight =(*os2).usWeightClass;
-                    match weight {
-                        1 | 100..199 => font_weight::Weight100,
-                        2 | 200..299 => font_weight::Weight200,
-                        3 | 300..399 => font_weight::Weight300,
-                        4 | 400..499 => font_weight::Weight400,
-                        5 | 500..599 => font_weight::Weight500,
-                        6 | 600..699 => font_weight::Weight600,
-                        7 | 700..799 => font_weight::Weight700,
-                        8 | 800..899 => font_weight::Weight800,
-                        9 | 900..999 => font_weight::Weight900,
-                        _ => default_weight
-                    }
-                } else {
-                    default_weight
+            font_weight::Weight700
+        };
+        unsafe {
+            let os2 = FT_Get_Sfnt_Table(self.face, ft_sfnt_os2) as *TT_OS2;
+            let valid = os2.is_not_null() && (*os2).version != 0xffff;
+            if valid {
+                let weight =(*os2).usWeightClass;
+                match weight {
+                    1 | 100..199 => font_weight::Weight100,
+                    2 | 200..299 => font_weight::Weight200,
+                    3 | 300..399 => font_weight::Weight300,
+                    4 | 400..499 => font_weight::Weight400,
+                    5 | 500..599 => font_weight::Weight500,
+                    6 | 600..699 => font_weight::Weight600,
+                    7 | 700..799 => font_weight::Weight700,
+                    8 | 800..899 => font_weight::Weight800,
+                    9 | 900..999 => font_weight::Weight900,
+                    _ => default_weight
                 }
+            } else {
+                default_weight
+            }
+        }
+    }
+    #[fixed_stack_segment]
+    fn stretchiness(&self) -> font_stretch::T {
+        unsafe {
+            let os2 = FT_Get_Sfnt_Table(self.face, ft_sfnt_os2) as *TT_OS2;
+            let valid = os2.is_not_null() && (*os2).version != 0xffff;
+            if valid {
+                match (*os2).usWidthClass {
+                    1 => font_stretch::ultra_condensed,
+                    2 => font_stretch::extra_condensed,
+                    3 => font_stretch::condensed,
+                    4 => font_stretch::semi_condensed,
+                    6 => font_stretch::semi_expanded,
+                    7 => font_stretch::expanded,
+                    8 => font_stretch::extra_expanded,
+                    9 => font_stretch::ultra_expanded,
+                    _ => font_stretch::normal,
+                }
+            } else {
+                font_stretch::normal
             }
         }
     }
@@ -176,6 +173,9 @@ impl FontHandleMethods for FontHandle {
             FontSourceFile(ref file) => {
                 FontHandle::new_from_file(fctx, (*file).clone(), style)
             }
+            FontSourceFileData(ref file, ref data) => {
+                FontHandle::new_from_file_data(fctx, (*file).clone(), data.clone(), style)
+            }
         }
     }
 
@@ -314,6 +314,41 @@ impl<'self> FontHandle {
         }
     }
 
+    /// Creates a handle for the given file from its data, which the handle keeps alive.
+    pub fn new_from_file_data(fctx: &FontContextHandle, file: ~str, data: Arc<~[u8]>,
+                              style: &SpecifiedFontStyle) -> Result<FontHandle, ()> {
+        do FontHandle::create_face_from_buffer(fctx, &data, style.pt_size).and_then |face| {
+            Ok(FontHandle {
+                face: face,
+                source: FontSourceFileData(file.clone(), data.clone()),
+                handle: fctx.clone()
+            })
+        }
+    }
+
+    #[fixed_stack_segment]
+    fn create_face_from_buffer(fctx: &FontContextHandle, buf: &Arc<~[u8]>, pt_size: f64)
+                               -> Result<FT_Face, ()> {
+        let ft_ctx: FT_Library = fctx.ctx.borrow().ctx;
+        if ft_ctx.is_null() { return Err(()); }
+
+        do buf.get().as_imm_buf |bytes: *u8, len: uint| {
+            unsafe {
+                let mut face: FT_Face = ptr::null();
+                let face_index = 0 as FT_Long;
+                let result = FT_New_Memory_Face(ft_ctx, bytes, len as FT_Long,
+                                                face_index, ptr::to_mut_unsafe_ptr(&mut face));
+
+                if result.succeeded() && face.is_not_null() &&
+                        FontHandle::set_char_size(face, pt_size).is_ok() {
+                    Ok(face)
+                } else {
+                    Err(())
+                }
+            }
+        }
+    }
+
     #[fixed_stack_segment]
     pub fn new_from_file_unstyled(fctx: &FontContextHandle, file: ~str)
                                -> Result<FontHandle, ()> {
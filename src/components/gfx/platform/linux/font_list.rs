@@ -14,7 +14,8 @@ use fontconfig::fontconfig::{
     FcPatternDestroy, FcFontSetDestroy, FcConfigSubstitute,
     FcDefaultSubstitute, FcPatternCreate, FcPatternAddString, FcPatternAddInteger,
     FcFontMatch, FcFontSetList, FcObjectSetCreate, FcObjectSetDestroy,
-    FcObjectSetAdd, FcPatternGetInteger
+    FcObjectSetAdd, FcPatternGetInteger, FcChar32, FcCharSetCreate, FcCharSetAddChar,
+    FcCharSetDestroy, FcPatternAddCharSet
 };
 
 use style::computed_values::font_style;
@@ -134,6 +135,58 @@ impl FontListHandle {
     pub fn get_last_resort_font_families() -> ~[~str] {
         ~[~"Arial"]
     }
+
+    /// Returns the file of a face identified by `FontHandle::face_identifier`. Faces of the font
+    /// list are identified by their file, and the others by their family.
+    pub fn file_for_identifier(identifier: &str) -> Option<~str> {
+        if identifier.starts_with("/") { Some(identifier.to_owned()) } else { None }
+    }
+
+    /// Returns the family of the font fontconfig prefers for the given character among those that
+    /// have a glyph for it. Fontconfig answers with its best match anyway when no font does, so
+    /// callers must check that the family has the glyph.
+    #[fixed_stack_segment]
+    pub fn fallback_family_for_char(&self, codepoint: char) -> Option<~str> {
+        unsafe {
+            let config = FcConfigGetCurrent();
+            let wrapper = AutoPattern { pattern: FcPatternCreate() };
+            let pattern = wrapper.pattern;
+            let charset = FcCharSetCreate();
+            FcCharSetAddChar(charset, codepoint as FcChar32);
+            // The pattern keeps a reference of its own to the character set.
+            let res = do "charset".to_c_str().with_ref |FC_CHARSET| {
+                FcPatternAddCharSet(pattern, FC_CHARSET, charset)
+            };
+            FcCharSetDestroy(charset);
+            if res != 1 {
+                debug!("adding charset to pattern failed");
+                return None;
+            }
+
+            if FcConfigSubstitute(config, pattern, FcMatchPattern) != 1 {
+                debug!("substitution failed");
+                return None;
+            }
+            FcDefaultSubstitute(pattern);
+            let result = FcResultNoMatch;
+            let result_wrapper = AutoPattern { pattern: FcFontMatch(config, pattern, &result) };
+            let result_pattern = result_wrapper.pattern;
+            if result != FcResultMatch || result_pattern.is_null() {
+                debug!("obtaining match to pattern failed");
+                return None;
+            }
+
+            let family: *FcChar8 = ptr::null();
+            let res = do "family".to_c_str().with_ref |FC_FAMILY| {
+                FcPatternGetString(result_pattern, FC_FAMILY, 0, &family)
+            };
+            if res != FcResultMatch {
+                debug!("getting family for font failed");
+                return None;
+            }
+            Some(str::raw::from_c_str(family as *c_char))
+        }
+    }
 }
 
 struct AutoPattern {
@@ -15,7 +15,7 @@ use servo_util::geometry::{Au, px_to_pt};
 use servo_util::geometry;
 use platform::macos::font_context::FontContextHandle;
 use text::glyph::GlyphIndex;
-use style::computed_values::font_weight;
+use style::computed_values::{font_stretch, font_weight};
 
 use core_foundation::base::CFIndex;
 use core_foundation::data::CFData;
@@ -28,6 +28,7 @@ use core_text::font_descriptor::{SymbolicTraitAccessors, TraitAccessors};
 use core_text::font_descriptor::{kCTFontDefaultOrientation};
 use core_text;
 
+use extra::arc::Arc;
 use std::ptr;
 use std::vec;
 
@@ -78,9 +79,10 @@ impl FontHandle {
 }
 
 impl FontHandleMethods for FontHandle {
-    fn new_from_buffer(_: &FontContextHandle, buf: ~[u8], style: &SpecifiedFontStyle)
+    fn new_from_buffer(_: &FontContextHandle, buf: Arc<~[u8]>, style: &SpecifiedFontStyle)
                     -> Result<FontHandle, ()> {
-        let fontprov = CGDataProvider::from_buffer(buf);
+        // TODO: share the buffer with the data provider instead of copying it.
+        let fontprov = CGDataProvider::from_buffer(buf.get().clone());
         let cgfont = CGFont::from_data_provider(fontprov);
         let ctfont = core_text::font::new_from_CGFont(&cgfont, style.pt_size);
 
@@ -120,6 +122,22 @@ impl FontHandleMethods for FontHandle {
         return font_weight::Weight900;
     }
 
+    fn stretchiness(&self) -> font_stretch::T {
+        // -1.0 to 1.0
+        let normalized = self.ctfont.all_traits().normalized_width();
+        // 0.0 to 9.0
+        let normalized = (normalized + 1.0) / 2.0 * 9.0;
+        if normalized < 1.0 { return font_stretch::ultra_condensed; }
+        if normalized < 2.0 { return font_stretch::extra_condensed; }
+        if normalized < 3.0 { return font_stretch::condensed; }
+        if normalized < 4.0 { return font_stretch::semi_condensed; }
+        if normalized < 5.0 { return font_stretch::normal; }
+        if normalized < 6.0 { return font_stretch::semi_expanded; }
+        if normalized < 7.0 { return font_stretch::expanded; }
+        if normalized < 8.0 { return font_stretch::extra_expanded; }
+        return font_stretch::ultra_expanded;
+    }
+
     fn clone_with_style(&self, fctx: &FontContextHandle, style: &SpecifiedFontStyle)
                      -> Result<FontHandle,()> {
         let new_font = self.ctfont.clone_with_font_size(style.pt_size);
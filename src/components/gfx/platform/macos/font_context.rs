@@ -2,11 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use font::UsedFontStyle;
+use font::{FontHandleMethods, UsedFontStyle};
 use font_context::FontContextHandleMethods;
 use platform::macos::font::FontHandle;
 
 use core_text;
+use extra::arc::Arc;
 
 #[deriving(Clone)]
 pub struct FontContextHandle {
@@ -31,4 +32,9 @@ impl FontContextHandleMethods for FontContextHandle {
             FontHandle::new_from_CTFont(self, ctfont)
         }
     }
+
+    fn create_font_from_data(&self, _identifier: ~str, data: Arc<~[u8]>, style: UsedFontStyle)
+                             -> Result<FontHandle, ()> {
+        FontHandleMethods::new_from_buffer(self, data, &style)
+    }
 }
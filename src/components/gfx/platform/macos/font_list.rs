@@ -61,4 +61,13 @@ impl FontListHandle {
     pub fn get_last_resort_font_families() -> ~[~str] {
         ~[~"Arial Unicode MS",~"Arial"]
     }
+    // TODO: identifiers are PostScript names; find their files through `kCTFontURLAttribute`.
+    pub fn file_for_identifier(_identifier: &str) -> Option<~str> {
+        None
+    }
+
+    // TODO: ask Core Text for a font covering the character (`CTFontCreateForString`).
+    pub fn fallback_family_for_char(&self, _codepoint: char) -> Option<~str> {
+        None
+    }
 }
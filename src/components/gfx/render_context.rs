@@ -2,22 +2,30 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use display_list::{BorderRadii, GradientShape, GradientStop, LinearGradientShape, blur_extent};
+use display_list::{RadialGradientShape};
 use font_context::FontContext;
 use style::computed_values::border_style;
 use opts::Opts;
 
 use azure::azure_hl::{B8G8R8A8, Color, ColorPattern, DrawOptions};
-use azure::azure_hl::{DrawSurfaceOptions, DrawTarget, Linear, StrokeOptions};
+use azure::azure_hl::{DrawSurfaceOptions, DrawTarget, Linear, PathBuilder, StrokeOptions};
+use azure::azure_hl::{ExtendClamp, ExtendRepeat, LinearGradientPattern, RadialGradientPattern};
+use AzureGradientStop = azure::azure_hl::GradientStop;
 use azure::AZ_CAP_BUTT;
 use azure::AzFloat;
 use extra::arc::Arc;
+use geom::matrix2d::Matrix2D;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use geom::side_offsets::SideOffsets2D;
 use servo_net::image::base::Image;
 use png::{RGBA8, K8, KA8};
+use servo_util::cache::{Cache, HashLRUCache};
 use servo_util::geometry::Au;
+use servo_util::geometry;
+use std::hash::Hash;
 use std::vec;
 use std::libc::types::common::c99::uint16_t;
 use std::libc::size_t;
@@ -25,6 +33,7 @@ use std::libc::size_t;
 pub struct RenderContext<'self> {
     draw_target: &'self DrawTarget,
     font_ctx: &'self mut ~FontContext,
+    blur_cache: &'self mut BlurCache,
     opts: &'self Opts,
     /// The rectangle that this context encompasses in page coordinates.
     page_rect: Rect<f32>,
@@ -44,29 +53,79 @@ impl<'self> RenderContext<'self>  {
         self.draw_target
     }
 
+    /// Returns the part of the page that this context draws into, in the current coordinate
+    /// space, or `None` if the current transform flattens everything.
+    pub fn visible_rect(&self) -> Option<Rect<Au>> {
+        let device_rect = Rect(Point2D(Au(0), Au(0)),
+                               Size2D(Au::from_px(self.screen_rect.size.width as int),
+                                      Au::from_px(self.screen_rect.size.height as int)));
+        geometry::inverse_transform_rect(&self.draw_target.get_transform(), &device_rect)
+    }
+
     pub fn draw_solid_color(&self, bounds: &Rect<Au>, color: Color) {
         self.draw_target.make_current();
         self.draw_target.fill_rect(&bounds.to_azure_rect(), &ColorPattern(color));
     }
 
+    pub fn draw_rounded_solid_color(&self, bounds: &Rect<Au>, radii: &BorderRadii<Au>,
+                                    color: Color) {
+        let rect = bounds.to_azure_rect();
+        let path_builder = self.draw_target.create_path_builder();
+        add_rounded_rect(&path_builder, &rect, &scale_radii(&rect, &radii.to_float_px()), true);
+        let path = path_builder.finish();
+
+        self.draw_target.make_current();
+        self.draw_target.fill(&path, &ColorPattern(color), &DrawOptions(1.0, 0));
+    }
+
     pub fn draw_border(&self,
                        bounds: &Rect<Au>,
                        border: SideOffsets2D<Au>,
+                       radii: &BorderRadii<Au>,
                        color: SideOffsets2D<Color>,
                        style: SideOffsets2D<border_style::T>) {
         let border = border.to_float_px();
         self.draw_target.make_current();
 
+        // Dotted and dashed sides can't follow rounded corners yet, so a border with any of them
+        // is drawn with square corners.
+        let is_dotted_or_dashed = |style: border_style::T| {
+            match style {
+                border_style::dotted | border_style::dashed => true,
+                border_style::none | border_style::hidden | border_style::solid => false,
+            }
+        };
+        let has_dotted_or_dashed_side = is_dotted_or_dashed(style.top) ||
+            is_dotted_or_dashed(style.right) || is_dotted_or_dashed(style.bottom) ||
+            is_dotted_or_dashed(style.left);
+
+        if !radii.is_square() && !has_dotted_or_dashed_side {
+            let radii = scale_radii(&bounds.to_azure_rect(), &radii.to_float_px());
+            self.draw_rounded_border_segment(Top, bounds, border, &radii, color, style);
+            self.draw_rounded_border_segment(Right, bounds, border, &radii, color, style);
+            self.draw_rounded_border_segment(Bottom, bounds, border, &radii, color, style);
+            self.draw_rounded_border_segment(Left, bounds, border, &radii, color, style);
+            return
+        }
+
         self.draw_border_segment(Top, bounds, border, color, style);
         self.draw_border_segment(Right, bounds, border, color, style);
         self.draw_border_segment(Bottom, bounds, border, color, style);
         self.draw_border_segment(Left, bounds, border, color, style);
     }
 
-    pub fn draw_push_clip(&self, bounds: &Rect<Au>) {
+    pub fn draw_push_clip(&self, bounds: &Rect<Au>, radii: &BorderRadii<Au>) {
         let rect = bounds.to_azure_rect();
         let path_builder = self.draw_target.create_path_builder();
 
+        if !radii.is_square() {
+            add_rounded_rect(&path_builder, &rect, &scale_radii(&rect, &radii.to_float_px()),
+                             true);
+            let path = path_builder.finish();
+            self.draw_target.push_clip(&path);
+            return
+        }
+
         let left_top = Point2D(rect.origin.x, rect.origin.y);
         let right_top = Point2D(rect.origin.x + rect.size.width, rect.origin.y);
         let left_bottom = Point2D(rect.origin.x, rect.origin.y + rect.size.height);
@@ -112,6 +171,215 @@ impl<'self> RenderContext<'self>  {
                                      draw_options);
     }
 
+    pub fn draw_gradient(&self,
+                         bounds: &Rect<Au>,
+                         shape: &GradientShape,
+                         stops: &[GradientStop],
+                         repeating: bool) {
+        self.draw_target.make_current();
+        let stops: ~[AzureGradientStop] = do stops.iter().map |stop| {
+            AzureGradientStop {
+                position: stop.offset as AzFloat,
+                color: stop.color,
+            }
+        }.collect();
+        let extend_mode = if repeating { ExtendRepeat } else { ExtendClamp };
+        let stops = self.draw_target.create_gradient_stops(stops, extend_mode);
+        let rect = bounds.to_azure_rect();
+        match *shape {
+            LinearGradientShape(ref start, ref end) => {
+                let pattern = LinearGradientPattern::new(&start.to_azure_point(),
+                                                         &end.to_azure_point(),
+                                                         stops,
+                                                         &Matrix2D::identity());
+                self.draw_target.fill_rect(&rect, &pattern);
+            }
+            RadialGradientShape(ref center, start_radius, end_radius, y_scale) => {
+                let center = center.to_azure_point();
+                // Squash the circles of the pattern vertically around the center.
+                let y_scale = y_scale as AzFloat;
+                let matrix = Matrix2D::new(1.0, 0.0,
+                                           0.0, y_scale,
+                                           0.0, center.y * (1.0 - y_scale));
+                let pattern = RadialGradientPattern::new(&center,
+                                                         &center,
+                                                         geometry::to_frac_px(start_radius)
+                                                             as AzFloat,
+                                                         geometry::to_frac_px(end_radius)
+                                                             as AzFloat,
+                                                         stops,
+                                                         &matrix);
+                self.draw_target.fill_rect(&rect, &pattern);
+            }
+        }
+    }
+
+    /// Draws the shadow of a box. See `BoxShadowDisplayItem`.
+    pub fn draw_box_shadow(&mut self,
+                           bounds: &Rect<Au>,
+                           box_bounds: &Rect<Au>,
+                           radii: &BorderRadii<Au>,
+                           offset: Point2D<Au>,
+                           color: Color,
+                           blur_radius: Au,
+                           spread_radius: Au,
+                           inset: bool) {
+        let spread = if inset { Au(0) - spread_radius } else { spread_radius };
+        let shadow_rect = inflate_rect(&box_bounds.translate(&offset), spread);
+        let box_rect = box_bounds.to_azure_rect();
+        let box_radii = scale_radii(&box_rect, &radii.to_float_px());
+        let shadow_azure_rect = shadow_rect.to_azure_rect();
+        let shadow_radii = scale_radii(&shadow_azure_rect,
+                                       &inflate_radii(&radii.to_float_px(),
+                                                      geometry::to_frac_px(spread) as AzFloat));
+        // Inset shadows are cast by everything around the shadow rectangle, which must extend
+        // far enough that the blur does not fade at the edges of the box.
+        let blur_area = if inset {
+            inflate_rect(box_bounds, blur_extent(blur_radius))
+        } else {
+            *bounds
+        };
+
+        // Outer shadows are painted outside of the box, and inset shadows inside of it.
+        let clip_builder = self.draw_target.create_path_builder();
+        if inset {
+            add_rounded_rect(&clip_builder, &box_rect, &box_radii, true);
+        } else {
+            add_rounded_rect(&clip_builder, &bounds.to_azure_rect(), &square_radii(), true);
+            add_rounded_rect(&clip_builder, &box_rect, &box_radii, false);
+        }
+        let clip = clip_builder.finish();
+
+        let draw_shadow: &fn(&mut RenderContext) = |render_context| {
+            let path_builder = render_context.draw_target.create_path_builder();
+            if inset {
+                let outer_rect = inflate_rect(&blur_area, blur_extent(blur_radius));
+                add_rounded_rect(&path_builder, &outer_rect.to_azure_rect(), &square_radii(), true);
+                add_rounded_rect(&path_builder, &shadow_azure_rect, &shadow_radii, false);
+            } else {
+                add_rounded_rect(&path_builder, &shadow_azure_rect, &shadow_radii, true);
+            }
+            let path = path_builder.finish();
+            render_context.draw_target.fill(&path, &ColorPattern(color), &DrawOptions(1.0, 0));
+        };
+
+        self.draw_target.make_current();
+        self.draw_target.push_clip(&clip);
+        if blur_radius == Au(0) {
+            draw_shadow(self)
+        } else {
+            self.draw_blurred(&blur_area, blur_radius, draw_shadow)
+        }
+        self.draw_target.pop_clip();
+    }
+
+    /// Calls `draw` to draw into a temporary CPU draw target covering the given area, blurs the
+    /// result with the given CSS blur radius, and draws it into this context. Nothing outside of
+    /// the area is drawn, so it must include the extent of the blur. Only the part of the area
+    /// that can show in this context is blurred.
+    pub fn draw_blurred(&mut self,
+                        area: &Rect<Au>,
+                        blur_radius: Au,
+                        draw: &fn(&mut RenderContext)) {
+        // Pixels further than the extent of the blur from the visible part of the page do not
+        // show once blurred.
+        let visible_rect = match self.visible_rect() {
+            Some(visible_rect) => inflate_rect(&visible_rect, blur_extent(blur_radius)),
+            None => return,
+        };
+        let rect = match area.intersection(&visible_rect) {
+            Some(area) => area.to_azure_rect(),
+            None => return,
+        };
+        let size = Size2D(rect.size.width as i32, rect.size.height as i32);
+        if size.width <= 0 || size.height <= 0 {
+            return
+        }
+
+        let draw_target = DrawTarget::new(self.opts.render_backend, size, B8G8R8A8);
+        {
+            let mut render_context = RenderContext {
+                draw_target: &draw_target,
+                font_ctx: &mut *self.font_ctx,
+                blur_cache: &mut *self.blur_cache,
+                opts: self.opts,
+                page_rect: rect,
+                screen_rect: Rect(Point2D(0u, 0u), Size2D(size.width as uint, size.height as uint)),
+            };
+            let matrix: Matrix2D<AzFloat> = Matrix2D::identity();
+            let matrix = matrix.translate(-rect.origin.x, -rect.origin.y);
+            render_context.draw_target.set_transform(&matrix);
+            draw(&mut render_context);
+            render_context.draw_target.flush();
+        }
+
+        let mut pixels = ~[];
+        do draw_target.snapshot().get_data_surface().with_data |data| {
+            pixels = data.to_owned();
+        }
+        let blurred_pixels = self.blur_cache.blur(pixels,
+                                                  size.width as uint,
+                                                  size.height as uint,
+                                                  blur_radius);
+
+        self.draw_target.make_current();
+        let surface = self.draw_target.create_source_surface_from_data(blurred_pixels.get().blurred,
+                                                                       size,
+                                                                       size.width * 4,
+                                                                       B8G8R8A8);
+        let source_rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat),
+                               Size2D(size.width as AzFloat, size.height as AzFloat));
+        self.draw_target.draw_surface(surface,
+                                      rect,
+                                      source_rect,
+                                      DrawSurfaceOptions(Linear, true),
+                                      DrawOptions(1.0, 0));
+    }
+
+    /// Calls `draw` to draw into an intermediate draw target covering this tile, and composites
+    /// the result with the given opacity.
+    pub fn draw_group(&mut self, opacity: f32, draw: &fn(&mut RenderContext)) {
+        let size = Size2D(self.screen_rect.size.width as i32, self.screen_rect.size.height as i32);
+        let draw_target = self.draw_target.create_similar_draw_target(&size, B8G8R8A8);
+        {
+            let mut render_context = RenderContext {
+                draw_target: &draw_target,
+                font_ctx: &mut *self.font_ctx,
+                blur_cache: &mut *self.blur_cache,
+                opts: self.opts,
+                page_rect: self.page_rect,
+                screen_rect: self.screen_rect,
+            };
+            render_context.draw_target.set_transform(&self.draw_target.get_transform());
+            draw(&mut render_context);
+            render_context.draw_target.flush();
+        }
+
+        // The intermediate draw target covers the tile in device pixels, whatever the current
+        // transform is.
+        self.draw_target.make_current();
+        let old_transform = self.draw_target.get_transform();
+        self.draw_target.set_transform(&Matrix2D::identity());
+        let rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat),
+                        Size2D(size.width as AzFloat, size.height as AzFloat));
+        self.draw_target.draw_surface(draw_target.snapshot(),
+                                      rect,
+                                      rect,
+                                      DrawSurfaceOptions(Linear, true),
+                                      DrawOptions(opacity as AzFloat, 0));
+        self.draw_target.set_transform(&old_transform);
+    }
+
+    /// Calls `draw` with the given transform, which maps page pixels, applied before the current
+    /// one.
+    pub fn draw_transformed(&mut self, transform: &Matrix2D<AzFloat>,
+                            draw: &fn(&mut RenderContext)) {
+        let old_transform = self.draw_target.get_transform();
+        self.draw_target.set_transform(&old_transform.mul(transform));
+        draw(self);
+        self.draw_target.set_transform(&old_transform);
+    }
+
     pub fn clear(&self) {
         let pattern = ColorPattern(Color(1.0, 1.0, 1.0, 1.0));
         let rect = Rect(Point2D(self.page_rect.origin.x as AzFloat,
@@ -149,6 +417,90 @@ impl<'self> RenderContext<'self>  {
         }
     }
 
+    /// Draws one side of a border with rounded corners: the part of the ring between the outer
+    /// and the inner rounded rectangles that is on this side of the lines going inward from the
+    /// corners, along the diagonals of the corners of the border.
+    fn draw_rounded_border_segment(&self,
+                                   direction: Direction,
+                                   bounds: &Rect<Au>,
+                                   border: SideOffsets2D<AzFloat>,
+                                   radii: &BorderRadii<AzFloat>,
+                                   color: SideOffsets2D<Color>,
+                                   style: SideOffsets2D<border_style::T>) {
+        let (style_select, color_select, width) = match direction {
+            Top => (style.top, color.top, border.top),
+            Left => (style.left, color.left, border.left),
+            Right => (style.right, color.right, border.right),
+            Bottom => (style.bottom, color.bottom, border.bottom)
+        };
+        match style_select {
+            border_style::none | border_style::hidden => return,
+            border_style::dotted | border_style::dashed => {
+                fail!("dotted and dashed borders are drawn with square corners")
+            }
+            border_style::solid => (),
+        }
+        if width <= 0.0 {
+            return
+        }
+
+        let rect = bounds.to_azure_rect();
+        let left_top = Point2D(rect.origin.x, rect.origin.y);
+        let right_top = Point2D(rect.origin.x + rect.size.width, rect.origin.y);
+        let left_bottom = Point2D(rect.origin.x, rect.origin.y + rect.size.height);
+        let right_bottom = Point2D(rect.origin.x + rect.size.width, rect.origin.y + rect.size.height);
+
+        // The diagonals are extended up to the middle of the box, where the curves of the inner
+        // corners may reach, without making the sides overlap.
+        let (start, start_diagonal, end, end_diagonal, scale) = match direction {
+            Top => (left_top, Point2D(border.left, border.top),
+                    right_top, Point2D(-border.right, border.top),
+                    (rect.size.height * 0.5 / border.top)
+                        .min(&(rect.size.width / (border.left + border.right)))),
+            Right => (right_top, Point2D(-border.right, border.top),
+                      right_bottom, Point2D(-border.right, -border.bottom),
+                      (rect.size.width * 0.5 / border.right)
+                          .min(&(rect.size.height / (border.top + border.bottom)))),
+            Bottom => (right_bottom, Point2D(-border.right, -border.bottom),
+                       left_bottom, Point2D(border.left, -border.bottom),
+                       (rect.size.height * 0.5 / border.bottom)
+                           .min(&(rect.size.width / (border.left + border.right)))),
+            Left => (left_bottom, Point2D(border.left, -border.bottom),
+                     left_top, Point2D(border.left, border.top),
+                     (rect.size.width * 0.5 / border.left)
+                         .min(&(rect.size.height / (border.top + border.bottom)))),
+        };
+        let clip_builder = self.draw_target.create_path_builder();
+        clip_builder.move_to(start);
+        clip_builder.line_to(end);
+        clip_builder.line_to(end + Point2D(end_diagonal.x * scale, end_diagonal.y * scale));
+        clip_builder.line_to(start + Point2D(start_diagonal.x * scale, start_diagonal.y * scale));
+        let clip = clip_builder.finish();
+
+        // The inner rectangle goes the other way around, so that it makes a hole in the outer one.
+        let inner_rect = Rect(Point2D(rect.origin.x + border.left, rect.origin.y + border.top),
+                              Size2D((rect.size.width - border.left - border.right).max(&0.0),
+                                     (rect.size.height - border.top - border.bottom).max(&0.0)));
+        let inner_radii = BorderRadii {
+            top_left: Size2D((radii.top_left.width - border.left).max(&0.0),
+                             (radii.top_left.height - border.top).max(&0.0)),
+            top_right: Size2D((radii.top_right.width - border.right).max(&0.0),
+                              (radii.top_right.height - border.top).max(&0.0)),
+            bottom_right: Size2D((radii.bottom_right.width - border.right).max(&0.0),
+                                 (radii.bottom_right.height - border.bottom).max(&0.0)),
+            bottom_left: Size2D((radii.bottom_left.width - border.left).max(&0.0),
+                                (radii.bottom_left.height - border.bottom).max(&0.0)),
+        };
+        let path_builder = self.draw_target.create_path_builder();
+        add_rounded_rect(&path_builder, &rect, radii, true);
+        add_rounded_rect(&path_builder, &inner_rect, &inner_radii, false);
+        let path = path_builder.finish();
+
+        self.draw_target.push_clip(&clip);
+        self.draw_target.fill(&path, &ColorPattern(color_select), &DrawOptions(1.0, 0));
+        self.draw_target.pop_clip();
+    }
+
     fn draw_dashed_border_segment(&self, direction: Direction, bounds: &Rect<Au>, border: SideOffsets2D<f32>, color: Color) {
         let rect = bounds.to_azure_rect();
         let draw_opts = DrawOptions(1 as AzFloat, 0 as uint16_t);
@@ -246,6 +598,220 @@ impl<'self> RenderContext<'self>  {
     }
 }
 
+/// The factor that places the control points of a cubic Bezier curve approximating a quarter of
+/// an ellipse, as a fraction of the radius.
+static KAPPA: AzFloat = 0.5522847498;
+
+/// Adds a rounded rectangle to the path, clockwise from the top left corner, or counterclockwise
+/// if `clockwise` is false.
+fn add_rounded_rect(path_builder: &PathBuilder,
+                    rect: &Rect<AzFloat>,
+                    radii: &BorderRadii<AzFloat>,
+                    clockwise: bool) {
+    let (left, top) = (rect.origin.x, rect.origin.y);
+    let (right, bottom) = (rect.origin.x + rect.size.width, rect.origin.y + rect.size.height);
+
+    // Each corner, clockwise: where its curve starts, the corner itself, and where it ends.
+    let mut corners = ~[
+        (Point2D(left, top + radii.top_left.height), Point2D(left, top),
+         Point2D(left + radii.top_left.width, top)),
+        (Point2D(right - radii.top_right.width, top), Point2D(right, top),
+         Point2D(right, top + radii.top_right.height)),
+        (Point2D(right, bottom - radii.bottom_right.height), Point2D(right, bottom),
+         Point2D(right - radii.bottom_right.width, bottom)),
+        (Point2D(left + radii.bottom_left.width, bottom), Point2D(left, bottom),
+         Point2D(left, bottom - radii.bottom_left.height)),
+    ];
+    if !clockwise {
+        corners.reverse();
+        for corner in corners.mut_iter() {
+            let (start, point, end) = *corner;
+            *corner = (end, point, start);
+        }
+    }
+
+    let (first_start, _, _) = corners[0];
+    path_builder.move_to(first_start);
+    for &(start, point, end) in corners.iter() {
+        path_builder.line_to(start);
+        path_builder.bezier_to(Point2D(start.x + (point.x - start.x) * KAPPA,
+                                       start.y + (point.y - start.y) * KAPPA),
+                               Point2D(end.x + (point.x - end.x) * KAPPA,
+                                       end.y + (point.y - end.y) * KAPPA),
+                               end);
+    }
+    path_builder.line_to(first_start);
+}
+
+fn square_radii() -> BorderRadii<AzFloat> {
+    let zero = Size2D(0.0 as AzFloat, 0.0 as AzFloat);
+    BorderRadii {
+        top_left: zero,
+        top_right: zero,
+        bottom_right: zero,
+        bottom_left: zero,
+    }
+}
+
+/// Grows the rounded corners of a rectangle by the given amount, or shrinks them if the amount is
+/// negative, for a shadow spread by that amount. Square corners stay square.
+fn inflate_radii(radii: &BorderRadii<AzFloat>, amount: AzFloat) -> BorderRadii<AzFloat> {
+    let inflate = |size: Size2D<AzFloat>| {
+        if size.width == 0.0 || size.height == 0.0 {
+            size
+        } else {
+            Size2D((size.width + amount).max(&0.0), (size.height + amount).max(&0.0))
+        }
+    };
+    BorderRadii {
+        top_left: inflate(radii.top_left),
+        top_right: inflate(radii.top_right),
+        bottom_right: inflate(radii.bottom_right),
+        bottom_left: inflate(radii.bottom_left),
+    }
+}
+
+/// Grows a rectangle by the given amount on each side, or shrinks it if the amount is negative.
+fn inflate_rect(rect: &Rect<Au>, amount: Au) -> Rect<Au> {
+    Rect(Point2D(rect.origin.x - amount, rect.origin.y - amount),
+         Size2D(Au::max(rect.size.width + amount + amount, Au(0)),
+                Au::max(rect.size.height + amount + amount, Au(0))))
+}
+
+/// The number of blurred images that a render task keeps.
+static BLUR_CACHE_SIZE: uint = 16;
+
+/// Finds the pixels that a blur produced, from the pixels it blurred.
+#[deriving(Clone, Eq, IterBytes)]
+struct BlurKey {
+    width: uint,
+    height: uint,
+    blur_radius: i32,
+    pixels_hash: u64,
+}
+
+struct BlurredPixels {
+    /// The pixels before the blur, which tell apart blurs whose keys collide.
+    source: ~[u8],
+    blurred: ~[u8],
+}
+
+/// Caches blurred pixels, so that a shadow is not blurred again when the same pixels are drawn
+/// again, because its tile is rendered again or because another box casts the same shadow.
+pub struct BlurCache {
+    priv entries: HashLRUCache<BlurKey, Arc<BlurredPixels>>,
+}
+
+impl BlurCache {
+    pub fn new() -> BlurCache {
+        BlurCache {
+            entries: HashLRUCache::new(BLUR_CACHE_SIZE),
+        }
+    }
+
+    /// Blurs premultiplied BGRA pixels with the given CSS blur radius, or finds the result of an
+    /// earlier blur of the same pixels.
+    fn blur(&mut self, pixels: ~[u8], width: uint, height: uint, blur_radius: Au)
+            -> Arc<BlurredPixels> {
+        let key = BlurKey {
+            width: width,
+            height: height,
+            blur_radius: *blur_radius,
+            pixels_hash: pixels.hash(),
+        };
+        match self.entries.find(&key) {
+            Some(entry) => {
+                if entry.get().source == pixels {
+                    return entry
+                }
+            }
+            None => {}
+        }
+
+        // The blur radius is twice the standard deviation.
+        let mut blurred = pixels.clone();
+        gaussian_blur(blurred, width, height, geometry::to_frac_px(blur_radius) / 2.0);
+        let entry = Arc::new(BlurredPixels {
+            source: pixels,
+            blurred: blurred,
+        });
+        self.entries.insert(key, entry.clone());
+        entry
+    }
+}
+
+/// Blurs premultiplied BGRA pixels in place with a Gaussian of the given standard deviation, in
+/// pixels. The blur is done as a horizontal pass followed by a vertical one.
+fn gaussian_blur(pixels: &mut [u8], width: uint, height: uint, sigma: f64) {
+    let radius = (sigma * 3.0).ceil() as int;
+    if radius <= 0 {
+        return
+    }
+    let weights: ~[f64] = do range(-radius, radius + 1).map |i| {
+        (-((i * i) as f64) / (2.0 * sigma * sigma)).exp()
+    }.collect();
+    let total = weights.iter().fold(0.0, |total, &weight| total + weight);
+    let kernel: ~[f64] = weights.iter().map(|&weight| weight / total).collect();
+
+    let mut horizontally_blurred = vec::from_elem(pixels.len(), 0u8);
+    blur_pass(pixels, horizontally_blurred, width, height, kernel, true);
+    blur_pass(horizontally_blurred, pixels, width, height, kernel, false);
+}
+
+/// Convolves the pixels with a kernel along one direction. Pixels outside of the image are
+/// transparent.
+fn blur_pass(source: &[u8], dest: &mut [u8], width: uint, height: uint, kernel: &[f64],
+             horizontal: bool) {
+    let radius = (kernel.len() / 2) as int;
+    for y in range(0, height) {
+        for x in range(0, width) {
+            let mut sums = [0.0f64, ..4];
+            for (i, &weight) in kernel.iter().enumerate() {
+                let offset = i as int - radius;
+                let (source_x, source_y) = if horizontal {
+                    (x as int + offset, y as int)
+                } else {
+                    (x as int, y as int + offset)
+                };
+                if source_x < 0 || source_y < 0 ||
+                        source_x >= width as int || source_y >= height as int {
+                    continue
+                }
+                let index = (source_y as uint * width + source_x as uint) * 4;
+                for channel in range(0u, 4) {
+                    sums[channel] += weight * (source[index + channel] as f64)
+                }
+            }
+            let index = (y * width + x) * 4;
+            for channel in range(0u, 4) {
+                dest[index + channel] = sums[channel].round().min(&255.0) as u8
+            }
+        }
+    }
+}
+
+/// Scales down radii that would overlap along a side of the rectangle, per CSS Backgrounds and
+/// Borders 3 section 5.5.
+fn scale_radii(rect: &Rect<AzFloat>, radii: &BorderRadii<AzFloat>) -> BorderRadii<AzFloat> {
+    let ratio = |length: AzFloat, first: AzFloat, second: AzFloat| {
+        if first + second > 0.0 { length / (first + second) } else { 1.0 }
+    };
+    let factor = ratio(rect.size.width, radii.top_left.width, radii.top_right.width)
+        .min(&ratio(rect.size.width, radii.bottom_left.width, radii.bottom_right.width))
+        .min(&ratio(rect.size.height, radii.top_left.height, radii.bottom_left.height))
+        .min(&ratio(rect.size.height, radii.top_right.height, radii.bottom_right.height));
+    if factor >= 1.0 {
+        return radii.clone()
+    }
+    let scale = |size: Size2D<AzFloat>| Size2D(size.width * factor, size.height * factor);
+    BorderRadii {
+        top_left: scale(radii.top_left),
+        top_right: scale(radii.top_right),
+        bottom_right: scale(radii.bottom_right),
+        bottom_left: scale(radii.bottom_left),
+    }
+}
+
 trait to_float {
     fn to_float(&self) -> f64;
 }
@@ -269,6 +835,35 @@ impl ToAzureRect for Rect<Au> {
     }
 }
 
+trait ToAzurePoint {
+    fn to_azure_point(&self) -> Point2D<AzFloat>;
+}
+
+impl ToAzurePoint for Point2D<Au> {
+    fn to_azure_point(&self) -> Point2D<AzFloat> {
+        Point2D(geometry::to_frac_px(self.x) as AzFloat, geometry::to_frac_px(self.y) as AzFloat)
+    }
+}
+
+trait ToBorderRadiiPx {
+    fn to_float_px(&self) -> BorderRadii<AzFloat>;
+}
+
+impl ToBorderRadiiPx for BorderRadii<Au> {
+    fn to_float_px(&self) -> BorderRadii<AzFloat> {
+        let to_px = |size: Size2D<Au>| {
+            Size2D(geometry::to_frac_px(size.width) as AzFloat,
+                   geometry::to_frac_px(size.height) as AzFloat)
+        };
+        BorderRadii {
+            top_left: to_px(self.top_left),
+            top_right: to_px(self.top_right),
+            bottom_right: to_px(self.bottom_right),
+            bottom_left: to_px(self.bottom_left),
+        }
+    }
+}
+
 trait ToSideOffsetsPx {
     fn to_float_px(&self) -> SideOffsets2D<AzFloat>;
 }
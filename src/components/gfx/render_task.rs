@@ -25,9 +25,10 @@ use extra::arc::Arc;
 
 use buffer_map::BufferMap;
 use display_list::DisplayList;
+use font_cache_task::FontCacheTask;
 use font_context::FontContext;
 use opts::Opts;
-use render_context::RenderContext;
+use render_context::{BlurCache, RenderContext};
 
 pub struct RenderLayer<T> {
     display_list: Arc<DisplayList<T>>,
@@ -100,6 +101,9 @@ pub struct RenderTask<C,T> {
     font_ctx: ~FontContext,
     opts: Opts,
 
+    /// The results of the blurs of shadows, kept across tiles and frames.
+    blur_cache: BlurCache,
+
     /// A channel to the profiler.
     profiler_chan: ProfilerChan,
 
@@ -135,11 +139,14 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                   port: Port<Msg<T>>,
                   compositor: C,
                   constellation_chan: ConstellationChan,
+                  font_cache_task: FontCacheTask,
                   opts: Opts,
                   profiler_chan: ProfilerChan,
                   shutdown_chan: Chan<()>) {
-        do spawn_with((port, compositor, constellation_chan, opts, profiler_chan, shutdown_chan))
-            |(port, compositor, constellation_chan, opts, profiler_chan, shutdown_chan)| {
+        do spawn_with((port, compositor, constellation_chan, font_cache_task, opts, profiler_chan,
+                       shutdown_chan))
+            |(port, compositor, constellation_chan, font_cache_task, opts, profiler_chan,
+              shutdown_chan)| {
 
             { // Ensures RenderTask and graphics context are destroyed before shutdown msg
                 let native_graphics_context = compositor.get_graphics_metadata().map(
@@ -154,8 +161,10 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                     constellation_chan: constellation_chan,
                     font_ctx: ~FontContext::new(opts.render_backend.clone(),
                                                     false,
+                                                    font_cache_task,
                                                     profiler_chan.clone()),
                     opts: opts,
+                    blur_cache: BlurCache::new(),
                     profiler_chan: profiler_chan,
 
                     graphics_context: if cpu_painting {
@@ -276,6 +285,7 @@ impl<C: RenderListener + Send,T:Send+Freeze> RenderTask<C,T> {
                         let mut ctx = RenderContext {
                             draw_target: &draw_target,
                             font_ctx: &mut self.font_ctx,
+                            blur_cache: &mut self.blur_cache,
                             opts: &self.opts,
                             page_rect: tile.page_rect,
                             screen_rect: tile.screen_rect,
@@ -0,0 +1,479 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The Unicode Bidirectional Algorithm (UAX #9), which resolves the embedding level of each
+//! character of a paragraph. Even levels are left-to-right and odd levels right-to-left; the
+//! visual order of the characters follows from the levels.
+//!
+//! Directional isolates are not supported. The table of bidirectional character types covers the
+//! common scripts and punctuation; other characters are taken to be left-to-right.
+
+use std::cmp;
+use std::cmp::{Equal, Greater, Less};
+use std::vec;
+
+/// The bidirectional character types of UAX #9, § 3.2.
+#[deriving(Clone, Eq)]
+pub enum BidiClass {
+    /// Left-to-right.
+    L,
+    /// Right-to-left.
+    R,
+    /// Right-to-left Arabic.
+    AL,
+    /// European number.
+    EN,
+    /// European number separator.
+    ES,
+    /// European number terminator.
+    ET,
+    /// Arabic number.
+    AN,
+    /// Common number separator.
+    CS,
+    /// Nonspacing mark.
+    NSM,
+    /// Boundary neutral.
+    BN,
+    /// Paragraph separator.
+    B,
+    /// Segment separator.
+    S,
+    /// Whitespace.
+    WS,
+    /// Other neutrals.
+    ON,
+    /// Left-to-right embedding.
+    LRE,
+    /// Left-to-right override.
+    LRO,
+    /// Right-to-left embedding.
+    RLE,
+    /// Right-to-left override.
+    RLO,
+    /// Pop directional format.
+    PDF,
+}
+
+/// The deepest embedding level, per rule X1.
+static MAX_DEPTH: u8 = 61;
+
+/// Returns the bidirectional character type of the given character.
+pub fn bidi_class(ch: char) -> BidiClass {
+    let index = do BIDI_CLASS_TABLE.bsearch |&(low, high, _)| {
+        if high < ch {
+            Less
+        } else if low > ch {
+            Greater
+        } else {
+            Equal
+        }
+    };
+    match index {
+        Some(index) => {
+            let (_, _, class) = BIDI_CLASS_TABLE[index];
+            class
+        }
+        None => L,
+    }
+}
+
+/// Whether characters of the given type can make a left-to-right paragraph need resolution.
+pub fn is_rtl_class(class: BidiClass) -> bool {
+    match class {
+        R | AL | AN | RLE | RLO => true,
+        _ => false,
+    }
+}
+
+/// Resolves the embedding level of each character of a paragraph, given the types of its
+/// characters and the level of the paragraph.
+///
+/// The explicit formatting characters and boundary neutrals take the level of the character
+/// before them. Of rule L1, only separators and the whitespace before them and at the end of the
+/// paragraph are reset to the paragraph level; resetting the whitespace at the end of each line is
+/// left to the caller.
+pub fn resolve_levels(classes: &[BidiClass], paragraph_level: u8) -> ~[u8] {
+    let len = classes.len();
+    let mut levels = vec::from_elem(len, paragraph_level);
+    let mut types = classes.to_owned();
+    let mut removed = vec::from_elem(len, false);
+
+    // X1-X9: Explicit levels and directions.
+    let mut stack: ~[(u8, Option<BidiClass>)] = ~[(paragraph_level, None)];
+    let mut overflow = 0u;
+    for i in range(0, len) {
+        let (level, override_class) = *stack.last();
+        match classes[i] {
+            RLE | LRE | RLO | LRO => {
+                let new_level = match classes[i] {
+                    RLE | RLO => (level + 1) | 1,
+                    _ => (level + 2) & !1u8,
+                };
+                if new_level <= MAX_DEPTH && overflow == 0 {
+                    let new_override = match classes[i] {
+                        RLO => Some(R),
+                        LRO => Some(L),
+                        _ => None,
+                    };
+                    stack.push((new_level, new_override));
+                } else {
+                    overflow += 1;
+                }
+                removed[i] = true;
+            }
+            PDF => {
+                if overflow > 0 {
+                    overflow -= 1;
+                } else if stack.len() > 1 {
+                    stack.pop();
+                }
+                removed[i] = true;
+            }
+            BN => removed[i] = true,
+            B => {
+                // X8: Paragraph separators end all embeddings.
+                stack.truncate(1);
+                overflow = 0;
+            }
+            class => {
+                levels[i] = level;
+                types[i] = override_class.unwrap_or(class);
+            }
+        }
+    }
+
+    // X10: Resolve the types of each run of characters at the same level on its own.
+    let kept: ~[uint] = range(0, len).filter(|&i| !removed[i]).collect();
+    let mut run_start = 0;
+    while run_start < kept.len() {
+        let level = levels[kept[run_start]];
+        let mut run_end = run_start + 1;
+        while run_end < kept.len() && levels[kept[run_end]] == level {
+            run_end += 1;
+        }
+
+        let previous_level = if run_start == 0 {
+            paragraph_level
+        } else {
+            levels[kept[run_start - 1]]
+        };
+        let next_level = if run_end == kept.len() {
+            paragraph_level
+        } else {
+            levels[kept[run_end]]
+        };
+        let sos = direction_of_level(cmp::max(level, previous_level));
+        let eos = direction_of_level(cmp::max(level, next_level));
+        resolve_weak_and_neutral_types(kept.slice(run_start, run_end), types, level, sos, eos);
+        run_start = run_end;
+    }
+
+    // I1, I2: Implicit levels.
+    for &i in kept.iter() {
+        let level = levels[i];
+        levels[i] = match (level & 1 == 0, types[i]) {
+            (true, R) => level + 1,
+            (true, AN) | (true, EN) => level + 2,
+            (false, L) | (false, EN) | (false, AN) => level + 1,
+            _ => level,
+        };
+    }
+
+    for i in range(0, len) {
+        if removed[i] {
+            levels[i] = if i == 0 { paragraph_level } else { levels[i - 1] };
+        }
+    }
+
+    // L1: Separators, and the whitespace before them and at the end of the paragraph, take the
+    // paragraph level.
+    let mut trailing = true;
+    for i in range(0, len).invert() {
+        match classes[i] {
+            B | S => {
+                levels[i] = paragraph_level;
+                trailing = true;
+            }
+            WS | BN | LRE | RLE | LRO | RLO | PDF if trailing => levels[i] = paragraph_level,
+            _ => trailing = false,
+        }
+    }
+
+    levels
+}
+
+/// Returns the indices of the given levels in visual order, per rule L2: from the highest level
+/// down to the lowest odd level, every sequence of characters at that level or higher is reversed.
+pub fn visual_order(levels: &[u8]) -> ~[uint] {
+    let mut order: ~[uint] = range(0, levels.len()).collect();
+    let highest = levels.iter().fold(0u8, |highest, &level| cmp::max(highest, level));
+    let lowest_odd = do levels.iter().fold(MAX_DEPTH + 1) |lowest, &level| {
+        if level & 1 == 1 { cmp::min(lowest, level) } else { lowest }
+    };
+
+    let mut level = highest;
+    while level >= lowest_odd && level > 0 {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order.mut_slice(start, i).reverse();
+        }
+        level -= 1;
+    }
+    order
+}
+
+fn direction_of_level(level: u8) -> BidiClass {
+    if level & 1 == 0 { L } else { R }
+}
+
+fn is_neutral(class: BidiClass) -> bool {
+    match class {
+        B | S | WS | ON => true,
+        _ => false,
+    }
+}
+
+/// The direction a resolved strong type or number counts as for rule N1.
+fn strong_direction(class: BidiClass) -> BidiClass {
+    match class {
+        L => L,
+        _ => R,
+    }
+}
+
+/// Resolves the weak and neutral types of a run of characters at the given level, given by their
+/// indices in `types`, per rules W1-W7 and N1-N2.
+fn resolve_weak_and_neutral_types(run: &[uint],
+                                  types: &mut [BidiClass],
+                                  level: u8,
+                                  sos: BidiClass,
+                                  eos: BidiClass) {
+    // W1: Nonspacing marks take the type of the character before them.
+    let mut previous = sos;
+    for &i in run.iter() {
+        if types[i] == NSM {
+            types[i] = previous;
+        }
+        previous = types[i];
+    }
+
+    // W2: European numbers after Arabic letters are Arabic numbers.
+    let mut last_strong = sos;
+    for &i in run.iter() {
+        match types[i] {
+            L | R | AL => last_strong = types[i],
+            EN if last_strong == AL => types[i] = AN,
+            _ => {}
+        }
+    }
+
+    // W3: Arabic letters are right-to-left.
+    for &i in run.iter() {
+        if types[i] == AL {
+            types[i] = R;
+        }
+    }
+
+    // W4: A single separator between two numbers of the same type joins them.
+    if run.len() >= 3 {
+        for j in range(1, run.len() - 1) {
+            match (types[run[j - 1]], types[run[j]], types[run[j + 1]]) {
+                (EN, ES, EN) | (EN, CS, EN) => types[run[j]] = EN,
+                (AN, CS, AN) => types[run[j]] = AN,
+                _ => {}
+            }
+        }
+    }
+
+    // W5: Terminators next to European numbers are European numbers.
+    let mut j = 0;
+    while j < run.len() {
+        if types[run[j]] != ET {
+            j += 1;
+            continue
+        }
+        let start = j;
+        while j < run.len() && types[run[j]] == ET {
+            j += 1;
+        }
+        if (start > 0 && types[run[start - 1]] == EN) || (j < run.len() && types[run[j]] == EN) {
+            for k in range(start, j) {
+                types[run[k]] = EN;
+            }
+        }
+    }
+
+    // W6: Remaining separators and terminators are neutral.
+    for &i in run.iter() {
+        match types[i] {
+            ES | ET | CS => types[i] = ON,
+            _ => {}
+        }
+    }
+
+    // W7: European numbers in left-to-right text are left-to-right.
+    let mut last_strong = sos;
+    for &i in run.iter() {
+        match types[i] {
+            L | R => last_strong = types[i],
+            EN if last_strong == L => types[i] = L,
+            _ => {}
+        }
+    }
+
+    // N1: Neutrals between characters of the same direction take that direction. N2: Other
+    // neutrals take the direction of the embedding.
+    let embedding_direction = direction_of_level(level);
+    let mut j = 0;
+    while j < run.len() {
+        if !is_neutral(types[run[j]]) {
+            j += 1;
+            continue
+        }
+        let start = j;
+        while j < run.len() && is_neutral(types[run[j]]) {
+            j += 1;
+        }
+        let before = if start == 0 { sos } else { strong_direction(types[run[start - 1]]) };
+        let after = if j == run.len() { eos } else { strong_direction(types[run[j]]) };
+        let resolved = if before == after { before } else { embedding_direction };
+        for k in range(start, j) {
+            types[run[k]] = resolved;
+        }
+    }
+}
+
+/// The bidirectional character types of ranges of characters, from UnicodeData.txt. Characters
+/// not listed are left-to-right.
+static BIDI_CLASS_TABLE: &'static [(char, char, BidiClass)] = &[
+    ('\x00', '\x08', BN), ('\x09', '\x09', S), ('\x0a', '\x0a', B), ('\x0b', '\x0b', S),
+    ('\x0c', '\x0c', WS), ('\x0d', '\x0d', B), ('\x0e', '\x1b', BN), ('\x1c', '\x1e', B),
+    ('\x1f', '\x1f', S), ('\x20', '\x20', WS), ('\x21', '\x22', ON), ('\x23', '\x25', ET),
+    ('\x26', '\x2a', ON), ('\x2b', '\x2b', ES), ('\x2c', '\x2c', CS), ('\x2d', '\x2d', ES),
+    ('\x2e', '\x2f', CS), ('\x30', '\x39', EN), ('\x3a', '\x3a', CS), ('\x3b', '\x40', ON),
+    ('\x5b', '\x60', ON), ('\x7b', '\x7e', ON), ('\x7f', '\u0084', BN), ('\u0085', '\u0085', B),
+    ('\u0086', '\u009f', BN), ('\u00a0', '\u00a0', CS), ('\u00a1', '\u00a1', ON),
+    ('\u00a2', '\u00a5', ET), ('\u00a6', '\u00a9', ON), ('\u00ab', '\u00ac', ON),
+    ('\u00ad', '\u00ad', BN), ('\u00ae', '\u00af', ON), ('\u00b0', '\u00b1', ET),
+    ('\u00b2', '\u00b3', EN), ('\u00b4', '\u00b4', ON), ('\u00b6', '\u00b8', ON),
+    ('\u00b9', '\u00b9', EN), ('\u00bb', '\u00bf', ON), ('\u00d7', '\u00d7', ON),
+    ('\u00f7', '\u00f7', ON), ('\u02b9', '\u02ba', ON), ('\u02c2', '\u02cf', ON),
+    ('\u02d2', '\u02df', ON), ('\u02e5', '\u02ed', ON), ('\u02ef', '\u02ff', ON),
+    ('\u0300', '\u036f', NSM), ('\u0374', '\u0375', ON), ('\u037e', '\u037e', ON),
+    ('\u0384', '\u0385', ON), ('\u0387', '\u0387', ON), ('\u03f6', '\u03f6', ON),
+    ('\u0483', '\u0489', NSM), ('\u058a', '\u058a', ON), ('\u0591', '\u05bd', NSM),
+    ('\u05be', '\u05be', R), ('\u05bf', '\u05bf', NSM), ('\u05c0', '\u05c0', R),
+    ('\u05c1', '\u05c2', NSM), ('\u05c3', '\u05c3', R), ('\u05c4', '\u05c5', NSM),
+    ('\u05c6', '\u05c6', R), ('\u05c7', '\u05c7', NSM), ('\u05c8', '\u05ff', R),
+    ('\u0600', '\u0605', AN), ('\u0606', '\u0607', ON), ('\u0608', '\u0608', AL),
+    ('\u0609', '\u060a', ET), ('\u060b', '\u060b', AL), ('\u060c', '\u060c', CS),
+    ('\u060d', '\u060d', AL), ('\u060e', '\u060f', ON), ('\u0610', '\u061a', NSM),
+    ('\u061b', '\u064a', AL), ('\u064b', '\u065f', NSM), ('\u0660', '\u0669', AN),
+    ('\u066a', '\u066a', ET), ('\u066b', '\u066c', AN), ('\u066d', '\u066f', AL),
+    ('\u0670', '\u0670', NSM), ('\u0671', '\u06d5', AL), ('\u06d6', '\u06dc', NSM),
+    ('\u06dd', '\u06dd', AN), ('\u06de', '\u06de', ON), ('\u06df', '\u06e4', NSM),
+    ('\u06e5', '\u06e6', AL), ('\u06e7', '\u06e8', NSM), ('\u06e9', '\u06e9', ON),
+    ('\u06ea', '\u06ed', NSM), ('\u06ee', '\u06ef', AL), ('\u06f0', '\u06f9', EN),
+    ('\u06fa', '\u0710', AL), ('\u0711', '\u0711', NSM), ('\u0712', '\u072f', AL),
+    ('\u0730', '\u074a', NSM), ('\u074b', '\u07a5', AL), ('\u07a6', '\u07b0', NSM),
+    ('\u07b1', '\u07bf', AL), ('\u07c0', '\u07ea', R), ('\u07eb', '\u07f3', NSM),
+    ('\u07f4', '\u07f5', R), ('\u07f6', '\u07f9', ON), ('\u07fa', '\u089f', R),
+    ('\u08a0', '\u08d2', AL), ('\u08d3', '\u08e1', NSM), ('\u08e2', '\u08e2', AN),
+    ('\u08e3', '\u08ff', NSM), ('\u1680', '\u1680', WS), ('\u2000', '\u200a', WS),
+    ('\u200b', '\u200d', BN), ('\u200f', '\u200f', R), ('\u2010', '\u2027', ON),
+    ('\u2028', '\u2028', WS), ('\u2029', '\u2029', B), ('\u202a', '\u202a', LRE),
+    ('\u202b', '\u202b', RLE), ('\u202c', '\u202c', PDF), ('\u202d', '\u202d', LRO),
+    ('\u202e', '\u202e', RLO), ('\u202f', '\u202f', CS), ('\u2030', '\u2034', ET),
+    ('\u2035', '\u2043', ON), ('\u2044', '\u2044', CS), ('\u2045', '\u205e', ON),
+    ('\u205f', '\u205f', WS), ('\u2060', '\u206f', BN), ('\u2070', '\u2070', EN),
+    ('\u2074', '\u2079', EN), ('\u207a', '\u207b', ES), ('\u207c', '\u207e', ON),
+    ('\u2080', '\u2089', EN), ('\u208a', '\u208b', ES), ('\u208c', '\u208e', ON),
+    ('\u20a0', '\u20cf', ET), ('\u20d0', '\u20f0', NSM), ('\u2100', '\u2101', ON),
+    ('\u2103', '\u2106', ON), ('\u2108', '\u2109', ON), ('\u2114', '\u2114', ON),
+    ('\u2116', '\u2118', ON), ('\u211e', '\u2123', ON), ('\u2125', '\u2125', ON),
+    ('\u2127', '\u2127', ON), ('\u2129', '\u2129', ON), ('\u212e', '\u212e', ET),
+    ('\u213a', '\u213b', ON), ('\u2140', '\u2144', ON), ('\u214a', '\u214d', ON),
+    ('\u2150', '\u215f', ON), ('\u2189', '\u218b', ON), ('\u2190', '\u2211', ON),
+    ('\u2212', '\u2212', ES), ('\u2213', '\u2213', ET), ('\u2214', '\u2335', ON),
+    ('\u237b', '\u2394', ON), ('\u2396', '\u2426', ON), ('\u2440', '\u244a', ON),
+    ('\u2460', '\u2487', ON), ('\u2488', '\u249b', EN), ('\u24ea', '\u26ab', ON),
+    ('\u26ad', '\u27ff', ON), ('\u2900', '\u2b73', ON), ('\u2ce5', '\u2cea', ON),
+    ('\u2e00', '\u2e4f', ON), ('\u2e80', '\u2ffb', ON), ('\u3000', '\u3000', WS),
+    ('\u3001', '\u3004', ON), ('\u3008', '\u3020', ON), ('\u302a', '\u302d', NSM),
+    ('\u3030', '\u3030', ON), ('\u3036', '\u3037', ON), ('\u303d', '\u303f', ON),
+    ('\u3099', '\u309a', NSM), ('\u309b', '\u309c', ON), ('\u30a0', '\u30a0', ON),
+    ('\u30fb', '\u30fb', ON), ('\ua490', '\ua4c6', ON), ('\ufb1d', '\ufb1d', R),
+    ('\ufb1e', '\ufb1e', NSM), ('\ufb1f', '\ufb28', R), ('\ufb29', '\ufb29', ES),
+    ('\ufb2a', '\ufb4f', R), ('\ufb50', '\ufd3d', AL), ('\ufd3e', '\ufd3f', ON),
+    ('\ufd40', '\ufdcf', AL), ('\ufdf0', '\ufdfc', AL), ('\ufdfd', '\ufdfd', ON),
+    ('\ufe00', '\ufe0f', NSM), ('\ufe10', '\ufe19', ON), ('\ufe20', '\ufe2f', NSM),
+    ('\ufe30', '\ufe4f', ON), ('\ufe50', '\ufe50', CS), ('\ufe51', '\ufe51', ON),
+    ('\ufe52', '\ufe52', CS), ('\ufe54', '\ufe54', ON), ('\ufe55', '\ufe55', CS),
+    ('\ufe56', '\ufe5e', ON), ('\ufe5f', '\ufe5f', ET), ('\ufe60', '\ufe61', ON),
+    ('\ufe62', '\ufe63', ES), ('\ufe64', '\ufe68', ON), ('\ufe69', '\ufe6a', ET),
+    ('\ufe6b', '\ufe6b', ON), ('\ufe70', '\ufefe', AL), ('\ufeff', '\ufeff', BN),
+    ('\uff01', '\uff02', ON), ('\uff03', '\uff05', ET), ('\uff06', '\uff0a', ON),
+    ('\uff0b', '\uff0b', ES), ('\uff0c', '\uff0c', CS), ('\uff0d', '\uff0d', ES),
+    ('\uff0e', '\uff0f', CS), ('\uff10', '\uff19', EN), ('\uff1a', '\uff1a', CS),
+    ('\uff1b', '\uff20', ON), ('\uff3b', '\uff40', ON), ('\uff5b', '\uff65', ON),
+    ('\uffe0', '\uffe1', ET), ('\uffe2', '\uffe4', ON), ('\uffe5', '\uffe6', ET),
+    ('\uffe8', '\uffee', ON), ('\ufff9', '\ufffd', ON), ('\U00010800', '\U00010e5f', R),
+    ('\U00010e60', '\U00010e7e', AN), ('\U00010e7f', '\U00010fff', R),
+    ('\U0001e800', '\U0001edff', R), ('\U0001ee00', '\U0001eeff', AL),
+    ('\U0001ef00', '\U0001efff', R), ('\U000e0001', '\U000e007f', BN),
+];
+
+#[test]
+fn test_bidi_class() {
+    assert_eq!(bidi_class('a'), L);
+    assert_eq!(bidi_class('1'), EN);
+    assert_eq!(bidi_class(' '), WS);
+    assert_eq!(bidi_class('\u05d0'), R);
+    assert_eq!(bidi_class('\u0627'), AL);
+    assert_eq!(bidi_class('\u0661'), AN);
+    assert_eq!(bidi_class('\u4e00'), L);
+}
+
+#[test]
+fn test_resolve_levels_mixed() {
+    // Left-to-right letters, a space and right-to-left letters in a left-to-right paragraph.
+    assert_eq!(resolve_levels([L, L, WS, R, R], 0), ~[0, 0, 0, 1, 1]);
+    // The reverse, in a right-to-left paragraph.
+    assert_eq!(resolve_levels([R, R, WS, L, L], 1), ~[1, 1, 1, 2, 2]);
+}
+
+#[test]
+fn test_resolve_levels_numbers() {
+    // Numbers after right-to-left letters are at the level above them.
+    assert_eq!(resolve_levels([R, WS, EN, CS, EN], 0), ~[1, 1, 2, 2, 2]);
+    // Numbers after Arabic letters are Arabic numbers.
+    assert_eq!(resolve_levels([AL, EN, L], 0), ~[1, 2, 0]);
+}
+
+#[test]
+fn test_resolve_levels_override() {
+    let levels = resolve_levels([L, RLO, L, L, PDF, L], 0);
+    assert_eq!(levels, ~[0, 0, 1, 1, 1, 0]);
+}
+
+#[test]
+fn test_resolve_levels_trailing_whitespace() {
+    assert_eq!(resolve_levels([R, WS, R, WS], 0), ~[1, 1, 1, 0]);
+}
+
+#[test]
+fn test_visual_order() {
+    assert_eq!(visual_order([0, 0, 0]), ~[0, 1, 2]);
+    assert_eq!(visual_order([1, 1, 1]), ~[2, 1, 0]);
+    assert_eq!(visual_order([0, 0, 1, 1, 2, 2]), ~[0, 1, 4, 5, 3, 2]);
+}
@@ -13,6 +13,7 @@ use std::u16;
 use std::vec;
 use std::util;
 use std::iter;
+use std::mem;
 use geom::point::Point2D;
 use extra::sort;
 
@@ -321,6 +322,11 @@ impl<'self> DetailedGlyphStore {
         }
     }
 
+    fn memory_usage(&self) -> uint {
+        self.detail_buffer.len() * mem::size_of::<DetailedGlyph>() +
+            self.detail_lookup.len() * mem::size_of::<DetailedGlyphRecord>()
+    }
+
     fn add_detailed_glyphs_for_entry(&mut self, entry_offset: uint, glyphs: &[DetailedGlyph]) {
         let entry = DetailedGlyphRecord {
             entry_offset: entry_offset,
@@ -540,6 +546,12 @@ impl<'self> GlyphStore {
         self.is_whitespace
     }
 
+    /// An estimate of the bytes the store takes up, buffers included.
+    pub fn memory_usage(&self) -> uint {
+        mem::size_of::<GlyphStore>() + self.entry_buffer.len() * mem::size_of::<GlyphEntry>() +
+            self.detail_store.memory_usage()
+    }
+
     pub fn finalize_changes(&mut self) {
         self.detail_store.ensure_sorted();
     }
@@ -14,6 +14,8 @@ pub use Shaper = text::shaping::harfbuzz::Shaper;
 pub mod harfbuzz;
 
 pub trait ShaperMethods {
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore);
+    /// Shapes the given text into the given glyph store, in logical order. Right-to-left text is
+    /// shaped right-to-left.
+    fn shape_text(&self, text: &str, rtl: bool, glyphs: &mut GlyphStore);
 }
 
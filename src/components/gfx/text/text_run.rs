@@ -5,20 +5,27 @@
 use std::vec::VecIterator;
 
 use servo_util::geometry::Au;
-use text::glyph::GlyphStore;
+use text::glyph::{GlyphData, GlyphStore};
 use font::{Font, FontDescriptor, RunMetrics, FontStyle, FontMetrics};
 use servo_util::range::Range;
 use extra::arc::Arc;
-use style::computed_values::text_decoration;
+use style::computed_values::{tab_size, text_decoration};
 
 /// A text run.
 #[deriving(Clone)]
 pub struct TextRun {
     text: Arc<~str>,
+    /// Whether the text is right-to-left. Its glyphs are in logical order all the same, and are
+    /// drawn from the right edge of the text.
+    rtl: bool,
     font_descriptor: FontDescriptor,
     font_metrics: FontMetrics,
     font_style: FontStyle,
     decoration: text_decoration::T,
+    /// Extra space after every character, from the `letter-spacing` property.
+    letter_spacing: Au,
+    /// Extra space after every whitespace character, from the `word-spacing` property.
+    word_spacing: Au,
     glyphs: Arc<~[Arc<GlyphStore>]>,
 }
 
@@ -97,15 +104,35 @@ impl<'self> Iterator<Range> for LineIterator<'self> {
 }
 
 impl<'self> TextRun {
-    pub fn new(font: &mut Font, text: ~str, decoration: text_decoration::T) -> TextRun {
-        let glyphs = TextRun::break_and_shape(font, text);
+    pub fn new(font: &mut Font,
+               text: ~str,
+               rtl: bool,
+               decoration: text_decoration::T,
+               letter_spacing: Au,
+               word_spacing: Au,
+               tab_size: tab_size::T)
+               -> TextRun {
+        let tab_width = match tab_size {
+            tab_size::Length(length) => length,
+            tab_size::Number(number) => {
+                let space_advance = match font.glyph_index(' ') {
+                    Some(glyph) => Au::from_frac_px(font.glyph_h_advance(glyph)),
+                    None => Au(0),
+                };
+                (space_advance + letter_spacing + word_spacing).scale_by(number)
+            }
+        };
+        let glyphs = TextRun::break_and_shape(font, text, rtl, tab_width);
 
         let run = TextRun {
             text: Arc::new(text),
+            rtl: rtl,
             font_style: font.style.clone(),
             font_metrics: font.metrics.clone(),
             font_descriptor: font.get_descriptor(),
             decoration: decoration,
+            letter_spacing: letter_spacing,
+            word_spacing: word_spacing,
             glyphs: Arc::new(glyphs),
         };
         return run;
@@ -114,13 +141,21 @@ impl<'self> TextRun {
     pub fn teardown(&self) {
     }
 
-    pub fn break_and_shape(font: &mut Font, text: &str) -> ~[Arc<GlyphStore>] {
+    /// Breaks the text into slices at line break opportunities and shapes them. Tabs and
+    /// newlines are slices of their own; tabs advance to the next multiple of `tab_width` from the
+    /// start of the run or the last newline.
+    ///
+    /// FIXME: Tab stops should be measured from the start of the line box.
+    pub fn break_and_shape(font: &mut Font, text: &str, rtl: bool, tab_width: Au)
+                           -> ~[Arc<GlyphStore>] {
         // TODO(Issue #230): do a better job. See Gecko's LineBreaker.
 
         let mut glyphs = ~[];
         let mut byte_i = 0u;
         let mut cur_slice_is_whitespace = false;
+        let mut cur_slice_is_separate = false;
         let mut byte_last_boundary = 0;
+        let mut line_advance = Au(0);
         while byte_i < text.len() {
             let range = text.char_range_at(byte_i);
             let ch = range.ch;
@@ -128,46 +163,89 @@ impl<'self> TextRun {
 
             // Slices alternate between whitespace and non-whitespace,
             // representing line break opportunities.
-            let can_break_before = if cur_slice_is_whitespace {
-                match ch {
-                    ' ' | '\t' | '\n' => false,
-                    _ => {
-                        cur_slice_is_whitespace = false;
-                        true
-                    }
-                }
-            } else {
-                match ch {
-                    ' ' | '\t' | '\n' => {
-                        cur_slice_is_whitespace = true;
-                        true
-                    },
-                    _ => false
-                }
+            let is_whitespace = match ch {
+                ' ' | '\t' | '\n' => true,
+                _ => false,
             };
+            let is_separate = ch == '\t' || ch == '\n';
+            let can_break_before = is_whitespace != cur_slice_is_whitespace || is_separate ||
+                cur_slice_is_separate;
 
             // Create a glyph store for this slice if it's nonempty.
             if can_break_before && byte_i > byte_last_boundary {
-                let slice = text.slice(byte_last_boundary, byte_i).to_owned();
+                let slice = text.slice(byte_last_boundary, byte_i);
                 debug!("creating glyph store for slice {} (ws? {}), {} - {} in run {}",
-                        slice, !cur_slice_is_whitespace, byte_last_boundary, byte_i, text);
-                glyphs.push(font.shape_text(slice, !cur_slice_is_whitespace));
+                        slice, cur_slice_is_whitespace, byte_last_boundary, byte_i, text);
+                glyphs.push(TextRun::shape_slice(font,
+                                                 slice,
+                                                 cur_slice_is_whitespace,
+                                                 rtl,
+                                                 tab_width,
+                                                 &mut line_advance));
                 byte_last_boundary = byte_i;
             }
 
+            cur_slice_is_whitespace = is_whitespace;
+            cur_slice_is_separate = is_separate;
             byte_i = next;
         }
 
         // Create a glyph store for the final slice if it's nonempty.
         if byte_i > byte_last_boundary {
-            let slice = text.slice_from(byte_last_boundary).to_owned();
+            let slice = text.slice_from(byte_last_boundary);
             debug!("creating glyph store for final slice {} (ws? {}), {} - {} in run {}",
                 slice, cur_slice_is_whitespace, byte_last_boundary, text.len(), text);
-            glyphs.push(font.shape_text(slice, cur_slice_is_whitespace));
+            glyphs.push(TextRun::shape_slice(font,
+                                             slice,
+                                             cur_slice_is_whitespace,
+                                             rtl,
+                                             tab_width,
+                                             &mut line_advance));
         }
 
         glyphs
     }
+
+    /// Shapes a slice of text, keeping track of the advance since the last newline for tab stops.
+    fn shape_slice(font: &mut Font,
+                   slice: &str,
+                   is_whitespace: bool,
+                   rtl: bool,
+                   tab_width: Au,
+                   line_advance: &mut Au)
+                   -> Arc<GlyphStore> {
+        let glyphs = match slice {
+            "\t" | "\n" => {
+                // Tabs are drawn as spaces wide enough to reach the next tab stop, and newlines as
+                // empty spaces.
+                let advance = if slice == "\t" && tab_width > Au(0) {
+                    tab_width - Au(**line_advance % *tab_width)
+                } else {
+                    Au(0)
+                };
+                let glyph = font.glyph_index(' ').unwrap_or(0);
+                let mut glyphs = GlyphStore::new(1, true);
+                glyphs.add_glyph_for_char_index(0, &GlyphData::new(glyph,
+                                                                   advance,
+                                                                   None,
+                                                                   false,
+                                                                   true,
+                                                                   true));
+                glyphs.finalize_changes();
+                Arc::new(glyphs)
+            }
+            _ => font.shape_text(slice.to_owned(), is_whitespace, rtl),
+        };
+        if slice == "\n" {
+            *line_advance = Au(0)
+        } else {
+            let char_range = Range::new(0, glyphs.get().char_len());
+            for (_i, glyph) in glyphs.get().iter_glyphs_for_char_range(&char_range) {
+                *line_advance = *line_advance + glyph.advance();
+            }
+        }
+        glyphs
+    }
     
     pub fn char_len(&self) -> uint {
         do self.glyphs.get().iter().fold(0u) |len, slice_glyphs| {
@@ -187,10 +265,9 @@ impl<'self> TextRun {
     }
 
     pub fn metrics_for_range(&self, range: &Range) -> RunMetrics {
-        // TODO(Issue #199): alter advance direction for RTL
-        // TODO(Issue #98): using inter-char and inter-word spacing settings  when measuring text
         let mut advance = Au(0);
         for (glyphs, _offset, slice_range) in self.iter_slices_for_range(range) {
+            advance = advance + self.spacing_for_slice(glyphs, &slice_range);
             for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
                 advance = advance + glyph.advance();
             }
@@ -199,12 +276,27 @@ impl<'self> TextRun {
     }
 
     pub fn metrics_for_slice(&self, glyphs: &GlyphStore, slice_range: &Range) -> RunMetrics {
-        let mut advance = Au(0);
+        let mut advance = self.spacing_for_slice(glyphs, slice_range);
         for (_i, glyph) in glyphs.iter_glyphs_for_char_range(slice_range) {
             advance = advance + glyph.advance();
         }
         RunMetrics::new(advance, self.font_metrics.ascent, self.font_metrics.descent)
     }
+
+    /// Returns the extra space added after each character of the given glyph store by the
+    /// `letter-spacing` and `word-spacing` properties.
+    pub fn spacing_for_char(&self, glyphs: &GlyphStore) -> Au {
+        if glyphs.is_whitespace() {
+            self.letter_spacing + self.word_spacing
+        } else {
+            self.letter_spacing
+        }
+    }
+
+    /// Returns the extra space added to the characters in the given range of a glyph store.
+    pub fn spacing_for_slice(&self, glyphs: &GlyphStore, slice_range: &Range) -> Au {
+        Au(*self.spacing_for_char(glyphs) * slice_range.length() as i32)
+    }
     pub fn min_width_for_range(&self, range: &Range) -> Au {
         let mut max_piece_width = Au(0);
         debug!("iterating outer range {:?}", range);
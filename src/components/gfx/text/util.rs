@@ -2,14 +2,70 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::char;
+use style::computed_values::text_transform;
+
 #[deriving(Eq)]
-enum CompressionMode {
+pub enum CompressionMode {
     CompressNone,
     CompressWhitespace,
     CompressWhitespaceNewline,
     DiscardNewline
 }
 
+/// Records, for each character of a transformed text, the index of the character of the original
+/// text it came from, so that offsets in text runs can be mapped back to offsets in the DOM.
+#[deriving(Clone)]
+pub struct CharIndexMap {
+    priv original_indices: ~[uint],
+    /// The length of the original text, in characters.
+    priv original_len: uint,
+}
+
+impl CharIndexMap {
+    pub fn new() -> CharIndexMap {
+        CharIndexMap {
+            original_indices: ~[],
+            original_len: 0,
+        }
+    }
+
+    /// Returns the number of characters in the transformed text.
+    pub fn len(&self) -> uint {
+        self.original_indices.len()
+    }
+
+    /// Returns the index in the original text of the given character of the transformed text.
+    /// The end of the transformed text maps to the end of the original text.
+    pub fn original_index(&self, index: uint) -> uint {
+        if index < self.original_indices.len() {
+            self.original_indices[index]
+        } else {
+            self.original_len
+        }
+    }
+
+    /// Records that the transformed text continues with a character taken from the given index
+    /// of the original text.
+    pub fn push(&mut self, original_index: uint) {
+        self.original_indices.push(original_index)
+    }
+
+    /// Returns the map of the given range of characters of the transformed text.
+    pub fn slice(&self, begin: uint, end: uint) -> CharIndexMap {
+        CharIndexMap {
+            original_indices: self.original_indices.slice(begin, end).to_owned(),
+            original_len: self.original_len,
+        }
+    }
+
+    /// Records that the given character of the transformed text was expanded into two.
+    pub fn expand(&mut self, index: uint) {
+        let original_index = self.original_indices[index];
+        self.original_indices.insert(index, original_index)
+    }
+}
+
 // ported from Gecko's nsTextFrameUtils::TransformText. 
 // 
 // High level TODOs:
@@ -17,21 +73,19 @@ enum CompressionMode {
 // * Issue #113: consider incoming text state (arabic, etc)
 //               and propogate outgoing text state (dual of above) 
 //
-// * Issue #114: record skipped and kept chars for mapping original to new text
-//
 // * Untracked: various edge cases for bidi, CJK, etc.
-pub fn transform_text(text: &str, mode: CompressionMode, incoming_whitespace: bool) -> (~str, bool) {
+pub fn transform_text(text: &str,
+                      mode: CompressionMode,
+                      incoming_whitespace: bool,
+                      char_map: &mut CharIndexMap)
+                      -> (~str, bool) {
     let mut out_str: ~str = ~"";
+    char_map.original_len = text.char_len();
     let out_whitespace = match mode {
         CompressNone | DiscardNewline => {
-            for ch in text.iter() {
-                if is_discardable_char(ch, mode) {
-                    // TODO: record skipped char
-                } else {
-                    // TODO: record kept char
-                    if ch == '\t' {
-                        // TODO: set "has tab" flag
-                    }
+            for (i, ch) in text.iter().enumerate() {
+                if !is_discardable_char(ch, mode) {
+                    char_map.push(i);
                     out_str.push_char(ch);
                 }
             }
@@ -40,7 +94,7 @@ pub fn transform_text(text: &str, mode: CompressionMode, incoming_whitespace: bo
 
         CompressWhitespace | CompressWhitespaceNewline => {
             let mut in_whitespace: bool = incoming_whitespace;
-            for ch in text.iter() {
+            for (i, ch) in text.iter().enumerate() {
                 // TODO: discard newlines between CJK chars
                 let mut next_in_whitespace: bool = is_in_whitespace(ch, mode);
                 
@@ -48,16 +102,13 @@ pub fn transform_text(text: &str, mode: CompressionMode, incoming_whitespace: bo
                     if is_always_discardable_char(ch) {
                         // revert whitespace setting, since this char was discarded
                         next_in_whitespace = in_whitespace;
-                        // TODO: record skipped char
                     } else {
-                        // TODO: record kept char
+                        char_map.push(i);
                         out_str.push_char(ch);
                     }
                 } else { /* next_in_whitespace; possibly add a space char */
-                    if in_whitespace {
-                        // TODO: record skipped char
-                    } else {
-                        // TODO: record kept char
+                    if !in_whitespace {
+                        char_map.push(i);
                         out_str.push_char(' ');
                     }
                 }
@@ -95,6 +146,131 @@ pub fn transform_text(text: &str, mode: CompressionMode, incoming_whitespace: bo
     }
 }
 
+/// Applies the `text-transform` property to the given text. `incoming_word_start` is true if the
+/// text follows whitespace, or starts a block, so that `capitalize` applies to its first letter.
+/// The given map of the text is updated for characters that expand.
+pub fn transform_case(text: &str,
+                      transform: text_transform::T,
+                      incoming_word_start: bool,
+                      char_map: &mut CharIndexMap)
+                      -> ~str {
+    match transform {
+        text_transform::none => text.to_owned(),
+        text_transform::uppercase => {
+            let mut out_str = ~"";
+            for ch in text.iter() {
+                push_uppercase(&mut out_str, ch, char_map)
+            }
+            out_str
+        }
+        text_transform::lowercase => {
+            let mut out_str = ~"";
+            let mut after_letter = false;
+            let mut chars = text.iter().peekable();
+            loop {
+                let ch = match chars.next() {
+                    Some(ch) => ch,
+                    None => break,
+                };
+                // Capital sigma becomes final sigma at the end of a word.
+                let at_word_end = match chars.peek() {
+                    Some(next) => !next.is_alphabetic(),
+                    None => true,
+                };
+                if ch == '\u03A3' && after_letter && at_word_end {
+                    out_str.push_char('\u03C2')
+                } else {
+                    out_str.push_char(to_lowercase(ch))
+                }
+                after_letter = ch.is_alphabetic();
+            }
+            out_str
+        }
+        text_transform::capitalize => {
+            let mut out_str = ~"";
+            let mut word_start = incoming_word_start;
+            for ch in text.iter() {
+                if ch.is_whitespace() {
+                    word_start = true;
+                    out_str.push_char(ch)
+                } else if word_start && ch.is_alphanumeric() {
+                    word_start = false;
+                    push_uppercase(&mut out_str, ch, char_map)
+                } else {
+                    // Punctuation before the first letter of a word, such as an opening quote,
+                    // leaves the word start pending.
+                    out_str.push_char(ch)
+                }
+            }
+            out_str
+        }
+    }
+
+    fn push_uppercase(out_str: &mut ~str, ch: char, char_map: &mut CharIndexMap) {
+        match ch {
+            '\u00DF' => {
+                char_map.expand(out_str.char_len());
+                out_str.push_str("SS")
+            }
+            _ => out_str.push_char(to_uppercase(ch)),
+        }
+    }
+}
+
+/// Returns the uppercase form of a character, for the Latin, Greek, Cyrillic and Armenian
+/// alphabets. Other characters are returned unchanged.
+pub fn to_uppercase(ch: char) -> char {
+    let c = ch as u32;
+    let upper = match c {
+        0x0061 .. 0x007A => c - 0x20,
+        0x00E0 .. 0x00FE if c != 0x00F7 => c - 0x20,
+        0x00FF => 0x0178,
+        0x0131 => 0x0049,
+        0x017F => 0x0053,
+        0x0101 .. 0x0137 | 0x014B .. 0x0177 if c % 2 == 1 => c - 1,
+        0x013A .. 0x0148 | 0x017A .. 0x017E if c % 2 == 0 => c - 1,
+        0x03AC => 0x0386,
+        0x03AD .. 0x03AF => c - 0x25,
+        0x03B1 .. 0x03C1 | 0x03C3 .. 0x03CB => c - 0x20,
+        0x03C2 => 0x03A3,
+        0x03CC => 0x038C,
+        0x03CD .. 0x03CE => c - 0x3F,
+        0x0430 .. 0x044F => c - 0x20,
+        0x0450 .. 0x045F => c - 0x50,
+        0x0461 .. 0x0481 | 0x048B .. 0x04BF if c % 2 == 1 => c - 1,
+        0x0561 .. 0x0586 => c - 0x30,
+        0xFF41 .. 0xFF5A => c - 0x20,
+        _ => c,
+    };
+    char::from_u32(upper).unwrap()
+}
+
+/// Returns the lowercase form of a character, for the Latin, Greek, Cyrillic and Armenian
+/// alphabets. Other characters are returned unchanged.
+pub fn to_lowercase(ch: char) -> char {
+    let c = ch as u32;
+    let lower = match c {
+        0x0041 .. 0x005A => c + 0x20,
+        0x00C0 .. 0x00DE if c != 0x00D7 => c + 0x20,
+        0x0178 => 0x00FF,
+        0x0130 => 0x0069,
+        0x0100 .. 0x0136 | 0x014A .. 0x0176 if c % 2 == 0 => c + 1,
+        0x0139 .. 0x0147 | 0x0179 .. 0x017D if c % 2 == 1 => c + 1,
+        0x0386 => 0x03AC,
+        0x0388 .. 0x038A => c + 0x25,
+        0x038C => 0x03CC,
+        0x038E .. 0x038F => c + 0x3F,
+        0x0391 .. 0x03A1 | 0x03A3 .. 0x03AB => c + 0x20,
+        0x0400 .. 0x040F => c + 0x50,
+        0x0410 .. 0x042F => c + 0x20,
+        0x0460 .. 0x0480 | 0x048A .. 0x04BE if c % 2 == 0 => c + 1,
+        0x0531 .. 0x0556 => c + 0x30,
+        0xFF21 .. 0xFF3A => c + 0x20,
+        _ => c,
+    };
+    char::from_u32(lower).unwrap()
+}
+
 pub fn float_to_fixed(before: int, f: f64) -> i32 {
     (1i32 << before) * (f as i32)
 }
@@ -139,7 +315,10 @@ fn test_transform_compress_none() {
     let mode = CompressNone;
 
     for i in range(0, test_strs.len()) {
-        let (trimmed_str, _out) = transform_text(test_strs[i], mode, true);
+        let (trimmed_str, _out) = transform_text(test_strs[i],
+                                                 mode,
+                                                 true,
+                                                 &mut CharIndexMap::new());
         assert_eq!(&trimmed_str, &test_strs[i])
     }
 }
@@ -167,7 +346,10 @@ fn test_transform_discard_newline() {
     let mode = DiscardNewline;
 
     for i in range(0, test_strs.len()) {
-        let (trimmed_str, _out) = transform_text(test_strs[i], mode, true);
+        let (trimmed_str, _out) = transform_text(test_strs[i],
+                                                 mode,
+                                                 true,
+                                                 &mut CharIndexMap::new());
         assert_eq!(&trimmed_str, &oracle_strs[i])
     }
 }
@@ -195,7 +377,10 @@ fn test_transform_compress_whitespace() {
     let mode = CompressWhitespace;
 
     for i in range(0, test_strs.len()) {
-        let (trimmed_str, _out) = transform_text(test_strs[i], mode, true);
+        let (trimmed_str, _out) = transform_text(test_strs[i],
+                                                 mode,
+                                                 true,
+                                                 &mut CharIndexMap::new());
         assert_eq!(&trimmed_str, &oracle_strs[i])
     }
 }
@@ -222,7 +407,10 @@ fn test_transform_compress_whitespace_newline() {
     let mode = CompressWhitespaceNewline;
 
     for i in range(0, test_strs.len()) {
-        let (trimmed_str, _out) = transform_text(test_strs[i], mode, true);
+        let (trimmed_str, _out) = transform_text(test_strs[i],
+                                                 mode,
+                                                 true,
+                                                 &mut CharIndexMap::new());
         assert_eq!(&trimmed_str, &oracle_strs[i])
     }
 }
@@ -252,7 +440,41 @@ fn test_transform_compress_whitespace_newline_no_incoming() {
     let mode = CompressWhitespaceNewline;
 
     for i in range(0, test_strs.len()) {
-        let (trimmed_str, _out) = transform_text(test_strs[i], mode, false);
+        let (trimmed_str, _out) = transform_text(test_strs[i],
+                                                 mode,
+                                                 false,
+                                                 &mut CharIndexMap::new());
         assert_eq!(&trimmed_str, &oracle_strs[i])
     }
 }
+
+#[test]
+fn test_transform_case() {
+    fn transform(text: &str, transform: text_transform::T, incoming_word_start: bool) -> ~str {
+        let mut char_map = CharIndexMap::new();
+        let (text, _) = transform_text(text, CompressNone, incoming_word_start, &mut char_map);
+        transform_case(text, transform, incoming_word_start, &mut char_map)
+    }
+    assert_eq!(transform("stra\u00DFe caf\u00E9", text_transform::uppercase, true),
+               ~"STRASSE CAF\u00C9");
+    assert_eq!(transform("\u039F\u0394\u039F\u03A3 \u0416", text_transform::lowercase, true),
+               ~"\u03BF\u03B4\u03BF\u03C2 \u0436");
+    assert_eq!(transform("hello \"big\" world", text_transform::capitalize, true),
+               ~"Hello \"Big\" World");
+    assert_eq!(transform("lo world", text_transform::capitalize, false), ~"lo World");
+}
+
+#[test]
+fn test_char_index_map() {
+    let mut char_map = CharIndexMap::new();
+    let (text, _) = transform_text("  a\u00DF  b ", CompressWhitespaceNewline, true, &mut char_map);
+    assert_eq!(&text, &~"a\u00DF b ");
+    let text = transform_case(text, text_transform::uppercase, true, &mut char_map);
+    assert_eq!(&text, &~"ASS B ");
+    let mut original_indices = ~[];
+    for i in range(0, char_map.len() + 1) {
+        original_indices.push(char_map.original_index(i));
+    }
+    assert_eq!(original_indices, ~[2, 3, 3, 4, 6, 7, 8]);
+    assert_eq!(char_map.slice(4, 6).original_index(0), 6);
+}
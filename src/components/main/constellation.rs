@@ -7,12 +7,14 @@ use compositing::{CompositorChan, SetIds, SetLayerClipRect};
 use extra::url::Url;
 use geom::rect::Rect;
 use geom::size::Size2D;
+use gfx::font_cache_task::FontCacheTask;
 use gfx::opts::Opts;
 use pipeline::{Pipeline, CompositionPipeline};
 use script::script_task::{ResizeMsg, ResizeInactiveMsg};
-use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, FailureMsg, FrameRectMsg};
-use servo_msg::constellation_msg::{IFrameSandboxState, IFrameUnsandboxed, InitLoadUrlMsg};
-use servo_msg::constellation_msg::{LoadIframeUrlMsg, LoadUrlMsg, Msg, NavigateMsg, NavigationType};
+use servo_msg::constellation_msg::{CSSErrorMsg, ConstellationChan, ExitMsg, FailureMsg};
+use servo_msg::constellation_msg::{FrameRectMsg, IFrameSandboxState, IFrameUnsandboxed};
+use servo_msg::constellation_msg::{InitLoadUrlMsg, LoadIframeUrlMsg, LoadUrlMsg, Msg, NavigateMsg};
+use servo_msg::constellation_msg::NavigationType;
 use servo_msg::constellation_msg::{PipelineId, RendererReadyMsg, ResizedWindowMsg, SubpageId};
 use servo_msg::constellation_msg;
 use servo_net::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
@@ -33,6 +35,7 @@ pub struct Constellation {
     compositor_chan: CompositorChan,
     resource_task: ResourceTask,
     image_cache_task: ImageCacheTask,
+    font_cache_task: FontCacheTask,
     pipelines: HashMap<PipelineId, @mut Pipeline>,
     navigation_context: NavigationContext,
     priv next_pipeline_id: PipelineId,
@@ -254,12 +257,14 @@ impl Constellation {
                  opts: &Opts,
                  resource_task: ResourceTask,
                  image_cache_task: ImageCacheTask,
+                 font_cache_task: FontCacheTask,
                  profiler_chan: ProfilerChan) {
         do spawn_with((constellation_port,
                        constellation_chan.clone(),
                        compositor_chan,
                        resource_task,
                        image_cache_task,
+                       font_cache_task,
                        profiler_chan,
                        opts.clone()))
             |(constellation_port,
@@ -267,6 +272,7 @@ impl Constellation {
               compositor_chan,
               resource_task,
               image_cache_task,
+              font_cache_task,
               profiler_chan,
               opts)| {
             let mut constellation = Constellation {
@@ -275,6 +281,7 @@ impl Constellation {
                 compositor_chan: compositor_chan,
                 resource_task: resource_task,
                 image_cache_task: image_cache_task,
+                font_cache_task: font_cache_task,
                 pipelines: HashMap::new(),
                 navigation_context: NavigationContext::new(),
                 next_pipeline_id: PipelineId(0),
@@ -365,6 +372,12 @@ impl Constellation {
                 debug!("constellation got window resize message");
                 self.handle_resized_window_msg(new_size);
             }
+            CSSErrorMsg(pipeline_id, error) => {
+                debug!("constellation got CSS error message from {:?}", pipeline_id);
+                if self.opts.css_errors {
+                    println(format!("CSS-ERROR | {:s}", error))
+                }
+            }
         }
         true
     }
@@ -374,6 +387,7 @@ impl Constellation {
             pipeline.exit();
         }
         self.image_cache_task.exit();
+        self.font_cache_task.exit();
         self.resource_task.send(resource_task::Exit);
 
         sender.send(());
@@ -387,6 +401,7 @@ impl Constellation {
                                              self.compositor_chan.clone(),
                                              self.image_cache_task.clone(),
                                              self.resource_task.clone(),
+                                             self.font_cache_task.clone(),
                                              self.profiler_chan.clone(),
                                              self.window_size,
                                              self.opts.clone());
@@ -409,6 +424,7 @@ impl Constellation {
                                              self.compositor_chan.clone(),
                                              self.image_cache_task.clone(),
                                              self.resource_task.clone(),
+                                             self.font_cache_task.clone(),
                                              self.profiler_chan.clone(),
                                              self.window_size,
                                              self.opts.clone());
@@ -534,6 +550,8 @@ impl Constellation {
                                   self.chan.clone(),
                                   self.compositor_chan.clone(),
                                   self.image_cache_task.clone(),
+                                  self.resource_task.clone(),
+                                  self.font_cache_task.clone(),
                                   self.profiler_chan.clone(),
                                   self.opts.clone(),
                                   source_pipeline)
@@ -546,6 +564,7 @@ impl Constellation {
                              self.compositor_chan.clone(),
                              self.image_cache_task.clone(),
                              self.resource_task.clone(),
+                             self.font_cache_task.clone(),
                              self.profiler_chan.clone(),
                              self.window_size,
                              self.opts.clone())
@@ -600,6 +619,7 @@ impl Constellation {
                                              self.compositor_chan.clone(),
                                              self.image_cache_task.clone(),
                                              self.resource_task.clone(),
+                                             self.font_cache_task.clone(),
                                              self.profiler_chan.clone(),
                                              self.window_size,
                                              self.opts.clone());
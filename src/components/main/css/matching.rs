@@ -5,30 +5,262 @@
 // High-level interface to CSS selector matching.
 
 use css::node_style::StyledNode;
+use layout::animation::Animations;
 use layout::incremental;
-use layout::util::LayoutDataAccess;
-use layout::wrapper::LayoutNode;
+use layout::incremental::RestyleDamage;
+use layout::util::{LayoutDataAccess, OpaqueNode};
+use layout::wrapper::{LayoutElement, LayoutNode};
 
 use extra::arc::{Arc, RWArc};
 use std::cast;
 use std::cell::Cell;
 use std::comm;
+use std::hashmap::HashMap;
 use std::libc::uintptr_t;
+use std::ptr;
 use std::rt;
 use std::task;
 use std::vec;
-use style::{TNode, Stylist, cascade};
-use style::{Before, After};
+use servo_util::bloom::BloomFilter;
+use servo_util::time;
+use servo_util::time::{ProfilerChan, SelectorFastRejectCounter, SelectorFullMatchCounter};
+use servo_util::time::{StyleSharingHitCounter, StyleSharingMissCounter};
+use style::{ComputedValues, PropertyDeclaration, SelectorMatchingStats, StyleSharingKey};
+use style::{TNode, Stylist, CascadeEnvironment, cascade, each_element_hash};
+use style::{Before, After, FirstLine, FirstLetter};
+
+/// The number of recently styled elements that later elements may share styles with.
+static STYLE_SHARING_CANDIDATE_CACHE_SIZE: uint = 40;
+
+/// A bloom filter of the IDs, classes and local names of the ancestors of an element. It is
+/// maintained incrementally as elements are visited in preorder.
+struct AncestorFilter<'self> {
+    filter: BloomFilter,
+    ancestors: ~[LayoutNode<'self>],
+}
+
+impl<'self> AncestorFilter<'self> {
+    fn new() -> AncestorFilter<'self> {
+        AncestorFilter {
+            filter: BloomFilter::new(),
+            ancestors: ~[],
+        }
+    }
+
+    /// Updates the filter so that it holds exactly the ancestors of the given node, which must
+    /// come after all previously visited nodes in preorder.
+    fn prepare_for(&mut self, node: &LayoutNode<'self>) {
+        let parent = node.parent_node();
+        while self.ancestors.len() > 0 && Some(*self.ancestors.last()) != parent {
+            let ancestor = self.ancestors.pop();
+            do ancestor.with_element |element| {
+                self.remove_element(element)
+            }
+        }
+
+        if self.ancestors.len() == 0 {
+            // Either this is the first node, or none of the nodes visited so far are ancestors
+            // of it. Walk up the tree.
+            let mut ancestors = ~[];
+            let mut current = parent;
+            loop {
+                match current {
+                    Some(ancestor) if ancestor.is_element() => {
+                        current = ancestor.parent_node();
+                        ancestors.push(ancestor)
+                    }
+                    _ => break,
+                }
+            }
+            for ancestor in ancestors.move_rev_iter() {
+                self.push(ancestor)
+            }
+        }
+    }
+
+    /// Adds the given node, which must be an element, to the filter.
+    fn push(&mut self, node: LayoutNode<'self>) {
+        do node.with_element |element| {
+            each_element_hash(element, |hash| self.filter.insert_hash(hash))
+        }
+        self.ancestors.push(node)
+    }
+
+    fn remove_element(&mut self, element: &LayoutElement<'self>) {
+        each_element_hash(element, |hash| self.filter.remove_hash(hash))
+    }
+}
+
+/// The declarations that apply to an element and to its pseudo-elements.
+#[deriving(Clone)]
+struct ApplicableDeclarations {
+    normal: ~[Arc<~[PropertyDeclaration]>],
+    before: ~[Arc<~[PropertyDeclaration]>],
+    after: ~[Arc<~[PropertyDeclaration]>],
+    first_line: ~[Arc<~[PropertyDeclaration]>],
+    first_letter: ~[Arc<~[PropertyDeclaration]>],
+}
+
+struct StyleSharingCandidate {
+    key: StyleSharingKey,
+    /// The style sharing source of the parent of the element.
+    parent_source: OpaqueNode,
+    source: OpaqueNode,
+    declarations: ApplicableDeclarations,
+}
+
+/// A cache of recently matched elements. Siblings, and cousins whose parents matched the same
+/// rules, often cannot be told apart by any selector; such elements reuse the selector matching
+/// results of the first of them. Each selector matching task has its own cache.
+struct StyleSharingCandidateCache {
+    candidates: ~[StyleSharingCandidate],
+    /// The style sharing sources of the elements this cache has seen.
+    sources: HashMap<OpaqueNode, OpaqueNode>,
+    hits: uint,
+    misses: uint,
+}
+
+impl StyleSharingCandidateCache {
+    fn new() -> StyleSharingCandidateCache {
+        StyleSharingCandidateCache {
+            candidates: ~[],
+            sources: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the style sharing source of the given parent node. Elements that another task
+    /// matched are their own sources, so that only their children can share with each other.
+    fn parent_source(&self, parent: Option<LayoutNode>) -> Option<OpaqueNode> {
+        match parent {
+            Some(ref parent) if parent.is_element() => {
+                let parent = OpaqueNode::from_layout_node(parent);
+                Some(*self.sources.find(&parent).unwrap_or(&parent))
+            }
+            _ => None,
+        }
+    }
+
+    fn find<'a>(&'a self, key: &StyleSharingKey, parent_source: OpaqueNode)
+                -> Option<&'a StyleSharingCandidate> {
+        // Elements whose parents have the same source have indistinguishable ancestors.
+        self.candidates.iter().find(|candidate| {
+            candidate.parent_source == parent_source && candidate.key == *key
+        })
+    }
+
+    fn insert(&mut self, candidate: StyleSharingCandidate) {
+        self.candidates.insert(0, candidate);
+        self.candidates.truncate(STYLE_SHARING_CANDIDATE_CACHE_SIZE)
+    }
+}
+
+/// The styles of an element and of its pseudo-elements.
+#[deriving(Clone)]
+struct SharedStyles {
+    style: Arc<ComputedValues>,
+    before_style: Option<Arc<ComputedValues>>,
+    after_style: Option<Arc<ComputedValues>>,
+    first_line_style: Option<Arc<ComputedValues>>,
+    first_letter_style: Option<Arc<ComputedValues>>,
+}
+
+struct CascadeSharingCandidate {
+    source: OpaqueNode,
+    parent_style: Arc<ComputedValues>,
+    styles: SharedStyles,
+}
+
+/// A cache of recently cascaded elements. Elements that share selector matching results and whose
+/// parents share a style reuse the styles of the first of them instead of cascading again.
+/// Candidates keep the styles without animations.
+pub struct CascadeSharingCache {
+    priv candidates: ~[CascadeSharingCandidate],
+}
+
+impl CascadeSharingCache {
+    fn new() -> CascadeSharingCache {
+        CascadeSharingCache {
+            candidates: ~[],
+        }
+    }
+
+    fn find(&self, source: OpaqueNode, parent_style: &Arc<ComputedValues>)
+            -> Option<SharedStyles> {
+        for candidate in self.candidates.iter() {
+            // Parent styles are compared by identity: equal pointers mean that the parents
+            // shared their styles.
+            if candidate.source == source && ptr::to_unsafe_ptr(candidate.parent_style.get()) ==
+                    ptr::to_unsafe_ptr(parent_style.get()) {
+                return Some(candidate.styles.clone())
+            }
+        }
+        None
+    }
+
+    fn insert(&mut self, candidate: CascadeSharingCandidate) {
+        self.candidates.insert(0, candidate);
+        self.candidates.truncate(STYLE_SHARING_CANDIDATE_CACHE_SIZE)
+    }
+}
 
 pub trait MatchMethods {
-    fn match_node(&self, stylist: &Stylist);
-    fn match_subtree(&self, stylist: RWArc<Stylist>);
+    /// Performs selector matching on this node. Returns whether any of the rules looked at
+    /// depends on siblings, in which case other elements cannot share the results.
+    fn match_node(&self,
+                  stylist: &Stylist,
+                  bloom_filter: &BloomFilter,
+                  stats: &mut SelectorMatchingStats)
+                  -> bool;
+
+    /// Performs selector matching on this node and its descendants in parallel, sharing the
+    /// results between elements where possible.
+    fn match_subtree(&self, stylist: RWArc<Stylist>, profiler_chan: ProfilerChan);
+
+    /// Performs selector matching and the cascade on this node and its descendants, then applies
+    /// the running transitions and animations.
+    fn recalc_style_for_subtree(&self,
+                                stylist: RWArc<Stylist>,
+                                animations: &mut Animations,
+                                environment: &mut CascadeEnvironment,
+                                profiler_chan: ProfilerChan);
+
+    /// Cascades the styles of this node and its descendants, whose selectors have been matched.
+    fn cascade_subtree(&self,
+                       stylist: &Stylist,
+                       cascade_sharing_cache: &mut CascadeSharingCache,
+                       animations: &mut Animations,
+                       environment: &mut CascadeEnvironment,
+                       parent: Option<LayoutNode>);
+
+    fn cascade_node(&self, parent: Option<LayoutNode>, environment: &mut CascadeEnvironment);
 
-    fn cascade_subtree(&self, parent: Option<LayoutNode>);
+    /// Cascades again the styles of this node and its descendants that depend on the size of
+    /// the viewport, after a resize. Selector matching results are reused, and running
+    /// transitions and animations are applied again at the time of the last frame.
+    fn recascade_viewport_dependent_styles(&self,
+                                           stylist: &Stylist,
+                                           animations: &mut Animations,
+                                           environment: &mut CascadeEnvironment,
+                                           parent: Option<LayoutNode>);
+
+    /// Applies the running transitions and animations at a new frame. Only the animated nodes
+    /// and their descendants, which inherit from them, are cascaded again.
+    fn animate_subtree(&self,
+                       stylist: &Stylist,
+                       animations: &mut Animations,
+                       environment: &mut CascadeEnvironment,
+                       parent: Option<LayoutNode>,
+                       parent_restyled: bool);
 }
 
 impl<'self> MatchMethods for LayoutNode<'self> {
-    fn match_node(&self, stylist: &Stylist) {
+    fn match_node(&self,
+                  stylist: &Stylist,
+                  bloom_filter: &BloomFilter,
+                  stats: &mut SelectorMatchingStats)
+                  -> bool {
         let style_attribute = do self.with_element |element| {
             match *element.style_attribute() {
                 None => None,
@@ -36,30 +268,53 @@ impl<'self> MatchMethods for LayoutNode<'self> {
             }
         };
 
+        let mut affected_by_siblings = false;
         match *self.mutate_layout_data().ptr {
             Some(ref mut layout_data) => {
                 layout_data.applicable_declarations = stylist.get_applicable_declarations(
-                    self, style_attribute, None);
+                    self, style_attribute, None, bloom_filter, stats, &mut affected_by_siblings);
                 layout_data.before_applicable_declarations = stylist.get_applicable_declarations(
-                    self, None, Some(Before));
+                    self, None, Some(Before), bloom_filter, stats, &mut affected_by_siblings);
                 layout_data.after_applicable_declarations = stylist.get_applicable_declarations(
-                    self, None, Some(After));
+                    self, None, Some(After), bloom_filter, stats, &mut affected_by_siblings);
+                layout_data.first_line_applicable_declarations =
+                    stylist.get_applicable_declarations(self,
+                                                        None,
+                                                        Some(FirstLine),
+                                                        bloom_filter,
+                                                        stats,
+                                                        &mut affected_by_siblings);
+                layout_data.first_letter_applicable_declarations =
+                    stylist.get_applicable_declarations(self,
+                                                        None,
+                                                        Some(FirstLetter),
+                                                        bloom_filter,
+                                                        stats,
+                                                        &mut affected_by_siblings);
             }
             None => fail!("no layout data")
         }
+        affected_by_siblings
     }
-    fn match_subtree(&self, stylist: RWArc<Stylist>) {
-        let num_tasks = rt::default_sched_threads() * 2;
-        let mut node_count = 0;
-        let mut nodes_per_task = vec::from_elem(num_tasks, ~[]);
 
+    fn match_subtree(&self, stylist: RWArc<Stylist>, profiler_chan: ProfilerChan) {
+        let num_tasks = rt::default_sched_threads() * 2;
+        let mut elements = ~[];
         for node in self.traverse_preorder() {
             if node.is_element() {
-                nodes_per_task[node_count % num_tasks].push(node);
-                node_count += 1;
+                elements.push(node);
             }
         }
 
+        // Give each task a contiguous run of elements in preorder, so that consecutive elements
+        // mostly share ancestors and the ancestor filter changes little between them. Siblings
+        // then mostly end up in the same task as well, where they can share matching results.
+        let elements_per_task = (elements.len() + num_tasks - 1) / num_tasks;
+        let mut nodes_per_task = vec::from_elem(num_tasks, ~[]);
+        for (i, node) in elements.move_iter().enumerate() {
+            nodes_per_task[i / elements_per_task].push(node);
+        }
+
         let (port, chan) = comm::stream();
         let chan = comm::SharedChan::new(chan);
         let mut num_spawned = 0;
@@ -83,70 +338,366 @@ impl<'self> MatchMethods for LayoutNode<'self> {
                     };
 
                     let nodes = Cell::new(nodes);
-                    do stylist.read |stylist| {
+                    let result = do stylist.read |stylist| {
                         let nodes = nodes.take();
+                        let mut ancestor_filter = AncestorFilter::new();
+                        let mut style_sharing_cache = StyleSharingCandidateCache::new();
+                        let mut stats = SelectorMatchingStats::new();
                         for node in nodes.iter() {
-                            node.match_node(stylist);
+                            ancestor_filter.prepare_for(node);
+                            match_node_with_sharing(node,
+                                                    stylist,
+                                                    &ancestor_filter.filter,
+                                                    &mut style_sharing_cache,
+                                                    &mut stats);
+                            ancestor_filter.push(*node);
                         }
-                    }
-                    chan.send(());
+                        (stats, style_sharing_cache.hits, style_sharing_cache.misses)
+                    };
+                    chan.send(result);
                 }
                 num_spawned += 1;
             }
         }
+        let mut stats = SelectorMatchingStats::new();
+        let (mut hits, mut misses) = (0, 0);
         for _ in range(0, num_spawned) {
-            port.recv();
+            let (task_stats, task_hits, task_misses) = port.recv();
+            stats.fast_rejected_rules += task_stats.fast_rejected_rules;
+            stats.fully_matched_rules += task_stats.fully_matched_rules;
+            hits += task_hits;
+            misses += task_misses;
         }
+        time::count(SelectorFastRejectCounter, stats.fast_rejected_rules, profiler_chan.clone());
+        time::count(SelectorFullMatchCounter, stats.fully_matched_rules, profiler_chan.clone());
+        time::count(StyleSharingHitCounter, hits, profiler_chan.clone());
+        time::count(StyleSharingMissCounter, misses, profiler_chan);
     }
 
-    fn cascade_subtree(&self, parent: Option<LayoutNode>) {
-        let layout_data = unsafe {
-            self.borrow_layout_data_unchecked().as_ref().unwrap()
+    fn recalc_style_for_subtree(&self,
+                                stylist: RWArc<Stylist>,
+                                animations: &mut Animations,
+                                environment: &mut CascadeEnvironment,
+                                profiler_chan: ProfilerChan) {
+        self.match_subtree(stylist.clone(), profiler_chan);
+
+        let mut cascade_sharing_cache = CascadeSharingCache::new();
+        animations.begin_frame();
+        do stylist.read |stylist| {
+            self.cascade_subtree(stylist,
+                                 &mut cascade_sharing_cache,
+                                 animations,
+                                 environment,
+                                 None)
+        }
+        animations.end_frame();
+    }
+
+    fn cascade_subtree(&self,
+                       stylist: &Stylist,
+                       cascade_sharing_cache: &mut CascadeSharingCache,
+                       animations: &mut Animations,
+                       environment: &mut CascadeEnvironment,
+                       parent: Option<LayoutNode>) {
+        let source = unsafe {
+            self.borrow_layout_data_unchecked().as_ref().unwrap().style_sharing_source
+        };
+        let parent_style = match parent {
+            Some(ref parent) => Some(parent.style().clone()),
+            None => None,
         };
-        macro_rules! cascade_node(
-            ($applicable_declarations: ident, $style: ident) => {{
-                let parent_style = match parent {
-                    Some(ref parent) => Some(parent.style()),
-                    None => None
-                };
 
-                let computed_values = Arc::new(cascade(
-                    layout_data.$applicable_declarations,
-                    parent_style.map(|parent_style| parent_style.get())));
-
-                match *self.mutate_layout_data().ptr {
-                    None => fail!("no layout data"),
-                    Some(ref mut layout_data) => {
-                        let style = &mut layout_data.$style;
-                        match *style {
-                            None => (),
-                            Some(ref previous_style) => {
-                                layout_data.restyle_damage = Some(incremental::compute_damage(
-                                    previous_style.get(), computed_values.get()).to_int())
-                            }
-                        }
-                        *style = Some(computed_values)
+        let shared_styles = match (source, &parent_style) {
+            (Some(source), &Some(ref parent_style)) => {
+                cascade_sharing_cache.find(source, parent_style)
+            }
+            _ => None,
+        };
+        match shared_styles {
+            Some(styles) => set_styles(self, styles),
+            None => {
+                self.cascade_node(parent, environment);
+
+                match (source, parent_style) {
+                    (Some(source), Some(parent_style)) => {
+                        cascade_sharing_cache.insert(CascadeSharingCandidate {
+                            source: source,
+                            parent_style: parent_style,
+                            styles: get_styles(self),
+                        })
                     }
+                    _ => {}
                 }
-            }}
-        );
+            }
+        }
 
-        unsafe {
-            if self.borrow_layout_data_unchecked().as_ref().unwrap().before_applicable_declarations.len() > 0 {
-                cascade_node!(before_applicable_declarations, before_style);
+        // Sharing candidates keep the styles without animations, so this is done last.
+        if self.is_element() {
+            animate_style(self, stylist, animations, environment, parent);
+        }
+        if parent.is_none() {
+            environment.root_font_size = self.style().get().Font.font_size
+        }
+
+        for kid in self.children() {
+            if kid.is_element() {
+                kid.cascade_subtree(stylist,
+                                    cascade_sharing_cache,
+                                    animations,
+                                    environment,
+                                    Some(*self));
             }
         }
-        cascade_node!(applicable_declarations, style);
-        unsafe {
-            if self.borrow_layout_data_unchecked().as_ref().unwrap().after_applicable_declarations.len() > 0 {
-                cascade_node!(after_applicable_declarations, after_style);
+    }
+
+    fn cascade_node(&self, parent: Option<LayoutNode>, environment: &mut CascadeEnvironment) {
+        let layout_data = unsafe {
+            self.borrow_layout_data_unchecked().as_ref().unwrap()
+        };
+        let parent_style = match parent {
+            Some(ref parent) => Some(parent.style().get()),
+            None => None,
+        };
+        let style = cascade(layout_data.applicable_declarations, parent_style, environment);
+
+        let styles = SharedStyles {
+            before_style: cascade_pseudo_element(layout_data.before_applicable_declarations,
+                                                 &style,
+                                                 environment),
+            after_style: cascade_pseudo_element(layout_data.after_applicable_declarations,
+                                                &style,
+                                                environment),
+            first_line_style: cascade_pseudo_element(
+                layout_data.first_line_applicable_declarations,
+                &style,
+                environment),
+            first_letter_style: cascade_pseudo_element(
+                layout_data.first_letter_applicable_declarations,
+                &style,
+                environment),
+            style: Arc::new(style),
+        };
+        set_styles(self, styles)
+    }
+
+    fn recascade_viewport_dependent_styles(&self,
+                                           stylist: &Stylist,
+                                           animations: &mut Animations,
+                                           environment: &mut CascadeEnvironment,
+                                           parent: Option<LayoutNode>) {
+        // The descendants of a dependent style inherit the dependency, so that they are
+        // cascaded again as well.
+        if self.style().get().depends_on_viewport {
+            self.cascade_node(parent, environment);
+            animate_style(self, stylist, animations, environment, parent);
+            if parent.is_none() {
+                environment.root_font_size = self.style().get().Font.font_size
+            }
+        }
+        for kid in self.children() {
+            if kid.is_element() {
+                kid.recascade_viewport_dependent_styles(stylist,
+                                                        animations,
+                                                        environment,
+                                                        Some(*self))
             }
         }
+    }
 
+    fn animate_subtree(&self,
+                       stylist: &Stylist,
+                       animations: &mut Animations,
+                       environment: &mut CascadeEnvironment,
+                       parent: Option<LayoutNode>,
+                       parent_restyled: bool) {
+        let restyled = parent_restyled ||
+            animations.is_animating(&OpaqueNode::from_layout_node(self));
+        if restyled {
+            self.cascade_node(parent, environment);
+            animate_style(self, stylist, animations, environment, parent);
+        }
+        if parent.is_none() {
+            environment.root_font_size = self.style().get().Font.font_size
+        }
         for kid in self.children() {
             if kid.is_element() {
-                kid.cascade_subtree(Some(*self));
+                kid.animate_subtree(stylist, animations, environment, Some(*self), restyled)
+            }
+        }
+    }
+}
+
+/// Performs selector matching on the given element, or reuses the results of an element in the
+/// cache that no selector can tell apart from it.
+fn match_node_with_sharing(node: &LayoutNode,
+                           stylist: &Stylist,
+                           bloom_filter: &BloomFilter,
+                           style_sharing_cache: &mut StyleSharingCandidateCache,
+                           stats: &mut SelectorMatchingStats) {
+    let opaque_node = OpaqueNode::from_layout_node(node);
+    let key = do node.with_element |element| {
+        stylist.style_sharing_key(element)
+    };
+    let shareable = key.is_some();
+    let parent_source = style_sharing_cache.parent_source(node.parent_node());
+
+    let shared = match (&key, parent_source) {
+        (&Some(ref key), Some(parent_source)) => {
+            match style_sharing_cache.find(key, parent_source) {
+                Some(candidate) => Some((candidate.source, candidate.declarations.clone())),
+                None => None,
+            }
+        }
+        _ => None,
+    };
+    let source = match shared {
+        Some((source, declarations)) => {
+            style_sharing_cache.hits += 1;
+            set_applicable_declarations(node, declarations);
+            source
+        }
+        None => {
+            style_sharing_cache.misses += 1;
+            // Elements with the same key look at the same rules, so the results can be shared
+            // unless one of those rules depends on siblings.
+            let affected_by_siblings = node.match_node(stylist, bloom_filter, stats);
+            match (key, parent_source) {
+                (Some(key), Some(parent_source)) => {
+                    if !affected_by_siblings {
+                        style_sharing_cache.insert(StyleSharingCandidate {
+                            key: key,
+                            parent_source: parent_source,
+                            source: opaque_node,
+                            declarations: get_applicable_declarations(node),
+                        })
+                    }
+                }
+                _ => {}
+            }
+            opaque_node
+        }
+    };
+
+    style_sharing_cache.sources.insert(opaque_node, source);
+    match *node.mutate_layout_data().ptr {
+        None => fail!("no layout data"),
+        Some(ref mut layout_data) => {
+            layout_data.style_sharing_source = if shareable { Some(source) } else { None }
+        }
+    }
+}
+
+/// Pseudo-elements inherit from the element itself. Those that no rule applies to have no style
+/// at all.
+fn cascade_pseudo_element(applicable_declarations: &[Arc<~[PropertyDeclaration]>],
+                          element_style: &ComputedValues,
+                          environment: &mut CascadeEnvironment)
+                          -> Option<Arc<ComputedValues>> {
+    if applicable_declarations.len() > 0 {
+        Some(Arc::new(cascade(applicable_declarations, Some(element_style), environment)))
+    } else {
+        None
+    }
+}
+
+fn get_styles(node: &LayoutNode) -> SharedStyles {
+    let layout_data = unsafe {
+        node.borrow_layout_data_unchecked().as_ref().unwrap()
+    };
+    SharedStyles {
+        style: layout_data.style.get_ref().clone(),
+        before_style: layout_data.before_style.clone(),
+        after_style: layout_data.after_style.clone(),
+        first_line_style: layout_data.first_line_style.clone(),
+        first_letter_style: layout_data.first_letter_style.clone(),
+    }
+}
+
+fn get_applicable_declarations(node: &LayoutNode) -> ApplicableDeclarations {
+    let layout_data = unsafe {
+        node.borrow_layout_data_unchecked().as_ref().unwrap()
+    };
+    ApplicableDeclarations {
+        normal: layout_data.applicable_declarations.clone(),
+        before: layout_data.before_applicable_declarations.clone(),
+        after: layout_data.after_applicable_declarations.clone(),
+        first_line: layout_data.first_line_applicable_declarations.clone(),
+        first_letter: layout_data.first_letter_applicable_declarations.clone(),
+    }
+}
+
+fn set_applicable_declarations(node: &LayoutNode, declarations: ApplicableDeclarations) {
+    match *node.mutate_layout_data().ptr {
+        None => fail!("no layout data"),
+        Some(ref mut layout_data) => {
+            layout_data.applicable_declarations = declarations.normal;
+            layout_data.before_applicable_declarations = declarations.before;
+            layout_data.after_applicable_declarations = declarations.after;
+            layout_data.first_line_applicable_declarations = declarations.first_line;
+            layout_data.first_letter_applicable_declarations = declarations.first_letter;
+        }
+    }
+}
+
+/// Replaces the style of the node with its animated style if any transition or animation runs on
+/// it. Its children then inherit the animated values.
+fn animate_style(node: &LayoutNode,
+                 stylist: &Stylist,
+                 animations: &mut Animations,
+                 environment: &mut CascadeEnvironment,
+                 parent: Option<LayoutNode>) {
+    let parent_style = match parent {
+        Some(ref parent) => Some(parent.style().clone()),
+        None => None,
+    };
+    match *node.mutate_layout_data().ptr {
+        None => fail!("no layout data"),
+        Some(ref mut layout_data) => {
+            let base_style = layout_data.style.get_ref().clone();
+            let animated_style = animations.animate(OpaqueNode::from_layout_node(node),
+                                                    &base_style,
+                                                    layout_data.applicable_declarations,
+                                                    parent_style.as_ref().map(|s| s.get()),
+                                                    stylist,
+                                                    environment);
+            match animated_style {
+                None => (),
+                Some(animated_style) => {
+                    // The damage from the previous style to the base style, plus the damage from
+                    // the base style to the animated one, covers every change.
+                    match layout_data.restyle_damage {
+                        None => (),
+                        Some(damage) => {
+                            let damage = RestyleDamage::from_int(damage).union(
+                                incremental::compute_damage(base_style.get(), &animated_style));
+                            layout_data.restyle_damage = Some(damage.to_int())
+                        }
+                    }
+                    layout_data.style = Some(Arc::new(animated_style));
+                }
+            }
+        }
+    }
+}
+
+/// Stores the given styles in the layout data of the node, computing the restyle damage if the
+/// node had a style before.
+fn set_styles(node: &LayoutNode, styles: SharedStyles) {
+    match *node.mutate_layout_data().ptr {
+        None => fail!("no layout data"),
+        Some(ref mut layout_data) => {
+            match layout_data.style {
+                None => (),
+                Some(ref previous_style) => {
+                    layout_data.restyle_damage = Some(incremental::compute_damage(
+                        previous_style.get(), styles.style.get()).to_int())
+                }
             }
+            layout_data.style = Some(styles.style);
+            layout_data.before_style = styles.before_style;
+            layout_data.after_style = styles.after_style;
+            layout_data.first_line_style = styles.first_line_style;
+            layout_data.first_letter_style = styles.first_letter_style;
         }
     }
 }
@@ -0,0 +1,453 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CSS transitions and keyframe animations.
+//!
+//! The layout task keeps the running transitions and animations of every element across reflows.
+//! During selector matching, the cascaded ("base") style of each element is compared to the one of
+//! the previous reflow to start transitions, and is then replaced by its animated style. While
+//! anything is running, the layout task asks script to reflow again at every frame.
+
+use layout::util::OpaqueNode;
+
+use extra::arc::Arc;
+use extra::sort::merge_sort;
+use extra::time::precise_time_s;
+use std::hashmap::HashMap;
+use style::computed_values::{animation_direction, animation_fill_mode, animation_iteration_count};
+use style::computed_values::animation_play_state;
+use style::{CascadeEnvironment, ComputedValues, PropertyDeclaration, Stylist, TimingFunction};
+use style::cascade;
+use style::{changed_animatable_longhands, interpolate_longhand};
+
+/// An event to fire on an element once a transition or an animation is done.
+pub enum AnimationEvent {
+    /// The transitioned longhand and the duration of the transition, in seconds.
+    TransitionEnded(OpaqueNode, &'static str, f64),
+    /// The name of the animation and its active duration, in seconds.
+    AnimationEnded(OpaqueNode, ~str, f64),
+}
+
+/// A transition of one longhand of an element.
+struct PropertyTransition {
+    property: &'static str,
+    /// The style holding the value at the start of the transition.
+    from: Arc<ComputedValues>,
+    /// The style holding the value at the end of the transition.
+    to: Arc<ComputedValues>,
+    /// In seconds, like the following fields.
+    start_time: f64,
+    delay: f64,
+    duration: f64,
+    timing_function: TimingFunction,
+}
+
+/// The computed style of an element at a keyframe.
+#[deriving(Clone)]
+struct ComputedKeyframe {
+    offset: f64,
+    style: Arc<ComputedValues>,
+    /// The `animation-timing-function` set in the keyframe, which applies until the next one.
+    timing_function: Option<TimingFunction>,
+}
+
+/// A running `@keyframes` animation of an element. Its duration, delay and the like are read from
+/// the style of the element at every frame, so that changing them affects running animations.
+struct KeyframeAnimation {
+    name: ~str,
+    /// Sorted by offset. The first keyframe is at 0 and the last one at 1.
+    keyframes: ~[ComputedKeyframe],
+    start_time: f64,
+    /// The time at which the animation was paused, if it is paused.
+    paused_time: Option<f64>,
+    finished: bool,
+}
+
+/// The transitions and animations of an element.
+struct NodeAnimations {
+    /// The cascaded style of the element at the last reflow, without any animation.
+    base_style: Arc<ComputedValues>,
+    /// The style of the element at the last reflow, if anything was animating it.
+    animated_style: Option<Arc<ComputedValues>>,
+    transitions: ~[PropertyTransition],
+    animations: ~[KeyframeAnimation],
+    /// The last frame in which the element was styled.
+    frame: uint,
+}
+
+impl NodeAnimations {
+    fn is_running(&self) -> bool {
+        !self.transitions.is_empty() || self.animations.iter().any(|animation| {
+            !animation.finished && animation.paused_time.is_none()
+        })
+    }
+}
+
+/// The transitions and animations of every element, owned by the layout task.
+pub struct Animations {
+    priv nodes: HashMap<OpaqueNode, NodeAnimations>,
+    /// The time of the current frame, in seconds.
+    priv now: f64,
+    /// Incremented at each frame, to find the elements that are gone.
+    priv frame: uint,
+    priv events: ~[AnimationEvent],
+}
+
+impl Animations {
+    pub fn new() -> Animations {
+        Animations {
+            nodes: HashMap::new(),
+            now: 0.,
+            frame: 0,
+            events: ~[],
+        }
+    }
+
+    /// Starts a frame. Every element must then be passed to `animate`.
+    pub fn begin_frame(&mut self) {
+        self.now = precise_time_s();
+        self.frame += 1;
+    }
+
+    /// Starts a frame in which nothing but the running transitions and animations changed. Only
+    /// the elements for which `is_animating` is true, and their descendants, must then be passed
+    /// to `animate`, and the frame is not ended.
+    pub fn begin_animation_frame(&mut self) {
+        self.now = precise_time_s();
+    }
+
+    /// Ends a frame, forgetting the elements that were not styled during it.
+    pub fn end_frame(&mut self) {
+        let mut gone = ~[];
+        for (node, node_animations) in self.nodes.iter() {
+            if node_animations.frame != self.frame {
+                gone.push(node.clone())
+            }
+        }
+        for node in gone.iter() {
+            self.nodes.remove(node);
+        }
+    }
+
+    /// Returns true if any transition or animation needs more frames.
+    pub fn is_running(&self) -> bool {
+        self.nodes.iter().any(|(_, node_animations)| node_animations.is_running())
+    }
+
+    /// Returns true if a transition or an animation of the given element needs more frames.
+    pub fn is_animating(&self, node: &OpaqueNode) -> bool {
+        match self.nodes.find(node) {
+            Some(node_animations) => node_animations.is_running(),
+            None => false,
+        }
+    }
+
+    /// Returns the events of the transitions and animations that ended since the last call.
+    pub fn take_events(&mut self) -> ~[AnimationEvent] {
+        ::std::util::replace(&mut self.events, ~[])
+    }
+
+    /// Starts, updates and applies the transitions and animations of the given element, whose
+    /// cascaded style is `base_style`. Returns its animated style, or `None` if nothing animates
+    /// it.
+    pub fn animate(&mut self,
+                   node: OpaqueNode,
+                   base_style: &Arc<ComputedValues>,
+                   applicable_declarations: &[Arc<~[PropertyDeclaration]>],
+                   parent_style: Option<&ComputedValues>,
+                   stylist: &Stylist,
+                   environment: &mut CascadeEnvironment)
+                   -> Option<ComputedValues> {
+        let now = self.now;
+        let frame = self.frame;
+        let events = &mut self.events;
+        let node_animations = self.nodes.find_or_insert_with(node.clone(), |_| {
+            NodeAnimations {
+                base_style: base_style.clone(),
+                animated_style: None,
+                transitions: ~[],
+                animations: ~[],
+                frame: frame,
+            }
+        });
+        node_animations.frame = frame;
+
+        if node_animations.base_style.get() != base_style.get() {
+            start_transitions(node_animations, base_style, now);
+            node_animations.base_style = base_style.clone();
+        }
+        update_keyframe_animations(node_animations, base_style, applicable_declarations,
+                                   parent_style, stylist, environment, now);
+
+        if node_animations.transitions.is_empty() && node_animations.animations.is_empty() {
+            node_animations.animated_style = None;
+            return None
+        }
+
+        let base = base_style.get();
+        let mut style = base.clone();
+        apply_keyframe_animations(node_animations, base, &mut style, &node, events, now);
+        apply_transitions(node_animations, &mut style, &node, events, now);
+        node_animations.animated_style = Some(Arc::new(style.clone()));
+        Some(style)
+    }
+}
+
+/// Returns the item of a list-valued property that applies to the item at `index` of another
+/// list-valued property. Shorter lists repeat.
+fn cyclic<'a, T>(list: &'a [T], index: uint) -> &'a T {
+    &list[index % list.len()]
+}
+
+/// Starts a transition for each animatable longhand that changed in the new base style and that
+/// `transition-property` lists with a positive duration.
+fn start_transitions(node_animations: &mut NodeAnimations,
+                     new_style: &Arc<ComputedValues>,
+                     now: f64) {
+    let old_style = node_animations.base_style.clone();
+    let box_style = &new_style.get().Box;
+    for &property in changed_animatable_longhands(old_style.get(), new_style.get()).iter() {
+        // The value being changed stops any transition of it.
+        node_animations.transitions.retain(|transition| transition.property != property);
+
+        let names = &box_style.transition_property;
+        let index = match names.iter().rposition(|name| {
+            name.as_slice() == "all" || name.as_slice() == property
+        }) {
+            Some(index) => index,
+            None => continue,
+        };
+        let duration = *cyclic(box_style.transition_duration, index);
+        if duration <= 0. {
+            continue
+        }
+        // A transition that interrupts another one starts from the current value.
+        let from = match node_animations.animated_style {
+            Some(ref animated_style) => animated_style.clone(),
+            None => old_style.clone(),
+        };
+        node_animations.transitions.push(PropertyTransition {
+            property: property,
+            from: from,
+            to: new_style.clone(),
+            start_time: now,
+            delay: *cyclic(box_style.transition_delay, index),
+            duration: duration,
+            timing_function: *cyclic(box_style.transition_timing_function, index),
+        })
+    }
+}
+
+/// Starts the animations that `animation-name` now lists and stops the ones it no longer lists.
+fn update_keyframe_animations(node_animations: &mut NodeAnimations,
+                              base_style: &Arc<ComputedValues>,
+                              applicable_declarations: &[Arc<~[PropertyDeclaration]>],
+                              parent_style: Option<&ComputedValues>,
+                              stylist: &Stylist,
+                              environment: &mut CascadeEnvironment,
+                              now: f64) {
+    let box_style = &base_style.get().Box;
+    let names = &box_style.animation_name;
+    node_animations.animations.retain(|animation| {
+        names.iter().any(|name| *name == animation.name)
+    });
+    for (index, name) in names.iter().enumerate() {
+        if name.as_slice() == "none" ||
+                node_animations.animations.iter().any(|animation| animation.name == *name) {
+            continue
+        }
+        let rule = match stylist.keyframes(name.as_slice()) {
+            Some(rule) => rule,
+            None => continue,
+        };
+
+        // Each keyframe is cascaded on top of the declarations of the element.
+        let mut keyframes = ~[];
+        for keyframe in rule.keyframes.iter() {
+            let mut declarations = applicable_declarations.to_owned();
+            declarations.push(keyframe.declarations.normal.clone());
+            let style = cascade(declarations, parent_style, environment);
+            let timing_function = if keyframe.declarations.normal.get().iter().any(
+                    |declaration| declaration.name() == "animation-timing-function") {
+                Some(style.Box.animation_timing_function[0])
+            } else {
+                None
+            };
+            let style = Arc::new(style);
+            for &offset in keyframe.offsets.iter() {
+                keyframes.push(ComputedKeyframe {
+                    offset: offset,
+                    style: style.clone(),
+                    timing_function: timing_function,
+                })
+            }
+        }
+        let mut keyframes = merge_sort(keyframes, |a, b| a.offset <= b.offset);
+
+        // Missing `from` and `to` keyframes have the base style.
+        if keyframes.is_empty() || keyframes[0].offset != 0. {
+            keyframes.insert(0, ComputedKeyframe {
+                offset: 0.,
+                style: base_style.clone(),
+                timing_function: None,
+            })
+        }
+        if keyframes.last().offset != 1. {
+            keyframes.push(ComputedKeyframe {
+                offset: 1.,
+                style: base_style.clone(),
+                timing_function: None,
+            })
+        }
+
+        node_animations.animations.push(KeyframeAnimation {
+            name: name.clone(),
+            keyframes: keyframes,
+            start_time: now,
+            paused_time: match *cyclic(box_style.animation_play_state, index) {
+                animation_play_state::paused => Some(now),
+                animation_play_state::running => None,
+            },
+            finished: false,
+        })
+    }
+}
+
+/// Applies the animations of the element to `style`, in the order of `animation-name` so that
+/// later animations win.
+fn apply_keyframe_animations(node_animations: &mut NodeAnimations,
+                             base_style: &ComputedValues,
+                             style: &mut ComputedValues,
+                             node: &OpaqueNode,
+                             events: &mut ~[AnimationEvent],
+                             now: f64) {
+    let box_style = &base_style.Box;
+    for (index, name) in box_style.animation_name.iter().enumerate() {
+        let animation = match node_animations.animations.mut_iter().find(|animation| {
+            animation.name == *name
+        }) {
+            Some(animation) => animation,
+            None => continue,
+        };
+
+        match (*cyclic(box_style.animation_play_state, index), animation.paused_time) {
+            (animation_play_state::paused, None) => animation.paused_time = Some(now),
+            (animation_play_state::running, Some(paused_time)) => {
+                animation.start_time += now - paused_time;
+                animation.paused_time = None
+            }
+            _ => (),
+        }
+        let time = animation.paused_time.unwrap_or(now);
+
+        let duration = *cyclic(box_style.animation_duration, index);
+        let delay = *cyclic(box_style.animation_delay, index);
+        let fill_mode = *cyclic(box_style.animation_fill_mode, index);
+        let iteration_count = match *cyclic(box_style.animation_iteration_count, index) {
+            animation_iteration_count::Count(count) => Some(count),
+            animation_iteration_count::Infinite => None,
+        };
+
+        // The number of iterations done, with the progress of the current one as the fraction.
+        let elapsed = time - animation.start_time - delay;
+        let iterations = if elapsed < 0. {
+            match fill_mode {
+                animation_fill_mode::backwards | animation_fill_mode::both => 0.,
+                _ => continue,
+            }
+        } else if duration <= 0. {
+            iteration_count.unwrap_or(0.)
+        } else {
+            elapsed / duration
+        };
+        let iterations = match iteration_count {
+            Some(count) if iterations >= count => {
+                if !animation.finished {
+                    animation.finished = true;
+                    events.push(AnimationEnded(node.clone(),
+                                               animation.name.clone(),
+                                               count * duration))
+                }
+                match fill_mode {
+                    animation_fill_mode::forwards | animation_fill_mode::both => count,
+                    _ => continue,
+                }
+            }
+            _ => iterations,
+        };
+
+        // An animation that ends after a whole number of iterations ends at the end of the last
+        // one, not at the start of the next.
+        let (iteration, progress) = if iterations > 0. && iterations == iterations.floor() &&
+                Some(iterations) == iteration_count {
+            (iterations as uint - 1, 1.)
+        } else {
+            (iterations.floor() as uint, iterations - iterations.floor())
+        };
+        let reversed = match *cyclic(box_style.animation_direction, index) {
+            animation_direction::normal => false,
+            animation_direction::reverse => true,
+            animation_direction::alternate => iteration % 2 == 1,
+            animation_direction::alternate_reverse => iteration % 2 == 0,
+        };
+        let progress = if reversed { 1. - progress } else { progress };
+        let timing_function = *cyclic(box_style.animation_timing_function, index);
+        apply_keyframes(animation.keyframes, progress, timing_function, base_style, style)
+    }
+}
+
+/// Sets the animatable longhands of `style` to their values at `progress` through the keyframes.
+fn apply_keyframes(keyframes: &[ComputedKeyframe],
+                   progress: f64,
+                   timing_function: TimingFunction,
+                   base_style: &ComputedValues,
+                   style: &mut ComputedValues) {
+    let next = match keyframes.iter().position(|keyframe| keyframe.offset > progress) {
+        Some(0) => 1,
+        Some(next) => next,
+        None => keyframes.len() - 1,
+    };
+    let (from, to) = (&keyframes[next - 1], &keyframes[next]);
+    let interval = to.offset - from.offset;
+    let progress = if interval > 0. { (progress - from.offset) / interval } else { 1. };
+    let progress = from.timing_function.unwrap_or(timing_function).solve(progress);
+    // The properties that are the same in both keyframes may still differ from the base style.
+    let mut properties = changed_animatable_longhands(from.style.get(), to.style.get());
+    for &property in changed_animatable_longhands(base_style, from.style.get()).iter() {
+        if !properties.contains(&property) {
+            properties.push(property)
+        }
+    }
+    for &property in properties.iter() {
+        interpolate_longhand(property, from.style.get(), to.style.get(), progress, style);
+    }
+}
+
+/// Applies the transitions of the element to `style`, dropping those that are done.
+fn apply_transitions(node_animations: &mut NodeAnimations,
+                     style: &mut ComputedValues,
+                     node: &OpaqueNode,
+                     events: &mut ~[AnimationEvent],
+                     now: f64) {
+    let mut finished = false;
+    for transition in node_animations.transitions.iter() {
+        let elapsed = now - transition.start_time - transition.delay;
+        if elapsed >= transition.duration {
+            finished = true;
+            events.push(TransitionEnded(node.clone(), transition.property, transition.duration));
+            continue
+        }
+        let progress = if elapsed < 0. { 0. } else { elapsed / transition.duration };
+        interpolate_longhand(transition.property,
+                             transition.from.get(),
+                             transition.to.get(),
+                             transition.timing_function.solve(progress),
+                             style);
+    }
+    if finished {
+        node_animations.transitions.retain(|transition| {
+            now - transition.start_time - transition.delay < transition.duration
+        })
+    }
+}
@@ -4,7 +4,7 @@
 
 //! CSS block formatting contexts.
 
-use layout::box::Box;
+use layout::box::{Box, ImageBox};
 use layout::context::LayoutContext;
 use layout::display_list_builder::{DisplayListBuilder, ExtraDisplayListData};
 use layout::flow::{BlockFlowClass, FlowClass, Flow, FlowData, ImmutableFlowUtils};
@@ -59,7 +59,11 @@ pub struct BlockFlow {
     is_root: bool,
 
     /// Additional floating flow members.
-    float: Option<~FloatedBlockInfo>
+    float: Option<~FloatedBlockInfo>,
+
+    /// The marker box of a list item whose marker is outside its principal box, per CSS 2.1 §
+    /// 12.5.1.
+    marker: Option<Box>,
 }
 
 impl BlockFlow {
@@ -68,7 +72,8 @@ impl BlockFlow {
             base: base,
             box: None,
             is_root: false,
-            float: None
+            float: None,
+            marker: None,
         }
     }
 
@@ -77,7 +82,8 @@ impl BlockFlow {
             base: base,
             box: Some(box),
             is_root: false,
-            float: None
+            float: None,
+            marker: None,
         }
     }
 
@@ -86,7 +92,8 @@ impl BlockFlow {
             base: base,
             box: Some(box),
             is_root: false,
-            float: Some(~FloatedBlockInfo::new(float_type))
+            float: Some(~FloatedBlockInfo::new(float_type)),
+            marker: None,
         }
     }
 
@@ -95,7 +102,8 @@ impl BlockFlow {
             base: base,
             box: None,
             is_root: true,
-            float: None
+            float: None,
+            marker: None,
         }
     }
 
@@ -104,7 +112,8 @@ impl BlockFlow {
             base: base,
             box: None,
             is_root: false,
-            float: Some(~FloatedBlockInfo::new(float_type))
+            float: Some(~FloatedBlockInfo::new(float_type)),
+            marker: None,
         }
     }
 
@@ -116,8 +125,31 @@ impl BlockFlow {
         for box in self.box.iter() {
             box.teardown();
         }
+        for marker in self.marker.iter() {
+            marker.teardown();
+        }
         self.box = None;
         self.float = None;
+        self.marker = None;
+    }
+
+    /// Positions the outside marker, if any, just left of the content of the first line. The
+    /// marker is outside the block's box, so it takes up no space in the flow.
+    fn position_marker(&self) {
+        let (marker, box) = match (&self.marker, &self.box) {
+            (&Some(ref marker), &Some(ref box)) => (marker, box),
+            _ => return,
+        };
+        marker.assign_width();
+        let mut size = marker.position.get().size;
+        match marker.specific {
+            ImageBox(ref image_box_info) => size.height = image_box_info.image_height(),
+            _ => {}
+        }
+        let border_box = box.position.get();
+        let content_left = border_box.origin.x + box.border.get().left + box.padding.get().left;
+        let content_top = border_box.origin.y + box.border.get().top + box.padding.get().top;
+        marker.position.set(Rect(Point2D(content_left - size.width, content_top), size));
     }
 
     /// Computes left and right margins and width based on CSS 2.1 section 10.3.3.
@@ -471,8 +503,7 @@ impl BlockFlow {
             return self.build_display_list_float(builder, dirty, list);
         }
 
-        let abs_rect = Rect(self.base.abs_position, self.base.position.size);
-        if !abs_rect.intersects(dirty) {
+        if !self.base.abs_overflow().intersects(dirty) {
             return true;
         }
 
@@ -483,6 +514,15 @@ impl BlockFlow {
             box.build_display_list(builder, dirty, self.base.abs_position, (&*self) as &Flow, list)
         }
 
+        self.position_marker();
+        for marker in self.marker.iter() {
+            marker.build_display_list(builder,
+                                      dirty,
+                                      self.base.abs_position,
+                                      (&*self) as &Flow,
+                                      list)
+        }
+
         // TODO: handle any out-of-flow elements
         let this_position = self.base.abs_position;
         for child in self.base.child_iter() {
@@ -499,17 +539,22 @@ impl BlockFlow {
                                     dirty: &Rect<Au>,
                                     list: &Cell<DisplayList<E>>)
                                     -> bool {
-        let abs_rect = Rect(self.base.abs_position, self.base.position.size);
-        if !abs_rect.intersects(dirty) {
+        let rel_pos = self.float.get_ref().rel_pos;
+        if !self.base.abs_overflow().translate(&rel_pos).intersects(dirty) {
             return true
         }
 
-        let offset = self.base.abs_position + self.float.get_ref().rel_pos;
+        let offset = self.base.abs_position + rel_pos;
         // add box that starts block context
         for box in self.box.iter() {
             box.build_display_list(builder, dirty, offset, (&*self) as &Flow, list)
         }
 
+        self.position_marker();
+        for marker in self.marker.iter() {
+            marker.build_display_list(builder, dirty, offset, (&*self) as &Flow, list)
+        }
+
 
         // TODO: handle any out-of-flow elements
 
@@ -7,15 +7,23 @@
 use extra::url::Url;
 use extra::arc::{MutexArc, Arc};
 use geom::{Point2D, Rect, Size2D, SideOffsets2D};
-use gfx::color::rgb;
-use gfx::display_list::{BaseDisplayItem, BorderDisplayItem, BorderDisplayItemClass};
+use geom::matrix2d::Matrix2D;
+use gfx::color::{rgb, rgba};
+use gfx::display_list::{BaseDisplayItem, BorderDisplayItem, BorderDisplayItemClass, BorderRadii};
 use gfx::display_list::{DisplayList, ImageDisplayItem, ImageDisplayItemClass};
 use gfx::display_list::{SolidColorDisplayItem, SolidColorDisplayItemClass, TextDisplayItem};
 use gfx::display_list::{TextDisplayItemClass, TextDisplayItemFlags, ClipDisplayItem};
-use gfx::display_list::{ClipDisplayItemClass};
+use gfx::display_list::{ClipDisplayItemClass, GradientDisplayItem, GradientDisplayItemClass};
+use gfx::display_list::{GradientShape, GradientStop, LinearGradientShape, RadialGradientShape};
+use gfx::display_list::{BoxShadowDisplayItem, BoxShadowDisplayItemClass, TextShadowDisplayItem};
+use gfx::display_list::{TextShadowDisplayItemClass, blur_extent};
+use gfx::display_list::{GroupDisplayItem, GroupDisplayItemClass, TransformDisplayItem};
+use gfx::display_list::TransformDisplayItemClass;
 use gfx::font::FontStyle;
 
+use gfx::text::bidi::{BidiClass, LRE, LRO, RLE, RLO};
 use gfx::text::text_run::TextRun;
+use gfx::text::util::CharIndexMap;
 use servo_msg::constellation_msg::{FrameRectMsg, PipelineId, SubpageId};
 use servo_net::image::holder::ImageHolder;
 use servo_net::local_image_cache::LocalImageCache;
@@ -27,18 +35,27 @@ use std::cast;
 use std::cell::Cell;
 use std::cmp::ApproxEq;
 use std::num::Zero;
-use style::{ComputedValues, TElement, TNode, cascade};
-use style::computed_values::{LengthOrPercentage, overflow};
-use style::computed_values::{border_style, clear, font_family, line_height};
-use style::computed_values::{text_align, text_decoration, vertical_align, visibility};
+use style::{ComputedValues, TElement, TNode, cascade_anonymous};
+use style::computed_values::{LengthOrPercentage, LP_Length, LP_Percentage, LP_Calc, overflow};
+use style::computed_values::{UrlImage, LinearGradientImage, RadialGradientImage, ColorStop};
+use style::computed_values::{LinearGradient, RadialGradient, AngleDirection, CornerDirection};
+use style::computed_values::{SideLeft, SideRight, SideTop, SideBottom};
+use style::computed_values::{CircleExtent, EllipseExtent, Circle, Ellipse, ShapeExtent};
+use style::computed_values::{ClosestSide, ClosestCorner, FarthestSide, FarthestCorner};
+use style::computed_values::{background_attachment, background_repeat, background_size};
+use style::computed_values::{border_style, border_top_left_radius, clear, direction};
+use style::computed_values::{font_family, unicode_bidi};
+use style::computed_values::{font_variant, line_height, transform};
+use style::computed_values::{tab_size, text_align, text_decoration, text_transform};
+use style::computed_values::{vertical_align, visibility, white_space};
 
 use css::node_style::StyledNode;
 use layout::context::LayoutContext;
 use layout::display_list_builder::{DisplayListBuilder, ExtraDisplayListData, ToGfxColor};
 use layout::float_context::{ClearType, ClearLeft, ClearRight, ClearBoth};
-use layout::flow::Flow;
+use layout::flow::{Flow, ImmutableFlowUtils};
 use layout::flow;
-use layout::model::{MaybeAuto, specified};
+use layout::model::{Auto, MaybeAuto, Specified, specified};
 use layout::util::OpaqueNode;
 use layout::wrapper::LayoutNode;
 
@@ -89,6 +106,50 @@ pub struct Box {
 
     /// positioned box offsets
     position_offsets: Slot<SideOffsets2D<Au>>,
+
+    /// The embeddings opened by the inline elements containing this box, outermost first.
+    bidi_embeddings: ~[BidiEmbedding],
+
+    /// The embedding level of this box, resolved by the Unicode Bidirectional Algorithm. Boxes at
+    /// odd levels are right-to-left.
+    bidi_level: u8,
+}
+
+/// The embedding an element with `unicode-bidi: embed` or `bidi-override` opens around its
+/// content, per CSS 2.1 § 9.10.
+#[deriving(Clone, Eq)]
+pub struct BidiEmbedding {
+    /// The element, which keeps the embeddings of adjacent elements apart.
+    node: OpaqueNode,
+    rtl: bool,
+    is_override: bool,
+}
+
+impl BidiEmbedding {
+    /// Returns the embedding the given element opens, if any.
+    pub fn from_node(node: &LayoutNode) -> Option<BidiEmbedding> {
+        let style = node.style().get();
+        let is_override = match style.Box.unicode_bidi {
+            unicode_bidi::normal => return None,
+            unicode_bidi::embed => false,
+            unicode_bidi::bidi_override => true,
+        };
+        Some(BidiEmbedding {
+            node: OpaqueNode::from_layout_node(node),
+            rtl: style.Box.direction == direction::rtl,
+            is_override: is_override,
+        })
+    }
+
+    /// Returns the type of the explicit formatting character this embedding stands for.
+    pub fn bidi_class(&self) -> BidiClass {
+        match (self.rtl, self.is_override) {
+            (false, false) => LRE,
+            (true, false) => RLE,
+            (false, true) => LRO,
+            (true, true) => RLO,
+        }
+    }
 }
 
 /// Info specific to the kind of box. Keep this enum small.
@@ -135,6 +196,17 @@ impl ImageBoxInfo {
         }
     }
 
+    /// Creates a new image box for generated content from the given URL and local image cache.
+    /// Such an image has no width or height attributes.
+    pub fn from_url(image_url: Url, local_image_cache: MutexArc<LocalImageCache>)
+                    -> ImageBoxInfo {
+        ImageBoxInfo {
+            image: Slot::init(ImageHolder::new(image_url, local_image_cache)),
+            dom_width: None,
+            dom_height: None,
+        }
+    }
+
     // Calculates the width of an image, accounting for the width attribute.
     fn image_width(&self) -> Au {
         // TODO(brson): Consult margins and borders?
@@ -184,16 +256,32 @@ pub struct ScannedTextBoxInfo {
 
     /// The range within the above text run that this represents.
     range: Range,
+
+    /// Maps the characters of the text run that came from the DOM text node of this box back to
+    /// character offsets in that node.
+    char_map: Arc<CharIndexMap>,
+
+    /// The index in the text run of the first character that came from the DOM text node of this
+    /// box.
+    node_start: uint,
 }
 
 impl ScannedTextBoxInfo {
     /// Creates the information specific to a scanned text box from a range and a text run.
-    pub fn new(run: Arc<~TextRun>, range: Range) -> ScannedTextBoxInfo {
+    pub fn new(run: Arc<~TextRun>, range: Range, char_map: Arc<CharIndexMap>, node_start: uint)
+               -> ScannedTextBoxInfo {
         ScannedTextBoxInfo {
             run: run,
             range: range,
+            char_map: char_map,
+            node_start: node_start,
         }
     }
+
+    /// Returns the information for the given range of the same text run.
+    pub fn with_range(&self, range: Range) -> ScannedTextBoxInfo {
+        ScannedTextBoxInfo::new(self.run.clone(), range, self.char_map.clone(), self.node_start)
+    }
 }
 
 /// Data for an unscanned text box. Unscanned text boxes are the results of flow construction that
@@ -212,6 +300,13 @@ impl UnscannedTextBoxInfo {
             text: node.text(),
         }
     }
+
+    /// Creates a new instance of `UnscannedTextBoxInfo` from the given text.
+    pub fn from_text(text: ~str) -> UnscannedTextBoxInfo {
+        UnscannedTextBoxInfo {
+            text: text,
+        }
+    }
 }
 
 /// Represents the outcome of attempting to split a box.
@@ -249,19 +344,27 @@ impl Box {
             }
 
             // Anonymous box: inheriting from the ancestor with no specified declarations.
-            Arc::new(cascade(&[Arc::new(~[])],
-                             Some(nearest_ancestor_element.style().get())))
+            Arc::new(cascade_anonymous(nearest_ancestor_element.style().get()))
         };
 
+        Box::from_style(node, node_style, specific)
+    }
+
+    /// Constructs a new `Box` instance for the given node with the given style. This is used for
+    /// boxes whose style is not that of the node itself, such as generated content.
+    pub fn from_style(node: LayoutNode, style: Arc<ComputedValues>, specific: SpecificBoxInfo)
+                      -> Box {
         Box {
             node: OpaqueNode::from_layout_node(&node),
-            style: node_style,
+            style: style,
             position: Slot::init(Au::zero_rect()),
             border: Slot::init(Zero::zero()),
             padding: Slot::init(Zero::zero()),
             margin: Slot::init(Zero::zero()),
             specific: specific,
             position_offsets: Slot::init(Zero::zero()),
+            bidi_embeddings: ~[],
+            bidi_level: 0,
         }
     }
 
@@ -284,7 +387,73 @@ impl Box {
             padding: Slot::init(self.padding.get()),
             margin: Slot::init(self.margin.get()),
             specific: specific,
-            position_offsets: Slot::init(Zero::zero())
+            position_offsets: Slot::init(Zero::zero()),
+            bidi_embeddings: self.bidi_embeddings.clone(),
+            bidi_level: self.bidi_level,
+        }
+    }
+
+    /// Transforms this box into another box of the given type, with the given size and style,
+    /// preserving all the other data.
+    pub fn transform_with_style(&self,
+                                size: Size2D<Au>,
+                                style: Arc<ComputedValues>,
+                                specific: SpecificBoxInfo)
+                                -> Box {
+        Box {
+            node: self.node,
+            style: style,
+            position: Slot::init(Rect(self.position.get().origin, size)),
+            border: Slot::init(self.border.get()),
+            padding: Slot::init(self.padding.get()),
+            margin: Slot::init(self.margin.get()),
+            specific: specific,
+            position_offsets: Slot::init(Zero::zero()),
+            bidi_embeddings: self.bidi_embeddings.clone(),
+            bidi_level: self.bidi_level,
+        }
+    }
+
+    /// Returns a copy of this box with the given style. Scanned text is reshaped with the font of
+    /// the new style, so the size of the box may change.
+    pub fn restyle(&self, ctx: &mut LayoutContext, style: Arc<ComputedValues>) -> Box {
+        self.transform_with_style(self.position.get().size, style, self.specific.clone())
+            .reshape(ctx)
+    }
+
+    /// Returns a copy of this box whose scanned text is shaped anew with the font of its style, in
+    /// the direction of its embedding level.
+    pub fn reshape(&self, ctx: &mut LayoutContext) -> Box {
+        match self.specific {
+            ScannedTextBox(ref text_box_info) => {
+                let range = &text_box_info.range;
+                let text = text_box_info.run.get().text.get().slice_chars(range.begin(),
+                                                                          range.end());
+                let font_style = self.font_style();
+                let decoration = self.text_decoration();
+                let letter_spacing = self.letter_spacing();
+                let word_spacing = self.word_spacing();
+                let tab_size = self.tab_size();
+                let run = ~ctx.font_ctx.create_textrun(&font_style,
+                                                       text.to_owned(),
+                                                       self.bidi_level & 1 == 1,
+                                                       decoration,
+                                                       letter_spacing,
+                                                       word_spacing,
+                                                       tab_size);
+
+                let new_range = Range::new(0, run.char_len());
+                let new_metrics = run.metrics_for_range(&new_range);
+                let char_map = text_box_info.char_map.get().slice(
+                    range.begin() - text_box_info.node_start,
+                    range.end() - text_box_info.node_start);
+                let new_text_box_info = ScannedTextBoxInfo::new(Arc::new(run),
+                                                                new_range,
+                                                                Arc::new(char_map),
+                                                                0);
+                self.transform(new_metrics.bounding_box.size, ScannedTextBox(new_text_box_info))
+            }
+            GenericBox | IframeBox(_) | ImageBox(_) | UnscannedTextBox(_) => self.clone(),
         }
     }
 
@@ -422,6 +591,7 @@ impl Box {
             pt_size: font_size,
             weight: my_style.Font.font_weight,
             style: my_style.Font.font_style,
+            stretch: my_style.Font.font_stretch,
             families: font_families,
         }
     }
@@ -456,6 +626,51 @@ impl Box {
         self.style().Text.text_decoration
     }
 
+    /// Returns the extra space after each character of this box, from the `letter-spacing`
+    /// property.
+    pub fn letter_spacing(&self) -> Au {
+        self.style().Text.letter_spacing.unwrap_or(Au(0))
+    }
+
+    /// Returns the extra space after each whitespace character of this box, from the
+    /// `word-spacing` property.
+    pub fn word_spacing(&self) -> Au {
+        self.style().Text.word_spacing.unwrap_or(Au(0))
+    }
+
+    pub fn text_transform(&self) -> text_transform::T {
+        self.style().Text.text_transform
+    }
+
+    pub fn font_variant(&self) -> font_variant::T {
+        self.style().Font.font_variant
+    }
+
+    pub fn white_space(&self) -> white_space::T {
+        self.style().Text.white_space
+    }
+
+    pub fn tab_size(&self) -> tab_size::T {
+        self.style().Text.tab_size
+    }
+
+    /// Returns true if the `white-space` property allows lines to wrap within this box.
+    pub fn allows_wrapping(&self) -> bool {
+        match self.white_space() {
+            white_space::normal | white_space::pre_wrap | white_space::pre_line => true,
+            white_space::pre | white_space::nowrap => false,
+        }
+    }
+
+    /// Returns true if the `white-space` property preserves spaces and tabs within this box, so
+    /// that they are not trimmed at the start of lines.
+    pub fn preserves_spaces(&self) -> bool {
+        match self.white_space() {
+            white_space::pre | white_space::pre_wrap => true,
+            white_space::normal | white_space::nowrap | white_space::pre_line => false,
+        }
+    }
+
     /// Returns the sum of margin, border, and padding on the left.
     pub fn offset(&self) -> Au {
         self.margin.get().left + self.border.get().left + self.padding.get().left
@@ -470,10 +685,25 @@ impl Box {
         }
     }
 
-    /// Returns true if this element can be split. This is true for text boxes.
+    /// Returns true if this element can be split. This is true for text boxes, unless the
+    /// `white-space` property prevents wrapping.
     pub fn can_split(&self) -> bool {
         match self.specific {
-            ScannedTextBox(*) => true,
+            ScannedTextBox(*) => self.allows_wrapping(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this box is a newline preserved by the `white-space` property, which ends
+    /// the line it is on.
+    pub fn is_forced_line_break(&self) -> bool {
+        match self.specific {
+            ScannedTextBox(ref text_box_info) => {
+                let range = &text_box_info.range;
+                let text = text_box_info.run.get().text.get().slice_chars(range.begin(),
+                                                                          range.end());
+                text == "\n"
+            }
             _ => false,
         }
     }
@@ -496,12 +726,16 @@ impl Box {
     /// necessary.
     pub fn paint_background_if_applicable<E:ExtraDisplayListData>(
                                           &self,
+                                          builder: &DisplayListBuilder,
                                           list: &Cell<DisplayList<E>>,
                                           absolute_bounds: &Rect<Au>) {
         // FIXME: This causes a lot of background colors to be displayed when they are clearly not
         // needed. We could use display list optimization to clean this up, but it still seems
         // inefficient. What we really want is something like "nearest ancestor element that
         // doesn't have a box".
+        // Outer shadows are painted below the background.
+        self.paint_box_shadows_if_applicable(list, absolute_bounds, false);
+
         let style = self.style();
         let background_color = style.resolve_color(style.Background.background_color);
         if !background_color.alpha.approx_eq(&0.0) {
@@ -512,11 +746,261 @@ impl Box {
                         extra: ExtraDisplayListData::new(self),
                     },
                     color: background_color.to_gfx_color(),
+                    radii: self.border_radii(absolute_bounds),
                 };
 
                 list.append_item(SolidColorDisplayItemClass(solid_color_display_item))
             })
         }
+
+        // The background image is painted over the background color, and inset shadows over it.
+        self.paint_background_image_if_applicable(builder, list, absolute_bounds);
+        self.paint_box_shadows_if_applicable(list, absolute_bounds, true)
+    }
+
+    /// Adds the display items for either the outer or the inset shadows of this box to a display
+    /// list. The first shadow is on top, so the items are added from the last one.
+    fn paint_box_shadows_if_applicable<E:ExtraDisplayListData>(
+                                       &self,
+                                       list: &Cell<DisplayList<E>>,
+                                       absolute_bounds: &Rect<Au>,
+                                       inset: bool) {
+        let style = self.style();
+        for shadow in style.Background.box_shadow.rev_iter() {
+            if shadow.inset != inset {
+                continue
+            }
+            let offset = Point2D(shadow.offset_x, shadow.offset_y);
+            // Inset shadows are cast inside the padding box. Outer shadows are cast around the
+            // border box, and spread and blur beyond it.
+            let (box_bounds, bounds) = if inset {
+                let padding_box = self.padding_box(absolute_bounds);
+                (padding_box, padding_box)
+            } else {
+                let amount = shadow.spread_radius + blur_extent(shadow.blur_radius);
+                (*absolute_bounds, shadow_bounds(absolute_bounds, offset, amount))
+            };
+
+            do list.with_mut_ref |list| {
+                let box_shadow_display_item = ~BoxShadowDisplayItem {
+                    base: BaseDisplayItem {
+                        bounds: bounds,
+                        extra: ExtraDisplayListData::new(self),
+                    },
+                    box_bounds: box_bounds,
+                    radii: if inset {
+                        self.padding_radii(absolute_bounds)
+                    } else {
+                        self.border_radii(absolute_bounds)
+                    },
+                    offset: offset,
+                    color: style.resolve_color(shadow.color).to_gfx_color(),
+                    blur_radius: shadow.blur_radius,
+                    spread_radius: shadow.spread_radius,
+                    inset: inset,
+                };
+                list.append_item(BoxShadowDisplayItemClass(box_shadow_display_item))
+            }
+        }
+    }
+
+    /// Returns the area that the display items of this box paint in, given its border box. Outer
+    /// box shadows and text shadows paint outside of the border box.
+    pub fn visual_overflow(&self, absolute_bounds: &Rect<Au>) -> Rect<Au> {
+        let style = self.style();
+        let mut overflow = *absolute_bounds;
+        let add_shadow = |overflow: Rect<Au>, offset_x: Au, offset_y: Au, amount: Au| {
+            let bounds = shadow_bounds(absolute_bounds, Point2D(offset_x, offset_y), amount);
+            if bounds.size.width > Au(0) && bounds.size.height > Au(0) {
+                overflow.union(&bounds)
+            } else {
+                overflow
+            }
+        };
+        for shadow in style.Background.box_shadow.iter() {
+            if !shadow.inset {
+                let amount = shadow.spread_radius + blur_extent(shadow.blur_radius);
+                overflow = add_shadow(overflow, shadow.offset_x, shadow.offset_y, amount)
+            }
+        }
+        match self.specific {
+            ScannedTextBox(_) => {
+                for shadow in style.Text.text_shadow.iter() {
+                    overflow = add_shadow(overflow,
+                                          shadow.offset_x,
+                                          shadow.offset_y,
+                                          blur_extent(shadow.blur_radius))
+                }
+            }
+            _ => {}
+        }
+        overflow
+    }
+
+    /// Returns the padding box of this box, given its border box.
+    fn padding_box(&self, absolute_bounds: &Rect<Au>) -> Rect<Au> {
+        let border = self.border.get();
+        Rect(Point2D(absolute_bounds.origin.x + border.left,
+                     absolute_bounds.origin.y + border.top),
+             Size2D(absolute_bounds.size.width - border.left - border.right,
+                    absolute_bounds.size.height - border.top - border.bottom))
+    }
+
+    /// Adds the display item for the background image of this box to a display list, if there is
+    /// one and it has been loaded. The image is clipped to the border box, and positioned relative
+    /// to the padding box, or to the viewport if it is fixed.
+    fn paint_background_image_if_applicable<E:ExtraDisplayListData>(
+                                            &self,
+                                            builder: &DisplayListBuilder,
+                                            list: &Cell<DisplayList<E>>,
+                                            absolute_bounds: &Rect<Au>) {
+        let style = self.style();
+        let background_image = match style.Background.background_image {
+            None => return,
+            Some(ref background_image) => background_image,
+        };
+
+        let positioning_area = match style.Background.background_attachment {
+            background_attachment::scroll => self.padding_box(absolute_bounds),
+            background_attachment::fixed => builder.ctx.screen_size,
+        };
+
+        let image = match *background_image {
+            UrlImage(ref image_url) => {
+                // If the image is not available yet, the image cache will ask for a reflow once
+                // it is.
+                let mut holder = ImageHolder::new(image_url.clone(),
+                                                  builder.ctx.image_cache.clone());
+                match holder.get_image() {
+                    None => return,
+                    Some(image) => Some(image),
+                }
+            }
+            LinearGradientImage(*) | RadialGradientImage(*) => None,
+        };
+        // Gradients have no intrinsic size: by default, they fill the positioning area.
+        let image_size = match image {
+            Some(ref image) => Size2D(Au::from_px(image.get().width as int),
+                                      Au::from_px(image.get().height as int)),
+            None => positioning_area.size,
+        };
+
+        let stretch_size = background_image_size(style.Background.background_size,
+                                                 image_size,
+                                                 positioning_area.size);
+        let position = style.Background.background_position;
+        let tile_origin = Point2D(
+            positioning_area.origin.x +
+                specified(position.horizontal, positioning_area.size.width - stretch_size.width),
+            positioning_area.origin.y +
+                specified(position.vertical, positioning_area.size.height - stretch_size.height));
+        let repeat = style.Background.background_repeat;
+
+        let item = match *background_image {
+            UrlImage(_) => ImageDisplayItemClass(~ImageDisplayItem {
+                base: BaseDisplayItem {
+                    bounds: *absolute_bounds,
+                    extra: ExtraDisplayListData::new(self),
+                },
+                image: image.unwrap(),
+                stretch_size: stretch_size,
+                tile_origin: tile_origin,
+                repeat_x: repeat == background_repeat::repeat ||
+                    repeat == background_repeat::repeat_x,
+                repeat_y: repeat == background_repeat::repeat ||
+                    repeat == background_repeat::repeat_y,
+            }),
+            // FIXME: Gradients are drawn once, without repeating them like images.
+            LinearGradientImage(ref gradient) => {
+                let tile = Rect(tile_origin, stretch_size);
+                let bounds = match tile.intersection(absolute_bounds) {
+                    Some(bounds) => bounds,
+                    None => return,
+                };
+                let (shape, stops) = linear_gradient(style, gradient, &tile);
+                GradientDisplayItemClass(~GradientDisplayItem {
+                    base: BaseDisplayItem {
+                        bounds: bounds,
+                        extra: ExtraDisplayListData::new(self),
+                    },
+                    shape: shape,
+                    stops: stops,
+                    repeating: gradient.repeating,
+                })
+            }
+            RadialGradientImage(ref gradient) => {
+                let tile = Rect(tile_origin, stretch_size);
+                let bounds = match tile.intersection(absolute_bounds) {
+                    Some(bounds) => bounds,
+                    None => return,
+                };
+                let (shape, stops) = match radial_gradient(style, gradient, &tile) {
+                    Some(shape_and_stops) => shape_and_stops,
+                    None => return,
+                };
+                GradientDisplayItemClass(~GradientDisplayItem {
+                    base: BaseDisplayItem {
+                        bounds: bounds,
+                        extra: ExtraDisplayListData::new(self),
+                    },
+                    shape: shape,
+                    stops: stops,
+                    repeating: gradient.repeating,
+                })
+            }
+        };
+
+        // The background is clipped to the rounded corners of the border box.
+        let radii = self.border_radii(absolute_bounds);
+        let item = if radii.is_square() {
+            item
+        } else {
+            ClipDisplayItemClass(~ClipDisplayItem {
+                base: BaseDisplayItem {
+                    bounds: *absolute_bounds,
+                    extra: ExtraDisplayListData::new(self),
+                },
+                child_list: ~[item],
+                need_clip: true,
+                radii: radii,
+            })
+        };
+
+        do list.with_mut_ref |list| {
+            list.append_item(item)
+        }
+    }
+
+    /// Returns the radii of the outer corners of the border of this box, given its border box.
+    fn border_radii(&self, absolute_bounds: &Rect<Au>) -> BorderRadii<Au> {
+        let style = self.style();
+        let corner = |radius: border_top_left_radius::T| {
+            Size2D(specified(radius.horizontal, absolute_bounds.size.width),
+                   specified(radius.vertical, absolute_bounds.size.height))
+        };
+        BorderRadii {
+            top_left: corner(style.Border.border_top_left_radius),
+            top_right: corner(style.Border.border_top_right_radius),
+            bottom_right: corner(style.Border.border_bottom_right_radius),
+            bottom_left: corner(style.Border.border_bottom_left_radius),
+        }
+    }
+
+    /// Returns the radii of the inner corners of the border of this box, which are those of its
+    /// padding box, given its border box.
+    fn padding_radii(&self, absolute_bounds: &Rect<Au>) -> BorderRadii<Au> {
+        let radii = self.border_radii(absolute_bounds);
+        let border = self.border.get();
+        let corner = |radius: Size2D<Au>, horizontal: Au, vertical: Au| {
+            Size2D(Au::max(radius.width - horizontal, Au(0)),
+                   Au::max(radius.height - vertical, Au(0)))
+        };
+        BorderRadii {
+            top_left: corner(radii.top_left, border.left, border.top),
+            top_right: corner(radii.top_right, border.right, border.top),
+            bottom_right: corner(radii.bottom_right, border.right, border.bottom),
+            bottom_left: corner(radii.bottom_left, border.left, border.bottom),
+        }
     }
 
     /// Adds the display items necessary to paint the borders of this box to a display list if
@@ -556,7 +1040,8 @@ impl Box {
                 style: SideOffsets2D::new(top_style,
                                           right_style,
                                           bottom_style,
-                                          left_style)
+                                          left_style),
+                radii: self.border_radii(abs_bounds),
             };
 
             list.append_item(BorderDisplayItemClass(border_display_item))
@@ -584,6 +1069,107 @@ impl Box {
                               offset: Point2D<Au>,
                               flow: &Flow,
                               list: &Cell<DisplayList<E>>) {
+        let opacity = self.style().Box.opacity;
+        let absolute_bounds = self.position.get().translate(&offset);
+        // Transforms don't apply to non-replaced inline boxes.
+        let transform = if flow.is_block_like() {
+            self.transform(&absolute_bounds)
+        } else {
+            None
+        };
+        if opacity >= 1.0 && transform.is_none() {
+            return self.build_display_list_for_box(builder, dirty, offset, flow, list)
+        }
+
+        let group_list = Cell::new(DisplayList::new());
+        self.build_display_list_for_box(builder, dirty, offset, flow, &group_list);
+        let mut items = group_list.take().list;
+        if items.is_empty() {
+            return
+        }
+
+        // The box and its descendants are painted as a group, composited with the opacity. The
+        // group is kept even when it is fully transparent, for hit testing.
+        if opacity < 1.0 {
+            items = ~[GroupDisplayItemClass(~GroupDisplayItem {
+                base: BaseDisplayItem {
+                    bounds: absolute_bounds,
+                    extra: ExtraDisplayListData::new(self),
+                },
+                child_list: items,
+                opacity: opacity as f32,
+            })];
+        }
+        match transform {
+            Some(matrix) => {
+                items = ~[TransformDisplayItemClass(~TransformDisplayItem {
+                    base: BaseDisplayItem {
+                        bounds: geometry::transform_rect(&matrix, &absolute_bounds),
+                        extra: ExtraDisplayListData::new(self),
+                    },
+                    child_list: items,
+                    transform: matrix,
+                })];
+            }
+            None => {}
+        }
+
+        let items = Cell::new(items);
+        do list.with_mut_ref |list| {
+            list.list.push_all_move(items.take())
+        }
+    }
+
+    /// Returns the transform of this box, which maps pixels, given its border box in the same
+    /// coordinates; or `None` if the box has no transform.
+    pub fn transform(&self, border_box: &Rect<Au>) -> Option<Matrix2D<f32>> {
+        let style = self.style();
+        if style.Box.transform.is_empty() {
+            return None
+        }
+
+        let origin = style.Box.transform_origin;
+        let origin_x = border_box.origin.x + specified(origin.horizontal, border_box.size.width);
+        let origin_y = border_box.origin.y + specified(origin.vertical, border_box.size.height);
+        let (origin_x, origin_y) = (geometry::to_frac_px(origin_x) as f32,
+                                    geometry::to_frac_px(origin_y) as f32);
+
+        // The functions apply from right to left, about the origin.
+        let matrix: Matrix2D<f32> = Matrix2D::identity();
+        let mut matrix = matrix.translate(origin_x, origin_y);
+        for operation in style.Box.transform.iter() {
+            let operation_matrix = match *operation {
+                transform::TransformMatrix(a, b, c, d, e, f) => {
+                    Matrix2D::new(a as f32, b as f32, c as f32, d as f32, e as f32, f as f32)
+                }
+                transform::TransformTranslate(x, y) => {
+                    let x = geometry::to_frac_px(specified(x, border_box.size.width));
+                    let y = geometry::to_frac_px(specified(y, border_box.size.height));
+                    Matrix2D::new(1.0, 0.0, 0.0, 1.0, x as f32, y as f32)
+                }
+                transform::TransformScale(x, y) => {
+                    Matrix2D::new(x as f32, 0.0, 0.0, y as f32, 0.0, 0.0)
+                }
+                transform::TransformRotate(angle) => {
+                    let (sin, cos) = (angle.sin() as f32, angle.cos() as f32);
+                    Matrix2D::new(cos, sin, -sin, cos, 0.0, 0.0)
+                }
+                transform::TransformSkew(x, y) => {
+                    Matrix2D::new(1.0, y.tan() as f32, x.tan() as f32, 1.0, 0.0, 0.0)
+                }
+            };
+            matrix = matrix.mul(&operation_matrix)
+        }
+        Some(matrix.translate(-origin_x, -origin_y))
+    }
+
+    fn build_display_list_for_box<E:ExtraDisplayListData>(
+                                  &self,
+                                  builder: &DisplayListBuilder,
+                                  dirty: &Rect<Au>,
+                                  offset: Point2D<Au>,
+                                  flow: &Flow,
+                                  list: &Cell<DisplayList<E>>) {
         let box_bounds = self.position.get();
         let absolute_box_bounds = box_bounds.translate(&offset);
         debug!("Box::build_display_list at rel={}, abs={}: {:s}",
@@ -594,7 +1180,7 @@ impl Box {
             return;
         }
 
-        if absolute_box_bounds.intersects(dirty) {
+        if self.visual_overflow(&absolute_box_bounds).intersects(dirty) {
             debug!("Box::build_display_list: intersected. Adding display item...");
         } else {
             debug!("Box::build_display_list: Did not intersect...");
@@ -602,7 +1188,7 @@ impl Box {
         }
 
         // Add the background to the list, if applicable.
-        self.paint_background_if_applicable(list, &absolute_box_bounds);
+        self.paint_background_if_applicable(builder, list, &absolute_box_bounds);
 
         match self.specific {
             UnscannedTextBox(_) => fail!("Shouldn't see unscanned boxes here."),
@@ -614,13 +1200,38 @@ impl Box {
                             extra: ExtraDisplayListData::new(self),
                         },
                         child_list: ~[],
-                        need_clip: false
+                        need_clip: false,
+                        radii: BorderRadii::zero(),
                     };
                     list.append_item(ClipDisplayItemClass(item));
                 }
 
                 let color = self.style().Color.color.to_gfx_color();
 
+                // Text shadows are painted below the text, the first one on top.
+                for shadow in self.style().Text.text_shadow.rev_iter() {
+                    let offset = Point2D(shadow.offset_x, shadow.offset_y);
+                    let origin = absolute_box_bounds.origin + offset;
+                    let bounds = shadow_bounds(&absolute_box_bounds,
+                                               offset,
+                                               blur_extent(shadow.blur_radius));
+                    let shadow_color = self.style().resolve_color(shadow.color).to_gfx_color();
+                    do list.with_mut_ref |list| {
+                        let text_shadow_display_item = ~TextShadowDisplayItem {
+                            base: BaseDisplayItem {
+                                bounds: bounds,
+                                extra: ExtraDisplayListData::new(self),
+                            },
+                            text_run: text_box.run.clone(),
+                            range: text_box.range,
+                            origin: origin,
+                            color: shadow_color,
+                            blur_radius: shadow.blur_radius,
+                        };
+                        list.append_item(TextShadowDisplayItemClass(text_shadow_display_item))
+                    }
+                }
+
                 // Set the various text display item flags.
                 let flow_flags = flow::base(flow).flags;
                 let mut text_flags = TextDisplayItemFlags::new();
@@ -639,6 +1250,8 @@ impl Box {
                         range: text_box.range,
                         color: color,
                         flags: text_flags,
+                        char_map: text_box.char_map.clone(),
+                        node_start: text_box.node_start,
                     };
 
                     list.append_item(TextDisplayItemClass(text_display_item))
@@ -660,8 +1273,8 @@ impl Box {
                             },
                             border: debug_border,
                             color: SideOffsets2D::new_all_same(rgb(0, 0, 200)),
-                            style: SideOffsets2D::new_all_same(border_style::solid)
-
+                            style: SideOffsets2D::new_all_same(border_style::solid),
+                            radii: BorderRadii::zero(),
                         };
                         list.append_item(BorderDisplayItemClass(border_display_item))
                     }
@@ -682,8 +1295,8 @@ impl Box {
                             },
                             border: debug_border,
                             color: SideOffsets2D::new_all_same(rgb(0, 200, 0)),
-                            style: SideOffsets2D::new_all_same(border_style::dashed)
-
+                            style: SideOffsets2D::new_all_same(border_style::dashed),
+                            radii: BorderRadii::zero(),
                         };
                         list.append_item(BorderDisplayItemClass(border_display_item))
                     }
@@ -693,13 +1306,16 @@ impl Box {
             },
             GenericBox | IframeBox(_) => {
                 do list.with_mut_ref |list| {
+                    // The contents are clipped to the padding box, whose corners are rounded by
+                    // the inner radii of the border.
                     let item = ~ClipDisplayItem {
                         base: BaseDisplayItem {
-                            bounds: absolute_box_bounds,
+                            bounds: self.padding_box(&absolute_box_bounds),
                             extra: ExtraDisplayListData::new(self),
                         },
                         child_list: ~[],
-                        need_clip: self.needs_clip()
+                        need_clip: self.needs_clip(),
+                        radii: self.padding_radii(&absolute_box_bounds),
                     };
                     list.append_item(ClipDisplayItemClass(item));
                 }
@@ -717,8 +1333,8 @@ impl Box {
                             },
                             border: debug_border,
                             color: SideOffsets2D::new_all_same(rgb(0, 0, 200)),
-                            style: SideOffsets2D::new_all_same(border_style::solid)
-
+                            style: SideOffsets2D::new_all_same(border_style::solid),
+                            radii: BorderRadii::zero(),
                         };
                         list.append_item(BorderDisplayItemClass(border_display_item))
                     }
@@ -734,7 +1350,8 @@ impl Box {
                             extra: ExtraDisplayListData::new(self),
                         },
                         child_list: ~[],
-                        need_clip: false
+                        need_clip: false,
+                        radii: BorderRadii::zero(),
                     };
                     list.append_item(ClipDisplayItemClass(item));
                 }
@@ -751,6 +1368,10 @@ impl Box {
                                     extra: ExtraDisplayListData::new(self),
                                 },
                                 image: image.clone(),
+                                stretch_size: absolute_box_bounds.size,
+                                tile_origin: absolute_box_bounds.origin,
+                                repeat_x: false,
+                                repeat_y: false,
                             };
                             list.append_item(ImageDisplayItemClass(image_display_item))
                         }
@@ -799,6 +1420,10 @@ impl Box {
             }
             ScannedTextBox(ref text_box_info) => {
                 let range = &text_box_info.range;
+                if !self.allows_wrapping() {
+                    let width = text_box_info.run.get().metrics_for_range(range).advance_width;
+                    return (guessed_width + width, guessed_width + width)
+                }
                 let min_line_width = text_box_info.run.get().min_width_for_range(range);
 
                 let mut max_line_width = Au::new(0);
@@ -847,6 +1472,7 @@ impl Box {
         match self.specific {
             GenericBox | IframeBox(_) | ImageBox(_) => CannotSplit,
             UnscannedTextBox(_) => fail!("Unscanned text boxes should have been scanned by now!"),
+            ScannedTextBox(_) if !self.allows_wrapping() => CannotSplit,
             ScannedTextBox(ref text_box_info) => {
                 let mut pieces_processed_count: uint = 0;
                 let mut remaining_width: Au = max_width;
@@ -874,7 +1500,8 @@ impl Box {
                     if advance <= remaining_width {
                         should_continue = true;
 
-                        if starts_line && pieces_processed_count == 0 && glyphs.is_whitespace() {
+                        if starts_line && pieces_processed_count == 0 && glyphs.is_whitespace() &&
+                                !self.preserves_spaces() {
                             debug!("split_to_width: case=skipping leading trimmable whitespace");
                             left_range.shift_by(slice_range.length() as int);
                         } else {
@@ -918,7 +1545,7 @@ impl Box {
                 }
 
                 let left_box = if left_range.length() > 0 {
-                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(), left_range);
+                    let new_text_box_info = text_box_info.with_range(left_range);
                     let new_metrics = new_text_box_info.run.get().metrics_for_range(&left_range);
                     Some(self.transform(new_metrics.bounding_box.size,
                                         ScannedTextBox(new_text_box_info)))
@@ -927,7 +1554,7 @@ impl Box {
                 };
 
                 let right_box = right_range.map_default(None, |range: Range| {
-                    let new_text_box_info = ScannedTextBoxInfo::new(text_box_info.run.clone(), range);
+                    let new_text_box_info = text_box_info.with_range(range);
                     let new_metrics = new_text_box_info.run.get().metrics_for_range(&range);
                     Some(self.transform(new_metrics.bounding_box.size,
                                         ScannedTextBox(new_text_box_info)))
@@ -973,7 +1600,12 @@ impl Box {
         match (&self.specific, &other.specific) {
             (&UnscannedTextBox(_), &UnscannedTextBox(_)) => {
                 self.font_style() == other.font_style() &&
-                    self.text_decoration() == other.text_decoration()
+                    self.text_decoration() == other.text_decoration() &&
+                    self.letter_spacing() == other.letter_spacing() &&
+                    self.word_spacing() == other.word_spacing() &&
+                    self.tab_size() == other.tab_size() &&
+                    self.font_variant() == font_variant::normal &&
+                    other.font_variant() == font_variant::normal
             }
             _ => false,
         }
@@ -1049,3 +1681,258 @@ impl Box {
     }
 }
 
+/// Returns the area that a shadow of the given rectangle paints in: the rectangle moved by the
+/// offset of the shadow and grown on each side by its spread and the extent of its blur.
+fn shadow_bounds(bounds: &Rect<Au>, offset: Point2D<Au>, amount: Au) -> Rect<Au> {
+    let shadow_box = bounds.translate(&offset);
+    Rect(Point2D(shadow_box.origin.x - amount, shadow_box.origin.y - amount),
+         Size2D(Au::max(shadow_box.size.width + amount + amount, Au(0)),
+                Au::max(shadow_box.size.height + amount + amount, Au(0))))
+}
+
+/// Returns the size at which to draw a background image of the given intrinsic size, when it is
+/// positioned in an area of the given size.
+fn background_image_size(size: background_size::T, image_size: Size2D<Au>, area_size: Size2D<Au>)
+                         -> Size2D<Au> {
+    if image_size.width == Au(0) || image_size.height == Au(0) {
+        return image_size
+    }
+    let scale_x = geometry::to_frac_px(area_size.width) / geometry::to_frac_px(image_size.width);
+    let scale_y = geometry::to_frac_px(area_size.height) / geometry::to_frac_px(image_size.height);
+    match size {
+        background_size::Cover => {
+            let scale = scale_x.max(&scale_y);
+            Size2D(image_size.width.scale_by(scale), image_size.height.scale_by(scale))
+        }
+        background_size::Contain => {
+            let scale = scale_x.min(&scale_y);
+            Size2D(image_size.width.scale_by(scale), image_size.height.scale_by(scale))
+        }
+        background_size::ExplicitSize(width, height) => {
+            // An `auto` dimension keeps the aspect ratio of the image.
+            let ratio = geometry::to_frac_px(image_size.width) /
+                geometry::to_frac_px(image_size.height);
+            match (MaybeAuto::from_style(width, area_size.width),
+                   MaybeAuto::from_style(height, area_size.height)) {
+                (Specified(width), Specified(height)) => Size2D(width, height),
+                (Specified(width), Auto) => Size2D(width, width.scale_by(1.0 / ratio)),
+                (Auto, Specified(height)) => Size2D(height.scale_by(ratio), height),
+                (Auto, Auto) => image_size,
+            }
+        }
+    }
+}
+
+/// Returns the geometry and the stops of a linear gradient drawn in the given area, per CSS Images
+/// 3 section 3.1.
+fn linear_gradient(style: &ComputedValues, gradient: &LinearGradient, area: &Rect<Au>)
+                   -> (GradientShape, ~[GradientStop]) {
+    let width = geometry::to_frac_px(area.size.width);
+    let height = geometry::to_frac_px(area.size.height);
+
+    // The direction of the gradient line, as a unit vector pointing down for `to bottom`.
+    let (dx, dy) = match gradient.direction {
+        AngleDirection(angle) => (angle.sin(), -angle.cos()),
+        CornerDirection(horizontal, vertical) => {
+            // The gradient line is perpendicular to the diagonal that joins the other corners.
+            let dx = match horizontal { SideLeft => -height, SideRight => height };
+            let dy = match vertical { SideTop => -width, SideBottom => width };
+            let norm = (dx * dx + dy * dy).sqrt();
+            if norm == 0. { (0., 0.) } else { (dx / norm, dy / norm) }
+        }
+    };
+
+    // The gradient line goes through the center, and the corners are at offsets 0 and 1.
+    let length = (width * dx).abs() + (height * dy).abs();
+    let center_x = geometry::to_frac_px(area.origin.x) + width / 2.;
+    let center_y = geometry::to_frac_px(area.origin.y) + height / 2.;
+    let stops = resolve_color_stops(style, gradient.stops, Au::from_frac_px(length));
+    let (first, last, stops) = normalize_color_stops(stops);
+    let point_at = |offset: f64| {
+        Point2D(Au::from_frac_px(center_x + dx * length * (offset - 0.5)),
+                Au::from_frac_px(center_y + dy * length * (offset - 0.5)))
+    };
+    (LinearGradientShape(point_at(first), point_at(last)), stops)
+}
+
+/// Returns the geometry and the stops of a radial gradient drawn in the given area, per CSS Images
+/// 3 section 3.2, or `None` if the gradient has no area.
+fn radial_gradient(style: &ComputedValues, gradient: &RadialGradient, area: &Rect<Au>)
+                   -> Option<(GradientShape, ~[GradientStop])> {
+    let center = Point2D(area.origin.x + specified(gradient.position.horizontal, area.size.width),
+                         area.origin.y + specified(gradient.position.vertical, area.size.height));
+
+    // The distances from the center to the closest and farthest sides, in each direction.
+    let left = center.x - area.origin.x;
+    let right = area.origin.x + area.size.width - center.x;
+    let top = center.y - area.origin.y;
+    let bottom = area.origin.y + area.size.height - center.y;
+    let (left, right, top, bottom) = (abs(left), abs(right), abs(top), abs(bottom));
+    let (closest_x, farthest_x) = (Au::min(left, right), Au::max(left, right));
+    let (closest_y, farthest_y) = (Au::min(top, bottom), Au::max(top, bottom));
+
+    let (radius_x, radius_y) = match gradient.shape {
+        Circle(radius) => (radius, radius),
+        Ellipse(radius_x, radius_y) => (specified(radius_x, area.size.width),
+                                        specified(radius_y, area.size.height)),
+        CircleExtent(extent) => {
+            let radius = match extent {
+                ClosestSide => Au::min(closest_x, closest_y),
+                FarthestSide => Au::max(farthest_x, farthest_y),
+                ClosestCorner => hypot(closest_x, closest_y),
+                FarthestCorner => hypot(farthest_x, farthest_y),
+            };
+            (radius, radius)
+        }
+        EllipseExtent(extent) => ellipse_radii(extent, closest_x, closest_y, farthest_x,
+                                               farthest_y),
+    };
+    if radius_x <= Au(0) || radius_y <= Au(0) {
+        return None
+    }
+
+    let stops = resolve_color_stops(style, gradient.stops, radius_x);
+    let stops = clamp_color_stops_at_center(stops, gradient.repeating);
+    let (first, last, stops) = normalize_color_stops(stops);
+    let y_scale = geometry::to_frac_px(radius_y) / geometry::to_frac_px(radius_x);
+    Some((RadialGradientShape(center, radius_x.scale_by(first), radius_x.scale_by(last), y_scale),
+          stops))
+}
+
+/// Moves the stops of a radial gradient that lie before its center to the center, since the
+/// ending shape cannot have a negative size. A repeating gradient is shifted by whole periods; any
+/// other gradient keeps the color it has at the center.
+fn clamp_color_stops_at_center(mut stops: ~[GradientStop], repeating: bool) -> ~[GradientStop] {
+    let first = stops[0].offset;
+    let last = stops[stops.len() - 1].offset;
+    if first >= 0. {
+        return stops
+    }
+    if repeating && last > first {
+        let period = last - first;
+        let shift = (-first / period).ceil() * period;
+        for stop in stops.mut_iter() {
+            stop.offset += shift
+        }
+        return stops
+    }
+
+    // Offsets never decrease, so the stops before the center come first.
+    let after = match stops.iter().position(|stop| stop.offset >= 0.) {
+        Some(after) => after,
+        None => {
+            for stop in stops.mut_iter() {
+                stop.offset = 0.
+            }
+            return stops
+        }
+    };
+    let (before, next) = (&stops[after - 1], &stops[after]);
+    let t = -before.offset / (next.offset - before.offset);
+    let mix = |from: f32, to: f32| from + (to - from) * t;
+    let center = GradientStop {
+        offset: 0.,
+        color: rgba(mix(before.color.r, next.color.r),
+                    mix(before.color.g, next.color.g),
+                    mix(before.color.b, next.color.b),
+                    mix(before.color.a, next.color.a)),
+    };
+    ~[center] + stops.slice_from(after)
+}
+
+/// Returns the radii of an ellipse sized with the given keyword. Corner keywords keep the aspect
+/// ratio of the matching side keyword.
+fn ellipse_radii(extent: ShapeExtent, closest_x: Au, closest_y: Au, farthest_x: Au,
+                 farthest_y: Au) -> (Au, Au) {
+    match extent {
+        ClosestSide => (closest_x, closest_y),
+        FarthestSide => (farthest_x, farthest_y),
+        ClosestCorner => (closest_x.scale_by(2f64.sqrt()), closest_y.scale_by(2f64.sqrt())),
+        FarthestCorner => (farthest_x.scale_by(2f64.sqrt()), farthest_y.scale_by(2f64.sqrt())),
+    }
+}
+
+fn abs(value: Au) -> Au {
+    if value < Au(0) { Au(0) - value } else { value }
+}
+
+fn hypot(x: Au, y: Au) -> Au {
+    let (x, y) = (geometry::to_frac_px(x), geometry::to_frac_px(y));
+    Au::from_frac_px((x * x + y * y).sqrt())
+}
+
+/// Resolves color stops to offsets along a gradient line of the given length, per CSS Images 3
+/// section 3.4.3: missing offsets are spread evenly, and offsets never decrease.
+fn resolve_color_stops(style: &ComputedValues, stops: &[ColorStop], length: Au)
+                       -> ~[GradientStop] {
+    let mut offsets: ~[Option<f64>] = do stops.iter().map |stop| {
+        match stop.position {
+            Some(LP_Length(position)) if length != Au(0) => {
+                Some(geometry::to_frac_px(position) / geometry::to_frac_px(length))
+            }
+            Some(LP_Length(_)) => Some(0.),
+            Some(LP_Percentage(percentage)) => Some(percentage),
+            Some(LP_Calc(calc)) if length != Au(0) => {
+                Some(geometry::to_frac_px(calc.resolve(length)) / geometry::to_frac_px(length))
+            }
+            Some(LP_Calc(calc)) => Some(calc.percentage),
+            None => None,
+        }
+    }.collect();
+
+    let last = offsets.len() - 1;
+    if offsets[0].is_none() {
+        offsets[0] = Some(0.)
+    }
+    if offsets[last].is_none() {
+        offsets[last] = Some(1.)
+    }
+
+    let mut max_offset = offsets[0].unwrap();
+    for offset in offsets.mut_iter() {
+        match *offset {
+            Some(value) if value < max_offset => *offset = Some(max_offset),
+            Some(value) => max_offset = value,
+            None => (),
+        }
+    }
+
+    let mut i = 1;
+    while i < last {
+        if offsets[i].is_none() {
+            let mut end = i;
+            while offsets[end].is_none() {
+                end += 1
+            }
+            let (from, to) = (offsets[i - 1].unwrap(), offsets[end].unwrap());
+            let steps = (end - i + 1) as f64;
+            for j in range(i, end) {
+                offsets[j] = Some(from + (to - from) * ((j - i + 1) as f64) / steps)
+            }
+            i = end
+        }
+        i += 1
+    }
+
+    do stops.iter().zip(offsets.iter()).map |(stop, offset)| {
+        GradientStop {
+            offset: offset.unwrap() as f32,
+            color: style.resolve_color(stop.color).to_gfx_color(),
+        }
+    }.collect()
+}
+
+/// Rescales the offsets of sorted stops so that they go from 0 to 1, as the renderer expects.
+/// Returns the original offsets of the first and the last stops, where the gradient must now
+/// start and end.
+fn normalize_color_stops(mut stops: ~[GradientStop]) -> (f64, f64, ~[GradientStop]) {
+    let first = stops[0].offset as f64;
+    let last = stops[stops.len() - 1].offset as f64;
+    if last <= first {
+        return (0., 1., stops)
+    }
+    for stop in stops.mut_iter() {
+        stop.offset = ((stop.offset as f64 - first) / (last - first)) as f32
+    }
+    (first, last, stops)
+}
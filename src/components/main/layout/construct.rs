@@ -22,11 +22,14 @@
 
 use css::node_style::StyledNode;
 use layout::block::BlockFlow;
-use layout::box::{Box, GenericBox, IframeBox, IframeBoxInfo, ImageBox, ImageBoxInfo};
+use layout::box::{BidiEmbedding, Box, GenericBox, IframeBox, IframeBoxInfo, ImageBox};
+use layout::box::ImageBoxInfo;
 use layout::box::{UnscannedTextBox, UnscannedTextBoxInfo};
 use layout::context::LayoutContext;
+use layout::counters::{CounterValues, LIST_ITEM_COUNTER, counter_content_text, marker_text};
 use layout::float_context::FloatType;
-use layout::flow::{Flow, FlowData, MutableFlowUtils};
+use layout::flow::{BlockFlowClass, Flow, FlowData, InlineFlowClass, MutableFlowUtils};
+use layout::flow;
 use layout::inline::InlineFlow;
 use layout::text::TextRunScanner;
 use layout::util::LayoutDataAccess;
@@ -35,9 +38,11 @@ use layout::wrapper::{LayoutNode, PostorderNodeMutTraversal};
 use script::dom::element::{HTMLIframeElementTypeId, HTMLImageElementTypeId};
 use script::dom::node::{CommentNodeTypeId, DoctypeNodeTypeId, DocumentFragmentNodeTypeId};
 use script::dom::node::{DocumentNodeTypeId, ElementNodeTypeId, TextNodeTypeId};
+use extra::arc::Arc;
 use servo_util::slot::Slot;
 use std::util;
-use style::computed_values::{display, float};
+use style::{ComputedValues, TElement, TNode, cascade_anonymous};
+use style::computed_values::{content, display, float, list_style_position, position};
 
 /// The results of flow construction for a DOM node.
 pub enum ConstructionResult {
@@ -233,13 +238,123 @@ impl<'self> FlowConstructor<'self> {
     #[inline(always)]
     fn flush_inline_boxes_to_flow(&mut self, boxes: ~[Box], flow: &mut ~Flow:, node: LayoutNode) {
         if boxes.len() > 0 {
-            let inline_base = FlowData::new(self.next_flow_id(), node);
-            let mut inline_flow = ~InlineFlow::from_boxes(inline_base, boxes) as ~Flow:;
-            TextRunScanner::new().scan_for_runs(self.layout_context, inline_flow);
+            // The `::first-letter` and `::first-line` pseudo-elements apply only if these boxes
+            // form the first line of the block.
+            let is_first_line = !has_formatted_line(flow);
+            let boxes = if is_first_line {
+                self.build_boxes_for_first_letter(boxes, flow, node)
+            } else {
+                boxes
+            };
+
+            let mut inline_flow = self.build_inline_flow(boxes, node);
+            if is_first_line {
+                for style in node.first_line_style().move_iter() {
+                    inline_flow.as_inline().first_line_styles.push((node.style().clone(), style))
+                }
+            }
             flow.add_new_child(inline_flow)
         }
     }
 
+    /// Adds a block-level child flow to the given flow. If no line precedes it, then its first
+    /// formatted line is that of the given flow too, and gets the `::first-line` style of the
+    /// node, per CSS 2.1 § 5.12.1.
+    fn add_block_level_child(&mut self, flow: &mut ~Flow:, kid_flow: ~Flow:, node: LayoutNode) {
+        let mut kid_flow = kid_flow;
+        match node.first_line_style() {
+            Some(first_line_style) => {
+                if !has_formatted_line(flow) {
+                    let styles = (node.style().clone(), first_line_style);
+                    do with_first_formatted_line(&mut kid_flow) |inline_flow| {
+                        inline_flow.first_line_styles.push(styles.clone())
+                    };
+                }
+            }
+            None => {}
+        }
+        flow.add_new_child(kid_flow)
+    }
+
+    /// Creates an inline flow from a set of inline boxes and scans it for text runs.
+    fn build_inline_flow(&mut self, boxes: ~[Box], node: LayoutNode) -> ~Flow: {
+        let inline_base = FlowData::new(self.next_flow_id(), node);
+        let mut inline_flow = ~InlineFlow::from_boxes(inline_base, boxes) as ~Flow:;
+        inline_flow.as_inline().paragraph_embedding = BidiEmbedding::from_node(&node);
+        TextRunScanner::new().scan_for_runs(self.layout_context, inline_flow);
+        inline_flow
+    }
+
+    /// Splits the first letter of the given inline boxes off into a box of its own, styled with
+    /// the `::first-letter` style of the given node, per CSS 2.1 § 5.12.2. If the first letter is
+    /// floated, then it is moved into a float flow of its own, which is added to the given flow.
+    fn build_boxes_for_first_letter(&mut self,
+                                    boxes: ~[Box],
+                                    flow: &mut ~Flow:,
+                                    node: LayoutNode)
+                                    -> ~[Box] {
+        let first_letter_style = match node.first_letter_style() {
+            None => return boxes,
+            Some(style) => style,
+        };
+
+        // The first letter must not be preceded by other content, such as an image.
+        let index = match boxes.iter().position(|box| !box.is_whitespace_only()) {
+            None => return boxes,
+            Some(index) => index,
+        };
+        let (letter, rest) = match boxes[index].specific {
+            UnscannedTextBox(ref text_box_info) => {
+                match split_first_letter(text_box_info.text) {
+                    None => return boxes,
+                    Some((letter, rest)) => (letter.to_owned(), rest.to_owned()),
+                }
+            }
+            _ => return boxes,
+        };
+
+        let mut boxes = boxes;
+        let text_box = boxes.remove(index);
+        let size = text_box.position.get().size;
+        if rest.len() > 0 {
+            boxes.insert(index, text_box.transform(size,
+                                                   UnscannedTextBox(UnscannedTextBoxInfo::from_text(
+                                                       rest))));
+        }
+
+        match first_letter_style.get().Box.float {
+            float::none => {
+                let letter_box = text_box.transform_with_style(
+                    size,
+                    first_letter_style,
+                    UnscannedTextBox(UnscannedTextBoxInfo::from_text(letter)));
+                boxes.insert(index, letter_box)
+            }
+            float_value => {
+                // The floated letter gets a block box in the `::first-letter` style. The text
+                // inside it is anonymous, inheriting from that style.
+                let block_box = text_box.transform_with_style(size,
+                                                              first_letter_style.clone(),
+                                                              GenericBox);
+                let anonymous_style = Arc::new(cascade_anonymous(first_letter_style.get()));
+                let letter_box = text_box.transform_with_style(
+                    size,
+                    anonymous_style,
+                    UnscannedTextBox(UnscannedTextBoxInfo::from_text(letter)));
+
+                let float_type = FloatType::from_property(float_value);
+                let float_base = FlowData::new(self.next_flow_id(), node);
+                let mut float_flow = ~BlockFlow::float_from_box(float_base, float_type, block_box)
+                    as ~Flow:;
+
+                float_flow.add_new_child(self.build_inline_flow(~[letter_box], node));
+                flow.add_new_child(float_flow)
+            }
+        }
+
+        boxes
+    }
+
     /// Creates an inline flow from a set of inline boxes, if present, and adds it as a child of
     /// the given flow.
     fn flush_inline_boxes_to_flow_if_necessary(&mut self,
@@ -252,6 +367,150 @@ impl<'self> FlowConstructor<'self> {
         }
     }
 
+    /// Returns the construction results of the kids of the given node, replacing them with
+    /// `NoConstructionResult`. The results for the `::before` and `::after` pseudo-elements of the
+    /// node, if any, come first and last respectively, and a list item's marker inside its
+    /// principal box comes before them all.
+    fn swap_out_kid_construction_results(&mut self, node: LayoutNode) -> ~[ConstructionResult] {
+        let mut results = ~[];
+        match self.build_marker_box(node, list_style_position::inside) {
+            None => {}
+            Some(marker) => {
+                let construction_item = InlineBoxesConstructionItem(InlineBoxesConstructionResult {
+                    splits: None,
+                    boxes: ~[marker],
+                });
+                results.push(ConstructionItemConstructionResult(construction_item))
+            }
+        }
+        match node.before_style() {
+            None => {}
+            Some(style) => {
+                let counters = node.before_counters();
+                results.push(self.build_construction_result_for_pseudo_element(node,
+                                                                               style,
+                                                                               &counters))
+            }
+        }
+        for kid in node.children() {
+            results.push(kid.swap_out_construction_result())
+        }
+        match node.after_style() {
+            None => {}
+            Some(style) => {
+                let counters = node.after_counters();
+                results.push(self.build_construction_result_for_pseudo_element(node,
+                                                                               style,
+                                                                               &counters))
+            }
+        }
+        results
+    }
+
+    /// Builds the marker box of a node with `display: list-item`, per CSS 2.1 § 12.5, if its
+    /// marker is in the given position and its `list-style-image` or `list-style-type` calls for
+    /// one. The style of the marker is anonymous, inheriting from that of the list item.
+    fn build_marker_box(&mut self, node: LayoutNode, position: list_style_position::T)
+                        -> Option<Box> {
+        let style = node.style().get();
+        if style.Box.display != display::list_item || style.List.list_style_position != position {
+            return None
+        }
+        let specific = match style.List.list_style_image {
+            Some(ref url) => {
+                ImageBox(ImageBoxInfo::from_url(url.clone(),
+                                                self.layout_context.image_cache.clone()))
+            }
+            None => {
+                let value = node.counters().value(LIST_ITEM_COUNTER);
+                let text = marker_text(value, style.List.list_style_type);
+                if text.is_empty() {
+                    return None
+                }
+                UnscannedTextBox(UnscannedTextBoxInfo::from_text(text))
+            }
+        };
+        Some(Box::from_style(node, Arc::new(cascade_anonymous(style)), specific))
+    }
+
+    /// Builds the marker box of a list item whose marker is outside its principal box. Such a
+    /// marker belongs to no inline flow, so it is scanned for text runs in one of its own.
+    fn build_outside_marker_box(&mut self, node: LayoutNode) -> Option<Box> {
+        let marker = match self.build_marker_box(node, list_style_position::outside) {
+            None => return None,
+            Some(marker) => marker,
+        };
+        let mut marker_flow = self.build_inline_flow(~[marker], node);
+        let boxes = util::replace(&mut marker_flow.as_inline().boxes, ~[]);
+        boxes.move_iter().next()
+    }
+
+    /// Builds the construction result for a `::before` or `::after` pseudo-element of the given
+    /// node with the given style and counters, per CSS 2.1 § 12.1. Each item of the `content`
+    /// property becomes a box whose style is anonymous, inheriting from that of the
+    /// pseudo-element.
+    fn build_construction_result_for_pseudo_element(&mut self,
+                                                     node: LayoutNode,
+                                                     style: Arc<ComputedValues>,
+                                                     counters: &CounterValues)
+                                                     -> ConstructionResult {
+        // `normal` computes to `none` for these pseudo-elements.
+        let items = match style.get().Box.content {
+            content::Content(ref items) => items.clone(),
+            content::normal | content::none => return NoConstructionResult,
+        };
+
+        let anonymous_style = Arc::new(cascade_anonymous(style.get()));
+        let mut boxes = ~[];
+        for item in items.move_iter() {
+            let specific = match item {
+                content::StringContent(string) => {
+                    UnscannedTextBox(UnscannedTextBoxInfo::from_text(string))
+                }
+                content::AttrContent(name) => {
+                    let value = node.with_element(|element| element.get_attr(None, name));
+                    UnscannedTextBox(UnscannedTextBoxInfo::from_text(value.unwrap_or(~"")))
+                }
+                content::UrlContent(url) => {
+                    ImageBox(ImageBoxInfo::from_url(url, self.layout_context.image_cache.clone()))
+                }
+                content::CounterContent(*) | content::CountersContent(*) => {
+                    let text = counter_content_text(&item, counters);
+                    UnscannedTextBox(UnscannedTextBoxInfo::from_text(text))
+                }
+            };
+            boxes.push(Box::from_style(node, anonymous_style.clone(), specific))
+        }
+
+        match (style.get().Box.display, style.get().Box.float) {
+            (display::none, _) => NoConstructionResult,
+            (display::inline, float::none) => {
+                let construction_item = InlineBoxesConstructionItem(InlineBoxesConstructionResult {
+                    splits: None,
+                    boxes: boxes,
+                });
+                ConstructionItemConstructionResult(construction_item)
+            }
+            (_, float_value) => {
+                // The pseudo-element is block-level, so it gets a flow of its own, holding its
+                // content in an inline flow.
+                let base = FlowData::new(self.next_flow_id(), node);
+                let box = Box::from_style(node, style.clone(), GenericBox);
+                let mut flow = match float_value {
+                    float::none => ~BlockFlow::from_box(base, box) as ~Flow:,
+                    _ => {
+                        let float_type = FloatType::from_property(float_value);
+                        ~BlockFlow::float_from_box(base, float_type, box) as ~Flow:
+                    }
+                };
+                if boxes.len() > 0 {
+                    flow.add_new_child(self.build_inline_flow(boxes, node))
+                }
+                FlowConstructionResult(flow)
+            }
+        }
+    }
+
     /// Builds the children flows underneath a node with `display: block`. After this call,
     /// other `BlockFlow`s or `InlineFlow`s will be populated underneath this node, depending on
     /// whether {ib} splits needed to happen.
@@ -261,8 +520,8 @@ impl<'self> FlowConstructor<'self> {
         // Gather up boxes for the inline flows we might need to create.
         let mut opt_boxes_for_inline_flow = None;
         let mut first_box = true;
-        for kid in node.children() {
-            match kid.swap_out_construction_result() {
+        for kid_result in self.swap_out_kid_construction_results(node).move_iter() {
+            match kid_result {
                 NoConstructionResult => {}
                 FlowConstructionResult(kid_flow) => {
                     // Strip ignorable whitespace from the start of this flow per CSS 2.1 §
@@ -280,7 +539,7 @@ impl<'self> FlowConstructor<'self> {
                     self.flush_inline_boxes_to_flow_if_necessary(&mut opt_boxes_for_inline_flow,
                                                                  flow,
                                                                  node);
-                    flow.add_new_child(kid_flow);
+                    self.add_block_level_child(flow, kid_flow, node);
                 }
                 ConstructionItemConstructionResult(InlineBoxesConstructionItem(
                         InlineBoxesConstructionResult {
@@ -320,7 +579,7 @@ impl<'self> FlowConstructor<'self> {
 
                                 // Push the flow generated by the {ib} split onto our list of
                                 // flows.
-                                flow.add_new_child(kid_flow);
+                                self.add_block_level_child(flow, kid_flow, node);
                             }
                         }
                     }
@@ -347,6 +606,7 @@ impl<'self> FlowConstructor<'self> {
         let box = self.build_box_for_node(node);
         let mut flow = ~BlockFlow::from_box(base, box) as ~Flow:;
         self.build_children_of_block_flow(&mut flow, node);
+        flow.as_block().marker = self.build_outside_marker_box(node);
         flow
     }
 
@@ -358,6 +618,7 @@ impl<'self> FlowConstructor<'self> {
         let box = self.build_box_for_node(node);
         let mut flow = ~BlockFlow::float_from_box(base, float_type, box) as ~Flow:;
         self.build_children_of_block_flow(&mut flow, node);
+        flow.as_block().marker = self.build_outside_marker_box(node);
         flow
     }
 
@@ -370,8 +631,8 @@ impl<'self> FlowConstructor<'self> {
         let mut opt_box_accumulator = None;
 
         // Concatenate all the boxes of our kids, creating {ib} splits as necessary.
-        for kid in node.children() {
-            match kid.swap_out_construction_result() {
+        for kid_result in self.swap_out_kid_construction_results(node).move_iter() {
+            match kid_result {
                 NoConstructionResult => {}
                 FlowConstructionResult(flow) => {
                     // {ib} split. Flush the accumulator to our new split and make a new
@@ -416,6 +677,22 @@ impl<'self> FlowConstructor<'self> {
 
         // TODO(pcwalton): Add in our own borders/padding/margins if necessary.
 
+        // Per CSS 2.1 § 9.10, `unicode-bidi` opens an embedding around all of our boxes.
+        for embedding in BidiEmbedding::from_node(&node).iter() {
+            match opt_inline_block_splits {
+                None => {}
+                Some(ref mut splits) => {
+                    for split in splits.mut_iter() {
+                        push_bidi_embedding(&mut split.predecessor_boxes, embedding)
+                    }
+                }
+            }
+            match opt_box_accumulator {
+                None => {}
+                Some(ref mut boxes) => push_bidi_embedding(boxes, embedding),
+            }
+        }
+
         // Finally, make a new construction result.
         if opt_inline_block_splits.len() > 0 || opt_box_accumulator.len() > 0 {
             let construction_item = InlineBoxesConstructionItem(InlineBoxesConstructionResult {
@@ -526,6 +803,27 @@ trait NodeUtils {
     /// Replaces the flow construction result in a node with `NoConstructionResult` and returns the
     /// old value.
     fn swap_out_construction_result(self) -> ConstructionResult;
+
+    /// Returns the style of the `::before` pseudo-element of this node, if any.
+    fn before_style(self) -> Option<Arc<ComputedValues>>;
+
+    /// Returns the style of the `::after` pseudo-element of this node, if any.
+    fn after_style(self) -> Option<Arc<ComputedValues>>;
+
+    /// Returns the style of the `::first-line` pseudo-element of this node, if any.
+    fn first_line_style(self) -> Option<Arc<ComputedValues>>;
+
+    /// Returns the style of the `::first-letter` pseudo-element of this node, if any.
+    fn first_letter_style(self) -> Option<Arc<ComputedValues>>;
+
+    /// Returns the counters in scope for this node, if it is a list item.
+    fn counters(self) -> CounterValues;
+
+    /// Returns the counters displayed by the `::before` pseudo-element of this node.
+    fn before_counters(self) -> CounterValues;
+
+    /// Returns the counters displayed by the `::after` pseudo-element of this node.
+    fn after_counters(self) -> CounterValues;
 }
 
 impl<'self> NodeUtils for LayoutNode<'self> {
@@ -558,6 +856,68 @@ impl<'self> NodeUtils for LayoutNode<'self> {
             None => fail!("no layout data"),
         }
     }
+
+    fn before_style(self) -> Option<Arc<ComputedValues>> {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => layout_data.before_style.clone(),
+            None => fail!("no layout data"),
+        }
+    }
+
+    fn after_style(self) -> Option<Arc<ComputedValues>> {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => layout_data.after_style.clone(),
+            None => fail!("no layout data"),
+        }
+    }
+
+    fn first_line_style(self) -> Option<Arc<ComputedValues>> {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => layout_data.first_line_style.clone(),
+            None => fail!("no layout data"),
+        }
+    }
+
+    fn first_letter_style(self) -> Option<Arc<ComputedValues>> {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => layout_data.first_letter_style.clone(),
+            None => fail!("no layout data"),
+        }
+    }
+
+    fn counters(self) -> CounterValues {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => {
+                layout_data.counters.clone().unwrap_or_else(|| CounterValues::new())
+            }
+            None => fail!("no layout data"),
+        }
+    }
+
+    fn before_counters(self) -> CounterValues {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => {
+                layout_data.before_counters.clone().unwrap_or_else(|| CounterValues::new())
+            }
+            None => fail!("no layout data"),
+        }
+    }
+
+    fn after_counters(self) -> CounterValues {
+        match *self.borrow_layout_data().ptr {
+            Some(ref layout_data) => {
+                layout_data.after_counters.clone().unwrap_or_else(|| CounterValues::new())
+            }
+            None => fail!("no layout data"),
+        }
+    }
+}
+
+/// Makes the given embedding the outermost embedding of each of the given boxes.
+fn push_bidi_embedding(boxes: &mut ~[Box], embedding: &BidiEmbedding) {
+    for box in boxes.mut_iter() {
+        box.bidi_embeddings.unshift(embedding.clone())
+    }
 }
 
 /// Strips ignorable whitespace from the start of a list of boxes.
@@ -599,3 +959,68 @@ fn strip_ignorable_whitespace_from_end(opt_boxes: &mut Option<~[Box]>) {
     }
 }
 
+/// Calls the given function with the inline flow that holds the first formatted line of the given
+/// flow, if it has one, per CSS 2.1 § 5.12.1. That is its first in-flow child if it is an inline
+/// flow, or else the first formatted line of that child. Floated and absolutely positioned
+/// children are skipped, and so are children that hold no line. Returns whether there was one.
+fn with_first_formatted_line(flow: &mut ~Flow:, f: &fn(&mut InlineFlow)) -> bool {
+    for kid in flow::mut_base(*flow).child_iter() {
+        match kid.class() {
+            InlineFlowClass => {
+                f(kid.as_inline());
+                return true
+            }
+            BlockFlowClass => {
+                let out_of_flow = {
+                    let block = kid.as_block();
+                    block.is_float() || block.box.iter().any(|box| {
+                        match box.style().Box.position {
+                            position::absolute | position::fixed => true,
+                            position::static_ | position::relative => false,
+                        }
+                    })
+                };
+                if !out_of_flow && with_first_formatted_line(kid, |inline_flow| f(inline_flow)) {
+                    return true
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Returns whether the given flow holds a line already, which is then its first formatted line.
+fn has_formatted_line(flow: &mut ~Flow:) -> bool {
+    with_first_formatted_line(flow, |_| ())
+}
+
+/// Splits the given text into its first letter and the remainder, per CSS 2.1 § 5.12.2. The first
+/// letter includes any punctuation immediately preceding or following it. Leading whitespace is
+/// dropped. Returns `None` if the text doesn't start with a letter or digit.
+fn split_first_letter<'a>(text: &'a str) -> Option<(&'a str, &'a str)> {
+    fn is_punctuation(ch: char) -> bool {
+        !ch.is_whitespace() && !ch.is_alphanumeric()
+    }
+
+    let text = text.trim_left();
+    let mut found_letter = false;
+    let mut end = text.len();
+    for (i, ch) in text.char_offset_iter() {
+        if found_letter {
+            if !is_punctuation(ch) {
+                end = i;
+                break
+            }
+        } else if ch.is_alphanumeric() {
+            found_letter = true
+        } else if !is_punctuation(ch) {
+            return None
+        }
+    }
+
+    if !found_letter {
+        return None
+    }
+    Some((text.slice_to(end), text.slice_from(end)))
+}
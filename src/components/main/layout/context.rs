@@ -6,10 +6,13 @@
 
 use extra::arc::MutexArc;
 use geom::rect::Rect;
+use gfx::font::FontStyle;
 use gfx::font_context::FontContext;
 use servo_msg::constellation_msg::ConstellationChan;
 use servo_net::local_image_cache::LocalImageCache;
 use servo_util::geometry::Au;
+use style::computed_values::font_family;
+use style::{FontMetricsProvider, style_structs};
 
 /// Data needed by the layout task.
 pub struct LayoutContext {
@@ -20,3 +23,32 @@ pub struct LayoutContext {
     /// A channel up to the constellation.
     constellation_chan: ConstellationChan,
 }
+
+impl FontMetricsProvider for LayoutContext {
+    fn zero_advance(&mut self, font: &style_structs::Font) -> Option<Au> {
+        let font_style = FontStyle {
+            pt_size: font.font_size.to_f64().unwrap() / 60.0,
+            weight: font.font_weight,
+            style: font.font_style,
+            stretch: font.font_stretch,
+            families: do font.font_family.map |family| {
+                match *family {
+                    font_family::FamilyName(ref name) => (*name).clone(),
+                }
+            },
+        };
+        let fontgroup = self.font_ctx.get_resolved_font_for_style(&font_style);
+        do fontgroup.with_borrow |fontgroup| {
+            if fontgroup.fonts.is_empty() {
+                None
+            } else {
+                do fontgroup.fonts[0].with_mut_borrow |font| {
+                    match font.glyph_index('0') {
+                        Some(glyph) => Some(Au::from_frac_px(font.glyph_h_advance(glyph))),
+                        None => None,
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,413 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CSS counters and list numbering, per CSS 2.1 § 12.4 and § 12.5.
+//!
+//! The scope of a counter runs in document order, from the element that resets it through its
+//! following siblings and their descendants, so counters can't be resolved during the bottom-up
+//! flow construction traversal. Instead, `resolve_counters` walks the DOM in preorder beforehand
+//! and records the counters in scope for each list item and for each `::before` and `::after`
+//! pseudo-element that displays counters. Flow construction reads them back out of the layout
+//! data.
+//!
+//! List items are numbered by the implicit `list-item` counter, which HTML lists reset and list
+//! items increment. The `start` and `reversed` attributes of `<ol>` and the `value` attribute of
+//! `<li>` adjust it as HTML specifies.
+
+use css::node_style::StyledNode;
+use layout::util::LayoutDataAccess;
+use layout::wrapper::LayoutNode;
+
+use script::dom::element::{HTMLLIElementTypeId, HTMLOListElementTypeId};
+use script::dom::node::ElementNodeTypeId;
+use std::ascii::StrAsciiExt;
+use std::from_str::FromStr;
+use style::{ComputedValues, TElement, TNode};
+use style::computed_values::{content, display, list_style_type};
+
+/// The name of the counter that numbers list items.
+pub static LIST_ITEM_COUNTER: &'static str = "list-item";
+
+/// One instance of a counter. Nested instances of the same counter each get one of these.
+#[deriving(Clone)]
+struct CounterInstance {
+    name: ~str,
+    value: int,
+    /// Whether list items count down this counter, as in `<ol reversed>`.
+    reversed: bool,
+}
+
+/// The values of the counters in scope at some point in the document.
+#[deriving(Clone)]
+pub struct CounterValues {
+    /// The counter instances in scope, outermost first.
+    priv instances: ~[CounterInstance],
+}
+
+impl CounterValues {
+    /// Returns a set of counter values with no counters in scope.
+    pub fn new() -> CounterValues {
+        CounterValues {
+            instances: ~[],
+        }
+    }
+
+    /// Returns the value of the innermost instance of the named counter, as used by `counter()`.
+    /// A counter that is not in scope has the value zero.
+    pub fn value(&self, name: &str) -> int {
+        match innermost_instance(self.instances.as_slice(), name) {
+            Some(index) => self.instances[index].value,
+            None => 0,
+        }
+    }
+
+    /// Returns the values of all nested instances of the named counter, outermost first, as used
+    /// by `counters()`. A counter that is not in scope has the single value zero.
+    pub fn values(&self, name: &str) -> ~[int] {
+        let values: ~[int] = do self.instances.iter().filter_map |instance| {
+            if instance.name.as_slice() == name {
+                Some(instance.value)
+            } else {
+                None
+            }
+        }.collect();
+        if values.is_empty() {
+            ~[0]
+        } else {
+            values
+        }
+    }
+}
+
+/// Resolves the counters of the tree rooted at the given node, storing their values in the layout
+/// data of the nodes that display them.
+pub fn resolve_counters(root: LayoutNode) {
+    let mut instances = ~[];
+    resolve_counters_for_node(root, &mut instances, 0)
+}
+
+/// Resolves the counters of the given node and its descendants. `instances` holds the counter
+/// instances in scope; those at or after `sibling_scope` were created by the preceding siblings of
+/// the node, so a reset of the same counter replaces them instead of nesting.
+fn resolve_counters_for_node(node: LayoutNode,
+                             instances: &mut ~[CounterInstance],
+                             sibling_scope: uint) {
+    match node.type_id() {
+        ElementNodeTypeId(_) => {}
+        _ => {
+            // The document has no style but may contain elements.
+            if node.is_document() {
+                let children_scope = instances.len();
+                for kid in node.children() {
+                    resolve_counters_for_node(kid, instances, children_scope)
+                }
+                instances.truncate(children_scope)
+            }
+            return
+        }
+    }
+
+    // Elements that generate no boxes don't affect counters, per CSS 2.1 § 12.4.3.
+    let style = node.style().clone();
+    if style.get().Box.display == display::none {
+        return
+    }
+
+    apply_counter_properties(Some(node), style.get(), instances, sibling_scope);
+
+    let counters = if style.get().Box.display == display::list_item {
+        Some(CounterValues {
+            instances: instances.clone(),
+        })
+    } else {
+        None
+    };
+
+    // The pseudo-elements are the first and last children of the element.
+    let (before_style, after_style) = match *node.borrow_layout_data().ptr {
+        Some(ref layout_data) => {
+            (layout_data.before_style.clone(), layout_data.after_style.clone())
+        }
+        None => fail!("no layout data"),
+    };
+    let children_scope = instances.len();
+    let before_counters = before_style.and_then(|before_style| {
+        resolve_counters_for_pseudo_element(before_style.get(), instances, children_scope)
+    });
+    for kid in node.children() {
+        resolve_counters_for_node(kid, instances, children_scope)
+    }
+    let after_counters = after_style.and_then(|after_style| {
+        resolve_counters_for_pseudo_element(after_style.get(), instances, children_scope)
+    });
+    instances.truncate(children_scope);
+
+    match *node.mutate_layout_data().ptr {
+        Some(ref mut layout_data) => {
+            layout_data.counters = counters;
+            layout_data.before_counters = before_counters;
+            layout_data.after_counters = after_counters;
+        }
+        None => fail!("no layout data"),
+    }
+}
+
+/// Applies the counter properties of a `::before` or `::after` pseudo-element with the given style
+/// and returns the counter values it displays, if any.
+fn resolve_counters_for_pseudo_element(style: &ComputedValues,
+                                       instances: &mut ~[CounterInstance],
+                                       sibling_scope: uint)
+                                       -> Option<CounterValues> {
+    let items = match style.Box.content {
+        content::Content(ref items) if style.Box.display != display::none => items,
+        _ => return None,
+    };
+    apply_counter_properties(None, style, instances, sibling_scope);
+
+    let displays_counters = do items.iter().any |item| {
+        match *item {
+            content::CounterContent(*) | content::CountersContent(*) => true,
+            _ => false,
+        }
+    };
+    if displays_counters {
+        Some(CounterValues {
+            instances: instances.clone(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Applies `counter-reset`, then `counter-increment`, then the implicit increment of the
+/// `list-item` counter by list items. `element` is `None` for pseudo-elements, which have no
+/// attributes.
+fn apply_counter_properties(element: Option<LayoutNode>,
+                            style: &ComputedValues,
+                            instances: &mut ~[CounterInstance],
+                            sibling_scope: uint) {
+    let ordered_list = element.filtered(|node| {
+        node.type_id() == ElementNodeTypeId(HTMLOListElementTypeId)
+    });
+    for &(ref name, value) in style.Box.counter_reset.iter() {
+        if ordered_list.is_some() && name.as_slice() == LIST_ITEM_COUNTER {
+            let (start, reversed) = ordered_list_start(ordered_list.unwrap());
+            let step = if reversed { -1 } else { 1 };
+            reset_counter(instances, sibling_scope, name.as_slice(), start - step, reversed)
+        } else {
+            reset_counter(instances, sibling_scope, name.as_slice(), value, false)
+        }
+    }
+
+    let mut increments_list_item = false;
+    for &(ref name, value) in style.Box.counter_increment.iter() {
+        increments_list_item = increments_list_item || name.as_slice() == LIST_ITEM_COUNTER;
+        increment_counter(instances, name.as_slice(), value)
+    }
+
+    if style.Box.display != display::list_item || increments_list_item {
+        return
+    }
+    match element.and_then(list_item_value) {
+        Some(value) => set_counter(instances, LIST_ITEM_COUNTER, value),
+        None => {
+            let reversed = match innermost_instance(instances.as_slice(), LIST_ITEM_COUNTER) {
+                Some(index) => instances[index].reversed,
+                None => false,
+            };
+            increment_counter(instances, LIST_ITEM_COUNTER, if reversed { -1 } else { 1 })
+        }
+    }
+}
+
+/// Returns the index of the innermost instance of the named counter, if it is in scope.
+fn innermost_instance(instances: &[CounterInstance], name: &str) -> Option<uint> {
+    instances.iter().rposition(|instance| instance.name.as_slice() == name)
+}
+
+/// Creates a new instance of the named counter, replacing any instance created by a preceding
+/// sibling.
+fn reset_counter(instances: &mut ~[CounterInstance],
+                 sibling_scope: uint,
+                 name: &str,
+                 value: int,
+                 reversed: bool) {
+    let instance = CounterInstance {
+        name: name.to_owned(),
+        value: value,
+        reversed: reversed,
+    };
+    match innermost_instance(instances.as_slice(), name) {
+        Some(index) if index >= sibling_scope => instances[index] = instance,
+        _ => instances.push(instance),
+    }
+}
+
+/// Increments the innermost instance of the named counter. If the counter is not in scope, the
+/// element instantiates it first, per CSS 2.1 § 12.4.1.
+fn increment_counter(instances: &mut ~[CounterInstance], name: &str, amount: int) {
+    match innermost_instance(instances.as_slice(), name) {
+        Some(index) => instances[index].value += amount,
+        None => {
+            instances.push(CounterInstance {
+                name: name.to_owned(),
+                value: amount,
+                reversed: false,
+            })
+        }
+    }
+}
+
+/// Sets the innermost instance of the named counter to the given value, instantiating it if it is
+/// not in scope.
+fn set_counter(instances: &mut ~[CounterInstance], name: &str, value: int) {
+    match innermost_instance(instances.as_slice(), name) {
+        Some(index) => instances[index].value = value,
+        None => {
+            instances.push(CounterInstance {
+                name: name.to_owned(),
+                value: value,
+                reversed: false,
+            })
+        }
+    }
+}
+
+/// Returns the ordinal value of the first item of the given `<ol>` element and whether the list is
+/// reversed. A reversed list without a `start` attribute counts down from its number of items.
+fn ordered_list_start(node: LayoutNode) -> (int, bool) {
+    let (start, reversed) = do node.with_element |element| {
+        let start = element.get_attr(None, "start").and_then(|string| {
+            let start: Option<int> = FromStr::from_str(string.trim());
+            start
+        });
+        (start, element.get_attr(None, "reversed").is_some())
+    };
+    match start {
+        Some(start) => (start, reversed),
+        None if reversed => {
+            let mut item_count = 0;
+            for kid in node.children() {
+                if kid.type_id() == ElementNodeTypeId(HTMLLIElementTypeId) {
+                    item_count += 1
+                }
+            }
+            (item_count, true)
+        }
+        None => (1, false),
+    }
+}
+
+/// Returns the ordinal value given by the `value` attribute of an `<li>` element, if any.
+fn list_item_value(node: LayoutNode) -> Option<int> {
+    if node.type_id() != ElementNodeTypeId(HTMLLIElementTypeId) {
+        return None
+    }
+    do node.with_element |element| {
+        element.get_attr(None, "value").and_then(|string| {
+            let value: Option<int> = FromStr::from_str(string.trim());
+            value
+        })
+    }
+}
+
+/// Formats a counter value in the given style, per CSS 2.1 § 12.6.2. Values that the alphabetic
+/// and roman styles can't represent fall back to decimal.
+pub fn format_counter(value: int, style: list_style_type::T) -> ~str {
+    match style {
+        list_style_type::none => ~"",
+        list_style_type::disc => ~"\u2022",
+        list_style_type::circle => ~"\u25e6",
+        list_style_type::square => ~"\u25aa",
+        list_style_type::decimal => value.to_str(),
+        list_style_type::lower_roman => {
+            roman(value).unwrap_or_else(|| value.to_str())
+        }
+        list_style_type::upper_roman => {
+            roman(value).map(|numeral| numeral.to_ascii_upper()).unwrap_or_else(|| value.to_str())
+        }
+        list_style_type::lower_alpha | list_style_type::lower_latin => {
+            alphabetic(value, "abcdefghijklmnopqrstuvwxyz").unwrap_or_else(|| value.to_str())
+        }
+        list_style_type::upper_alpha | list_style_type::upper_latin => {
+            alphabetic(value, "ABCDEFGHIJKLMNOPQRSTUVWXYZ").unwrap_or_else(|| value.to_str())
+        }
+        list_style_type::lower_greek => {
+            // Alpha to omega, without the final sigma.
+            alphabetic(value, "\u03b1\u03b2\u03b3\u03b4\u03b5\u03b6\u03b7\u03b8\u03b9\u03ba\
+                               \u03bb\u03bc\u03bd\u03be\u03bf\u03c0\u03c1\u03c3\u03c4\u03c5\
+                               \u03c6\u03c7\u03c8\u03c9").unwrap_or_else(|| value.to_str())
+        }
+    }
+}
+
+/// Returns the text of the marker of a list item with the given ordinal value: the bullet for the
+/// glyph styles, and the formatted number followed by a period otherwise. The trailing space
+/// separates the marker from the content of the list item.
+pub fn marker_text(value: int, style: list_style_type::T) -> ~str {
+    match style {
+        list_style_type::none => ~"",
+        list_style_type::disc | list_style_type::circle | list_style_type::square => {
+            format!("{:s} ", format_counter(value, style))
+        }
+        _ => format!("{:s}. ", format_counter(value, style)),
+    }
+}
+
+/// Formats a value from 1 to 3999 as a lowercase roman numeral.
+fn roman(value: int) -> Option<~str> {
+    static NUMERALS: [(int, &'static str), ..13] = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"), (50, "l"),
+        (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    if value < 1 || value > 3999 {
+        return None
+    }
+    let mut result = ~"";
+    let mut value = value;
+    for &(numeral_value, numeral) in NUMERALS.iter() {
+        while value >= numeral_value {
+            result.push_str(numeral);
+            value -= numeral_value
+        }
+    }
+    Some(result)
+}
+
+/// Formats a positive value in the bijective base of the given alphabet: a, b, ..., z, aa, ab....
+fn alphabetic(value: int, alphabet: &str) -> Option<~str> {
+    if value < 1 {
+        return None
+    }
+    let alphabet: ~[char] = alphabet.iter().collect();
+    let base = alphabet.len() as int;
+    let mut digits = ~[];
+    let mut value = value;
+    while value > 0 {
+        value -= 1;
+        digits.push(alphabet[value % base]);
+        value /= base
+    }
+    let mut result = ~"";
+    for &digit in digits.rev_iter() {
+        result.push_char(digit)
+    }
+    Some(result)
+}
+
+/// Returns the text of the given `counter()` or `counters()` item of the `content` property.
+pub fn counter_content_text(item: &content::Content, counters: &CounterValues) -> ~str {
+    match *item {
+        content::CounterContent(ref name, style) => {
+            format_counter(counters.value(name.as_slice()), style)
+        }
+        content::CountersContent(ref name, ref separator, style) => {
+            let values = do counters.values(name.as_slice()).map |&value| {
+                format_counter(value, style)
+            };
+            values.connect(separator.as_slice())
+        }
+        _ => fail!("not a counter"),
+    }
+}
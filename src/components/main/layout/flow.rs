@@ -39,12 +39,13 @@ use extra::dlist::{DList, DListIterator, MutDListIterator};
 use extra::container::Deque;
 use geom::point::Point2D;
 use geom::rect::Rect;
-use gfx::display_list::{ClipDisplayItemClass, DisplayList};
+use gfx::display_list::DisplayList;
 use servo_util::geometry::Au;
+use servo_util::geometry;
 use std::cast;
 use std::cell::Cell;
 use style::ComputedValues;
-use style::computed_values::text_align;
+use style::computed_values::{direction, text_align};
 
 /// Virtual methods that make up a float context.
 ///
@@ -317,7 +318,7 @@ static TEXT_DECORATION_OVERRIDE_BITMASK: u8 = 0b00001110;
 /// The bitmask of flags that represent the text alignment field.
 ///
 /// NB: If you update this field, you must update the bitfields below.
-static TEXT_ALIGN_BITMASK: u8 = 0b00110000;
+static TEXT_ALIGN_BITMASK: u8 = 0b01110000;
 
 /// The number of bits we must shift off to handle the text alignment field.
 ///
@@ -332,6 +333,7 @@ impl FlowFlags {
         flags.set_override_underline(text_decoration.underline);
         flags.set_override_overline(text_decoration.overline);
         flags.set_override_line_through(text_decoration.line_through);
+        flags.set_rtl(style.Box.direction == direction::rtl);
         flags
     }
 
@@ -364,6 +366,10 @@ bitfield!(FlowFlags, override_overline, set_override_overline, 0x04)
 // NB: If you update this, you need to update TEXT_DECORATION_OVERRIDE_BITMASK.
 bitfield!(FlowFlags, override_line_through, set_override_line_through, 0x08)
 
+// Whether the direction of this flow is right-to-left, from the `direction` property of the node
+// that created it. For an inline flow, that is the block container of its boxes.
+bitfield!(FlowFlags, rtl, set_rtl, 0x80)
+
 // The text alignment for this flow.
 impl FlowFlags {
     #[inline]
@@ -454,6 +460,14 @@ impl FlowData {
         }
     }
 
+    /// Returns the overflow of this flow in absolute coordinates. Nothing that this flow or its
+    /// descendants paint lies outside of it.
+    pub fn abs_overflow(&self) -> Rect<Au> {
+        let containing_block_origin = Point2D(self.abs_position.x - self.position.origin.x,
+                                              self.abs_position.y - self.position.origin.y);
+        self.overflow.translate(&containing_block_origin)
+    }
+
     pub fn child_iter<'a>(&'a mut self) -> MutDListIterator<'a,~Flow:> {
         self.children.mut_iter()
     }
@@ -575,8 +589,41 @@ impl<'self> MutableFlowUtils for &'self mut Flow {
     fn store_overflow(self, _: &mut LayoutContext) {
         let my_position = mut_base(self).position;
         let mut overflow = my_position;
+
+        // Shadows paint outside of the boxes that cast them.
+        let add_box_overflow = |overflow: Rect<Au>, box: &Box| {
+            let bounds = box.position.get().translate(&my_position.origin);
+            overflow.union(&box.visual_overflow(&bounds))
+        };
+        match self.class() {
+            BlockFlowClass => {
+                for box in self.as_block().box.iter() {
+                    overflow = add_box_overflow(overflow, box)
+                }
+            }
+            InlineFlowClass => {
+                for box in self.as_inline().boxes.iter() {
+                    overflow = add_box_overflow(overflow, box)
+                }
+            }
+            _ => {}
+        }
+
         for kid in mut_base(self).child_iter() {
             let mut kid_overflow = base(*kid).overflow;
+            match kid.class() {
+                BlockFlowClass => {
+                    // The overflow of a transformed block is where its content is painted.
+                    let kid_position = base(*kid).position;
+                    for box in kid.as_block().box.iter() {
+                        let border_box = box.position.get().translate(&kid_position.origin);
+                        for matrix in box.transform(&border_box).iter() {
+                            kid_overflow = geometry::transform_rect(matrix, &kid_overflow)
+                        }
+                    }
+                }
+                _ => {}
+            }
             kid_overflow = kid_overflow.translate(&my_position.origin);
             overflow = overflow.union(&kid_overflow)
         }
@@ -606,20 +653,9 @@ impl<'self> MutableFlowUtils for &'self mut Flow {
         }
 
         do list.with_mut_ref |list| {
-            let result = list.list.mut_rev_iter().position(|item| {
-                match *item {
-                    ClipDisplayItemClass(ref mut item) => {
-                        item.child_list.push_all_move(child_list.take().list);
-                        true
-                    },
-                    _ => false,
-                }
-            });
-
-            if result.is_none() {
+            if !list.append_to_last_clip(child_list.take().list) {
                 fail!("fail to find parent item");
             }
-
         }
         true
     }
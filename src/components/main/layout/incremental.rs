@@ -125,7 +125,8 @@ pub fn compute_damage(old: &ComputedValues, new: &ComputedValues) -> RestyleDama
     add_if_not_equal!(old, new, damage, [ Repaint ],
         [ Color.color, Background.background_color,
           Border.border_top_color, Border.border_right_color,
-          Border.border_bottom_color, Border.border_left_color ]);
+          Border.border_bottom_color, Border.border_left_color,
+          Box.opacity, Box.transform, Box.transform_origin ]);
 
     add_if_not_equal!(old, new, damage, [ Repaint, BubbleWidths, Reflow ],
         [ Border.border_top_width, Border.border_right_width,
@@ -133,8 +134,13 @@ pub fn compute_damage(old: &ComputedValues, new: &ComputedValues) -> RestyleDama
           Margin.margin_top, Margin.margin_right, Margin.margin_bottom, Margin.margin_left,
           Padding.padding_top, Padding.padding_right, Padding.padding_bottom, Padding.padding_left,
           Box.position, Box.width, Box.height, Box.float, Box.display,
+          Box.direction, Box.unicode_bidi,
           Font.font_family, Font.font_size, Font.font_style, Font.font_weight,
-          Text.text_align, Text.text_decoration, Box.line_height ]);
+          Font.font_variant, Font.font_stretch, Text.text_align, Text.text_decoration,
+          Text.letter_spacing, Text.word_spacing, Text.text_transform, Text.white_space,
+          Text.tab_size,
+          Box.line_height, Box.counter_reset, Box.counter_increment,
+          List.list_style_type, List.list_style_position, List.list_style_image ]);
 
     // FIXME: test somehow that we checked every CSS property
 
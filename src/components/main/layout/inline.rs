@@ -3,8 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use css::node_style::StyledNode;
-use layout::box::{Box, CannotSplit, GenericBox, IframeBox, ImageBox, ScannedTextBox, SplitDidFit};
-use layout::box::{SplitDidNotFit, UnscannedTextBox};
+use layout::box::{BidiEmbedding, Box, CannotSplit, GenericBox, IframeBox, ImageBox};
+use layout::box::{ScannedTextBox, SplitDidFit, SplitDidNotFit, UnscannedTextBox};
 use layout::context::LayoutContext;
 use layout::display_list_builder::{DisplayListBuilder, ExtraDisplayListData};
 use layout::flow::{FlowClass, Flow, FlowData, InlineFlowClass};
@@ -13,15 +13,19 @@ use layout::float_context::FloatContext;
 use layout::util::{ElementMapping};
 use layout::float_context::{PlacementInfo, FloatLeft};
 
+use extra::arc::Arc;
 use extra::container::Deque;
 use extra::ringbuf::RingBuf;
 use geom::{Point2D, Rect, Size2D};
 use gfx::display_list::DisplayList;
+use gfx::text::bidi;
 use servo_util::geometry::Au;
 use servo_util::range::Range;
 use std::cell::Cell;
+use std::ptr;
 use std::u16;
 use std::util;
+use style::{ComputedValues, apply_first_line_style};
 use style::computed_values::{text_align, vertical_align};
 
 /// Lineboxes are represented as offsets into the child list, rather than
@@ -62,6 +66,10 @@ struct LineboxScanner {
     pending_line: LineBox,
     lines: ~[LineBox],
     cur_y: Au,
+    /// The `::first-line` style of each box restyled for the first line, paired with that box's
+    /// original style. Any remainder of a box that gets pushed to the next line reverts to the
+    /// original style paired with its own `::first-line` style.
+    first_line_original_styles: ~[(Arc<ComputedValues>, Arc<ComputedValues>)],
 }
 
 impl LineboxScanner {
@@ -76,7 +84,8 @@ impl LineboxScanner {
                 green_zone: Size2D(Au::new(0), Au::new(0))
             },
             lines: ~[],
-            cur_y: Au::new(0)
+            cur_y: Au::new(0),
+            first_line_original_styles: ~[],
         }
     }
 
@@ -90,6 +99,7 @@ impl LineboxScanner {
         self.pending_line.green_zone = Size2D(Au::new(0), Au::new(0));
         self.lines.truncate(0);
         self.cur_y = Au::new(0);
+        self.first_line_original_styles = ~[];
     }
 
     pub fn floats_out(&mut self) -> FloatContext {
@@ -101,6 +111,7 @@ impl LineboxScanner {
         self.lines = ~[];
         self.new_boxes = ~[];
         self.cur_y = Au::new(0);
+        self.first_line_original_styles = ~[];
         self.reset_linebox();
     }
 
@@ -110,7 +121,7 @@ impl LineboxScanner {
         self.pending_line.green_zone = Size2D(Au::new(0), Au::new(0))
     }
 
-    pub fn scan_for_lines(&mut self, flow: &mut InlineFlow) {
+    pub fn scan_for_lines(&mut self, ctx: &mut LayoutContext, flow: &mut InlineFlow) {
         self.reset_scanner(flow);
 
         loop {
@@ -121,7 +132,7 @@ impl LineboxScanner {
                 }
                 let box = flow.boxes.remove(0); // FIXME: use a linkedlist
                 debug!("LineboxScanner: Working with box from box list: b{}", box.debug_id());
-                box
+                self.style_box_for_first_line(ctx, box, flow)
             } else {
                 let box = self.work_list.pop_front().unwrap();
                 debug!("LineboxScanner: Working with box from work list: b{}", box.debug_id());
@@ -132,7 +143,7 @@ impl LineboxScanner {
             if !box_was_appended {
                 debug!("LineboxScanner: Box wasn't appended, because line {:u} was full.",
                         self.lines.len());
-                self.flush_current_line();
+                self.flush_current_line(ctx);
             } else {
                 debug!("LineboxScanner: appended a box to line {:u}", self.lines.len());
             }
@@ -141,7 +152,7 @@ impl LineboxScanner {
         if self.pending_line.range.length() > 0 {
             debug!("LineboxScanner: Partially full linebox {:u} left at end of scanning.",
                     self.lines.len());
-            self.flush_current_line();
+            self.flush_current_line(ctx);
         }
 
         flow.elems.repair_for_box_changes(flow.boxes, self.new_boxes);
@@ -158,7 +169,28 @@ impl LineboxScanner {
         util::swap(&mut flow.lines, &mut self.lines);
     }
 
-    fn flush_current_line(&mut self) {
+    /// If no line has been flushed yet and the flow has `::first-line` styles, restyles the given
+    /// box with them, per CSS 2.1 § 5.12.1.
+    fn style_box_for_first_line(&mut self, ctx: &mut LayoutContext, box: Box, flow: &InlineFlow)
+                                -> Box {
+        if self.lines.len() > 0 || flow.first_line_styles.is_empty() {
+            return box
+        }
+        // The styles of the innermost block container come first, so that its `::first-line`
+        // style wins over those of its ancestors.
+        let mut style = box.style().clone();
+        for &(ref block_style, ref first_line_style) in flow.first_line_styles.iter() {
+            style = apply_first_line_style(&style, block_style.get(), first_line_style.get())
+        }
+        if style == *box.style() {
+            return box
+        }
+        let style = Arc::new(style);
+        self.first_line_original_styles.push((style.clone(), box.style.clone()));
+        box.restyle(ctx, style)
+    }
+
+    fn flush_current_line(&mut self, ctx: &mut LayoutContext) {
         debug!("LineboxScanner: Flushing line {:u}: {:?}",
                self.lines.len(), self.pending_line);
 
@@ -167,6 +199,23 @@ impl LineboxScanner {
         self.lines.push(self.pending_line);
         self.cur_y = self.pending_line.bounds.origin.y + self.pending_line.bounds.size.height;
         self.reset_linebox();
+
+        // Whatever was deferred past the first line is no longer subject to `::first-line`.
+        if self.lines.len() == 1 {
+            let original_styles = util::replace(&mut self.first_line_original_styles, ~[]);
+            for box in self.work_list.mut_iter() {
+                let style = ptr::to_unsafe_ptr(box.style.get());
+                let original_style = original_styles.iter().find(|&&(ref first_line_style, _)| {
+                    ptr::to_unsafe_ptr(first_line_style.get()) == style
+                });
+                match original_style {
+                    None => {}
+                    Some(&(_, ref original_style)) => {
+                        *box = box.restyle(ctx, original_style.clone())
+                    }
+                }
+            }
+        }
     }
 
     // FIXME(eatkinson): this assumes that the tallest box in the line determines the line height
@@ -328,6 +377,12 @@ impl LineboxScanner {
             self.pending_line.green_zone = line_bounds.size;
         }
 
+        if in_box.is_forced_line_break() {
+            debug!("LineboxScanner: case=preserved newline, ending line {:u}", self.lines.len());
+            self.push_box_to_line(in_box);
+            return false
+        }
+
         debug!("LineboxScanner: Trying to append box to line {:u} (box size: {}, green zone: \
                 {}): {:s}",
                self.lines.len(),
@@ -445,6 +500,14 @@ pub struct InlineFlow {
     // must be well-nested, and are only related to the content of
     // boxes (not lines). Ranges are only kept for non-leaf elements.
     elems: ElementMapping,
+
+    /// The styles of the block containers whose first formatted line this flow holds and of their
+    /// `::first-line` pseudo-elements, innermost first.
+    first_line_styles: ~[(Arc<ComputedValues>, Arc<ComputedValues>)],
+
+    /// The embedding that the `unicode-bidi` property of the block container opens around all of
+    /// the boxes of this flow, if any.
+    paragraph_embedding: Option<BidiEmbedding>,
 }
 
 impl InlineFlow {
@@ -454,6 +517,8 @@ impl InlineFlow {
             boxes: ~[],
             lines: ~[],
             elems: ElementMapping::new(),
+            first_line_styles: ~[],
+            paragraph_embedding: None,
         }
     }
 
@@ -463,6 +528,8 @@ impl InlineFlow {
             boxes: boxes,
             lines: ~[],
             elems: ElementMapping::new(),
+            first_line_styles: ~[],
+            paragraph_embedding: None,
         }
     }
 
@@ -479,8 +546,7 @@ impl InlineFlow {
                                      dirty: &Rect<Au>,
                                      list: &Cell<DisplayList<E>>)
                                      -> bool {
-        let abs_rect = Rect(self.base.abs_position, self.base.position.size);
-        if !abs_rect.intersects(dirty) {
+        if !self.base.abs_overflow().intersects(dirty) {
             return true;
         }
 
@@ -569,32 +635,62 @@ impl InlineFlow {
                 let percent_offset = line_height.scale_by(p);
                 (-(percent_offset + ascent), false)
             }
+            vertical_align::Calc(calc) => {
+                let pt_size = cur_box.font_style().pt_size;
+                let line_height = cur_box.calculate_line_height(Au::from_pt(pt_size));
+                (-(calc.resolve(line_height) + ascent), false)
+            }
         }
     }
 
-    /// Sets box X positions based on alignment for one line.
-    fn set_horizontal_box_positions(boxes: &[Box], line: &LineBox, linebox_align: text_align::T) {
+    /// Sets box X positions based on alignment for one line, placing the boxes in visual order.
+    fn set_horizontal_box_positions(boxes: &[Box],
+                                    line: &LineBox,
+                                    linebox_align: text_align::T,
+                                    rtl: bool) {
         // Figure out how much width we have.
         let slack_width = Au::max(Au(0), line.green_zone.width - line.bounds.size.width);
 
-        // Set the box x positions based on that alignment.
+        // Set the box x positions based on that alignment. `start` and `end` follow the direction
+        // of the paragraph.
         let mut offset_x = line.bounds.origin.x;
-        offset_x = offset_x + match linebox_align {
+        offset_x = offset_x + match (linebox_align, rtl) {
             // So sorry, but justified text is more complicated than shuffling linebox
-            // coordinates.
+            // coordinates. Until then, it is aligned to the start, like its last line.
             //
             // TODO(burg, issue #213): Implement `text-align: justify`.
-            text_align::left | text_align::justify => Au(0),
-            text_align::center => slack_width.scale_by(0.5),
-            text_align::right => slack_width,
+            (text_align::left, _) | (text_align::start, false) | (text_align::end, true) |
+            (text_align::justify, false) => Au(0),
+            (text_align::center, _) => slack_width.scale_by(0.5),
+            (text_align::right, _) | (text_align::start, true) | (text_align::end, false) |
+            (text_align::justify, true) => slack_width,
         };
 
-        for i in line.range.eachi() {
-            let box = &boxes[i];
+        // Per rule L1 of the Unicode Bidirectional Algorithm, the whitespace at the end of the
+        // line takes the level of the paragraph.
+        let paragraph_level = if rtl { 1 } else { 0 };
+        let mut levels: ~[u8] = line.range.eachi().map(|i| boxes[i].bidi_level).collect();
+        let mut trailing = levels.len();
+        while trailing > 0 && is_whitespace_box(&boxes[line.range.begin() + trailing - 1]) {
+            trailing -= 1;
+            levels[trailing] = paragraph_level;
+        }
+
+        for &i in bidi::visual_order(levels).iter() {
+            let box = &boxes[line.range.begin() + i];
             let size = box.position.get().size;
             box.position.set(Rect(Point2D(offset_x, box.position.get().origin.y), size));
             offset_x = offset_x + size.width;
         }
+
+        fn is_whitespace_box(box: &Box) -> bool {
+            match box.specific {
+                ScannedTextBox(ref text_box_info) => {
+                    text_box_info.run.get().range_is_trimmable_whitespace(&text_box_info.range)
+                }
+                _ => false,
+            }
+        }
     }
 }
 
@@ -674,7 +770,7 @@ impl Flow for InlineFlow {
         self.assign_height(ctx);
     }
 
-    fn assign_height(&mut self, _: &mut LayoutContext) {
+    fn assign_height(&mut self, ctx: &mut LayoutContext) {
         debug!("assign_height_inline: assigning height for flow {}", self.base.id);
 
         // Divide the boxes into lines.
@@ -692,17 +788,18 @@ impl Flow for InlineFlow {
         let mut scanner = LineboxScanner::new(scanner_floats);
 
         // Access the linebox scanner.
-        scanner.scan_for_lines(self);
+        scanner.scan_for_lines(ctx, self);
 
         let mut line_height_offset = Au::new(0);
 
-        // All lines use text alignment of the flow.
+        // All lines use text alignment and direction of the flow.
         let text_align = self.base.flags.text_align();
+        let rtl = self.base.flags.rtl();
 
         // Now, go through each line and lay out the boxes inside.
         for line in self.lines.mut_iter() {
             // Lay out boxes horizontally.
-            InlineFlow::set_horizontal_box_positions(self.boxes, line, text_align);
+            InlineFlow::set_horizontal_box_positions(self.boxes, line, text_align, rtl);
 
             // Set the top y position of the current linebox.
             // `line_height_offset` is updated at the end of the previous loop.
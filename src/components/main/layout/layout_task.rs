@@ -8,8 +8,10 @@
 use css::matching::MatchMethods;
 use css::select::new_stylist;
 use css::node_style::StyledNode;
+use layout::animation::{AnimationEnded, Animations, TransitionEnded};
 use layout::construct::{FlowConstructionResult, FlowConstructor, NoConstructionResult};
 use layout::context::LayoutContext;
+use layout::counters::resolve_counters;
 use layout::display_list_builder::{DisplayListBuilder, ToGfxColor};
 use layout::extra::LayoutAuxMethods;
 use layout::flow::{Flow, ImmutableFlowUtils, MutableFlowUtils, PreorderFlowTraversal};
@@ -24,33 +26,50 @@ use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use gfx::display_list::{ClipDisplayItemClass, DisplayItem, DisplayItemIterator, DisplayList};
+use gfx::display_list::{GroupDisplayItemClass, TextDisplayItemClass, TransformDisplayItemClass};
+use gfx::font::{FontStyle, SelectorPlatformIdentifier, WebFontData};
+use gfx::font_cache_task::FontCacheTask;
 use gfx::font_context::FontContext;
+use gfx::font_list::WebFont;
 use gfx::opts::Opts;
 use gfx::render_task::{RenderMsg, RenderChan, RenderLayer};
 use gfx::{render_task, color};
-use script::dom::event::ReflowEvent;
+use script::dom::event::{AnimationEndEvent, AnimationEndEvents, AnimationFrameEvent, ReflowEvent};
+use script::dom::event::TransitionEndEvent;
 use script::dom::node::{AbstractNode, ElementNodeTypeId, LayoutDataRef};
 use script::dom::element::{HTMLBodyElementTypeId, HTMLHtmlElementTypeId};
-use script::layout_interface::{AddStylesheetMsg, ContentBoxQuery};
+use script::layout_interface::{AddStylesheetMsg, SetStylesheetsMsg, ContentBoxQuery};
 use script::layout_interface::{ContentBoxesQuery, ContentBoxesResponse, ExitNowMsg, LayoutQuery};
 use script::layout_interface::{HitTestQuery, ContentBoxResponse, HitTestResponse};
-use script::layout_interface::{ContentChangedDocumentDamage, Msg, PrepareToExitMsg};
+use script::layout_interface::{ComputedStyleQuery, ComputedStyleResponse};
+use script::layout_interface::{AnimateDocumentDamage, ContentChangedDocumentDamage, Msg};
+use script::layout_interface::PrepareToExitMsg;
 use script::layout_interface::{QueryMsg, ReapLayoutDataMsg, Reflow, ReflowDocumentDamage};
 use script::layout_interface::{ReflowForDisplay, ReflowMsg};
 use script::script_task::{ReflowCompleteMsg, ScriptChan, SendEventMsg};
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId};
 use servo_net::image_cache_task::{ImageCacheTask, ImageResponseMsg};
 use servo_net::local_image_cache::{ImageResponder, LocalImageCache};
+use servo_net::resource_task::{ResourceTask, load_whole_resource};
 use servo_util::geometry::Au;
+use servo_util::geometry;
 use servo_util::time::{ProfilerChan, profile};
 use servo_util::time;
 use std::cast::transmute;
 use std::cast;
 use std::cell::Cell;
-use std::comm::Port;
+use std::comm::{Port, SharedChan};
+use std::hashmap::HashSet;
+use std::rt::io::timer::Timer;
 use std::task;
 use std::util;
-use style::{AuthorOrigin, Stylesheet, Stylist};
+use style::{After, AuthorOrigin, Before, FirstLetter, FirstLine, Stylesheet, Stylist};
+use style::{CascadeEnvironment, FontMetricsProvider};
+use style::{FontFaceRule, FontFaceSource, LocalSource, UrlSource};
+use style::serialize_computed_values;
+
+/// The time between two frames of transitions and animations, in milliseconds.
+static ANIMATION_FRAME_INTERVAL: u64 = 16;
 
 /// Information needed by the layout task.
 struct LayoutTask {
@@ -75,6 +94,26 @@ struct LayoutTask {
     /// The local image cache.
     local_image_cache: MutexArc<LocalImageCache>,
 
+    /// The channel on which messages can be sent to the resource task.
+    resource_task: ResourceTask,
+
+    /// The channel on which messages can be sent to the font cache task.
+    font_cache_task: FontCacheTask,
+
+    /// The font context, kept from one reflow to the next so that the faces of the `@font-face`
+    /// fonts are created once, when the fonts finish loading. It is lent to the layout context
+    /// during a reflow.
+    font_ctx: Option<~FontContext>,
+
+    /// The family names and URLs of the `@font-face` fonts requested so far, loaded or not.
+    requested_web_fonts: HashSet<(~str, ~str)>,
+
+    /// The port on which the tasks loading `@font-face` fonts send them.
+    web_font_port: Port<WebFont>,
+
+    /// The channel handed to the tasks loading `@font-face` fonts.
+    web_font_chan: SharedChan<WebFont>,
+
     /// The size of the viewport.
     screen_size: Option<Size2D<Au>>,
 
@@ -83,6 +122,13 @@ struct LayoutTask {
 
     stylist: RWArc<Stylist>,
 
+    /// The running transitions and animations.
+    animations: Animations,
+
+    /// The channel that stops the task asking script for a reflow at every frame, if it is
+    /// running.
+    animation_timer_chan: Option<Chan<()>>,
+
     /// The channel on which messages can be sent to the profiler.
     profiler_chan: ProfilerChan,
 
@@ -204,11 +250,14 @@ impl LayoutTask {
                   script_chan: ScriptChan,
                   render_chan: RenderChan<OpaqueNode>,
                   img_cache_task: ImageCacheTask,
+                  resource_task: ResourceTask,
+                  font_cache_task: FontCacheTask,
                   opts: Opts,
                   profiler_chan: ProfilerChan,
                   shutdown_chan: Chan<()>) {
-        spawn_with!(task::task(), [port, constellation_chan, script_chan,
-                                   render_chan, img_cache_task, profiler_chan, shutdown_chan], {
+        spawn_with!(task::task(), [port, constellation_chan, script_chan, render_chan,
+                                   img_cache_task, resource_task, font_cache_task, profiler_chan,
+                                   shutdown_chan], {
             { // Ensures LayoutTask gets destroyed before we send the shutdown message
                 let mut layout = LayoutTask::new(id,
                                                  port,
@@ -216,6 +265,8 @@ impl LayoutTask {
                                                  script_chan,
                                                  render_chan,
                                                  img_cache_task,
+                                                 resource_task,
+                                                 font_cache_task,
                                                  &opts,
                                                  profiler_chan);
                 layout.start();
@@ -232,9 +283,12 @@ impl LayoutTask {
            script_chan: ScriptChan,
            render_chan: RenderChan<OpaqueNode>, 
            image_cache_task: ImageCacheTask,
+           resource_task: ResourceTask,
+           font_cache_task: FontCacheTask,
            opts: &Opts,
            profiler_chan: ProfilerChan)
            -> LayoutTask {
+        let (web_font_port, web_font_chan) = stream();
 
         LayoutTask {
             id: id,
@@ -244,11 +298,19 @@ impl LayoutTask {
             render_chan: render_chan,
             image_cache_task: image_cache_task.clone(),
             local_image_cache: MutexArc::new(LocalImageCache(image_cache_task)),
+            resource_task: resource_task,
+            font_cache_task: font_cache_task,
+            font_ctx: None,
+            requested_web_fonts: HashSet::new(),
+            web_font_port: web_font_port,
+            web_font_chan: SharedChan::new(web_font_chan),
             screen_size: None,
 
             display_list: None,
 
             stylist: RWArc::new(new_stylist()),
+            animations: Animations::new(),
+            animation_timer_chan: None,
             profiler_chan: profiler_chan,
             opts: opts.clone()
         }
@@ -261,11 +323,22 @@ impl LayoutTask {
         }
     }
 
-    // Create a layout context for use in building display lists, hit testing, &c.
-    fn build_layout_context(&self) -> LayoutContext {
+    /// Returns the font context, creating it if no reflow has created it yet.
+    fn font_context<'a>(&'a mut self) -> &'a mut ~FontContext {
+        if self.font_ctx.is_none() {
+            self.font_ctx = Some(~FontContext::new(self.opts.render_backend, true,
+                                                   self.font_cache_task.clone(),
+                                                   self.profiler_chan.clone()))
+        }
+        self.font_ctx.get_mut_ref()
+    }
+
+    // Create a layout context for use in building display lists, hit testing, &c. The layout
+    // context borrows the font context until `return_layout_context` is called.
+    fn build_layout_context(&mut self) -> LayoutContext {
+        self.font_context();
+        let font_ctx = self.font_ctx.take_unwrap();
         let image_cache = self.local_image_cache.clone();
-        let font_ctx = ~FontContext::new(self.opts.render_backend, true,
-                                            self.profiler_chan.clone());
         let screen_size = self.screen_size.unwrap();
 
         LayoutContext {
@@ -276,10 +349,16 @@ impl LayoutTask {
         }
     }
 
+    /// Takes back the font context lent to the given layout context.
+    fn return_layout_context(&mut self, layout_ctx: LayoutContext) {
+        self.font_ctx = Some(layout_ctx.font_ctx)
+    }
+
     /// Receives and dispatches messages from the port.
     fn handle_request(&mut self) -> bool {
         match self.port.recv() {
             AddStylesheetMsg(sheet) => self.handle_add_stylesheet(sheet),
+            SetStylesheetsMsg(sheets) => self.handle_set_stylesheets(sheets),
             ReflowMsg(data) => {
                 let data = Cell::new(data);
 
@@ -350,6 +429,120 @@ impl LayoutTask {
         do self.stylist.write |stylist| {
             stylist.add_stylesheet(sheet.take(), AuthorOrigin);
         }
+        self.load_web_fonts();
+    }
+
+    /// Rebuilds the stylist from the user agent stylesheet and the given author stylesheets.
+    fn handle_set_stylesheets(&mut self, sheets: ~[Stylesheet]) {
+        let mut stylist = new_stylist();
+        for sheet in sheets.move_iter() {
+            stylist.add_stylesheet(sheet, AuthorOrigin);
+        }
+        self.stylist = RWArc::new(stylist);
+        self.load_web_fonts();
+    }
+
+    /// Starts loading the fonts of the `@font-face` rules that haven't been requested yet, each in
+    /// a task of its own. Until a font arrives, text in its family falls back to the next families
+    /// in `font-family`; once it does, script is asked for a reflow that uses it.
+    fn load_web_fonts(&mut self) {
+        let rules = do self.stylist.read |stylist| {
+            stylist.font_face_rules().to_owned()
+        };
+        for rule in rules.iter() {
+            let sources: ~[FontFaceSource] = rule.sources.iter().filter(|source| {
+                match **source {
+                    UrlSource(_, ref formats) => has_supported_font_format(*formats),
+                    LocalSource(_) => true,
+                }
+            }).map(|source| source.clone()).collect();
+            if sources.is_empty() ||
+                    !self.requested_web_fonts.insert((rule.family.clone(),
+                                                      font_face_source_name(&sources[0]))) {
+                continue
+            }
+            self.spawn_web_font_load(rule, sources);
+        }
+    }
+
+    /// Loads the first of the given sources that can be fetched and that holds a usable font, for
+    /// the given rule.
+    fn spawn_web_font_load(&self, rule: &FontFaceRule, sources: ~[FontFaceSource]) {
+        let resource_task = self.resource_task.clone();
+        let font_cache_task = self.font_cache_task.clone();
+        let web_font_chan = self.web_font_chan.clone();
+        let script_chan = self.script_chan.clone();
+        let id = self.id.clone();
+        let family_name = rule.family.clone();
+        let weight = rule.weight;
+        let style = rule.style;
+        let stretch = rule.stretch;
+        let unicode_range = rule.unicode_range.clone();
+        do spawn {
+            for source in sources.iter() {
+                let name = font_face_source_name(source);
+                let data = match *source {
+                    UrlSource(ref url, _) => {
+                        match load_whole_resource(&resource_task, url.clone()) {
+                            Ok((_, data)) => Some(Arc::new(data)),
+                            Err(()) => None,
+                        }
+                    }
+                    LocalSource(ref local_name) => {
+                        // The system fonts are looked up by family, in the style of the rule.
+                        let font_style = FontStyle {
+                            pt_size: 16.0,
+                            weight: weight,
+                            style: style,
+                            stretch: stretch,
+                            families: ~[local_name.clone()],
+                        };
+                        let selectors = font_cache_task.find_fonts_in_family(local_name.clone(),
+                                                                             &font_style);
+                        match selectors.move_iter().next() {
+                            Some(SelectorPlatformIdentifier(identifier)) => {
+                                font_cache_task.get_font_data(identifier)
+                            }
+                            _ => None,
+                        }
+                    }
+                };
+                let data = match data {
+                    Some(data) => data,
+                    None => {
+                        debug!("layout: couldn't load font {:s}", name);
+                        continue
+                    }
+                };
+                let web_font = WebFont {
+                    family_name: family_name.clone(),
+                    weight: weight,
+                    style: style,
+                    stretch: stretch,
+                    data: WebFontData {
+                        url: name,
+                        data: data,
+                        unicode_range: unicode_range.clone(),
+                    },
+                };
+                if !web_font.is_usable() {
+                    debug!("layout: {:s} isn't a font", web_font.data.url);
+                    continue
+                }
+                web_font_chan.send(web_font);
+                script_chan.try_send(SendEventMsg(id.clone(), ReflowEvent));
+                break
+            }
+        }
+    }
+
+    /// Adds the `@font-face` fonts that finished loading since the last reflow to the font
+    /// context.
+    fn receive_web_fonts(&mut self) {
+        while self.web_font_port.peek() {
+            let web_font = self.web_font_port.recv();
+            self.font_context().add_web_font(&web_font)
+        }
     }
 
     /// Builds the flow tree.
@@ -360,6 +553,8 @@ impl LayoutTask {
     /// marked `#[inline(never)]` to aid benchmarking in sampling profilers.
     #[inline(never)]
     fn construct_flow_tree(&self, layout_context: &mut LayoutContext, node: LayoutNode) -> ~Flow: {
+        // Counters run in document order, so they are resolved before the bottom-up traversal.
+        resolve_counters(node);
         node.traverse_postorder_mut(&mut FlowConstructor::init(layout_context));
 
         let result = match *node.mutate_layout_data().ptr {
@@ -425,12 +620,14 @@ impl LayoutTask {
 
         let screen_size = Size2D(Au::from_px(data.window_size.width as int),
                                  Au::from_px(data.window_size.height as int));
-        if self.screen_size != Some(screen_size) {
+        let viewport_changed = self.screen_size != Some(screen_size);
+        if viewport_changed {
             all_style_damage = true;
         }
         self.screen_size = Some(screen_size);
 
         // Create a layout context for use throughout the following passes.
+        self.receive_web_fonts();
         let mut layout_ctx = self.build_layout_context();
 
         // Initialize layout data for each node.
@@ -440,13 +637,61 @@ impl LayoutTask {
             node.initialize_style_for_subtree();
         }
 
-        // Perform CSS selector matching if necessary.
-        match data.damage.level {
-            ReflowDocumentDamage => {}
-            _ => {
-                do profile(time::LayoutSelectorMatchCategory, self.profiler_chan.clone()) {
-                    node.match_subtree(self.stylist.clone());
-                    node.cascade_subtree(None);
+        // Perform CSS selector matching if necessary. Otherwise, cascade again the styles that
+        // depend on the size of the viewport if it changed.
+        {
+            let mut environment = CascadeEnvironment {
+                // Set once the root element is styled.
+                root_font_size: Au::from_px(16),
+                viewport_width: screen_size.width,
+                viewport_height: screen_size.height,
+                font_metrics: &mut layout_ctx as &mut FontMetricsProvider,
+            };
+            match data.damage.level {
+                ReflowDocumentDamage => {
+                    if viewport_changed {
+                        environment.root_font_size = node.style().get().Font.font_size;
+                        do profile(time::LayoutSelectorMatchCategory,
+                                   self.profiler_chan.clone()) {
+                            let animations = &mut self.animations;
+                            do self.stylist.read |stylist| {
+                                node.recascade_viewport_dependent_styles(stylist,
+                                                                         animations,
+                                                                         &mut environment,
+                                                                         None)
+                            }
+                        }
+                        self.send_animation_events();
+                        self.update_animation_timer();
+                    }
+                }
+                AnimateDocumentDamage => {
+                    environment.root_font_size = node.style().get().Font.font_size;
+                    do profile(time::LayoutSelectorMatchCategory, self.profiler_chan.clone()) {
+                        let animations = &mut self.animations;
+                        do self.stylist.read |stylist| {
+                            if viewport_changed {
+                                node.recascade_viewport_dependent_styles(stylist,
+                                                                         animations,
+                                                                         &mut environment,
+                                                                         None)
+                            }
+                            animations.begin_animation_frame();
+                            node.animate_subtree(stylist, animations, &mut environment, None, false)
+                        }
+                    }
+                    self.send_animation_events();
+                    self.update_animation_timer();
+                }
+                _ => {
+                    do profile(time::LayoutSelectorMatchCategory, self.profiler_chan.clone()) {
+                        node.recalc_style_for_subtree(self.stylist.clone(),
+                                                      &mut self.animations,
+                                                      &mut environment,
+                                                      self.profiler_chan.clone());
+                    }
+                    self.send_animation_events();
+                    self.update_animation_timer();
                 }
             }
         }
@@ -474,9 +719,15 @@ impl LayoutTask {
         // Build the display list if necessary, and send it to the renderer.
         if data.goal == ReflowForDisplay {
             do profile(time::LayoutDispListBuildCategory, self.profiler_chan.clone()) {
-                let root_size = flow::base(layout_root).position.size;
+                // Transformed content may be painted outside of the root flow.
+                let root_bounds = {
+                    let root_base = flow::base(layout_root);
+                    root_base.position.union(&root_base.overflow)
+                };
+                let root_size = Size2D(root_bounds.origin.x + root_bounds.size.width,
+                                       root_bounds.origin.y + root_bounds.size.height);
                 let display_list = ~Cell::new(DisplayList::<OpaqueNode>::new());
-                let dirty = flow::base(layout_root).position.clone();
+                let dirty = root_bounds;
                 let display_list_builder = DisplayListBuilder {
                     ctx: &layout_ctx,
                 };
@@ -523,10 +774,62 @@ impl LayoutTask {
         //
         // FIXME(pcwalton): This should probably be *one* channel, but we can't fix this without
         // either select or a filtered recv() that only looks for messages of a given type.
+        self.return_layout_context(layout_ctx);
+
         data.script_join_chan.send(());
         data.script_chan.send(ReflowCompleteMsg(self.id, data.id));
     }
 
+    /// Fires the `transitionend` and `animationend` events of the transitions and animations that
+    /// ended during selector matching.
+    fn send_animation_events(&mut self) {
+        let events = self.animations.take_events();
+        if events.is_empty() {
+            return
+        }
+        // Script may run before it handles the events, so it checks that the nodes are still in
+        // the document.
+        let events = events.move_iter().map(|event| {
+            match event {
+                TransitionEnded(node, property, elapsed_time) => {
+                    TransitionEndEvent(*node, property.to_owned(), elapsed_time as f32)
+                }
+                AnimationEnded(node, name, elapsed_time) => {
+                    AnimationEndEvent(*node, name, elapsed_time as f32)
+                }
+            }
+        }).collect();
+        self.script_chan.send(SendEventMsg(self.id.clone(), AnimationEndEvents(events)))
+    }
+
+    /// Starts asking script for a reflow at every frame while transitions or animations are
+    /// running, and stops once they are all done.
+    fn update_animation_timer(&mut self) {
+        match (self.animations.is_running(), self.animation_timer_chan.is_some()) {
+            (true, false) => {
+                let (port, chan) = stream();
+                let id = self.id.clone();
+                let script_chan = self.script_chan.clone();
+                do spawn {
+                    let mut timer = Timer::new().unwrap();
+                    loop {
+                        timer.sleep(ANIMATION_FRAME_INTERVAL);
+                        if port.peek() ||
+                                !script_chan.try_send(SendEventMsg(id.clone(),
+                                                                   AnimationFrameEvent)) {
+                            break
+                        }
+                    }
+                }
+                self.animation_timer_chan = Some(chan);
+            }
+            (false, true) => {
+                self.animation_timer_chan.take_unwrap().send(());
+            }
+            _ => (),
+        }
+    }
+
     /// Handles a query from the script task. This is the main routine that DOM functions like
     /// `getClientRects()` or `getBoundingClientRect()` ultimately invoke.
     fn handle_query(&self, query: LayoutQuery) {
@@ -542,7 +845,7 @@ impl LayoutTask {
                                         node: OpaqueNode) {
                     for item in iter {
                         union_boxes_for_node(accumulator, item.children(), node);
-                        if item.base().extra == node {
+                        if item.base().extra == node && !item.is_shadow() {
                             match *accumulator {
                                 None => *accumulator = Some(item.base().bounds),
                                 Some(ref mut acc) => *acc = acc.union(&item.base().bounds),
@@ -565,7 +868,7 @@ impl LayoutTask {
                                       node: OpaqueNode) {
                     for item in iter {
                         add_boxes_for_node(accumulator, item.children(), node);
-                        if item.base().extra == node {
+                        if item.base().extra == node && !item.is_shadow() {
                             accumulator.push(item.base().bounds)
                         }
                     }
@@ -587,13 +890,37 @@ impl LayoutTask {
                                     return ret;
                                 }
                             }
+                            // Transparent content is hit too.
+                            GroupDisplayItemClass(ref group) => {
+                                let ret = hit_test(x, y, group.child_list);
+                                if !ret.is_none() {
+                                    return ret;
+                                }
+                            }
+                            // Map the point to the untransformed content.
+                            TransformDisplayItemClass(ref transform) => {
+                                let point = Point2D(x, y);
+                                match geometry::inverse_transform_point(&transform.transform,
+                                                                        &point) {
+                                    Some(point) => {
+                                        let ret = hit_test(point.x, point.y,
+                                                           transform.child_list);
+                                        if !ret.is_none() {
+                                            return ret;
+                                        }
+                                    }
+                                    None => {}
+                                }
+                            }
                             _ => {}
                         }
                     }
 
                     for item in list.rev_iter() {
                         match *item {
-                            ClipDisplayItemClass(_) => continue,
+                            ClipDisplayItemClass(_) | GroupDisplayItemClass(_) |
+                            TransformDisplayItemClass(_) => continue,
+                            _ if item.is_shadow() => continue,
                             _ => {}
                         }
                         let bounds = item.bounds();
@@ -611,7 +938,11 @@ impl LayoutTask {
                             let node: AbstractNode = unsafe {
                                 item.base().extra.to_script_node()
                             };
-                            let resp = Some(HitTestResponse(node));
+                            let offset = match *item {
+                                TextDisplayItemClass(ref text) => Some(text.node_offset_at(x)),
+                                _ => None,
+                            };
+                            let resp = Some(HitTestResponse(node, offset));
                             return resp;
                         }
                     }
@@ -641,6 +972,30 @@ impl LayoutTask {
 
                 reply_chan.send(response)
             }
+            ComputedStyleQuery(node, pseudo_element, reply_chan) => {
+                // The reflow that script forced before this query has restyled the document, so
+                // the styles stored in the layout data are up to date.
+                let declarations = unsafe {
+                    do LayoutNode::with_layout_node(node) |node| {
+                        match *node.borrow_layout_data().ptr {
+                            None => None,
+                            Some(ref layout_data) => {
+                                let style = match pseudo_element {
+                                    None => &layout_data.style,
+                                    Some(Before) => &layout_data.before_style,
+                                    Some(After) => &layout_data.after_style,
+                                    Some(FirstLine) => &layout_data.first_line_style,
+                                    Some(FirstLetter) => &layout_data.first_letter_style,
+                                };
+                                do style.as_ref().map |style| {
+                                    serialize_computed_values(style.get())
+                                }
+                            }
+                        }
+                    }
+                };
+                reply_chan.send(ComputedStyleResponse(declarations))
+            }
         }
     }
 
@@ -668,3 +1023,22 @@ impl LayoutTask {
     }
 }
 
+/// Whether the `format()` hints of an `@font-face` source allow a format the font backend can
+/// read. A source without hints is always tried.
+/// Returns the URL of a font source, or `local(...)` with the name of a local one. It identifies
+/// the font that the source loads.
+fn font_face_source_name(source: &FontFaceSource) -> ~str {
+    match *source {
+        UrlSource(ref url, _) => url.to_str(),
+        LocalSource(ref name) => format!("local({:s})", *name),
+    }
+}
+
+fn has_supported_font_format(formats: &[~str]) -> bool {
+    formats.is_empty() || formats.iter().any(|format| {
+        match format.as_slice() {
+            "truetype" | "opentype" => true,
+            _ => false,
+        }
+    })
+}
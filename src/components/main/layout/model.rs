@@ -20,7 +20,8 @@ impl MaybeAuto {
         match length {
             computed::LPA_Auto => Auto,
             computed::LPA_Percentage(percent) => Specified(containing_length.scale_by(percent)),
-            computed::LPA_Length(length) => Specified(length)
+            computed::LPA_Length(length) => Specified(length),
+            computed::LPA_Calc(calc) => Specified(calc.resolve(containing_length)),
         }
     }
 
@@ -43,12 +44,14 @@ pub fn specified_or_none(length: computed::LengthOrPercentageOrNone, containing_
         computed::LPN_None => None,
         computed::LPN_Percentage(percent) => Some(containing_length.scale_by(percent)),
         computed::LPN_Length(length) => Some(length),
+        computed::LPN_Calc(calc) => Some(calc.resolve(containing_length)),
     }
 }
 
 pub fn specified(length: computed::LengthOrPercentage, containing_length: Au) -> Au {
     match length {
         computed::LP_Length(length) => length,
-        computed::LP_Percentage(p) => containing_length.scale_by(p)
+        computed::LP_Percentage(p) => containing_length.scale_by(p),
+        computed::LP_Calc(calc) => calc.resolve(containing_length),
     }
 }
@@ -4,21 +4,54 @@
 
 //! Text layout.
 
-use layout::box::{Box, ScannedTextBox, ScannedTextBoxInfo, UnscannedTextBox};
+use layout::box::{BidiEmbedding, Box, ScannedTextBox, ScannedTextBoxInfo, UnscannedTextBox};
 use layout::context::LayoutContext;
 use layout::flow::Flow;
+use layout::inline::InlineFlow;
 
 use extra::arc::Arc;
+use gfx::text::bidi::{ON, PDF, bidi_class, is_rtl_class, resolve_levels};
 use gfx::text::text_run::TextRun;
-use gfx::text::util::{CompressWhitespaceNewline, transform_text};
+use gfx::text::util::{CharIndexMap, CompressionMode, CompressNone, CompressWhitespace};
+use gfx::text::util::{CompressWhitespaceNewline, to_uppercase, transform_case, transform_text};
 use servo_util::range::Range;
+use std::cmp;
 use std::vec;
+use style::computed_values::{font_variant, white_space};
+
+/// The size of synthesized small capitals, relative to the font size.
+static SMALL_CAPS_SCALE: f64 = 0.7;
 
 /// A stack-allocated object for scanning an inline flow into `TextRun`-containing `TextBox`es.
 struct TextRunScanner {
     clump: Range,
 }
 
+/// A range of inline flow leaves that can be merged together into a single box, with its text
+/// transformed but not yet shaped.
+enum Clump {
+    /// A box other than text, which is never merged.
+    OtherClump(uint),
+    /// Adjacent text boxes with the same style.
+    TextClump(TransformedText),
+}
+
+/// The text of a clump of text boxes, transformed per the `white-space`, `text-transform` and
+/// `font-variant` properties.
+struct TransformedText {
+    /// The range of the boxes of the clump in the flow.
+    boxes: Range,
+    /// The text of all the boxes of the clump.
+    text: ~str,
+    /// The range of the text of each box of the clump.
+    box_ranges: ~[Range],
+    /// Maps the characters of each box of the clump back to character offsets in its DOM node.
+    char_maps: ~[Arc<CharIndexMap>],
+    /// The lengths of the segments of the text and whether they are in synthesized small
+    /// capitals, which need a run of their own for each change of size.
+    segments: ~[(uint, bool)],
+}
+
 impl TextRunScanner {
     pub fn new() -> TextRunScanner {
         TextRunScanner {
@@ -27,59 +60,70 @@ impl TextRunScanner {
     }
 
     pub fn scan_for_runs(&mut self, ctx: &mut LayoutContext, flow: &mut Flow) {
-        {
-            let inline = flow.as_immutable_inline();
-            debug!("TextRunScanner: scanning {:u} boxes for text runs...", inline.boxes.len());
-        }
+        let inline = flow.as_inline();
+        debug!("TextRunScanner: scanning {:u} boxes for text runs...", inline.boxes.len());
 
+        // First, transform the text of each clump.
+        let mut clumps = ~[];
         let mut last_whitespace = true;
-        let mut out_boxes = ~[];
-        for box_i in range(0, flow.as_immutable_inline().boxes.len()) {
+        for box_i in range(0, inline.boxes.len()) {
             debug!("TextRunScanner: considering box: {:u}", box_i);
-            if box_i > 0 && !can_coalesce_text_nodes(flow.as_immutable_inline().boxes,
-                                                     box_i - 1,
-                                                     box_i) {
-                last_whitespace = self.flush_clump_to_list(ctx, flow, last_whitespace, &mut out_boxes);
+            if box_i > 0 && !inline.boxes[box_i - 1].can_merge_with_box(&inline.boxes[box_i]) {
+                last_whitespace = self.flush_clump_to_list(inline.boxes,
+                                                           last_whitespace,
+                                                           &mut clumps);
             }
             self.clump.extend_by(1);
         }
         // handle remaining clumps
         if self.clump.length() > 0 {
-            self.flush_clump_to_list(ctx, flow, last_whitespace, &mut out_boxes);
+            self.flush_clump_to_list(inline.boxes, last_whitespace, &mut clumps);
         }
 
+        // Next, resolve the embedding levels of the text, so that each run is shaped once, in
+        // its own direction.
+        let levels = resolve_bidi_levels(inline, clumps);
+
+        // Finally, shape the text into runs, and make the new boxes.
+        let mut out_boxes = ~[];
+        for (clump, levels) in clumps.move_iter().zip(levels.iter()) {
+            match clump {
+                OtherClump(box_i) => {
+                    // FIXME(pcwalton): Stop cloning boxes.
+                    let mut box = inline.boxes[box_i].clone();
+                    box.bidi_level = levels[0];
+                    out_boxes.push(box)
+                }
+                TextClump(text) => {
+                    shape_text_clump(ctx, inline.boxes, text, *levels, &mut out_boxes)
+                }
+            }
+        }
+
+        debug!("--- Out boxes: ---");
+        for (i, box) in out_boxes.iter().enumerate() {
+            debug!("{:u} --> {:s}", i, box.debug_str());
+        }
+        debug!("------------------");
+
         debug!("TextRunScanner: swapping out boxes.");
 
         // Swap out the old and new box list of the flow.
-        flow.as_inline().boxes = out_boxes;
-
-        // A helper function.
-        fn can_coalesce_text_nodes(boxes: &[Box], left_i: uint, right_i: uint) -> bool {
-            assert!(left_i < boxes.len());
-            assert!(right_i > 0 && right_i < boxes.len());
-            assert!(left_i != right_i);
-            boxes[left_i].can_merge_with_box(&boxes[right_i])
-        }
+        inline.boxes = out_boxes;
     }
 
     /// A "clump" is a range of inline flow leaves that can be merged together into a single box.
-    /// Adjacent text with the same style can be merged, and nothing else can.
+    /// Adjacent text with the same style can be merged, and nothing else can. This transforms the
+    /// text of the current clump, and returns whether it ends in whitespace.
     ///
     /// The flow keeps track of the boxes contained by all non-leaf DOM nodes. This is necessary
     /// for correct painting order. Since we compress several leaf boxes here, the mapping must be
     /// adjusted.
-    ///
-    /// FIXME(pcwalton): Stop cloning boxes. Instead we will need to consume the `in_box`es as we
-    /// iterate over them.
     pub fn flush_clump_to_list(&mut self,
-                               ctx: &mut LayoutContext,
-                               flow: &mut Flow,
+                               in_boxes: &[Box],
                                last_whitespace: bool,
-                               out_boxes: &mut ~[Box])
+                               clumps: &mut ~[Clump])
                                -> bool {
-        let inline = flow.as_inline();
-        let in_boxes = &mut inline.boxes;
-
         assert!(self.clump.length() > 0);
 
         debug!("TextRunScanner: flushing boxes in range={}", self.clump);
@@ -96,9 +140,8 @@ impl TextRunScanner {
                 fail!(~"WAT: can't coalesce non-text nodes in flush_clump_to_list()!")
             }
             (true, false) => {
-                // FIXME(pcwalton): Stop cloning boxes, as above.
                 debug!("TextRunScanner: pushing single non-text box in range: {}", self.clump);
-                out_boxes.push(in_boxes[self.clump.begin()].clone());
+                clumps.push(OtherClump(self.clump.begin()));
             },
             (true, true)  => {
                 let old_box = &in_boxes[self.clump.begin()];
@@ -107,56 +150,70 @@ impl TextRunScanner {
                     _ => fail!("Expected an unscanned text box!"),
                 };
 
-                let font_style = old_box.font_style();
-                let decoration = old_box.text_decoration();
-
-                // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
-                let compression = CompressWhitespaceNewline;
+                let compression = compression_mode(old_box.white_space());
 
+                let mut char_map = CharIndexMap::new();
                 let (transformed_text, whitespace) = transform_text(*text,
                                                                     compression,
-                                                                    last_whitespace);
+                                                                    last_whitespace,
+                                                                    &mut char_map);
+                let transformed_text = transform_case(transformed_text,
+                                                      old_box.text_transform(),
+                                                      last_whitespace,
+                                                      &mut char_map);
                 new_whitespace = whitespace;
 
-                if transformed_text.len() > 0 {
-                    // TODO(#177): Text run creation must account for the renderability of text by
-                    // font group fonts. This is probably achieved by creating the font group above
-                    // and then letting `FontGroup` decide which `Font` to stick into the text run.
-                    let fontgroup = ctx.font_ctx.get_resolved_font_for_style(&font_style);
-                    let run = ~fontgroup.with_borrow(|fg| fg.create_textrun(transformed_text.clone(), decoration));
-
-                    debug!("TextRunScanner: pushing single text box in range: {} ({})",
-                           self.clump,
-                           *text);
-                    let range = Range::new(0, run.char_len());
-                    let new_metrics = run.metrics_for_range(&range);
-                    let new_text_box_info = ScannedTextBoxInfo::new(Arc::new(run), range);
-                    let new_box = old_box.transform(new_metrics.bounding_box.size,
-                                                    ScannedTextBox(new_text_box_info));
-                    out_boxes.push(new_box)
+                let segments = match old_box.font_variant() {
+                    font_variant::normal => ~[(transformed_text, false)],
+                    font_variant::small_caps => small_caps_segments(transformed_text,
+                                                                    &mut char_map),
+                };
+                let mut run_str = ~"";
+                let mut segment_lengths = ~[];
+                for (segment_text, is_small) in segments.move_iter() {
+                    segment_lengths.push((segment_text.char_len(), is_small));
+                    run_str.push_str(segment_text);
                 }
+
+                debug!("TextRunScanner: pushing single text box in range: {} ({})",
+                       self.clump,
+                       run_str);
+                let run_len = run_str.char_len();
+                clumps.push(TextClump(TransformedText {
+                    boxes: self.clump,
+                    text: run_str,
+                    box_ranges: ~[Range::new(0, run_len)],
+                    char_maps: ~[Arc::new(char_map)],
+                    segments: segment_lengths,
+                }));
             },
             (false, true) => {
-                // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
-                let compression = CompressWhitespaceNewline;
-
                 // First, transform/compress text of all the nodes.
                 let mut last_whitespace_in_clump = new_whitespace;
-                let transformed_strs: ~[~str] = do vec::from_fn(self.clump.length()) |i| {
+                let transformed_strs: ~[(~str, CharIndexMap)] =
+                        do vec::from_fn(self.clump.length()) |i| {
                     // TODO(#113): We should be passing the compression context between calls to
                     // `transform_text`, so that boxes starting and/or ending with whitespace can
                     // be compressed correctly with respect to the text run.
                     let idx = i + self.clump.begin();
-                    let in_box = match in_boxes[idx].specific {
+                    let in_box = &in_boxes[idx];
+                    let text = match in_box.specific {
                         UnscannedTextBox(ref text_box_info) => &text_box_info.text,
                         _ => fail!("Expected an unscanned text box!"),
                     };
 
-                    let (new_str, new_whitespace) = transform_text(*in_box,
-                                                                   compression,
-                                                                   last_whitespace_in_clump);
+                    let mut char_map = CharIndexMap::new();
+                    let (new_str, new_whitespace) = transform_text(*text,
+                                                                   compression_mode(
+                                                                       in_box.white_space()),
+                                                                   last_whitespace_in_clump,
+                                                                   &mut char_map);
+                    let new_str = transform_case(new_str,
+                                                 in_box.text_transform(),
+                                                 last_whitespace_in_clump,
+                                                 &mut char_map);
                     last_whitespace_in_clump = new_whitespace;
-                    new_str
+                    (new_str, char_map)
                 };
                 new_whitespace = last_whitespace_in_clump;
 
@@ -164,78 +221,277 @@ impl TextRunScanner {
                 // character indices.
                 let mut run_str: ~str = ~"";
                 let mut new_ranges: ~[Range] = ~[];
+                let mut char_maps: ~[Arc<CharIndexMap>] = ~[];
                 let mut char_total = 0;
-                for i in range(0, transformed_strs.len()) {
-                    let added_chars = transformed_strs[i].char_len();
+                for (transformed_str, char_map) in transformed_strs.move_iter() {
+                    let added_chars = transformed_str.char_len();
                     new_ranges.push(Range::new(char_total, added_chars));
-                    run_str.push_str(transformed_strs[i]);
+                    run_str.push_str(transformed_str);
+                    char_maps.push(Arc::new(char_map));
                     char_total += added_chars;
                 }
 
-                // Now create the run.
-                //
-                // TODO(#177): Text run creation must account for the renderability of text by
-                // font group fonts. This is probably achieved by creating the font group above
-                // and then letting `FontGroup` decide which `Font` to stick into the text run.
-                let in_box = &in_boxes[self.clump.begin()];
-                let font_style = in_box.font_style();
-                let fontgroup = ctx.font_ctx.get_resolved_font_for_style(&font_style);
-                let decoration = in_box.text_decoration();
-
-                // TextRuns contain a cycle which is usually resolved by the teardown
-                // sequence. If no clump takes ownership, however, it will leak.
-                let clump = self.clump;
-                let run = if clump.length() != 0 && run_str.len() > 0 {
-                    fontgroup.with_borrow( |fg| {
-                        fg.fonts[0].with_mut_borrow( |font| {
-                            Some(Arc::new(~TextRun::new(font, run_str.clone(), decoration)))
-                        })
-                    })
-                } else {
-                    None
-                };
-
-                // Make new boxes with the run and adjusted text indices.
                 debug!("TextRunScanner: pushing box(es) in range: {}", self.clump);
-                for i in clump.eachi() {
-                    let range = new_ranges[i - self.clump.begin()];
-                    if range.length() == 0 {
+                for i in self.clump.eachi() {
+                    if new_ranges[i - self.clump.begin()].length() == 0 {
                         debug!("Elided an `UnscannedTextbox` because it was zero-length after \
                                 compression; {:s}",
                                in_boxes[i].debug_str());
-                        continue
                     }
-
-                    let new_text_box_info = ScannedTextBoxInfo::new(run.get_ref().clone(), range);
-                    let new_metrics = new_text_box_info.run.get().metrics_for_range(&range);
-                    let new_box = in_boxes[i].transform(new_metrics.bounding_box.size,
-                                                        ScannedTextBox(new_text_box_info));
-                    out_boxes.push(new_box)
                 }
+                clumps.push(TextClump(TransformedText {
+                    boxes: self.clump,
+                    text: run_str,
+                    box_ranges: new_ranges,
+                    char_maps: char_maps,
+                    segments: ~[(char_total, false)],
+                }));
             }
         } // End of match.
 
-        debug!("--- In boxes: ---");
-        for (i, box) in in_boxes.iter().enumerate() {
-            debug!("{:u} --> {:s}", i, box.debug_str());
+        let end = self.clump.end(); // FIXME: borrow checker workaround
+        self.clump.reset(end, 0);
+
+        new_whitespace
+    } // End of `flush_clump_to_list`.
+}
+
+/// Shapes the text of a clump into runs, one for each change of font or of embedding level, given
+/// the level of each character, and pushes boxes for them. A box whose text spans several runs is
+/// split at their boundaries.
+fn shape_text_clump(ctx: &mut LayoutContext,
+                    in_boxes: &[Box],
+                    clump: TransformedText,
+                    levels: &[u8],
+                    out_boxes: &mut ~[Box]) {
+    let in_box = &in_boxes[clump.boxes.begin()];
+    let font_style = in_box.font_style();
+    let decoration = in_box.text_decoration();
+    let letter_spacing = in_box.letter_spacing();
+    let word_spacing = in_box.word_spacing();
+    let tab_size = in_box.tab_size();
+
+    // TextRuns contain a cycle which is usually resolved by the teardown sequence. If no clump
+    // takes ownership, however, it will leak.
+    let mut run_start = 0;
+    let mut segment_start = 0;
+    for &(segment_len, is_small) in clump.segments.iter() {
+        let mut segment_font_style = font_style.clone();
+        if is_small {
+            segment_font_style.pt_size = font_style.pt_size * SMALL_CAPS_SCALE;
         }
-        debug!("------------------");
 
-        debug!("--- Out boxes: ---");
-        for (i, box) in out_boxes.iter().enumerate() {
-            debug!("{:u} --> {:s}", i, box.debug_str());
+        let segment_end = segment_start + segment_len;
+        let mut piece_start = segment_start;
+        while piece_start < segment_end {
+            let level = levels[piece_start];
+            let mut piece_end = piece_start + 1;
+            while piece_end < segment_end && levels[piece_end] == level {
+                piece_end += 1
+            }
+
+            // Characters the first font can't render get runs of their own.
+            let runs = ctx.font_ctx.create_textruns(&segment_font_style,
+                                                    clump.text.slice_chars(piece_start,
+                                                                           piece_end),
+                                                    level & 1 == 1,
+                                                    decoration,
+                                                    letter_spacing,
+                                                    word_spacing,
+                                                    tab_size);
+            for run in runs.move_iter() {
+                let run_end = run_start + run.char_len();
+                let run = Arc::new(~run);
+                for (i, range) in clump.box_ranges.iter().enumerate() {
+                    let begin = cmp::max(range.begin(), run_start);
+                    let end = cmp::min(range.end(), run_end);
+                    if begin >= end {
+                        continue
+                    }
+
+                    let char_map = &clump.char_maps[i];
+                    let char_map = if begin == range.begin() {
+                        char_map.clone()
+                    } else {
+                        Arc::new(char_map.get().slice(begin - range.begin(), range.length()))
+                    };
+                    push_text_boxes(out_boxes,
+                                    &in_boxes[clump.boxes.begin() + i],
+                                    &run,
+                                    Range::new(begin - run_start, end - begin),
+                                    &char_map,
+                                    begin - run_start,
+                                    level)
+                }
+                run_start = run_end;
+            }
+            piece_start = piece_end;
         }
-        debug!("------------------");
+        segment_start = segment_end;
+    }
+}
 
-        debug!("--- Elem ranges: ---");
-        for (i, nr) in inline.elems.eachi() {
-            debug!("{:u}: {} --> {:?}", i, nr.range, nr.node.id()); ()
+/// Returns how the `white-space` property compresses the whitespace of text.
+fn compression_mode(white_space: white_space::T) -> CompressionMode {
+    match white_space {
+        white_space::normal | white_space::nowrap => CompressWhitespaceNewline,
+        white_space::pre | white_space::pre_wrap => CompressNone,
+        white_space::pre_line => CompressWhitespace,
+    }
+}
+
+/// Pushes boxes at the given embedding level for the given range of a text run, in the style of
+/// the given unscanned text box. Each newline preserved by the `white-space` property gets a box
+/// of its own, which the line box scanner ends the line with.
+fn push_text_boxes(out_boxes: &mut ~[Box],
+                   in_box: &Box,
+                   run: &Arc<~TextRun>,
+                   range: Range,
+                   char_map: &Arc<CharIndexMap>,
+                   node_start: uint,
+                   bidi_level: u8) {
+    let push_piece = |piece: Range| {
+        if piece.length() > 0 {
+            let new_text_box_info = ScannedTextBoxInfo::new(run.clone(),
+                                                            piece,
+                                                            char_map.clone(),
+                                                            node_start);
+            let new_metrics = run.get().metrics_for_range(&piece);
+            let mut new_box = in_box.transform(new_metrics.bounding_box.size,
+                                               ScannedTextBox(new_text_box_info));
+            new_box.bidi_level = bidi_level;
+            out_boxes.push(new_box)
         }
-        debug!("--------------------");
+    };
+
+    let text = run.get().text.get().slice_chars(range.begin(), range.end());
+    let mut piece_begin = range.begin();
+    for (i, ch) in text.iter().enumerate() {
+        if ch == '\n' {
+            let newline = range.begin() + i;
+            push_piece(Range::new(piece_begin, newline - piece_begin));
+            push_piece(Range::new(newline, 1));
+            piece_begin = newline + 1;
+        }
+    }
+    push_piece(Range::new(piece_begin, range.end() - piece_begin));
+}
 
-        let end = self.clump.end(); // FIXME: borrow checker workaround
-        self.clump.reset(end, 0);
+/// Resolves the embedding levels of the clumps of an inline flow with the Unicode Bidirectional
+/// Algorithm, per CSS 2.1 § 9.10. Returns the level of each character of each text clump, and the
+/// level of each clump of a box other than text.
+fn resolve_bidi_levels(inline: &InlineFlow, clumps: &[Clump]) -> ~[~[u8]] {
+    let paragraph_level = if inline.base.flags.rtl() { 1 } else { 0 };
+
+    // The types of the characters of the flow, with the explicit formatting characters that the
+    // embeddings of inline elements stand for between them. Boxes other than text are neutral,
+    // like U+FFFC OBJECT REPLACEMENT CHARACTER.
+    let mut classes = ~[];
+
+    // The `direction` of the block container sets the paragraph level, so its `embed` adds
+    // nothing. Its override applies to all of its content, as if an inline element surrounded it.
+    match inline.paragraph_embedding {
+        Some(ref embedding) if embedding.is_override => classes.push(embedding.bidi_class()),
+        _ => {}
+    }
 
-        new_whitespace
-    } // End of `flush_clump_to_list`.
+    // The index in `classes` of the first character of each box of each clump.
+    let mut box_starts = ~[];
+    let mut open_embeddings: ~[BidiEmbedding] = ~[];
+    for clump in clumps.iter() {
+        let boxes = match *clump {
+            OtherClump(box_i) => Range::new(box_i, 1),
+            TextClump(ref text) => text.boxes,
+        };
+        let mut clump_box_starts = ~[];
+        for box_i in boxes.eachi() {
+            let box = &inline.boxes[box_i];
+            let mut common = 0;
+            while common < open_embeddings.len() && common < box.bidi_embeddings.len() &&
+                    open_embeddings[common] == box.bidi_embeddings[common] {
+                common += 1;
+            }
+            for _ in range(common, open_embeddings.len()) {
+                classes.push(PDF);
+            }
+            for embedding in box.bidi_embeddings.slice_from(common).iter() {
+                classes.push(embedding.bidi_class());
+            }
+            open_embeddings = box.bidi_embeddings.clone();
+
+            clump_box_starts.push(classes.len());
+            match *clump {
+                OtherClump(_) => classes.push(ON),
+                TextClump(ref text) => {
+                    let range = &text.box_ranges[box_i - boxes.begin()];
+                    for ch in text.text.slice_chars(range.begin(), range.end()).iter() {
+                        classes.push(bidi_class(ch));
+                    }
+                }
+            }
+        }
+        box_starts.push(clump_box_starts);
+    }
+
+    // Left-to-right text outside of embeddings is all at the paragraph level already.
+    let levels = if paragraph_level == 0 && !classes.iter().any(|&class| is_rtl_class(class)) {
+        vec::from_elem(classes.len(), 0u8)
+    } else {
+        resolve_levels(classes, paragraph_level)
+    };
+
+    clumps.iter().zip(box_starts.iter()).map(|(clump, clump_box_starts)| {
+        match *clump {
+            OtherClump(_) => ~[levels[clump_box_starts[0]]],
+            TextClump(ref text) => {
+                let mut clump_levels = ~[];
+                for (range, &start) in text.box_ranges.iter().zip(clump_box_starts.iter()) {
+                    clump_levels.push_all(levels.slice(start, start + range.length()));
+                }
+                clump_levels
+            }
+        }
+    }).collect()
+}
+
+/// Splits text in the `small-caps` font variant into segments of lowercase letters, which are
+/// uppercased and marked to be shaped with a smaller font, and segments of everything else.
+/// Whitespace stays in the segment it follows. The given map of the text is updated for characters
+/// that expand.
+///
+/// The shaper does not apply OpenType features, so the small capitals of a face (`smcp`) are never
+/// used, and are always synthesized this way.
+fn small_caps_segments(text: &str, char_map: &mut CharIndexMap) -> ~[(~str, bool)] {
+    let mut segments = ~[];
+    let mut segment = ~"";
+    let mut segment_is_small = false;
+    let mut out_len = 0;
+    for ch in text.iter() {
+        let uppercase = to_uppercase(ch);
+        let is_small = if ch.is_whitespace() {
+            segment_is_small
+        } else {
+            uppercase != ch || ch == '\u00DF'
+        };
+        if is_small != segment_is_small && segment.len() > 0 {
+            segments.push((segment, segment_is_small));
+            segment = ~"";
+        }
+        segment_is_small = is_small;
+        match ch {
+            '\u00DF' => {
+                char_map.expand(out_len);
+                segment.push_str("SS");
+                out_len += 2
+            }
+            _ => {
+                segment.push_char(uppercase);
+                out_len += 1
+            }
+        }
+    }
+    if segment.len() > 0 {
+        segments.push((segment, segment_is_small));
+    }
+    segments
 }
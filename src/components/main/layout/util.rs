@@ -4,6 +4,7 @@
 
 use layout::box::Box;
 use layout::construct::{ConstructionResult, NoConstructionResult};
+use layout::counters::CounterValues;
 use layout::wrapper::LayoutNode;
 
 use extra::arc::Arc;
@@ -133,6 +134,14 @@ pub struct LayoutData {
 
     after_applicable_declarations: ~[Arc<~[PropertyDeclaration]>],
 
+    first_line_applicable_declarations: ~[Arc<~[PropertyDeclaration]>],
+
+    first_letter_applicable_declarations: ~[Arc<~[PropertyDeclaration]>],
+
+    /// The element whose selector matching results this element reuses, or the element itself if
+    /// it went through selector matching. `None` if its style cannot be shared.
+    style_sharing_source: Option<OpaqueNode>,
+
     /// The results of CSS styling for this node.
     before_style: Option<Arc<ComputedValues>>,
 
@@ -140,9 +149,23 @@ pub struct LayoutData {
 
     after_style: Option<Arc<ComputedValues>>,
 
+    /// The styles of the `::first-line` and `::first-letter` pseudo-elements. These inherit from
+    /// `style`, not from the parent node's style.
+    first_line_style: Option<Arc<ComputedValues>>,
+
+    first_letter_style: Option<Arc<ComputedValues>>,
+
     /// Description of how to account for recent style changes.
     restyle_damage: Option<int>,
 
+    /// The counters in scope for this node and its `::before` and `::after` pseudo-elements, if
+    /// they display any. See `counters.rs`.
+    counters: Option<CounterValues>,
+
+    before_counters: Option<CounterValues>,
+
+    after_counters: Option<CounterValues>,
+
     /// The current results of flow construction for this node. This is either a flow or a
     /// `ConstructionItem`. See comments in `construct.rs` for more details.
     flow_construction_result: ConstructionResult,
@@ -155,10 +178,18 @@ impl LayoutData {
             applicable_declarations: ~[],
             before_applicable_declarations: ~[],
             after_applicable_declarations: ~[],
+            first_line_applicable_declarations: ~[],
+            first_letter_applicable_declarations: ~[],
+            style_sharing_source: None,
             before_style: None,
             style: None,
             after_style: None,
+            first_line_style: None,
+            first_letter_style: None,
             restyle_damage: None,
+            counters: None,
+            before_counters: None,
+            after_counters: None,
             flow_construction_result: NoConstructionResult,
         }
     }
@@ -203,7 +234,7 @@ impl<'self> LayoutDataAccess for LayoutNode<'self> {
 /// Because the script task's GC does not trace layout, node data cannot be safely stored in layout
 /// data structures. Also, layout code tends to be faster when the DOM is not being accessed, for
 /// locality reasons. Using `OpaqueNode` enforces this invariant.
-#[deriving(Clone, Eq)]
+#[deriving(Clone, Eq, IterBytes)]
 pub struct OpaqueNode(uintptr_t);
 
 impl OpaqueNode {
@@ -7,6 +7,7 @@ use layout::layout_task::LayoutTask;
 
 use extra::url::Url;
 use geom::size::Size2D;
+use gfx::font_cache_task::FontCacheTask;
 use gfx::opts::Opts;
 use gfx::render_task::{PaintPermissionGranted, PaintPermissionRevoked};
 use gfx::render_task::{RenderChan, RenderTask};
@@ -51,6 +52,8 @@ impl Pipeline {
                        constellation_chan: ConstellationChan,
                        compositor_chan: CompositorChan,
                        image_cache_task: ImageCacheTask,
+                       resource_task: ResourceTask,
+                       font_cache_task: FontCacheTask,
                        profiler_chan: ProfilerChan,
                        opts: Opts,
                        script_pipeline: &Pipeline)
@@ -64,6 +67,7 @@ impl Pipeline {
                            render_port,
                            compositor_chan.clone(),
                            constellation_chan.clone(),
+                           font_cache_task.clone(),
                            opts.clone(),
                            profiler_chan.clone(),
                            render_shutdown_chan);
@@ -74,6 +78,8 @@ impl Pipeline {
                            script_pipeline.script_chan.clone(),
                            render_chan.clone(),
                            image_cache_task.clone(),
+                           resource_task,
+                           font_cache_task,
                            opts.clone(),
                            profiler_chan,
                            layout_shutdown_chan);
@@ -101,6 +107,7 @@ impl Pipeline {
                   compositor_chan: CompositorChan,
                   image_cache_task: ImageCacheTask,
                   resource_task: ResourceTask,
+                  font_cache_task: FontCacheTask,
                   profiler_chan: ProfilerChan,
                   window_size: Size2D<uint>,
                   opts: Opts)
@@ -133,6 +140,7 @@ impl Pipeline {
                     layout_port,
                     constellation_chan,
                     image_cache_task,
+                    font_cache_task,
                     profiler_chan,
                     layout_shutdown_chan,
                     render_shutdown_chan
@@ -143,7 +151,7 @@ impl Pipeline {
                                script_port,
                                script_chan.clone(),
                                constellation_chan.clone(),
-                               resource_task,
+                               resource_task.clone(),
                                image_cache_task.clone(),
                                window_size);
 
@@ -151,6 +159,7 @@ impl Pipeline {
                                render_port,
                                compositor_chan.clone(),
                                constellation_chan.clone(),
+                               font_cache_task.clone(),
                                opts.clone(),
                                profiler_chan.clone(),
                                render_shutdown_chan);
@@ -161,6 +170,8 @@ impl Pipeline {
                                script_chan.clone(),
                                render_chan.clone(),
                                image_cache_task,
+                               resource_task,
+                               font_cache_task,
                                opts.clone(),
                                profiler_chan,
                                layout_shutdown_chan);
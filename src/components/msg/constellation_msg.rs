@@ -36,6 +36,9 @@ pub enum Msg {
     NavigateMsg(NavigationDirection),
     RendererReadyMsg(PipelineId),
     ResizedWindowMsg(Size2D<uint>),
+    /// A CSS error in a stylesheet or a style attribute of a page, as formatted by the style
+    /// crate, for the embedder.
+    CSSErrorMsg(PipelineId, ~str),
 }
 
 /// Represents the two different ways to which a page can be navigated
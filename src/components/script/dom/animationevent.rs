@@ -0,0 +1,79 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::AnimationEventBinding;
+use dom::bindings::utils::{DOMString, Fallible};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::event::{AbstractEvent, Event, AnimationEventTypeId};
+use dom::window::Window;
+
+pub struct AnimationEvent {
+    parent: Event,
+    animation_name: DOMString,
+    elapsed_time: f32,
+    pseudo_element: DOMString,
+}
+
+impl AnimationEvent {
+    pub fn new_inherited() -> AnimationEvent {
+        AnimationEvent {
+            parent: Event::new_inherited(AnimationEventTypeId),
+            animation_name: ~"",
+            elapsed_time: 0.,
+            pseudo_element: ~"",
+        }
+    }
+
+    /// Creates an event about the animation, whose elapsed time is in seconds.
+    pub fn new(window: @mut Window,
+               type_: DOMString,
+               can_bubble: bool,
+               cancelable: bool,
+               animation_name: DOMString,
+               elapsed_time: f32,
+               pseudo_element: DOMString)
+               -> AbstractEvent {
+        let ev = Event::as_abstract(reflect_dom_object(@mut AnimationEvent::new_inherited(),
+                                                       window,
+                                                       AnimationEventBinding::Wrap));
+        {
+            let event = ev.mut_animationevent();
+            event.parent.InitEvent(type_, can_bubble, cancelable);
+            event.animation_name = animation_name;
+            event.elapsed_time = elapsed_time;
+            event.pseudo_element = pseudo_element;
+        }
+        ev
+    }
+
+    pub fn Constructor(owner: @mut Window,
+                       type_: DOMString,
+                       init: &AnimationEventBinding::AnimationEventInit)
+                       -> Fallible<AbstractEvent> {
+        Ok(AnimationEvent::new(owner, type_, init.parent.bubbles, init.parent.cancelable,
+                     init.animationName.clone(), init.elapsedTime, init.pseudoElement.clone()))
+    }
+
+    pub fn AnimationName(&self) -> DOMString {
+        self.animation_name.clone()
+    }
+
+    pub fn ElapsedTime(&self) -> f32 {
+        self.elapsed_time
+    }
+
+    pub fn PseudoElement(&self) -> DOMString {
+        self.pseudo_element.clone()
+    }
+}
+
+impl Reflectable for AnimationEvent {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.parent.reflector()
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        self.parent.mut_reflector()
+    }
+}
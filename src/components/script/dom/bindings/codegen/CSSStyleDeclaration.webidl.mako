@@ -0,0 +1,34 @@
+/* -*- Mode: IDL; tab-width: 2; indent-tabs-mode: nil; c-basic-offset: 2 -*- */
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this file,
+ * You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * The origin of this IDL file is
+ * http://dev.w3.org/csswg/cssom/#the-cssstyledeclaration-interface
+ */
+
+// This file is a Mako template, rendered with the properties of
+// src/components/style/properties.rs.mako. See also dom/cssproperties.rs.mako.
+
+<%
+def to_idl_name(name):
+    if name == "float":
+        return "cssFloat"
+    parts = name.split("-")
+    return parts[0] + "".join(part.capitalize() for part in parts[1:])
+%>
+
+interface CSSStyleDeclaration {
+           attribute DOMString cssText;
+  readonly attribute unsigned long length;
+  getter DOMString item(unsigned long index);
+  DOMString getPropertyValue(DOMString property);
+  DOMString getPropertyPriority(DOMString property);
+  void setProperty(DOMString property, DOMString value, optional DOMString priority = "");
+  DOMString removeProperty(DOMString property);
+
+  // http://dev.w3.org/csswg/cssom/#the-css2properties-interface
+% for property in LONGHANDS + SHORTHANDS:
+           attribute DOMString ${to_idl_name(property.name)};
+% endfor
+};
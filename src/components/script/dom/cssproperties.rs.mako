@@ -0,0 +1,30 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// This file is a Mako template, rendered with the properties of
+// src/components/style/properties.rs.mako. It implements the camel case attributes of
+// dom/bindings/codegen/CSSStyleDeclaration.webidl.mako.
+
+use dom::bindings::utils::DOMString;
+use dom::cssstyledeclaration::CSSStyleDeclaration;
+
+<%
+def to_native_name(name):
+    if name == "float":
+        return "CssFloat"
+    return "".join(part.capitalize() for part in name.split("-"))
+%>
+
+impl CSSStyleDeclaration {
+% for property in LONGHANDS + SHORTHANDS:
+    pub fn ${to_native_name(property.name)}(&self) -> DOMString {
+        self.GetPropertyValue(~"${property.name}")
+    }
+
+    pub fn Set${to_native_name(property.name)}(&mut self, value: DOMString) {
+        self.SetProperty(~"${property.name}", value, ~"")
+    }
+
+% endfor
+}
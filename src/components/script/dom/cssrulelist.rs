@@ -0,0 +1,65 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::CSSRuleListBinding;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::cssstylerule::CSSStyleRule;
+use dom::window::Window;
+
+use style;
+use style::Stylesheet;
+
+/// The style rules at the top level of a stylesheet. At-rules are not listed yet.
+pub struct CSSRuleList {
+    reflector_: Reflector,
+    rules: ~[@mut CSSStyleRule],
+}
+
+impl CSSRuleList {
+    pub fn new_inherited(window: @mut Window, stylesheet: @mut Stylesheet) -> CSSRuleList {
+        let mut rules = ~[];
+        for (i, rule) in stylesheet.rules.iter().enumerate() {
+            match *rule {
+                style::CSSStyleRule(_) => rules.push(CSSStyleRule::new(window, stylesheet, i)),
+                _ => {}
+            }
+        }
+        CSSRuleList {
+            reflector_: Reflector::new(),
+            rules: rules,
+        }
+    }
+
+    pub fn new(window: @mut Window, stylesheet: @mut Stylesheet) -> @mut CSSRuleList {
+        reflect_dom_object(@mut CSSRuleList::new_inherited(window, stylesheet),
+                           window, CSSRuleListBinding::Wrap)
+    }
+
+    pub fn Length(&self) -> u32 {
+        self.rules.len() as u32
+    }
+
+    pub fn Item(&self, index: u32) -> Option<@mut CSSStyleRule> {
+        if index < self.rules.len() as u32 {
+            Some(self.rules[index])
+        } else {
+            None
+        }
+    }
+
+    pub fn IndexedGetter(&self, index: u32, found: &mut bool) -> Option<@mut CSSStyleRule> {
+        *found = index < self.rules.len() as u32;
+        self.Item(index)
+    }
+}
+
+impl Reflectable for CSSRuleList {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
@@ -0,0 +1,285 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The `CSSStyleDeclaration` interface, for style attributes, style rules and computed styles. The
+//! camel case attributes for each property are in the generated `cssproperties.rs`.
+
+use dom::bindings::codegen::CSSStyleDeclarationBinding;
+use dom::bindings::utils::{Reflectable, Reflector, DOMString, reflect_dom_object};
+use dom::node::AbstractNode;
+use dom::window::Window;
+use layout_interface::{ComputedStyleQuery, ComputedStyleResponse};
+
+use extra::url::Url;
+use std::ascii::StrAsciiExt;
+use std::comm;
+use style;
+use style::{PropertyDeclarationBlock, PseudoElement, SourceDeclaration, Stylesheet};
+use style::{is_custom_property_name, is_supported_property, longhands_for_shorthand};
+use style::longhand_value_in_shorthand;
+use style::{parse_style_attribute, serialize_declarations, with_errors_silenced};
+
+/// What a declaration block belongs to. The declarations are read from it on every access, and
+/// changes are written back to it.
+pub enum CSSStyleDeclarationOwner {
+    /// The style attribute of an element.
+    InlineStyleOwner(AbstractNode),
+    /// The style rule at the given index of the rules of a stylesheet.
+    StyleRuleOwner(@mut Stylesheet, uint),
+    /// The computed style of an element or of one of its pseudo-elements, as returned by
+    /// `getComputedStyle()`. It is read-only.
+    ComputedStyleOwner(AbstractNode, Option<PseudoElement>),
+    /// The computed style of a pseudo-element that isn't supported, which is empty.
+    UnsupportedPseudoElementOwner(AbstractNode),
+}
+
+pub struct CSSStyleDeclaration {
+    reflector_: Reflector,
+    owner: CSSStyleDeclarationOwner,
+    window: @mut Window,
+}
+
+impl CSSStyleDeclaration {
+    pub fn new_inherited(window: @mut Window, owner: CSSStyleDeclarationOwner)
+                         -> CSSStyleDeclaration {
+        CSSStyleDeclaration {
+            reflector_: Reflector::new(),
+            owner: owner,
+            window: window,
+        }
+    }
+
+    pub fn new(window: @mut Window, owner: CSSStyleDeclarationOwner)
+               -> @mut CSSStyleDeclaration {
+        reflect_dom_object(@mut CSSStyleDeclaration::new_inherited(window, owner),
+                           window, CSSStyleDeclarationBinding::Wrap)
+    }
+
+    fn declarations(&self) -> ~[SourceDeclaration] {
+        match self.owner {
+            InlineStyleOwner(node) => do node.with_imm_element |element| {
+                match element.style_attribute {
+                    Some(ref block) => block.source.clone(),
+                    None => ~[],
+                }
+            },
+            StyleRuleOwner(stylesheet, index) => match stylesheet.rules[index] {
+                style::CSSStyleRule(ref rule) => rule.declarations.source.clone(),
+                _ => fail!("CSSStyleDeclaration: not a style rule"),
+            },
+            // Layout serializes all the properties at once, so that each access makes a single
+            // query.
+            ComputedStyleOwner(node, ref pseudo_element) => {
+                self.window.reflow_for_script_query();
+                let (port, chan) = comm::stream();
+                let query = ComputedStyleQuery(node, pseudo_element.clone(), chan);
+                match self.window.page.query_layout(query, port) {
+                    ComputedStyleResponse(declarations) => declarations.unwrap_or(~[]),
+                }
+            }
+            UnsupportedPseudoElementOwner(_) => ~[],
+        }
+    }
+
+    fn set_declarations(&mut self, declarations: ~[SourceDeclaration]) {
+        match self.owner {
+            // Setting the attribute parses it again and restyles the document.
+            InlineStyleOwner(node) => {
+                let value = serialize_declarations(declarations);
+                do node.as_mut_element |element| {
+                    element.set_string_attribute(node, "style", value.clone())
+                }
+            }
+            StyleRuleOwner(stylesheet, index) => {
+                let block = PropertyDeclarationBlock::from_source(declarations,
+                                                                  &stylesheet.base_url);
+                match stylesheet.rules[index] {
+                    style::CSSStyleRule(ref mut rule) => rule.declarations = block,
+                    _ => fail!("CSSStyleDeclaration: not a style rule"),
+                }
+                let document = self.window.page.frame.get_ref().document;
+                document.document().stylesheets_changed()
+            }
+            // FIXME: This should throw a `NoModificationAllowedError`.
+            ComputedStyleOwner(*) | UnsupportedPseudoElementOwner(_) => (),
+        }
+    }
+
+    /// The URL that relative URLs in the declarations are resolved against.
+    fn base_url(&self) -> Url {
+        match self.owner {
+            InlineStyleOwner(node) | ComputedStyleOwner(node, _) |
+            UnsupportedPseudoElementOwner(node) => {
+                node.node().owner_doc().document().base_url()
+            }
+            StyleRuleOwner(stylesheet, _) => stylesheet.base_url.clone(),
+        }
+    }
+
+    pub fn CssText(&self) -> DOMString {
+        serialize_declarations(self.declarations())
+    }
+
+    pub fn SetCssText(&mut self, value: DOMString) {
+        let base_url = self.base_url();
+        let block = do self.window.page.with_css_error_reporting(base_url.to_str()) {
+            parse_style_attribute(value, &base_url)
+        };
+        self.set_declarations(block.source)
+    }
+
+    pub fn Length(&self) -> u32 {
+        self.declarations().len() as u32
+    }
+
+    pub fn Item(&self, index: u32) -> DOMString {
+        let declarations = self.declarations();
+        if index < declarations.len() as u32 {
+            declarations[index].name.clone()
+        } else {
+            ~""
+        }
+    }
+
+    pub fn IndexedGetter(&self, index: u32, found: &mut bool) -> DOMString {
+        let declarations = self.declarations();
+        *found = index < declarations.len() as u32;
+        if *found {
+            declarations[index].name.clone()
+        } else {
+            ~""
+        }
+    }
+
+    pub fn GetPropertyValue(&self, property: DOMString) -> DOMString {
+        let name = normalize_property_name(property);
+        match find_value(self.declarations(), name, &self.base_url()) {
+            Some((value, _)) => value,
+            None => ~"",
+        }
+    }
+
+    pub fn GetPropertyPriority(&self, property: DOMString) -> DOMString {
+        let name = normalize_property_name(property);
+        match find_value(self.declarations(), name, &self.base_url()) {
+            Some((_, true)) => ~"important",
+            _ => ~"",
+        }
+    }
+
+    pub fn SetProperty(&mut self, property: DOMString, value: DOMString, priority: DOMString) {
+        let name = normalize_property_name(property);
+        if !is_supported_property(name) {
+            return
+        }
+        if value.is_empty() {
+            self.RemoveProperty(property);
+            return
+        }
+        let priority = priority.to_ascii_lower();
+        let important = match priority.as_slice() {
+            "important" => true,
+            "" => false,
+            _ => return,
+        };
+
+        // Invalid values are ignored, and so are values that would smuggle in other
+        // declarations.
+        let source = serialize_declarations([SourceDeclaration {
+            name: name.clone(),
+            value: value,
+            important: important,
+        }]);
+        let base_url = self.base_url();
+        let block = with_errors_silenced(|| parse_style_attribute(source, &base_url));
+        if block.source.len() != 1 || block.source[0].name != name {
+            return
+        }
+
+        // A declaration that is already there is updated where it is; a new one goes last.
+        let mut declarations = self.declarations();
+        let longhands = longhands_for_shorthand(name).unwrap_or(~[]);
+        let position = declarations.iter().position(|declaration| {
+            declaration.name == name ||
+                longhands.iter().any(|longhand| declaration.name.as_slice() == *longhand)
+        });
+        let declaration = block.source.pop();
+        match position {
+            Some(position) => {
+                remove_declarations(&mut declarations, name);
+                declarations.insert(position, declaration)
+            }
+            None => declarations.push(declaration),
+        }
+        self.set_declarations(declarations)
+    }
+
+    pub fn RemoveProperty(&mut self, property: DOMString) -> DOMString {
+        let name = normalize_property_name(property);
+        let mut declarations = self.declarations();
+        let value = match find_value(declarations, name, &self.base_url()) {
+            Some((value, _)) => value,
+            None => ~"",
+        };
+        let length = declarations.len();
+        remove_declarations(&mut declarations, name);
+        if declarations.len() != length {
+            self.set_declarations(declarations)
+        }
+        value
+    }
+}
+
+/// Property names are ASCII case-insensitive, except for those of custom properties.
+fn normalize_property_name(property: &str) -> ~str {
+    if is_custom_property_name(property) {
+        property.to_owned()
+    } else {
+        property.to_ascii_lower()
+    }
+}
+
+/// Returns the value of the given property and whether it is important, from the declaration that
+/// sets it last, either directly or through one of its shorthands. Important declarations win over
+/// normal ones.
+fn find_value(declarations: &[SourceDeclaration], name: &str, base_url: &Url)
+              -> Option<(~str, bool)> {
+    let mut result = None;
+    for declaration in declarations.iter() {
+        let value = if declaration.name.as_slice() == name {
+            declaration.value.clone()
+        } else {
+            match longhands_for_shorthand(declaration.name) {
+                Some(longhands) if longhands.iter().any(|longhand| *longhand == name) => {
+                    longhand_value_in_shorthand(declaration.name, name, declaration.value, base_url)
+                }
+                _ => continue,
+            }
+        };
+        match result {
+            Some((_, true)) if !declaration.important => (),
+            _ => result = Some((value, declaration.important)),
+        }
+    }
+    result
+}
+
+/// Removes the declarations of the given property, and of its longhands if it is a shorthand.
+fn remove_declarations(declarations: &mut ~[SourceDeclaration], name: &str) {
+    let longhands = longhands_for_shorthand(name).unwrap_or(~[]);
+    declarations.retain(|declaration| {
+        declaration.name.as_slice() != name &&
+            !longhands.iter().any(|longhand| declaration.name.as_slice() == *longhand)
+    })
+}
+
+impl Reflectable for CSSStyleDeclaration {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
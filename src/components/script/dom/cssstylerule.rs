@@ -0,0 +1,87 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::CSSStyleRuleBinding;
+use dom::bindings::utils::{Reflectable, Reflector, DOMString, reflect_dom_object};
+use dom::cssstyledeclaration::{CSSStyleDeclaration, StyleRuleOwner};
+use dom::window::Window;
+
+use style;
+use style::{StyleRule, Stylesheet, serialize_declarations};
+
+/// The style rule at the given index of the rules of a stylesheet.
+pub struct CSSStyleRule {
+    reflector_: Reflector,
+    stylesheet: @mut Stylesheet,
+    index: uint,
+    /// The object `style` returns, created on first access.
+    style_declaration: Option<@mut CSSStyleDeclaration>,
+    window: @mut Window,
+}
+
+impl CSSStyleRule {
+    pub fn new_inherited(window: @mut Window, stylesheet: @mut Stylesheet, index: uint)
+                         -> CSSStyleRule {
+        CSSStyleRule {
+            reflector_: Reflector::new(),
+            stylesheet: stylesheet,
+            index: index,
+            style_declaration: None,
+            window: window,
+        }
+    }
+
+    pub fn new(window: @mut Window, stylesheet: @mut Stylesheet, index: uint)
+               -> @mut CSSStyleRule {
+        reflect_dom_object(@mut CSSStyleRule::new_inherited(window, stylesheet, index),
+                           window, CSSStyleRuleBinding::Wrap)
+    }
+
+    fn with_rule<R>(&self, f: &fn(&StyleRule) -> R) -> R {
+        match self.stylesheet.rules[self.index] {
+            style::CSSStyleRule(ref rule) => f(rule),
+            _ => fail!("CSSStyleRule: not a style rule"),
+        }
+    }
+
+    pub fn CssText(&self) -> DOMString {
+        do self.with_rule |rule| {
+            let declarations = serialize_declarations(rule.declarations.source);
+            if declarations.is_empty() {
+                rule.selector_text + " { }"
+            } else {
+                rule.selector_text + " { " + declarations + " }"
+            }
+        }
+    }
+
+    pub fn SelectorText(&self) -> DOMString {
+        do self.with_rule |rule| {
+            rule.selector_text.clone()
+        }
+    }
+
+    pub fn Style(&mut self) -> @mut CSSStyleDeclaration {
+        match self.style_declaration {
+            None => {
+                let declaration = CSSStyleDeclaration::new(self.window,
+                                                           StyleRuleOwner(self.stylesheet,
+                                                                          self.index));
+                self.style_declaration = Some(declaration);
+                declaration
+            }
+            Some(declaration) => declaration
+        }
+    }
+}
+
+impl Reflectable for CSSStyleRule {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
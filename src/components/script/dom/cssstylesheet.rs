@@ -0,0 +1,57 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::CSSStyleSheetBinding;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::cssrulelist::CSSRuleList;
+use dom::window::Window;
+
+use style::Stylesheet;
+
+/// A stylesheet of the document. Script modifies its own copy of the `Stylesheet`; the document
+/// sends copies of all its sheets to layout whenever one of them changes.
+pub struct CSSStyleSheet {
+    reflector_: Reflector,
+    stylesheet: @mut Stylesheet,
+    /// The object `cssRules` returns, created on first access.
+    rule_list: Option<@mut CSSRuleList>,
+    window: @mut Window,
+}
+
+impl CSSStyleSheet {
+    pub fn new_inherited(window: @mut Window, stylesheet: Stylesheet) -> CSSStyleSheet {
+        CSSStyleSheet {
+            reflector_: Reflector::new(),
+            stylesheet: @mut stylesheet,
+            rule_list: None,
+            window: window,
+        }
+    }
+
+    pub fn new(window: @mut Window, stylesheet: Stylesheet) -> @mut CSSStyleSheet {
+        reflect_dom_object(@mut CSSStyleSheet::new_inherited(window, stylesheet),
+                           window, CSSStyleSheetBinding::Wrap)
+    }
+
+    pub fn CssRules(&mut self) -> @mut CSSRuleList {
+        match self.rule_list {
+            None => {
+                let list = CSSRuleList::new(self.window, self.stylesheet);
+                self.rule_list = Some(list);
+                list
+            }
+            Some(list) => list
+        }
+    }
+}
+
+impl Reflectable for CSSStyleSheet {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::comment::Comment;
+use dom::cssstylesheet::CSSStyleSheet;
 use dom::bindings::codegen::DocumentBinding;
 use dom::bindings::utils::{Reflectable, Reflector, Traceable, reflect_dom_object};
 use dom::bindings::utils::{ErrorResult, Fallible, NotSupported, InvalidCharacter, HierarchyRequest};
@@ -17,13 +18,17 @@ use dom::htmldocument::HTMLDocument;
 use dom::mouseevent::MouseEvent;
 use dom::namespace::Null;
 use dom::node::{AbstractNode, Node, ElementNodeTypeId, DocumentNodeTypeId};
+use dom::stylesheetlist::StyleSheetList;
 use dom::text::Text;
 use dom::uievent::UIEvent;
 use dom::window::Window;
 use dom::htmltitleelement::HTMLTitleElement;
 use html::hubbub_html_parser::build_element_from_tag;
 use layout_interface::{DocumentDamageLevel, ContentChangedDocumentDamage};
+use layout_interface::{MatchSelectorsDocumentDamage, SetStylesheetsMsg};
+use servo_util::url::make_url;
 
+use extra::url::Url;
 use js::jsapi::{JSObject, JSContext, JSTracer};
 use std::ascii::StrAsciiExt;
 use std::cast;
@@ -92,7 +97,13 @@ pub struct Document {
     window: @mut Window,
     doctype: DocumentType,
     title: ~str,
-    idmap: HashMap<DOMString, AbstractNode>
+    /// The address the document was loaded from, if it was loaded.
+    url: Option<Url>,
+    idmap: HashMap<DOMString, AbstractNode>,
+    /// The stylesheets of the document, in document order.
+    stylesheets: ~[@mut CSSStyleSheet],
+    /// The object `document.styleSheets` returns, created on first access.
+    stylesheet_list: Option<@mut StyleSheetList>,
 }
 
 impl Document {
@@ -113,7 +124,8 @@ impl Document {
         abstract
     }
 
-    pub fn new_inherited(window: @mut Window, doctype: DocumentType) -> Document {
+    pub fn new_inherited(window: @mut Window, url: Option<Url>, doctype: DocumentType)
+                         -> Document {
         let node_type = match doctype {
             HTML => HTMLDocumentTypeId,
             SVG | XML => PlainDocumentTypeId
@@ -124,17 +136,33 @@ impl Document {
             window: window,
             doctype: doctype,
             title: ~"",
-            idmap: HashMap::new()
+            url: url,
+            idmap: HashMap::new(),
+            stylesheets: ~[],
+            stylesheet_list: None,
         }
     }
 
     pub fn new(window: @mut Window, doctype: DocumentType) -> AbstractDocument {
-        let document = Document::new_inherited(window, doctype);
+        let document = Document::new_inherited(window, None, doctype);
         Document::reflect_document(@mut document, window, DocumentBinding::Wrap)
     }
 }
 
 impl Document {
+    /// Returns the URL relative URLs in the document are resolved against: the address of the
+    /// document, or else that of the page of its window. The page has no address until it has
+    /// finished loading.
+    pub fn base_url(&self) -> Url {
+        match self.url {
+            Some(ref url) => url.clone(),
+            None => match self.window.page.url {
+                Some((ref url, _)) => url.clone(),
+                None => make_url(~"about:blank", None),
+            },
+        }
+    }
+
     pub fn Constructor(owner: @mut Window) -> Fallible<AbstractDocument> {
         Ok(Document::new(owner, XML))
     }
@@ -303,6 +331,18 @@ impl Document {
         }
     }
 
+    // http://dev.w3.org/csswg/cssom/#dom-document-stylesheets
+    pub fn StyleSheets(&mut self) -> @mut StyleSheetList {
+        match self.stylesheet_list {
+            None => {
+                let list = StyleSheetList::new(self.window, self.stylesheets.clone());
+                self.stylesheet_list = Some(list);
+                list
+            }
+            Some(list) => list
+        }
+    }
+
     // http://www.whatwg.org/specs/web-apps/current-work/#dom-document-head
     pub fn GetHead(&self) -> Option<AbstractNode> {
         self.get_html_element().and_then(|root| {
@@ -393,6 +433,23 @@ impl Document {
         self.window.damage_and_reflow(damage);
     }
 
+    /// Adds a stylesheet after the others, in `document.styleSheets` as well.
+    pub fn add_stylesheet(&mut self, sheet: @mut CSSStyleSheet) {
+        self.stylesheets.push(sheet);
+        match self.stylesheet_list {
+            Some(list) => list.sheets.push(sheet),
+            None => (),
+        }
+    }
+
+    /// Sends all the stylesheets of this document to layout again, after script modified one of
+    /// them.
+    pub fn stylesheets_changed(&self) {
+        let sheets = self.stylesheets.iter().map(|sheet| (*sheet.stylesheet).clone()).collect();
+        self.window.page.layout_chan.send(SetStylesheetsMsg(sheets));
+        self.damage_and_reflow(MatchSelectorsDocumentDamage);
+    }
+
     pub fn wait_until_safe_to_modify_dom(&self) {
         self.window.wait_until_safe_to_modify_dom();
     }
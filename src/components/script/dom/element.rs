@@ -12,6 +12,7 @@ use dom::bindings::utils::{InvalidCharacter, QName, Name, InvalidXMLName, xml_na
 use dom::htmlcollection::HTMLCollection;
 use dom::clientrect::ClientRect;
 use dom::clientrectlist::ClientRectList;
+use dom::cssstyledeclaration::{CSSStyleDeclaration, InlineStyleOwner};
 use dom::document::AbstractDocument;
 use dom::node::{AbstractNode, ElementNodeTypeId, Node};
 use dom::document;
@@ -32,7 +33,9 @@ pub struct Element {
     namespace: Namespace,
     attrs: ~[@mut Attr],
     style_attribute: Option<style::PropertyDeclarationBlock>,
-    attr_list: Option<@mut AttrList>
+    attr_list: Option<@mut AttrList>,
+    /// The object `element.style` returns, created on first access.
+    style_declaration: Option<@mut CSSStyleDeclaration>,
 }
 
 impl Reflectable for Element {
@@ -132,6 +135,7 @@ impl<'self> Element {
             attrs: ~[],
             attr_list: None,
             style_attribute: None,
+            style_declaration: None,
         }
     }
 
@@ -224,7 +228,15 @@ impl<'self> Element {
 
         match local_name.as_slice() {
             "style" => {
-                self.style_attribute = Some(style::parse_style_attribute(value))
+                // The page has no URL yet while the document is being parsed, so resolve against
+                // the document's own.
+                let document = self.node.owner_doc();
+                let base_url = document.document().base_url();
+                let block = do document.document().window.page.with_css_error_reporting(
+                        base_url.to_str()) {
+                    style::parse_style_attribute(value, &base_url)
+                };
+                self.style_attribute = Some(block)
             }
             "id" => {
                 let doc = self.node.owner_doc();
@@ -310,6 +322,19 @@ impl Element {
         }
     }
 
+    pub fn Style(&mut self, abstract_self: AbstractNode) -> @mut CSSStyleDeclaration {
+        match self.style_declaration {
+            None => {
+                let window = self.node.owner_doc().document().window;
+                let declaration = CSSStyleDeclaration::new(window,
+                                                           InlineStyleOwner(abstract_self));
+                self.style_declaration = Some(declaration);
+                declaration
+            }
+            Some(declaration) => declaration
+        }
+    }
+
     pub fn GetAttribute(&self, name: DOMString) -> Option<DOMString> {
         self.get_attr(Null, name).map(|s| s.to_owned())
     }
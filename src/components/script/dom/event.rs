@@ -7,20 +7,40 @@ use dom::window::Window;
 use dom::bindings::codegen::EventBinding;
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::bindings::utils::{DOMString, ErrorResult, Fallible};
+use dom::animationevent::AnimationEvent;
 use dom::mouseevent::MouseEvent;
+use dom::transitionevent::TransitionEvent;
 use dom::uievent::UIEvent;
 
 use geom::point::Point2D;
 
 use std::cast;
+use std::libc::uintptr_t;
 use std::unstable::raw::Box;
 
+/// The address of a node, as sent by layout. The node may have been removed from the document, or
+/// even freed, by the time script receives it; script must look for a node with that address in
+/// the document instead of dereferencing it.
+pub type UntrustedNodeAddress = uintptr_t;
+
 pub enum Event_ {
     ResizeEvent(uint, uint), 
     ReflowEvent,
+    /// A frame of the running CSS transitions and animations is due.
+    AnimationFrameEvent,
     ClickEvent(uint, Point2D<f32>),
     MouseDownEvent(uint, Point2D<f32>),
     MouseUpEvent(uint, Point2D<f32>),
+    /// CSS transitions and animations ended, in this order.
+    AnimationEndEvents(~[AnimationEndEvent_]),
+}
+
+/// The end of a CSS transition or animation of the node at the given address.
+pub enum AnimationEndEvent_ {
+    /// The transitioned property and the duration of the transition, in seconds.
+    TransitionEndEvent(UntrustedNodeAddress, ~str, f32),
+    /// The name of the animation and its active duration, in seconds.
+    AnimationEndEvent(UntrustedNodeAddress, ~str, f32),
 }
 
 pub struct AbstractEvent {
@@ -99,6 +119,34 @@ impl AbstractEvent {
         self.transmute_mut()
     }
 
+    pub fn is_transitionevent(&self) -> bool {
+        self.type_id() == TransitionEventTypeId
+    }
+
+    pub fn transitionevent<'a>(&'a self) -> &'a TransitionEvent {
+        assert!(self.is_transitionevent());
+        self.transmute()
+    }
+
+    pub fn mut_transitionevent<'a>(&'a self) -> &'a mut TransitionEvent {
+        assert!(self.is_transitionevent());
+        self.transmute_mut()
+    }
+
+    pub fn is_animationevent(&self) -> bool {
+        self.type_id() == AnimationEventTypeId
+    }
+
+    pub fn animationevent<'a>(&'a self) -> &'a AnimationEvent {
+        assert!(self.is_animationevent());
+        self.transmute()
+    }
+
+    pub fn mut_animationevent<'a>(&'a self) -> &'a mut AnimationEvent {
+        assert!(self.is_animationevent());
+        self.transmute_mut()
+    }
+
     pub fn propagation_stopped(&self) -> bool {
         self.event().stop_propagation
     }
@@ -123,7 +171,9 @@ pub enum EventTypeId {
     HTMLEventTypeId,
     UIEventTypeId,
     MouseEventTypeId,
-    KeyEventTypeId
+    KeyEventTypeId,
+    TransitionEventTypeId,
+    AnimationEventTypeId,
 }
 
 pub struct Event {
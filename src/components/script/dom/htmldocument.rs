@@ -11,6 +11,7 @@ use dom::namespace::Null;
 use dom::node::{AbstractNode, ElementNodeTypeId};
 use dom::window::Window;
 
+use extra::url::Url;
 use js::jsapi::JSTracer;
 use std::str::eq_slice;
 use style::TElement;
@@ -20,14 +21,14 @@ pub struct HTMLDocument {
 }
 
 impl HTMLDocument {
-    pub fn new_inherited(window: @mut Window) -> HTMLDocument {
+    pub fn new_inherited(window: @mut Window, url: Option<Url>) -> HTMLDocument {
         HTMLDocument {
-            parent: Document::new_inherited(window, HTML)
+            parent: Document::new_inherited(window, url, HTML)
         }
     }
 
-    pub fn new(window: @mut Window) -> AbstractDocument {
-        let document = HTMLDocument::new_inherited(window);
+    pub fn new(window: @mut Window, url: Option<Url>) -> AbstractDocument {
+        let document = HTMLDocument::new_inherited(window, url);
         Document::reflect_document(@mut document, window, HTMLDocumentBinding::Wrap)
     }
 }
@@ -0,0 +1,58 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::StyleSheetListBinding;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::cssstylesheet::CSSStyleSheet;
+use dom::window::Window;
+
+pub struct StyleSheetList {
+    reflector_: Reflector,
+    sheets: ~[@mut CSSStyleSheet],
+    window: @mut Window,
+}
+
+impl StyleSheetList {
+    pub fn new_inherited(window: @mut Window,
+                         sheets: ~[@mut CSSStyleSheet]) -> StyleSheetList {
+        StyleSheetList {
+            reflector_: Reflector::new(),
+            sheets: sheets,
+            window: window,
+        }
+    }
+
+    pub fn new(window: @mut Window,
+               sheets: ~[@mut CSSStyleSheet]) -> @mut StyleSheetList {
+        reflect_dom_object(@mut StyleSheetList::new_inherited(window, sheets),
+                           window, StyleSheetListBinding::Wrap)
+    }
+
+    pub fn Length(&self) -> u32 {
+        self.sheets.len() as u32
+    }
+
+    pub fn Item(&self, index: u32) -> Option<@mut CSSStyleSheet> {
+        if index < self.sheets.len() as u32 {
+            Some(self.sheets[index])
+        } else {
+            None
+        }
+    }
+
+    pub fn IndexedGetter(&self, index: u32, found: &mut bool) -> Option<@mut CSSStyleSheet> {
+        *found = index < self.sheets.len() as u32;
+        self.Item(index)
+    }
+}
+
+impl Reflectable for StyleSheetList {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        &mut self.reflector_
+    }
+}
@@ -0,0 +1,79 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::TransitionEventBinding;
+use dom::bindings::utils::{DOMString, Fallible};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::event::{AbstractEvent, Event, TransitionEventTypeId};
+use dom::window::Window;
+
+pub struct TransitionEvent {
+    parent: Event,
+    property_name: DOMString,
+    elapsed_time: f32,
+    pseudo_element: DOMString,
+}
+
+impl TransitionEvent {
+    pub fn new_inherited() -> TransitionEvent {
+        TransitionEvent {
+            parent: Event::new_inherited(TransitionEventTypeId),
+            property_name: ~"",
+            elapsed_time: 0.,
+            pseudo_element: ~"",
+        }
+    }
+
+    /// Creates an event about the transitioned property, whose elapsed time is in seconds.
+    pub fn new(window: @mut Window,
+               type_: DOMString,
+               can_bubble: bool,
+               cancelable: bool,
+               property_name: DOMString,
+               elapsed_time: f32,
+               pseudo_element: DOMString)
+               -> AbstractEvent {
+        let ev = Event::as_abstract(reflect_dom_object(@mut TransitionEvent::new_inherited(),
+                                                       window,
+                                                       TransitionEventBinding::Wrap));
+        {
+            let event = ev.mut_transitionevent();
+            event.parent.InitEvent(type_, can_bubble, cancelable);
+            event.property_name = property_name;
+            event.elapsed_time = elapsed_time;
+            event.pseudo_element = pseudo_element;
+        }
+        ev
+    }
+
+    pub fn Constructor(owner: @mut Window,
+                       type_: DOMString,
+                       init: &TransitionEventBinding::TransitionEventInit)
+                       -> Fallible<AbstractEvent> {
+        Ok(TransitionEvent::new(owner, type_, init.parent.bubbles, init.parent.cancelable,
+                     init.propertyName.clone(), init.elapsedTime, init.pseudoElement.clone()))
+    }
+
+    pub fn PropertyName(&self) -> DOMString {
+        self.property_name.clone()
+    }
+
+    pub fn ElapsedTime(&self) -> f32 {
+        self.elapsed_time
+    }
+
+    pub fn PseudoElement(&self) -> DOMString {
+        self.pseudo_element.clone()
+    }
+}
+
+impl Reflectable for TransitionEvent {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.parent.reflector()
+    }
+
+    fn mut_reflector<'a>(&'a mut self) -> &'a mut Reflector {
+        self.parent.mut_reflector()
+    }
+}
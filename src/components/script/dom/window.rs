@@ -6,22 +6,27 @@ use dom::bindings::codegen::WindowBinding;
 use dom::bindings::utils::{Reflectable, Reflector, Traceable};
 use dom::bindings::utils::{trace_option, trace_reflector};
 use dom::bindings::utils::DOMString;
+use dom::cssstyledeclaration::{CSSStyleDeclaration, ComputedStyleOwner};
+use dom::cssstyledeclaration::UnsupportedPseudoElementOwner;
 use dom::document::AbstractDocument;
 use dom::eventtarget::{EventTarget, WindowTypeId};
 use dom::node::AbstractNode;
 use dom::location::Location;
 use dom::navigator::Navigator;
 
-use layout_interface::{ReflowForDisplay, DocumentDamageLevel};
+use layout_interface::{ReflowDocumentDamage, ReflowForDisplay, ReflowForScriptQuery};
+use layout_interface::DocumentDamageLevel;
 use script_task::{ExitWindowMsg, FireTimerMsg, Page, ScriptChan};
 use servo_msg::compositor_msg::ScriptListener;
 use servo_net::image_cache_task::ImageCacheTask;
+use style::{After, Before, FirstLetter, FirstLine};
 
 use js::glue::*;
 use js::jsapi::{JSObject, JSContext, JS_DefineProperty};
 use js::jsapi::{JSPropertyOp, JSStrictPropertyOp, JSTracer};
 use js::{JSVAL_NULL, JSPROP_ENUMERATE};
 
+use std::ascii::StrAsciiExt;
 use std::cell::Cell;
 use std::comm;
 use std::comm::SharedChan;
@@ -143,6 +148,27 @@ impl Window {
     pub fn Print(&self) {
     }
 
+    pub fn GetComputedStyle(&self, element: AbstractNode, pseudo_element: DOMString)
+                            -> @mut CSSStyleDeclaration {
+        let window = element.node().owner_doc().document().window;
+        // An argument that doesn't start with a colon is ignored, and the style of the element is
+        // returned. A pseudo-element that isn't supported has no style.
+        if !pseudo_element.starts_with(":") {
+            return CSSStyleDeclaration::new(window, ComputedStyleOwner(element, None))
+        }
+        let pseudo_element = pseudo_element.to_ascii_lower();
+        let pseudo_element = match pseudo_element.as_slice() {
+            ":before" | "::before" => Before,
+            ":after" | "::after" => After,
+            ":first-line" | "::first-line" => FirstLine,
+            ":first-letter" | "::first-letter" => FirstLetter,
+            _ => {
+                return CSSStyleDeclaration::new(window, UnsupportedPseudoElementOwner(element))
+            }
+        };
+        CSSStyleDeclaration::new(window, ComputedStyleOwner(element, Some(pseudo_element)))
+    }
+
     pub fn ShowModalDialog(&self, _cx: *JSContext, _url: DOMString, _argument: JSVal) -> JSVal {
         JSVAL_NULL
     }
@@ -193,6 +219,13 @@ impl Window {
         self.page.reflow(ReflowForDisplay, self.script_chan.clone(), self.compositor);
     }
 
+    /// Brings the styles and the layout of the document up to date before answering a query from
+    /// script. No display list is built.
+    pub fn reflow_for_script_query(&self) {
+        self.page.damage(ReflowDocumentDamage);
+        self.page.reflow(ReflowForScriptQuery, self.script_chan.clone(), self.compositor);
+    }
+
     pub fn wait_until_safe_to_modify_dom(&self) {
         // FIXME: This disables concurrent layout while we are modifying the DOM, since
         //        our current architecture is entirely unsafe in the presence of races.
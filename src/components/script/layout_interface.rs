@@ -16,7 +16,7 @@ use script_task::{ScriptChan};
 use servo_util::geometry::Au;
 use std::comm::{Chan, SharedChan};
 use std::cmp;
-use style::Stylesheet;
+use style::{PseudoElement, SourceDeclaration, Stylesheet};
 
 /// Asynchronous messages that script can send to layout.
 ///
@@ -25,6 +25,10 @@ pub enum Msg {
     /// Adds the given stylesheet to the document.
     AddStylesheetMsg(Stylesheet),
 
+    /// Replaces all the stylesheets of the document with the given ones, after script modified
+    /// them through the CSSOM.
+    SetStylesheetsMsg(~[Stylesheet]),
+
     /// Requests a reflow.
     ReflowMsg(~Reflow),
 
@@ -54,19 +58,28 @@ pub enum LayoutQuery {
     ContentBoxQuery(AbstractNode, Chan<ContentBoxResponse>),
     /// Requests the dimensions of all the content boxes, as in the `getClientRects()` call.
     ContentBoxesQuery(AbstractNode, Chan<ContentBoxesResponse>),
-    /// Requests the node containing the point of interest
+    /// Requests the node containing the point of interest, and the character offset of the point
+    /// within it if it is a text node
     HitTestQuery(AbstractNode, Point2D<f32>, Chan<Result<HitTestResponse, ()>>),
+    /// Requests the computed style of an element or of one of its pseudo-elements, as in the
+    /// `getComputedStyle()` call.
+    ComputedStyleQuery(AbstractNode, Option<PseudoElement>, Chan<ComputedStyleResponse>),
 }
 
 pub struct ContentBoxResponse(Rect<Au>);
 pub struct ContentBoxesResponse(~[Rect<Au>]);
-pub struct HitTestResponse(AbstractNode);
+pub struct HitTestResponse(AbstractNode, Option<uint>);
+/// The serialized computed value of every longhand, or `None` if the node has not been styled.
+pub struct ComputedStyleResponse(Option<~[SourceDeclaration]>);
 
 /// Determines which part of the 
 #[deriving(Eq, Ord)]
 pub enum DocumentDamageLevel {
     /// Reflow, but do not perform CSS selector matching.
     ReflowDocumentDamage,
+    /// Apply the running transitions and animations and reflow, but do not perform CSS selector
+    /// matching.
+    AnimateDocumentDamage,
     /// Perform CSS selector matching and reflow.
     MatchSelectorsDocumentDamage,
     /// Content changed; set full style damage and do the above.
@@ -138,6 +151,8 @@ fn test_add_damage() {
 
     assert_add(ReflowDocumentDamage, ReflowDocumentDamage, ReflowDocumentDamage);
     assert_add(ContentChangedDocumentDamage, ContentChangedDocumentDamage, ContentChangedDocumentDamage);
+    assert_add(ReflowDocumentDamage, AnimateDocumentDamage, AnimateDocumentDamage);
+    assert_add(AnimateDocumentDamage, MatchSelectorsDocumentDamage, MatchSelectorsDocumentDamage);
     assert_add(ReflowDocumentDamage, MatchSelectorsDocumentDamage, MatchSelectorsDocumentDamage);
     assert_add(MatchSelectorsDocumentDamage, ReflowDocumentDamage, MatchSelectorsDocumentDamage);
     assert_add(ReflowDocumentDamage, ContentChangedDocumentDamage, ContentChangedDocumentDamage);
@@ -5,16 +5,20 @@
 //! The script task is the task that owns the DOM in memory, runs JavaScript, and spawns parsing
 //! and layout tasks.
 
+use dom::animationevent::AnimationEvent;
 use dom::bindings::codegen::RegisterBindings;
 use dom::bindings::utils::{Reflectable, GlobalStaticData};
+use dom::cssstylesheet::CSSStyleSheet;
 use dom::document::AbstractDocument;
 use dom::element::Element;
 use dom::event::{Event_, ResizeEvent, ReflowEvent, ClickEvent, MouseDownEvent, MouseUpEvent};
-use dom::event::Event;
+use dom::event::{AnimationFrameEvent, AnimationEndEvents, AnimationEndEvent_};
+use dom::event::{Event, TransitionEndEvent, AnimationEndEvent, UntrustedNodeAddress};
 use dom::eventtarget::AbstractEventTarget;
 use dom::htmldocument::HTMLDocument;
 use dom::namespace::Null;
 use dom::node::{AbstractNode, LayoutDataRef};
+use dom::transitionevent::TransitionEvent;
 use dom::window::{TimerData, Window};
 use html::hubbub_html_parser::HtmlParserResult;
 use html::hubbub_html_parser::{HtmlDiscoveredStyle, HtmlDiscoveredIFrame, HtmlDiscoveredScript};
@@ -24,7 +28,7 @@ use layout_interface::{ContentBoxQuery, ContentBoxResponse};
 use layout_interface::{DocumentDamageLevel, HitTestQuery, HitTestResponse, LayoutQuery};
 use layout_interface::{LayoutChan, MatchSelectorsDocumentDamage, QueryMsg, ReapLayoutDataMsg};
 use layout_interface::{Reflow, ReflowDocumentDamage, ReflowForDisplay, ReflowGoal, ReflowMsg};
-use layout_interface::ContentChangedDocumentDamage;
+use layout_interface::{AnimateDocumentDamage, ContentChangedDocumentDamage};
 use layout_interface;
 
 use extra::url::Url;
@@ -38,7 +42,8 @@ use js::jsapi::{JS_CallFunctionValue, JS_GetContextPrivate};
 use js::rust::{Compartment, Cx};
 use js;
 use servo_msg::compositor_msg::{FinishedLoading, Loading, PerformingLayout, ScriptListener};
-use servo_msg::constellation_msg::{ConstellationChan, IFrameSandboxed, IFrameUnsandboxed};
+use servo_msg::constellation_msg::{CSSErrorMsg, ConstellationChan, IFrameSandboxed};
+use servo_msg::constellation_msg::IFrameUnsandboxed;
 use servo_msg::constellation_msg::{LoadIframeUrlMsg, LoadUrlMsg, NavigationDirection, PipelineId};
 use servo_msg::constellation_msg::{SubpageId};
 use servo_msg::constellation_msg;
@@ -49,10 +54,12 @@ use servo_util::url::make_url;
 use std::cell::Cell;
 use std::comm::{Port, SharedChan};
 use std::comm;
+use std::hashmap::{HashMap, HashSet};
 use std::ptr;
 use std::str::eq_slice;
 use std::task::{spawn_sched, SingleThreaded};
 use std::util::replace;
+use style::{CSSError, CSSErrorReporter, with_css_error_reporter};
 
 /// Messages used to control the script task.
 pub enum ScriptMsg {
@@ -109,6 +116,9 @@ pub struct Page {
     /// A handle for communicating messages to the layout task.
     layout_chan: LayoutChan,
 
+    /// The constellation, which passes the CSS errors of the page on to the embedder.
+    constellation_chan: ConstellationChan,
+
     /// The port that we will use to join layout. If this is `None`, then layout is not running.
     layout_join_port: Option<Port<()>>,
 
@@ -145,12 +155,17 @@ pub struct PageTreeIterator<'self> {
 }
 
 impl PageTree {
-    fn new(id: PipelineId, layout_chan: LayoutChan, window_size: Size2D<uint>) -> PageTree {
+    fn new(id: PipelineId,
+           layout_chan: LayoutChan,
+           constellation_chan: ConstellationChan,
+           window_size: Size2D<uint>)
+           -> PageTree {
         PageTree {
             page: @mut Page {
                 id: id,
                 frame: None,
                 layout_chan: layout_chan,
+                constellation_chan: constellation_chan,
                 layout_join_port: None,
                 damage: None,
                 window_size: window_size,
@@ -219,7 +234,40 @@ impl<'self> Iterator<@mut Page> for PageTreeIterator<'self> {
     }
 }
 
+/// Sends the CSS errors of a page to the constellation.
+struct ConstellationCSSErrorReporter {
+    pipeline_id: PipelineId,
+    constellation_chan: ConstellationChan,
+}
+
+impl CSSErrorReporter for ConstellationCSSErrorReporter {
+    fn report_css_error(&mut self, error: CSSError) {
+        self.constellation_chan.send(CSSErrorMsg(self.pipeline_id, error.to_str()))
+    }
+}
+
 impl Page {
+    fn css_error_reporter(&self) -> @mut CSSErrorReporter {
+        @mut ConstellationCSSErrorReporter {
+            pipeline_id: self.id,
+            constellation_chan: self.constellation_chan.clone(),
+        } as @mut CSSErrorReporter
+    }
+
+    /// Runs `f`, which parses CSS for this page, and reports the errors it finds as errors of the
+    /// given URL.
+    pub fn with_css_error_reporting<T>(&self, url: ~str, f: &fn() -> T) -> T {
+        with_css_error_reporter(url, self.css_error_reporter(), f)
+    }
+
+    /// Reports the errors that were collected while parsing a stylesheet of this page.
+    pub fn report_css_errors(&self, errors: &[CSSError]) {
+        let reporter = self.css_error_reporter();
+        for error in errors.iter() {
+            reporter.report_css_error(error.clone())
+        }
+    }
+
     /// Adds the given damage.
     pub fn damage(&mut self, level: DocumentDamageLevel) {
         let root = match self.frame {
@@ -434,7 +482,7 @@ impl ScriptTask {
         let js_runtime = js::rust::rt();
 
         let script_task = @mut ScriptTask {
-            page_tree: PageTree::new(id, layout_chan, window_size),
+            page_tree: PageTree::new(id, layout_chan, constellation_chan.clone(), window_size),
 
             image_cache_task: img_cache_task,
             resource_task: resource_task,
@@ -574,7 +622,10 @@ impl ScriptTask {
         let parent_page_tree = self.page_tree.find(old_id).expect("ScriptTask: received a layout
             whose parent has a PipelineId which does not correspond to a pipeline in the script
             task's page tree. This is a bug.");
-        let new_page_tree = PageTree::new(new_id, layout_chan, parent_page_tree.page.window_size);
+        let new_page_tree = PageTree::new(new_id,
+                                          layout_chan,
+                                          self.constellation_chan.clone(),
+                                          parent_page_tree.page.window_size);
         parent_page_tree.inner.push(new_page_tree);
     }
 
@@ -710,7 +761,7 @@ impl ScriptTask {
         // Parse HTML.
         //
         // Note: We can parse the next document in parallel with any previous documents.
-        let document = HTMLDocument::new(window);
+        let document = HTMLDocument::new(window, Some(url.clone()));
 
         let html_parsing_result = hubbub_html_parser::parse_html(cx.ptr,
                                                                  document,
@@ -742,7 +793,10 @@ impl ScriptTask {
                     js_scripts = Some(scripts);
                 }
                 Some(HtmlDiscoveredStyle(sheet)) => {
-                    page.layout_chan.send(AddStylesheetMsg(sheet));
+                    page.report_css_errors(sheet.errors);
+                    // Script keeps its own copy of each sheet for the CSSOM.
+                    page.layout_chan.send(AddStylesheetMsg(sheet.clone()));
+                    document.mut_document().add_stylesheet(CSSStyleSheet::new(window, sheet));
                 }
                 Some(HtmlDiscoveredIFrame((iframe_url, subpage_id, sandboxed))) => {
                     page.next_subpage_id = SubpageId(*subpage_id + 1);
@@ -862,6 +916,15 @@ impl ScriptTask {
                 }
             }
 
+            AnimationFrameEvent => {
+                debug!("script got animation frame event");
+
+                if page.frame.is_some() {
+                    page.damage(AnimateDocumentDamage);
+                    page.reflow(ReflowForDisplay, self.chan.clone(), self.compositor)
+                }
+            }
+
             ClickEvent(_button, point) => {
                 debug!("ClickEvent: clicked at {:?}", point);
 
@@ -873,7 +936,7 @@ impl ScriptTask {
                 let (port, chan) = comm::stream();
                 match page.query_layout(HitTestQuery(root.unwrap(), point, chan), port) {
                     Ok(node) => match node {
-                        HitTestResponse(node) => {
+                        HitTestResponse(node, _) => {
                             debug!("clicked on {:s}", node.debug_str());
                             let mut node = node;
                             // traverse node generations until a node that is an element is found
@@ -901,6 +964,56 @@ impl ScriptTask {
             }
             MouseDownEvent(*) => {}
             MouseUpEvent(*) => {}
+
+            AnimationEndEvents(events) => self.fire_animation_end_events(page, events),
+        }
+    }
+
+    /// Fires the `transitionend` and `animationend` events that layout reported, in order. Their
+    /// targets are looked up in a single walk of the document, since layout only knows their
+    /// addresses; events whose target has left the document are dropped.
+    fn fire_animation_end_events(&self, page: @mut Page, events: ~[AnimationEndEvent_]) {
+        let (document, window) = match page.frame {
+            Some(ref frame) => (frame.document, frame.window),
+            None => return,
+        };
+        let address_of = |event: &AnimationEndEvent_| {
+            match *event {
+                TransitionEndEvent(address, _, _) | AnimationEndEvent(address, _, _) => address,
+            }
+        };
+        let addresses: HashSet<UntrustedNodeAddress> = events.iter().map(address_of).collect();
+        let mut targets = HashMap::new();
+        for node in AbstractNode::from_document(document).traverse_preorder() {
+            let address = unsafe {
+                node.raw_object() as UntrustedNodeAddress
+            };
+            if addresses.contains(&address) {
+                targets.insert(address, node);
+            }
+        }
+
+        for event in events.move_iter() {
+            let node = match targets.find(&address_of(&event)) {
+                // The handlers of earlier events may have removed the node.
+                Some(node) if node.is_in_doc() => *node,
+                _ => {
+                    debug!("ScriptTask: animation event target left the document");
+                    continue
+                }
+            };
+            let event = match event {
+                TransitionEndEvent(_, property_name, elapsed_time) => {
+                    TransitionEvent::new(window, ~"transitionend", true, true, property_name,
+                                         elapsed_time, ~"")
+                }
+                AnimationEndEvent(_, animation_name, elapsed_time) => {
+                    AnimationEvent::new(window, ~"animationend", true, false, animation_name,
+                                        elapsed_time, ~"")
+                }
+            };
+            let target = AbstractEventTarget::from_node(node);
+            let _ = target.eventtarget().dispatch_event_with_target(target, None, event);
         }
     }
 
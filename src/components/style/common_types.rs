@@ -6,12 +6,120 @@ pub use servo_util::geometry::Au;
 
 pub type CSSFloat = f64;
 
+pub static PI: CSSFloat = 3.14159265358979323846264338327950288;
+
+
+/// The direction of a linear gradient.
+#[deriving(Eq, Clone)]
+pub enum GradientDirection {
+    /// An angle in radians, clockwise from `to top`.
+    AngleDirection(CSSFloat),
+    /// Towards a corner of the box. The angle then depends on the size of the box.
+    CornerDirection(HorizontalSide, VerticalSide),
+}
+
+#[deriving(Eq, Clone)]
+pub enum HorizontalSide {
+    SideLeft,
+    SideRight,
+}
+
+#[deriving(Eq, Clone)]
+pub enum VerticalSide {
+    SideTop,
+    SideBottom,
+}
+
+/// How far the ending shape of a radial gradient extends, relative to the box.
+#[deriving(Eq, Clone)]
+pub enum ShapeExtent {
+    ClosestSide,
+    ClosestCorner,
+    FarthestSide,
+    FarthestCorner,
+}
+
+impl ShapeExtent {
+    pub fn from_keyword(keyword: &str) -> Option<ShapeExtent> {
+        match keyword {
+            "closest-side" => Some(ClosestSide),
+            "closest-corner" => Some(ClosestCorner),
+            "farthest-side" => Some(FarthestSide),
+            "farthest-corner" => Some(FarthestCorner),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ClosestSide => "closest-side",
+            ClosestCorner => "closest-corner",
+            FarthestSide => "farthest-side",
+            FarthestCorner => "farthest-corner",
+        }
+    }
+}
+
+/// How the progress of a transition or an animation maps to the progress of the values.
+#[deriving(Eq, Clone)]
+pub enum TimingFunction {
+    /// A cubic Bezier curve from (0, 0) to (1, 1) with the given two control points.
+    CubicBezier(CSSFloat, CSSFloat, CSSFloat, CSSFloat),
+    /// A number of equal steps, and whether each step happens at the start of its interval rather
+    /// than at the end.
+    Steps(uint, bool),
+}
+
+impl TimingFunction {
+    #[inline]
+    pub fn ease() -> TimingFunction {
+        CubicBezier(0.25, 0.1, 0.25, 1.)
+    }
+
+    /// Returns the progress of the values when the time progress, from 0 to 1, is `progress`.
+    pub fn solve(&self, progress: CSSFloat) -> CSSFloat {
+        match *self {
+            CubicBezier(x1, y1, x2, y2) => {
+                let bezier = |t: CSSFloat, p1: CSSFloat, p2: CSSFloat| {
+                    let u = 1. - t;
+                    3. * u * u * t * p1 + 3. * u * t * t * p2 + t * t * t
+                };
+                // The curve is monotonic in x, so bisect for the parameter that gives `progress`.
+                let (mut low, mut high) = (0., 1.);
+                let mut t = progress;
+                for _ in range(0, 30) {
+                    let x = bezier(t, x1, x2);
+                    if (x - progress).abs() < 1e-6 {
+                        break
+                    }
+                    if x < progress { low = t } else { high = t }
+                    t = (low + high) / 2.;
+                }
+                bezier(t, y1, y2)
+            }
+            Steps(steps, at_start) => {
+                let steps = steps as CSSFloat;
+                let step = if at_start {
+                    (progress * steps).ceil()
+                } else {
+                    (progress * steps).floor()
+                };
+                (step / steps).max(&0.).min(&1.)
+            }
+        }
+    }
+}
+
 
 pub mod specified {
     use std::ascii::StrAsciiExt;
     use cssparser::ast;
     use cssparser::ast::*;
-    use super::{Au, CSSFloat};
+    use extra::url::Url;
+    use servo_util::url::make_url;
+    use super::*;
+    use super::super::longhands::background_position;
+    use parsing_utils::{get_ident_lower, one_component_value, split_on_commas};
     pub use CSSColor = cssparser::Color;
 
     #[deriving(Clone)]
@@ -19,13 +127,12 @@ pub mod specified {
         Au_(Au),  // application units
         Em(CSSFloat),
         Ex(CSSFloat),
-        // XXX uncomment when supported:
-//        Ch(CSSFloat),
-//        Rem(CSSFloat),
-//        Vw(CSSFloat),
-//        Vh(CSSFloat),
-//        Vmin(CSSFloat),
-//        Vmax(CSSFloat),
+        Ch(CSSFloat),
+        Rem(CSSFloat),
+        Vw(CSSFloat),
+        Vh(CSSFloat),
+        Vmin(CSSFloat),
+        Vmax(CSSFloat),
     }
     static AU_PER_PX: CSSFloat = 60.;
     static AU_PER_IN: CSSFloat = AU_PER_PX * 96.;
@@ -61,6 +168,12 @@ pub mod specified {
                 "pc" => Some(Au_(Au((value * AU_PER_PC) as i32))),
                 "em" => Some(Em(value)),
                 "ex" => Some(Ex(value)),
+                "ch" => Some(Ch(value)),
+                "rem" => Some(Rem(value)),
+                "vw" => Some(Vw(value)),
+                "vh" => Some(Vh(value)),
+                "vmin" => Some(Vmin(value)),
+                "vmax" => Some(Vmax(value)),
                 _ => None
             }
         }
@@ -70,10 +183,208 @@ pub mod specified {
         }
     }
 
+    /// A parsed `calc()`, `min()` or `max()` expression. Subtraction is parsed as the sum with a
+    /// term scaled by -1, and division by a number as a scale by its inverse.
+    #[deriving(Clone)]
+    pub enum CalcNode {
+        CalcLength(Length),
+        CalcPercentage(CSSFloat),  // [0 .. 100%] maps to [0.0 .. 1.0]
+        CalcSum(~CalcNode, ~CalcNode),
+        CalcScale(~CalcNode, CSSFloat),
+        CalcMin(~[CalcNode]),
+        CalcMax(~[CalcNode]),
+        /// An expression for a property that takes no negative values, which is clamped at zero
+        /// once computed, or once laid out if it has a percentage.
+        CalcNonNegative(~CalcNode),
+    }
+
+    /// The percentages in a `calc()` expression, which are known at parse time: that of the
+    /// expression, and that of the other argument of the `min()` or `max()` in it whose arguments
+    /// have different percentages, if there is one.
+    #[deriving(Eq)]
+    pub struct CalcPercentages {
+        percentage: CSSFloat,
+        bound: Option<CSSFloat>,
+    }
+    impl CalcNode {
+        /// Parses the arguments of a `calc()`, `min()` or `max()` function.
+        pub fn parse_function(name: &str, arguments: &[ComponentValue]) -> Option<CalcNode> {
+            // FIXME: Workaround for https://github.com/mozilla/rust/issues/10683
+            let name_lower = name.to_ascii_lower();
+            let node = match name_lower.as_slice() {
+                "calc" => CalcNode::parse(arguments),
+                "min" | "max" => {
+                    let mut nodes = ~[];
+                    for argument in split_on_commas(arguments).move_iter() {
+                        match CalcNode::parse(argument) {
+                            Some(node) => nodes.push(node),
+                            None => return None,
+                        }
+                    }
+                    if name_lower.as_slice() == "min" {
+                        Some(CalcMin(nodes))
+                    } else {
+                        Some(CalcMax(nodes))
+                    }
+                }
+                _ => None
+            };
+            // Layout keeps at most one bound for the whole expression.
+            match node {
+                Some(node) => if node.percentages().is_some() { Some(node) } else { None },
+                None => None,
+            }
+        }
+
+        fn parse(input: &[ComponentValue]) -> Option<CalcNode> {
+            let tokens: ~[&ComponentValue] = input.skip_whitespace().collect();
+            let mut position = 0;
+            match CalcNode::parse_sum(tokens, &mut position) {
+                Some(node) if position == tokens.len() => Some(node),
+                _ => None
+            }
+        }
+
+        /// product [ [ '+' | '-' ] product ]*
+        fn parse_sum(tokens: &[&ComponentValue], position: &mut uint) -> Option<CalcNode> {
+            let mut sum = match CalcNode::parse_product(tokens, position) {
+                Some(node) => node,
+                None => return None,
+            };
+            while *position < tokens.len() {
+                let negate = match tokens[*position] {
+                    &Delim('+') => false,
+                    &Delim('-') => true,
+                    _ => break,
+                };
+                *position += 1;
+                let term = match CalcNode::parse_product(tokens, position) {
+                    Some(node) => node,
+                    None => return None,
+                };
+                let term = if negate { CalcScale(~term, -1.) } else { term };
+                sum = CalcSum(~sum, ~term);
+            }
+            Some(sum)
+        }
+
+        /// [ <number> '*' ]* value [ [ '*' | '/' ] <number> ]*
+        fn parse_product(tokens: &[&ComponentValue], position: &mut uint) -> Option<CalcNode> {
+            let mut factor = 1.;
+            while *position + 1 < tokens.len() {
+                match (tokens[*position], tokens[*position + 1]) {
+                    (&Number(ref value), &Delim('*')) => factor *= value.value,
+                    _ => break,
+                }
+                *position += 2;
+            }
+            let node = match CalcNode::parse_value(tokens, position) {
+                Some(node) => node,
+                None => return None,
+            };
+            while *position + 1 < tokens.len() {
+                match (tokens[*position], tokens[*position + 1]) {
+                    (&Delim('*'), &Number(ref value)) => factor *= value.value,
+                    (&Delim('/'), &Number(ref value)) if value.value != 0. => {
+                        factor /= value.value
+                    }
+                    _ => break,
+                }
+                *position += 2;
+            }
+            if factor == 1. { Some(node) } else { Some(CalcScale(~node, factor)) }
+        }
+
+        /// <length> | <percentage> | ( sum ) | calc() | min() | max()
+        fn parse_value(tokens: &[&ComponentValue], position: &mut uint) -> Option<CalcNode> {
+            if *position >= tokens.len() {
+                return None
+            }
+            let node = match tokens[*position] {
+                &Dimension(ref value, ref unit)
+                => Length::parse_dimension(value.value, unit.as_slice()).map(CalcLength),
+                &ast::Percentage(ref value) => Some(CalcPercentage(value.value / 100.)),
+                &ParenthesisBlock(ref contents) => CalcNode::parse(contents.as_slice()),
+                &Function(ref name, ref arguments)
+                => CalcNode::parse_function(name.as_slice(), arguments.as_slice()),
+                _ => None
+            };
+            *position += 1;
+            node
+        }
+
+        /// Returns the percentages in this expression, or `None` if it would need more than one
+        /// bound: if it adds two `min()` or `max()` expressions whose arguments have different
+        /// percentages, or if such an expression has more than two distinct percentages.
+        pub fn percentages(&self) -> Option<CalcPercentages> {
+            match *self {
+                CalcLength(_) => Some(CalcPercentages { percentage: 0., bound: None }),
+                CalcPercentage(percentage) => {
+                    Some(CalcPercentages { percentage: percentage, bound: None })
+                }
+                CalcSum(ref left, ref right) => {
+                    let (left, right) = match (left.percentages(), right.percentages()) {
+                        (Some(left), Some(right)) => (left, right),
+                        _ => return None,
+                    };
+                    // The bound moves with the term it is added to.
+                    let bound = match (left.bound, right.bound) {
+                        (Some(_), Some(_)) => return None,
+                        (Some(bound), None) => Some(bound + right.percentage),
+                        (None, Some(bound)) => Some(bound + left.percentage),
+                        (None, None) => None,
+                    };
+                    Some(CalcPercentages {
+                        percentage: left.percentage + right.percentage,
+                        bound: bound,
+                    })
+                }
+                CalcScale(ref node, factor) => do node.percentages().map |percentages| {
+                    CalcPercentages {
+                        percentage: percentages.percentage * factor,
+                        bound: percentages.bound.map(|bound| bound * factor),
+                    }
+                },
+                CalcMin(ref nodes) | CalcMax(ref nodes) => {
+                    let mut distinct: ~[CSSFloat] = ~[];
+                    for node in nodes.iter() {
+                        match node.percentages() {
+                            Some(CalcPercentages { percentage: percentage, bound: None }) => {
+                                if !distinct.contains(&percentage) {
+                                    distinct.push(percentage)
+                                }
+                            }
+                            _ => return None,
+                        }
+                    }
+                    match distinct.len() {
+                        1 => Some(CalcPercentages { percentage: distinct[0], bound: None }),
+                        2 => Some(CalcPercentages {
+                            percentage: distinct[0],
+                            bound: Some(distinct[1]),
+                        }),
+                        _ => None,
+                    }
+                }
+                CalcNonNegative(ref node) => node.percentages(),
+            }
+        }
+    }
+
+    /// Parses a `calc()`, `min()` or `max()` expression for a property, which is clamped at zero
+    /// if the property takes no negative values.
+    fn parse_calc_function(name: &str, arguments: &[ComponentValue], negative_ok: bool)
+                           -> Option<CalcNode> {
+        do CalcNode::parse_function(name, arguments).map |node| {
+            if negative_ok { node } else { CalcNonNegative(~node) }
+        }
+    }
+
     #[deriving(Clone)]
     pub enum LengthOrPercentage {
         LP_Length(Length),
         LP_Percentage(CSSFloat),  // [0 .. 100%] maps to [0.0 .. 1.0]
+        LP_Calc(CalcNode),
     }
     impl LengthOrPercentage {
         fn parse_internal(input: &ComponentValue, negative_ok: bool)
@@ -84,6 +395,9 @@ pub mod specified {
                 &ast::Percentage(ref value) if negative_ok || value.value >= 0.
                 => Some(LP_Percentage(value.value / 100.)),
                 &Number(ref value) if value.value == 0. =>  Some(LP_Length(Au_(Au(0)))),
+                &Function(ref name, ref arguments)
+                => parse_calc_function(name.as_slice(), arguments.as_slice(), negative_ok)
+                   .map(LP_Calc),
                 _ => None
             }
         }
@@ -101,6 +415,7 @@ pub mod specified {
     pub enum LengthOrPercentageOrAuto {
         LPA_Length(Length),
         LPA_Percentage(CSSFloat),  // [0 .. 100%] maps to [0.0 .. 1.0]
+        LPA_Calc(CalcNode),
         LPA_Auto,
     }
     impl LengthOrPercentageOrAuto {
@@ -112,6 +427,9 @@ pub mod specified {
                 &ast::Percentage(ref value) if negative_ok || value.value >= 0.
                 => Some(LPA_Percentage(value.value / 100.)),
                 &Number(ref value) if value.value == 0. => Some(LPA_Length(Au_(Au(0)))),
+                &Function(ref name, ref arguments)
+                => parse_calc_function(name.as_slice(), arguments.as_slice(), negative_ok)
+                   .map(LPA_Calc),
                 &Ident(ref value) if value.eq_ignore_ascii_case("auto") => Some(LPA_Auto),
                 _ => None
             }
@@ -130,6 +448,7 @@ pub mod specified {
     pub enum LengthOrPercentageOrNone {
         LPN_Length(Length),
         LPN_Percentage(CSSFloat),  // [0 .. 100%] maps to [0.0 .. 1.0]
+        LPN_Calc(CalcNode),
         LPN_None,
     }
     impl LengthOrPercentageOrNone {
@@ -141,6 +460,9 @@ pub mod specified {
                 &ast::Percentage(ref value) if negative_ok || value.value >= 0.
                 => Some(LPN_Percentage(value.value / 100.)),
                 &Number(ref value) if value.value == 0. => Some(LPN_Length(Au_(Au(0)))),
+                &Function(ref name, ref arguments)
+                => parse_calc_function(name.as_slice(), arguments.as_slice(), negative_ok)
+                   .map(LPN_Calc),
                 &Ident(ref value) if value.eq_ignore_ascii_case("none") => Some(LPN_None),
                 _ => None
             }
@@ -154,17 +476,368 @@ pub mod specified {
             LengthOrPercentageOrNone::parse_internal(input, /* negative_ok = */ false)
         }
     }
+
+    /// Parses an angle, in radians.
+    pub fn parse_angle(input: &ComponentValue) -> Option<CSSFloat> {
+        match input {
+            &Dimension(ref value, ref unit) => {
+                // FIXME: Workaround for https://github.com/mozilla/rust/issues/10683
+                let unit_lower = unit.to_ascii_lower();
+                match unit_lower.as_slice() {
+                    "deg" => Some(value.value * PI / 180.),
+                    "grad" => Some(value.value * PI / 200.),
+                    "rad" => Some(value.value),
+                    "turn" => Some(value.value * 2. * PI),
+                    _ => None
+                }
+            },
+            &Number(ref value) if value.value == 0. => Some(0.),
+            _ => None
+        }
+    }
+
+    /// Parses a <time>, in seconds.
+    pub fn parse_time(input: &ComponentValue) -> Option<CSSFloat> {
+        match input {
+            &Dimension(ref value, ref unit) => {
+                // FIXME: Workaround for https://github.com/mozilla/rust/issues/10683
+                let unit_lower = unit.to_ascii_lower();
+                match unit_lower.as_slice() {
+                    "s" => Some(value.value),
+                    "ms" => Some(value.value / 1000.),
+                    _ => None
+                }
+            },
+            _ => None
+        }
+    }
+
+    /// ease | linear | ease-in | ease-out | ease-in-out | step-start | step-end |
+    /// steps(<integer>[, [ start | end ] ]?) | cubic-bezier(<number>, <number>, <number>, <number>)
+    pub fn parse_timing_function(input: &ComponentValue) -> Option<TimingFunction> {
+        match input {
+            &Ident(ref value) => {
+                // FIXME: Workaround for https://github.com/mozilla/rust/issues/10683
+                let value_lower = value.to_ascii_lower();
+                match value_lower.as_slice() {
+                    "ease" => Some(TimingFunction::ease()),
+                    "linear" => Some(CubicBezier(0., 0., 1., 1.)),
+                    "ease-in" => Some(CubicBezier(0.42, 0., 1., 1.)),
+                    "ease-out" => Some(CubicBezier(0., 0., 0.58, 1.)),
+                    "ease-in-out" => Some(CubicBezier(0.42, 0., 0.58, 1.)),
+                    "step-start" => Some(Steps(1, true)),
+                    "step-end" => Some(Steps(1, false)),
+                    _ => None
+                }
+            },
+            &Function(ref name, ref arguments) => {
+                let name_lower = name.to_ascii_lower();
+                let arguments: ~[Option<&ComponentValue>] = split_on_commas(arguments.as_slice())
+                    .move_iter().map(one_component_value).collect();
+                match name_lower.as_slice() {
+                    "cubic-bezier" if arguments.len() == 4 => {
+                        let mut numbers = ~[];
+                        for argument in arguments.iter() {
+                            match *argument {
+                                Some(&Number(ref value)) => numbers.push(value.value),
+                                _ => return None,
+                            }
+                        }
+                        // The x coordinates must stay within [0, 1] for the curve to be a function
+                        // of time.
+                        if numbers[0] < 0. || numbers[0] > 1. || numbers[2] < 0. ||
+                                numbers[2] > 1. {
+                            return None
+                        }
+                        Some(CubicBezier(numbers[0], numbers[1], numbers[2], numbers[3]))
+                    }
+                    "steps" if arguments.len() == 1 || arguments.len() == 2 => {
+                        let steps = match arguments[0] {
+                            Some(&Number(ref value)) => match value.int_value {
+                                Some(steps) if steps > 0 => steps as uint,
+                                _ => return None,
+                            },
+                            _ => return None,
+                        };
+                        let at_start = if arguments.len() == 1 {
+                            false
+                        } else {
+                            match arguments[1].and_then(get_ident_lower) {
+                                Some(ref keyword) if keyword.as_slice() == "start" => true,
+                                Some(ref keyword) if keyword.as_slice() == "end" => false,
+                                _ => return None,
+                            }
+                        };
+                        Some(Steps(steps, at_start))
+                    }
+                    _ => None
+                }
+            },
+            _ => None
+        }
+    }
+
+    #[deriving(Clone)]
+    pub struct ColorStop {
+        color: CSSColor,
+        /// Where the stop is on the gradient line, or `None` to space it evenly between its
+        /// neighbours.
+        position: Option<LengthOrPercentage>,
+    }
+    impl ColorStop {
+        /// <color> [ <percentage> | <length> ]?
+        pub fn parse(input: &[ComponentValue]) -> Option<ColorStop> {
+            let mut iter = input.skip_whitespace();
+            let color = match iter.next().and_then(CSSColor::parse) {
+                Some(color) => color,
+                None => return None,
+            };
+            let position = match iter.next() {
+                Some(value) => match LengthOrPercentage::parse(value) {
+                    Some(position) => Some(position),
+                    None => return None,
+                },
+                None => None,
+            };
+            if iter.next().is_some() {
+                return None
+            }
+            Some(ColorStop { color: color, position: position })
+        }
+
+        /// <color-stop> [ , <color-stop> ]+
+        fn parse_list(groups: &[&[ComponentValue]]) -> Option<~[ColorStop]> {
+            if groups.len() < 2 {
+                return None
+            }
+            let mut stops = ~[];
+            for group in groups.iter() {
+                match ColorStop::parse(*group) {
+                    Some(stop) => stops.push(stop),
+                    None => return None,
+                }
+            }
+            Some(stops)
+        }
+    }
+
+    #[deriving(Clone)]
+    pub struct LinearGradient {
+        direction: GradientDirection,
+        stops: ~[ColorStop],
+        repeating: bool,
+    }
+    impl LinearGradient {
+        /// [ [ <angle> | to <side-or-corner> ] , ]? <color-stop> [ , <color-stop> ]+
+        pub fn parse(arguments: &[ComponentValue], repeating: bool) -> Option<LinearGradient> {
+            let groups = split_on_commas(arguments);
+            let (direction, stops) = match LinearGradient::parse_direction(groups[0]) {
+                Some(direction) => (direction, groups.slice_from(1)),
+                // `to bottom`
+                None => (AngleDirection(PI), groups.as_slice()),
+            };
+            do ColorStop::parse_list(stops).map |stops| {
+                LinearGradient { direction: direction, stops: stops, repeating: repeating }
+            }
+        }
+
+        fn parse_direction(input: &[ComponentValue]) -> Option<GradientDirection> {
+            let mut iter = input.skip_whitespace();
+            let first = match iter.next() {
+                Some(first) => first,
+                None => return None,
+            };
+            match get_ident_lower(first) {
+                Some(ref keyword) if keyword.as_slice() == "to" => (),
+                _ => return parse_angle(first).filtered(|_| iter.next().is_none())
+                                              .map(AngleDirection),
+            }
+            let mut horizontal = None;
+            let mut vertical = None;
+            for value in iter {
+                let keyword = get_ident_lower(value);
+                match keyword.as_ref().map(|keyword| keyword.as_slice()) {
+                    Some("left") if horizontal.is_none() => horizontal = Some(SideLeft),
+                    Some("right") if horizontal.is_none() => horizontal = Some(SideRight),
+                    Some("top") if vertical.is_none() => vertical = Some(SideTop),
+                    Some("bottom") if vertical.is_none() => vertical = Some(SideBottom),
+                    _ => return None,
+                }
+            }
+            match (horizontal, vertical) {
+                (Some(horizontal), Some(vertical)) => Some(CornerDirection(horizontal, vertical)),
+                (Some(SideLeft), None) => Some(AngleDirection(PI * 1.5)),
+                (Some(SideRight), None) => Some(AngleDirection(PI * 0.5)),
+                (None, Some(SideTop)) => Some(AngleDirection(0.)),
+                (None, Some(SideBottom)) => Some(AngleDirection(PI)),
+                (None, None) => None,
+            }
+        }
+    }
+
+    #[deriving(Clone)]
+    pub enum EndingShape {
+        CircleExtent(ShapeExtent),
+        EllipseExtent(ShapeExtent),
+        /// A circle of the given radius.
+        Circle(Length),
+        /// An ellipse of the given horizontal and vertical radii.
+        Ellipse(LengthOrPercentage, LengthOrPercentage),
+    }
+
+    #[deriving(Clone)]
+    pub struct RadialGradient {
+        shape: EndingShape,
+        /// The center of the gradient.
+        position: background_position::SpecifiedValue,
+        stops: ~[ColorStop],
+        repeating: bool,
+    }
+    impl RadialGradient {
+        /// [ <ending-shape> || <size> ]? [ at <position> ]? , ]? <color-stop> [ , <color-stop> ]+
+        pub fn parse(arguments: &[ComponentValue], repeating: bool) -> Option<RadialGradient> {
+            let groups = split_on_commas(arguments);
+            let ((shape, position), stops) = match ColorStop::parse(groups[0]) {
+                Some(_) => ((EllipseExtent(FarthestCorner), None), groups.as_slice()),
+                None => match RadialGradient::parse_shape(groups[0]) {
+                    Some(shape_and_position) => (shape_and_position, groups.slice_from(1)),
+                    None => return None,
+                },
+            };
+            let position = match position {
+                Some(position) => position,
+                None => background_position::SpecifiedValue {
+                    horizontal: LP_Percentage(0.5),
+                    vertical: LP_Percentage(0.5),
+                },
+            };
+            do ColorStop::parse_list(stops).map |stops| {
+                RadialGradient {
+                    shape: shape,
+                    position: position.clone(),
+                    stops: stops,
+                    repeating: repeating,
+                }
+            }
+        }
+
+        fn parse_shape(input: &[ComponentValue])
+                       -> Option<(EndingShape, Option<background_position::SpecifiedValue>)> {
+            let mut shape_keyword = None;
+            let mut extent = None;
+            let mut lengths = ~[];
+            let mut iter = input.skip_whitespace();
+            let mut has_position = false;
+            for value in iter.by_ref() {
+                let keyword = get_ident_lower(value);
+                match keyword.as_ref().map(|keyword| keyword.as_slice()) {
+                    Some("at") => {
+                        has_position = true;
+                        break
+                    }
+                    Some("circle") if shape_keyword.is_none() => shape_keyword = Some(true),
+                    Some("ellipse") if shape_keyword.is_none() => shape_keyword = Some(false),
+                    Some(keyword) => match ShapeExtent::from_keyword(keyword) {
+                        Some(value) if extent.is_none() => extent = Some(value),
+                        _ => return None,
+                    },
+                    None => match LengthOrPercentage::parse_non_negative(value) {
+                        Some(length) => lengths.push(length),
+                        None => return None,
+                    },
+                }
+            }
+
+            let position = if has_position {
+                let mut components = iter.map(background_position::parse_component);
+                let first = components.next().unwrap_or(None);
+                let second = components.next();
+                if first.is_none() || components.next().is_some() {
+                    return None
+                }
+                let position = match second {
+                    None => background_position::from_components(first.unwrap(), None),
+                    Some(None) => None,
+                    Some(second) => background_position::from_components(first.unwrap(), second),
+                };
+                if position.is_none() {
+                    return None
+                }
+                position
+            } else {
+                None
+            };
+
+            if shape_keyword.is_none() && extent.is_none() && lengths.is_empty() &&
+                    !has_position {
+                return None
+            }
+            // `shape_keyword` is `Some(true)` for `circle`.
+            let shape = match (shape_keyword, extent, lengths.len()) {
+                (Some(true), None, 0) => CircleExtent(FarthestCorner),
+                (Some(true), Some(extent), 0) => CircleExtent(extent),
+                (Some(true), None, 1) | (None, None, 1) => match lengths[0].clone() {
+                    // A circle cannot be sized with a percentage.
+                    LP_Length(radius) => Circle(radius),
+                    LP_Percentage(_) | LP_Calc(_) => return None,
+                },
+                (Some(false), None, 0) | (None, None, 0) => EllipseExtent(FarthestCorner),
+                (Some(false), Some(extent), 0) | (None, Some(extent), 0) => EllipseExtent(extent),
+                (Some(false), None, 2) | (None, None, 2)
+                => Ellipse(lengths[0].clone(), lengths[1].clone()),
+                _ => return None,
+            };
+            Some((shape, position))
+        }
+    }
+
+    #[deriving(Clone)]
+    pub enum Image {
+        UrlImage(Url),
+        LinearGradientImage(LinearGradient),
+        RadialGradientImage(RadialGradient),
+    }
+    impl Image {
+        /// <url> | <linear-gradient> | <radial-gradient> | <repeating-linear-gradient> |
+        /// <repeating-radial-gradient>
+        pub fn parse(input: &ComponentValue, base_url: &Url) -> Option<Image> {
+            match input {
+                &URL(ref value) => Some(UrlImage(make_url(value.to_owned(),
+                                                          Some(base_url.clone())))),
+                &Function(ref name, ref arguments) => {
+                    // FIXME: Workaround for https://github.com/mozilla/rust/issues/10683
+                    let name_lower = name.to_ascii_lower();
+                    let arguments = arguments.as_slice();
+                    match name_lower.as_slice() {
+                        "linear-gradient"
+                        => LinearGradient::parse(arguments, false).map(LinearGradientImage),
+                        "repeating-linear-gradient"
+                        => LinearGradient::parse(arguments, true).map(LinearGradientImage),
+                        "radial-gradient"
+                        => RadialGradient::parse(arguments, false).map(RadialGradientImage),
+                        "repeating-radial-gradient"
+                        => RadialGradient::parse(arguments, true).map(RadialGradientImage),
+                        _ => None,
+                    }
+                },
+                _ => None,
+            }
+        }
+    }
 }
 
 pub mod computed {
     use cssparser;
+    use std::cell::Cell;
+    use extra::url::Url;
     pub use CSSColor = cssparser::Color;
     pub use compute_CSSColor = super::super::longhands::computed_as_specified;
     use super::*;
     use super::super::longhands;
+    use super::super::{FontMetricsProvider, style_structs};
     pub use servo_util::geometry::Au;
 
-    pub struct Context {
+    pub struct Context<'self> {
         current_color: cssparser::RGBA,
         font_size: Au,
         font_weight: longhands::font_weight::computed_value::T,
@@ -175,7 +848,44 @@ pub mod computed {
         has_border_right: bool,
         has_border_bottom: bool,
         has_border_left: bool,
-        // TODO, as needed: root font size, viewport size, etc.
+        /// The computed font size of the root element, for `rem`.
+        root_font_size: Au,
+        /// The computed font of the element, once known, and what measures it for `ch`.
+        font: Option<style_structs::Font>,
+        font_metrics: Cell<&'self mut FontMetricsProvider>,
+        /// The advance of the "0" glyph in the font of the element, filled the first time a `ch`
+        /// value is computed.
+        zero_advance: Cell<Au>,
+        viewport_width: Au,
+        viewport_height: Au,
+        /// Filled once a computed value depends on the size of the viewport.
+        depends_on_viewport: Cell<()>,
+    }
+
+    impl<'self> Context<'self> {
+        /// Records that a computed value depends on the size of the viewport, so that it is
+        /// computed again when the viewport is resized.
+        fn note_viewport_dependency(&self) {
+            if self.depends_on_viewport.is_empty() {
+                self.depends_on_viewport.put_back(())
+            }
+        }
+
+        /// Returns the advance of the "0" glyph in the font of the element, for `ch`. Measuring
+        /// it means loading the font, so this is only done for elements that use `ch`.
+        fn zero_advance(&self) -> Au {
+            if self.zero_advance.is_empty() {
+                let font = self.font.get_ref();
+                let font_metrics = self.font_metrics.take();
+                let advance = match font_metrics.zero_advance(font) {
+                    Some(advance) => advance,
+                    None => self.font_size.scale_by(0.5),
+                };
+                self.font_metrics.put_back(font_metrics);
+                self.zero_advance.put_back(advance);
+            }
+            self.zero_advance.with_ref(|advance| *advance)
+        }
     }
 
     pub fn compute_Au(value: specified::Length, context: &Context) -> Au {
@@ -186,19 +896,199 @@ pub mod computed {
                 let x_height = 0.5;  // TODO: find that from the font
                 context.font_size.scale_by(value * x_height)
             },
+            specified::Ch(value) => context.zero_advance().scale_by(value),
+            specified::Rem(value) => context.root_font_size.scale_by(value),
+            specified::Vw(value) => {
+                context.note_viewport_dependency();
+                context.viewport_width.scale_by(value / 100.)
+            }
+            specified::Vh(value) => {
+                context.note_viewport_dependency();
+                context.viewport_height.scale_by(value / 100.)
+            }
+            specified::Vmin(value) => {
+                context.note_viewport_dependency();
+                Au::min(context.viewport_width, context.viewport_height).scale_by(value / 100.)
+            }
+            specified::Vmax(value) => {
+                context.note_viewport_dependency();
+                Au::max(context.viewport_width, context.viewport_height).scale_by(value / 100.)
+            }
         }
     }
 
+    /// A computed `calc()` expression: a length plus a fraction of a reference length that is only
+    /// known at layout time, such as the width of the containing block. The arguments of a
+    /// `min()` or `max()` with different percentages can only be compared then as well, so the
+    /// expression keeps one of them as a bound.
+    #[deriving(Eq, Clone)]
+    pub struct CalcLengthOrPercentage {
+        length: Au,
+        percentage: CSSFloat,
+        bound: Option<CalcBound>,
+        /// Whether the result is clamped at zero, for properties that take no negative values.
+        non_negative: bool,
+    }
+
+    /// The other argument of a `min()`, which bounds the result from above, or of a `max()`,
+    /// which bounds it from below.
+    #[deriving(Eq, Clone)]
+    pub struct CalcBound {
+        length: Au,
+        percentage: CSSFloat,
+        upper: bool,
+    }
+
+    impl CalcLengthOrPercentage {
+        #[inline]
+        pub fn new(length: Au, percentage: CSSFloat) -> CalcLengthOrPercentage {
+            CalcLengthOrPercentage {
+                length: length,
+                percentage: percentage,
+                bound: None,
+                non_negative: false,
+            }
+        }
+
+        pub fn resolve(&self, reference_length: Au) -> Au {
+            let value = self.length + reference_length.scale_by(self.percentage);
+            let value = match self.bound {
+                None => value,
+                Some(bound) => {
+                    let bound_value = bound.length + reference_length.scale_by(bound.percentage);
+                    if bound.upper {
+                        Au::min(value, bound_value)
+                    } else {
+                        Au::max(value, bound_value)
+                    }
+                }
+            };
+            if self.non_negative { Au::max(value, Au(0)) } else { value }
+        }
+
+        /// Whether the value is a plain length, without percentage or bound.
+        #[inline]
+        pub fn is_length(&self) -> bool {
+            self.percentage == 0. && self.bound.is_none()
+        }
+    }
+
+    /// Computes a `calc()` expression. The parser only accepts expressions with at most one
+    /// bound; see `CalcNode::percentages`.
+    pub fn compute_Calc(value: specified::CalcNode, context: &Context) -> CalcLengthOrPercentage {
+        match value {
+            specified::CalcLength(length) => {
+                CalcLengthOrPercentage::new(compute_Au(length, context), 0.)
+            }
+            specified::CalcPercentage(percentage) => {
+                CalcLengthOrPercentage::new(Au(0), percentage)
+            }
+            specified::CalcSum(left, right) => {
+                let (left, right) = (compute_Calc(*left, context), compute_Calc(*right, context));
+                // The bound moves with the term it is added to.
+                let offset = |bound: Option<CalcBound>, other: &CalcLengthOrPercentage| {
+                    do bound.map |bound| {
+                        CalcBound {
+                            length: bound.length + other.length,
+                            percentage: bound.percentage + other.percentage,
+                            upper: bound.upper,
+                        }
+                    }
+                };
+                let bound = match offset(left.bound, &right) {
+                    Some(bound) => Some(bound),
+                    None => offset(right.bound, &left),
+                };
+                CalcLengthOrPercentage {
+                    length: left.length + right.length,
+                    percentage: left.percentage + right.percentage,
+                    bound: bound,
+                    non_negative: false,
+                }
+            }
+            specified::CalcScale(node, factor) => {
+                let node = compute_Calc(*node, context);
+                CalcLengthOrPercentage {
+                    length: node.length.scale_by(factor),
+                    percentage: node.percentage * factor,
+                    // Scaling by a negative factor turns a minimum into a maximum.
+                    bound: do node.bound.map |bound| {
+                        CalcBound {
+                            length: bound.length.scale_by(factor),
+                            percentage: bound.percentage * factor,
+                            upper: bound.upper == (factor >= 0.),
+                        }
+                    },
+                    non_negative: false,
+                }
+            }
+            specified::CalcMin(nodes) => compute_comparison(nodes, true, context),
+            specified::CalcMax(nodes) => compute_comparison(nodes, false, context),
+            specified::CalcNonNegative(node) => {
+                let mut node = compute_Calc(*node, context);
+                if node.is_length() {
+                    node.length = Au::max(node.length, Au(0))
+                } else {
+                    node.non_negative = true
+                }
+                node
+            }
+        }
+    }
+
+    /// Computes a `min()` or `max()` expression. Arguments with the same percentage are compared
+    /// by their lengths; if there are two percentages, the argument that wins for the second one
+    /// becomes the bound.
+    fn compute_comparison(nodes: ~[specified::CalcNode], minimum: bool, context: &Context)
+                          -> CalcLengthOrPercentage {
+        let mut winners: ~[CalcLengthOrPercentage] = ~[];
+        for node in nodes.move_iter() {
+            let node = compute_Calc(node, context);
+            match winners.iter().position(|winner| winner.percentage == node.percentage) {
+                Some(index) => {
+                    if (node.length < winners[index].length) == minimum {
+                        winners[index] = node
+                    }
+                }
+                None => winners.push(node),
+            }
+        }
+        let mut result = winners[0];
+        if winners.len() > 1 {
+            result.bound = Some(CalcBound {
+                length: winners[1].length,
+                percentage: winners[1].percentage,
+                upper: minimum,
+            })
+        }
+        result
+    }
+
     #[deriving(Eq, Clone)]
     pub enum LengthOrPercentage {
         LP_Length(Au),
         LP_Percentage(CSSFloat),
+        LP_Calc(CalcLengthOrPercentage),
+    }
+    impl LengthOrPercentage {
+        #[inline]
+        pub fn to_calc(&self) -> CalcLengthOrPercentage {
+            match *self {
+                LP_Length(length) => CalcLengthOrPercentage::new(length, 0.),
+                LP_Percentage(percentage) => CalcLengthOrPercentage::new(Au(0), percentage),
+                LP_Calc(calc) => calc,
+            }
+        }
     }
     pub fn compute_LengthOrPercentage(value: specified::LengthOrPercentage, context: &Context)
                                    -> LengthOrPercentage {
         match value {
             specified::LP_Length(value) => LP_Length(compute_Au(value, context)),
             specified::LP_Percentage(value) => LP_Percentage(value),
+            specified::LP_Calc(value) => match compute_Calc(value, context) {
+                calc if calc.is_length() => LP_Length(calc.length),
+                calc => LP_Calc(calc),
+            },
         }
     }
 
@@ -206,13 +1096,30 @@ pub mod computed {
     pub enum LengthOrPercentageOrAuto {
         LPA_Length(Au),
         LPA_Percentage(CSSFloat),
+        LPA_Calc(CalcLengthOrPercentage),
         LPA_Auto,
     }
+    impl LengthOrPercentageOrAuto {
+        /// Returns `None` for `auto`.
+        #[inline]
+        pub fn to_calc(&self) -> Option<CalcLengthOrPercentage> {
+            match *self {
+                LPA_Length(length) => Some(LP_Length(length).to_calc()),
+                LPA_Percentage(percentage) => Some(LP_Percentage(percentage).to_calc()),
+                LPA_Calc(calc) => Some(calc),
+                LPA_Auto => None,
+            }
+        }
+    }
     pub fn compute_LengthOrPercentageOrAuto(value: specified::LengthOrPercentageOrAuto,
                                             context: &Context) -> LengthOrPercentageOrAuto {
         match value {
             specified::LPA_Length(value) => LPA_Length(compute_Au(value, context)),
             specified::LPA_Percentage(value) => LPA_Percentage(value),
+            specified::LPA_Calc(value) => match compute_Calc(value, context) {
+                calc if calc.is_length() => LPA_Length(calc.length),
+                calc => LPA_Calc(calc),
+            },
             specified::LPA_Auto => LPA_Auto,
         }
     }
@@ -221,14 +1128,101 @@ pub mod computed {
     pub enum LengthOrPercentageOrNone {
         LPN_Length(Au),
         LPN_Percentage(CSSFloat),
+        LPN_Calc(CalcLengthOrPercentage),
         LPN_None,
     }
+    impl LengthOrPercentageOrNone {
+        /// Returns `None` for `none`.
+        #[inline]
+        pub fn to_calc(&self) -> Option<CalcLengthOrPercentage> {
+            match *self {
+                LPN_Length(length) => Some(LP_Length(length).to_calc()),
+                LPN_Percentage(percentage) => Some(LP_Percentage(percentage).to_calc()),
+                LPN_Calc(calc) => Some(calc),
+                LPN_None => None,
+            }
+        }
+    }
     pub fn compute_LengthOrPercentageOrNone(value: specified::LengthOrPercentageOrNone,
                                             context: &Context) -> LengthOrPercentageOrNone {
         match value {
             specified::LPN_Length(value) => LPN_Length(compute_Au(value, context)),
             specified::LPN_Percentage(value) => LPN_Percentage(value),
+            specified::LPN_Calc(value) => match compute_Calc(value, context) {
+                calc if calc.is_length() => LPN_Length(calc.length),
+                calc => LPN_Calc(calc),
+            },
             specified::LPN_None => LPN_None,
         }
     }
+
+    #[deriving(Eq, Clone)]
+    pub struct ColorStop {
+        color: CSSColor,
+        position: Option<LengthOrPercentage>,
+    }
+    fn compute_ColorStops(stops: ~[specified::ColorStop], context: &Context) -> ~[ColorStop] {
+        do stops.move_iter().map |stop| {
+            ColorStop {
+                color: stop.color,
+                position: stop.position.map(|position| {
+                    compute_LengthOrPercentage(position, context)
+                }),
+            }
+        }.collect()
+    }
+
+    #[deriving(Eq, Clone)]
+    pub struct LinearGradient {
+        direction: GradientDirection,
+        stops: ~[ColorStop],
+        repeating: bool,
+    }
+
+    #[deriving(Eq, Clone)]
+    pub enum EndingShape {
+        CircleExtent(ShapeExtent),
+        EllipseExtent(ShapeExtent),
+        Circle(Au),
+        Ellipse(LengthOrPercentage, LengthOrPercentage),
+    }
+
+    #[deriving(Eq, Clone)]
+    pub struct RadialGradient {
+        shape: EndingShape,
+        position: longhands::background_position::computed_value::T,
+        stops: ~[ColorStop],
+        repeating: bool,
+    }
+
+    #[deriving(Eq, Clone)]
+    pub enum Image {
+        UrlImage(Url),
+        LinearGradientImage(LinearGradient),
+        RadialGradientImage(RadialGradient),
+    }
+    pub fn compute_Image(value: specified::Image, context: &Context) -> Image {
+        match value {
+            specified::UrlImage(url) => UrlImage(url),
+            specified::LinearGradientImage(gradient) => LinearGradientImage(LinearGradient {
+                direction: gradient.direction,
+                stops: compute_ColorStops(gradient.stops, context),
+                repeating: gradient.repeating,
+            }),
+            specified::RadialGradientImage(gradient) => RadialGradientImage(RadialGradient {
+                shape: match gradient.shape {
+                    specified::CircleExtent(extent) => CircleExtent(extent),
+                    specified::EllipseExtent(extent) => EllipseExtent(extent),
+                    specified::Circle(radius) => Circle(compute_Au(radius, context)),
+                    specified::Ellipse(horizontal, vertical) => Ellipse(
+                        compute_LengthOrPercentage(horizontal, context),
+                        compute_LengthOrPercentage(vertical, context)),
+                },
+                position: longhands::background_position::to_computed_value(gradient.position,
+                                                                            context),
+                stops: compute_ColorStops(gradient.stops, context),
+                repeating: gradient.repeating,
+            }),
+        }
+    }
 }
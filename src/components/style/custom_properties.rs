@@ -0,0 +1,333 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Custom properties (`--name: value`) and the substitution of `var()` functions, per CSS Custom
+//! Properties for Cascading Variables Module Level 1.
+
+use std::ascii::StrAsciiExt;
+use std::hashmap::{HashMap, HashSet};
+use extra::arc::Arc;
+use extra::sort::tim_sort;
+use cssparser::ast::*;
+
+use properties::{CSSWideKeyword, Initial, Inherit};
+
+
+/// The value of a custom property: the tokens it was declared with, once its own `var()` functions
+/// are substituted.
+pub type Value = ~[ComponentValue];
+
+/// Returns true if the given name, as written, is that of a custom property. Unlike other
+/// property names, these are case-sensitive.
+pub fn is_custom_property_name(name: &str) -> bool {
+    name.len() > 2 && name.starts_with("--")
+}
+
+/// The declared value of a custom property.
+#[deriving(Clone)]
+pub enum DeclaredCustomValue {
+    Tokens(Value),
+    /// `initial` makes the property invalid, as if it was never set. `unset` is `inherit`, since
+    /// custom properties are inherited.
+    Keyword(CSSWideKeyword),
+}
+
+impl DeclaredCustomValue {
+    /// Any value is valid as long as its `var()` functions are.
+    pub fn parse(input: &[ComponentValue]) -> Option<DeclaredCustomValue> {
+        match CSSWideKeyword::parse(input) {
+            Some(Left(keyword)) => Some(Keyword(keyword)),
+            Some(Right(_)) => Some(Keyword(Inherit)),
+            None => find_var_functions(input).map(|_| Tokens(trim_whitespace(input).to_owned())),
+        }
+    }
+}
+
+/// The computed custom properties of an element. Elements that declare none of their own share
+/// the map of their parent.
+#[deriving(Clone)]
+pub struct CustomProperties {
+    map: Option<Arc<HashMap<~str, Value>>>,
+}
+
+impl CustomProperties {
+    pub fn new() -> CustomProperties {
+        CustomProperties {
+            map: None,
+        }
+    }
+
+    pub fn get<'a>(&'a self, name: &str) -> Option<&'a Value> {
+        match self.map {
+            Some(ref map) => map.get().find_equiv(&name),
+            None => None,
+        }
+    }
+
+    /// Returns the names of the properties that are set, in alphabetical order.
+    pub fn names(&self) -> ~[~str] {
+        let mut names: ~[~str] = match self.map {
+            Some(ref map) => map.get().keys().map(|name| name.clone()).collect(),
+            None => ~[],
+        };
+        tim_sort(names);
+        names
+    }
+}
+
+impl Eq for CustomProperties {
+    fn eq(&self, other: &CustomProperties) -> bool {
+        match (&self.map, &other.map) {
+            (&None, &None) => true,
+            (&Some(ref map), &Some(ref other_map)) => map.get() == other_map.get(),
+            _ => false,
+        }
+    }
+}
+
+/// Computes the custom properties of an element from those of its parent and the custom property
+/// declarations that apply to it, in cascade order. Custom properties that reference each other
+/// in a cycle, or that reference an invalid property without a fallback, are invalid at
+/// computed-value time, and so are not set.
+pub fn cascade(declarations: &[(&~str, &DeclaredCustomValue)], parent: &CustomProperties)
+               -> CustomProperties {
+    if declarations.is_empty() {
+        return parent.clone()
+    }
+    let mut specified = match parent.map {
+        Some(ref map) => map.get().clone(),
+        None => HashMap::new(),
+    };
+    for &(name, value) in declarations.iter() {
+        match *value {
+            Tokens(ref tokens) => {
+                specified.insert(name.clone(), tokens.clone());
+            }
+            Keyword(Inherit) => match parent.get(*name) {
+                Some(value) => {
+                    specified.insert(name.clone(), value.clone());
+                }
+                None => {
+                    specified.remove(name);
+                }
+            },
+            Keyword(Initial) => {
+                specified.remove(name);
+            }
+        }
+    }
+
+    let mut resolver = Resolver {
+        specified: &specified,
+        computed: HashMap::new(),
+        cycles: HashSet::new(),
+        stack: ~[],
+    };
+    for name in specified.keys() {
+        resolver.resolve(name.as_slice());
+    }
+    let Resolver { computed, cycles, .. } = resolver;
+    let mut map = HashMap::new();
+    for (name, value) in computed.move_iter() {
+        if cycles.contains(&name) {
+            continue
+        }
+        match value {
+            Some(value) => {
+                map.insert(name, value);
+            }
+            None => (),
+        }
+    }
+    CustomProperties {
+        map: if map.is_empty() { None } else { Some(Arc::new(map)) },
+    }
+}
+
+/// Substitutes the `var()` functions in the value of a property. Returns `None` if the value
+/// references an invalid custom property without a fallback, in which case the property is
+/// invalid at computed-value time.
+pub fn substitute(input: &[ComponentValue], custom_properties: &CustomProperties)
+                  -> Option<~[ComponentValue]> {
+    let mut lookup = ComputedLookup {
+        custom_properties: custom_properties,
+    };
+    substitute_with(input, &mut lookup as &mut VariableLookup)
+}
+
+/// Returns whether the given value contains `var()` functions, or `None` if one of them is not
+/// valid, which makes the whole declaration invalid at parse time.
+pub fn find_var_functions(input: &[ComponentValue]) -> Option<bool> {
+    let mut found = false;
+    for component_value in input.iter() {
+        let nested = match component_value {
+            &Function(ref name, ref arguments) if name.eq_ignore_ascii_case("var") => {
+                match parse_var_function(arguments.as_slice()) {
+                    Some((_, Some(fallback))) => find_var_functions(fallback).map(|_| true),
+                    Some((_, None)) => Some(true),
+                    None => None,
+                }
+            }
+            &Function(_, ref arguments) => find_var_functions(arguments.as_slice()),
+            &ParenthesisBlock(ref contents) | &SquareBracketBlock(ref contents) => {
+                find_var_functions(contents.as_slice())
+            }
+            _ => Some(false),
+        };
+        match nested {
+            Some(nested) => found = found || nested,
+            None => return None,
+        }
+    }
+    Some(found)
+}
+
+
+/// Looks up custom properties for `var()` functions.
+trait VariableLookup {
+    /// Returns the value of the given custom property, or `None` if it is not set or invalid.
+    fn lookup(&mut self, name: &str) -> Option<Value>;
+}
+
+struct ComputedLookup<'self> {
+    custom_properties: &'self CustomProperties,
+}
+
+impl<'self> VariableLookup for ComputedLookup<'self> {
+    fn lookup(&mut self, name: &str) -> Option<Value> {
+        self.custom_properties.get(name).map(|value| value.clone())
+    }
+}
+
+/// Substitutes the references between the custom properties of an element, depth first.
+struct Resolver<'self> {
+    specified: &'self HashMap<~str, Value>,
+    /// The properties that are resolved so far, with `None` for invalid ones.
+    computed: HashMap<~str, Option<Value>>,
+    /// The properties found in a reference cycle.
+    cycles: HashSet<~str>,
+    /// The properties being resolved, each one referencing the next.
+    stack: ~[~str],
+}
+
+impl<'self> Resolver<'self> {
+    fn resolve(&mut self, name: &str) -> Option<Value> {
+        match self.computed.find_equiv(&name) {
+            Some(value) => return value.clone(),
+            None => (),
+        }
+        match self.stack.iter().position(|other| other.as_slice() == name) {
+            Some(start) => {
+                // Every property from `name` on references the next one, and the last one
+                // references `name`.
+                for other in self.stack.slice_from(start).iter() {
+                    self.cycles.insert(other.clone());
+                }
+                return None
+            }
+            None => (),
+        }
+        let specified = self.specified;
+        let specified = match specified.find_equiv(&name) {
+            Some(value) => value,
+            None => return None,
+        };
+        self.stack.push(name.to_owned());
+        let value = substitute_with(specified.as_slice(), self as &mut VariableLookup);
+        self.stack.pop();
+        let value = if self.cycles.contains(&name.to_owned()) { None } else { value };
+        self.computed.insert(name.to_owned(), value.clone());
+        value
+    }
+}
+
+impl<'self> VariableLookup for Resolver<'self> {
+    fn lookup(&mut self, name: &str) -> Option<Value> {
+        self.resolve(name)
+    }
+}
+
+fn substitute_with(input: &[ComponentValue], lookup: &mut VariableLookup)
+                   -> Option<~[ComponentValue]> {
+    let mut result = ~[];
+    for component_value in input.iter() {
+        match component_value {
+            &Function(ref name, ref arguments) if name.eq_ignore_ascii_case("var") => {
+                let (name, fallback) = match parse_var_function(arguments.as_slice()) {
+                    Some(function) => function,
+                    None => return None,
+                };
+                let value = match (lookup.lookup(name), fallback) {
+                    (Some(value), _) => Some(value),
+                    (None, Some(fallback)) => substitute_with(fallback, lookup),
+                    (None, None) => None,
+                };
+                match value {
+                    Some(value) => result.push_all_move(value),
+                    None => return None,
+                }
+            }
+            &Function(ref name, ref arguments) => {
+                match substitute_with(arguments.as_slice(), lookup) {
+                    Some(arguments) => result.push(Function(name.clone(), arguments)),
+                    None => return None,
+                }
+            }
+            &ParenthesisBlock(ref contents) => match substitute_with(contents.as_slice(), lookup) {
+                Some(contents) => result.push(ParenthesisBlock(contents)),
+                None => return None,
+            },
+            &SquareBracketBlock(ref contents) => {
+                match substitute_with(contents.as_slice(), lookup) {
+                    Some(contents) => result.push(SquareBracketBlock(contents)),
+                    None => return None,
+                }
+            }
+            _ => result.push(component_value.clone()),
+        }
+    }
+    Some(result)
+}
+
+/// var( <custom-property-name> [, <declaration-value> ]? )
+///
+/// Returns the name of the referenced property and the fallback value, if any.
+fn parse_var_function<'a>(arguments: &'a [ComponentValue])
+                          -> Option<(&'a str, Option<&'a [ComponentValue]>)> {
+    let arguments = trim_whitespace(arguments);
+    if arguments.is_empty() {
+        return None
+    }
+    let name = match arguments[0] {
+        Ident(ref name) if is_custom_property_name(name.as_slice()) => name.as_slice(),
+        _ => return None,
+    };
+    let rest = trim_whitespace(arguments.slice_from(1));
+    if rest.is_empty() {
+        return Some((name, None))
+    }
+    match rest[0] {
+        Comma => Some((name, Some(rest.slice_from(1)))),
+        _ => None,
+    }
+}
+
+fn trim_whitespace<'a>(input: &'a [ComponentValue]) -> &'a [ComponentValue] {
+    let mut start = 0;
+    while start < input.len() && is_whitespace(&input[start]) {
+        start += 1
+    }
+    let mut end = input.len();
+    while end > start && is_whitespace(&input[end - 1]) {
+        end -= 1
+    }
+    input.slice(start, end)
+}
+
+fn is_whitespace(component_value: &ComponentValue) -> bool {
+    match *component_value {
+        WhiteSpace => true,
+        _ => false,
+    }
+}
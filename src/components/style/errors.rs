@@ -13,7 +13,8 @@ impl<T, I: Iterator<Result<T, SyntaxError>>> Iterator<T> for ErrorLoggerIterator
         for result in **self {
             match result {
                 Ok(v) => return Some(v),
-                Err(error) => log_css_error(error.location, format!("{:?}", error.reason))
+                Err(error) => log_css_error(error.location, CSSSyntaxError, "",
+                                            format!("{:?}", error.reason))
             }
         }
         None
@@ -21,19 +22,135 @@ impl<T, I: Iterator<Result<T, SyntaxError>>> Iterator<T> for ErrorLoggerIterator
 }
 
 
-local_data_key!(silence_errors: ())
+/// What kind of construct was dropped because of a CSS error.
+#[deriving(Eq, Clone)]
+pub enum CSSErrorCategory {
+    /// The tokens could not be parsed into rules or declarations.
+    CSSSyntaxError,
+    /// A known at-rule that is invalid or misplaced.
+    InvalidAtRuleError,
+    /// An at-rule that is not supported.
+    UnsupportedAtRuleError,
+    /// A selector that is invalid or uses unsupported features.
+    InvalidSelectorError,
+    /// A declaration for a property that is not supported.
+    UnsupportedPropertyError,
+    /// A declaration whose value is invalid or unsupported for its property.
+    InvalidValueError,
+}
 
-pub fn log_css_error(location: SourceLocation, message: &str) {
-    // TODO eventually this will got into a "web console" or something.
-    if local_data::get(silence_errors, |silenced| silenced.is_none()) {
-        error!("{:u}:{:u} {:s}", location.line, location.column, message)
+impl CSSErrorCategory {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CSSSyntaxError => "syntax-error",
+            InvalidAtRuleError => "invalid-at-rule",
+            UnsupportedAtRuleError => "unsupported-at-rule",
+            InvalidSelectorError => "invalid-selector",
+            UnsupportedPropertyError => "unsupported-property",
+            InvalidValueError => "invalid-value",
+        }
     }
 }
 
 
-pub fn with_errors_silenced<T>(f: &fn() -> T) -> T {
-    local_data::set(silence_errors, ());
+/// An error found while parsing CSS. The offending construct was ignored.
+#[deriving(Clone)]
+pub struct CSSError {
+    /// The URL of the stylesheet, or of the document for style attributes.
+    url: ~str,
+    line: uint,
+    column: uint,
+    category: CSSErrorCategory,
+    /// The offending property declaration, selector or at-rule name, as written. May be empty.
+    source: ~str,
+    message: ~str,
+}
+
+impl ToStr for CSSError {
+    fn to_str(&self) -> ~str {
+        format!("{:s}:{:u}:{:u} [{:s}] {:s}{:s}{:s}", self.url, self.line, self.column,
+                self.category.name(), self.message,
+                if self.source.is_empty() { "" } else { ": " }, self.source)
+    }
+}
+
+
+/// Receives the CSS errors found while parsing.
+pub trait CSSErrorReporter {
+    fn report_css_error(&mut self, error: CSSError);
+}
+
+/// A reporter that keeps the errors, as is done for each stylesheet.
+pub struct CSSErrorCollector {
+    errors: ~[CSSError],
+}
+
+impl CSSErrorReporter for CSSErrorCollector {
+    fn report_css_error(&mut self, error: CSSError) {
+        self.errors.push(error)
+    }
+}
+
+struct SilentReporter;
+
+impl CSSErrorReporter for SilentReporter {
+    fn report_css_error(&mut self, _error: CSSError) {}
+}
+
+
+/// The reporter installed for the current task, with the URL of what is being parsed.
+local_data_key!(css_error_reporter: (~str, @mut CSSErrorReporter))
+
+pub fn log_css_error(location: SourceLocation, category: CSSErrorCategory, source: &str,
+                     message: &str) {
+    let reported = do local_data::get(css_error_reporter) |reporter| {
+        match reporter {
+            Some(&(ref url, reporter)) => {
+                reporter.report_css_error(CSSError {
+                    url: url.clone(),
+                    line: location.line,
+                    column: location.column,
+                    category: category,
+                    source: source.to_owned(),
+                    message: message.to_owned(),
+                });
+                true
+            }
+            None => false,
+        }
+    };
+    if !reported {
+        // Nobody is listening: fall back to the log.
+        error!("{:u}:{:u} [{:s}] {:s} {:s}", location.line, location.column, category.name(),
+               message, source)
+    }
+}
+
+
+/// Sends the errors found while running `f` to the given reporter, instead of the log or of any
+/// previously installed reporter.
+pub fn with_css_error_reporter<T>(url: ~str, reporter: @mut CSSErrorReporter, f: &fn() -> T)
+                                  -> T {
+    let previous = local_data::pop(css_error_reporter);
+    local_data::set(css_error_reporter, (url, reporter));
     let result = f();
-    local_data::pop(silence_errors);
+    local_data::pop(css_error_reporter);
+    match previous {
+        Some(previous) => local_data::set(css_error_reporter, previous),
+        None => (),
+    }
     result
 }
+
+
+/// Returns the errors found while running `f`, attributed to the given URL.
+pub fn collect_css_errors<T>(url: ~str, f: &fn() -> T) -> (T, ~[CSSError]) {
+    let collector = @mut CSSErrorCollector { errors: ~[] };
+    let result = with_css_error_reporter(url, collector as @mut CSSErrorReporter, f);
+    (result, collector.errors.clone())
+}
+
+
+pub fn with_errors_silenced<T>(f: &fn() -> T) -> T {
+    with_css_error_reporter(~"", @mut SilentReporter as @mut CSSErrorReporter, f)
+}
@@ -0,0 +1,195 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::ascii::StrAsciiExt;
+use extra::url::Url;
+use cssparser::{parse_declaration_list, ToCss};
+use cssparser::ast::*;
+use errors::{ErrorLoggerIterator, InvalidAtRuleError, InvalidValueError};
+use errors::{UnsupportedPropertyError, log_css_error};
+use parsing_utils::{one_component_value, split_on_commas};
+use properties::longhands::{font_family, font_stretch, font_style, font_weight};
+use properties::computed_values;
+use servo_util::url::make_url;
+use stylesheets::{CSSRule, CSSFontFaceRule};
+
+
+/// An `@font-face` rule. Rules without a `font-family` or a `src` descriptor are dropped while
+/// parsing.
+#[deriving(Clone)]
+pub struct FontFaceRule {
+    family: ~str,
+    /// The sources to try, in order.
+    sources: ~[FontFaceSource],
+    weight: computed_values::font_weight::T,
+    style: computed_values::font_style::T,
+    stretch: computed_values::font_stretch::T,
+    /// The inclusive ranges of code points the font is used for. Empty means all of them.
+    unicode_range: ~[(u32, u32)],
+}
+
+
+#[deriving(Clone)]
+pub enum FontFaceSource {
+    /// `url(...) format(...)?`, with the format hints lower-cased. No hints means any format.
+    UrlSource(Url, ~[~str]),
+    /// `local(...)`, the full name of a font installed on the system.
+    LocalSource(~str),
+}
+
+
+pub fn parse_font_face_rule(rule: AtRule, parent_rules: &mut ~[CSSRule], base_url: &Url) {
+    let location = rule.location;
+    macro_rules! syntax_error(
+        ($message: expr) => {{
+            log_css_error(location, InvalidAtRuleError, "@font-face", $message);
+            return
+        }};
+    );
+    if rule.prelude.skip_whitespace().next().is_some() {
+        syntax_error!("@font-face does not accept a prelude")
+    }
+    let block = match rule.block {
+        Some(block) => block,
+        None => syntax_error!("Invalid @font-face rule"),
+    };
+    let mut family = None;
+    let mut sources = None;
+    let mut weight = font_weight::get_initial_value();
+    let mut style = font_style::get_initial_value();
+    let mut stretch = font_stretch::get_initial_value();
+    let mut unicode_range = ~[];
+    for item in ErrorLoggerIterator(parse_declaration_list(block.move_iter())) {
+        match item {
+            Decl_AtRule(rule) => log_css_error(
+                rule.location, InvalidAtRuleError, format!("@{:s}", rule.name),
+                "At-rules are not allowed in @font-face"),
+            Declaration(Declaration{ location: l, name: n, value: v, important: _}) => {
+                let lower_name = n.to_ascii_lower();
+                let valid = match lower_name.as_slice() {
+                    "font-family" => match parse_family_name(v) {
+                        Some(name) => { family = Some(name); true },
+                        None => false,
+                    },
+                    "src" => match parse_sources(v, base_url) {
+                        Some(s) => { sources = Some(s); true },
+                        None => false,
+                    },
+                    "font-weight" => match parse_weight(v) {
+                        Some(w) => { weight = w; true },
+                        None => false,
+                    },
+                    "font-style" => match font_style::parse(v) {
+                        Some(s) => { style = s; true },
+                        None => false,
+                    },
+                    "font-stretch" => match font_stretch::parse(v) {
+                        Some(s) => { stretch = s; true },
+                        None => false,
+                    },
+                    "unicode-range" => match parse_unicode_range(v) {
+                        Some(r) => { unicode_range = r; true },
+                        None => false,
+                    },
+                    _ => {
+                        log_css_error(l, UnsupportedPropertyError,
+                                      format!("{}:{}", n, v.iter().to_css()),
+                                      "Unsupported @font-face descriptor");
+                        true
+                    }
+                };
+                if !valid {
+                    log_css_error(l, InvalidValueError, format!("{}:{}", n, v.iter().to_css()),
+                                  "Invalid value")
+                }
+            }
+        }
+    }
+    match (family, sources) {
+        (Some(family), Some(sources)) => parent_rules.push(CSSFontFaceRule(FontFaceRule {
+            family: family,
+            sources: sources,
+            weight: weight,
+            style: style,
+            stretch: stretch,
+            unicode_range: unicode_range,
+        })),
+        _ => syntax_error!("@font-face requires the font-family and src descriptors"),
+    }
+}
+
+
+/// `[ <url> [ format(<string>#) ]? | local(<family-name>) ]#`
+fn parse_sources(input: &[ComponentValue], base_url: &Url) -> Option<~[FontFaceSource]> {
+    let mut sources = ~[];
+    for item in split_on_commas(input).move_iter() {
+        let values: ~[&ComponentValue] = item.skip_whitespace().collect();
+        let source = match values.as_slice() {
+            [&URL(ref url)] => UrlSource(make_url(url.to_owned(), Some(base_url.clone())), ~[]),
+            [&URL(ref url), &Function(ref name, ref arguments)]
+                    if name.eq_ignore_ascii_case("format") => {
+                let mut formats = ~[];
+                for argument in split_on_commas(*arguments).move_iter() {
+                    match one_component_value(argument) {
+                        Some(&String(ref format)) => formats.push(format.to_ascii_lower()),
+                        _ => return None,
+                    }
+                }
+                UrlSource(make_url(url.to_owned(), Some(base_url.clone())), formats)
+            }
+            [&Function(ref name, ref arguments)] if name.eq_ignore_ascii_case("local") => {
+                match parse_family_name(*arguments) {
+                    Some(name) => LocalSource(name),
+                    None => return None,
+                }
+            }
+            _ => return None,
+        };
+        sources.push(source)
+    }
+    Some(sources)
+}
+
+
+/// A single `<family-name>`.
+fn parse_family_name(input: &[ComponentValue]) -> Option<~str> {
+    match font_family::parse(input) {
+        Some(families) => match families.as_slice() {
+            [font_family::FamilyName(ref name)] => Some(name.clone()),
+            _ => None,
+        },
+        None => None,
+    }
+}
+
+
+/// normal | bold | 100 | 200 | 300 | 400 | 500 | 600 | 700 | 800 | 900
+fn parse_weight(input: &[ComponentValue]) -> Option<computed_values::font_weight::T> {
+    match one_component_value(input).and_then(font_weight::from_component_value) {
+        Some(font_weight::SpecifiedWeight100) => Some(computed_values::font_weight::Weight100),
+        Some(font_weight::SpecifiedWeight200) => Some(computed_values::font_weight::Weight200),
+        Some(font_weight::SpecifiedWeight300) => Some(computed_values::font_weight::Weight300),
+        Some(font_weight::SpecifiedWeight400) => Some(computed_values::font_weight::Weight400),
+        Some(font_weight::SpecifiedWeight500) => Some(computed_values::font_weight::Weight500),
+        Some(font_weight::SpecifiedWeight600) => Some(computed_values::font_weight::Weight600),
+        Some(font_weight::SpecifiedWeight700) => Some(computed_values::font_weight::Weight700),
+        Some(font_weight::SpecifiedWeight800) => Some(computed_values::font_weight::Weight800),
+        Some(font_weight::SpecifiedWeight900) => Some(computed_values::font_weight::Weight900),
+        // bolder and lighter are relative to an inherited weight, which descriptors don't have.
+        _ => None,
+    }
+}
+
+
+/// `<urange>#`
+fn parse_unicode_range(input: &[ComponentValue]) -> Option<~[(u32, u32)]> {
+    let mut ranges = ~[];
+    for item in split_on_commas(input).move_iter() {
+        match one_component_value(item) {
+            Some(&UnicodeRange(start, end)) if start <= end => ranges.push((start, end)),
+            _ => return None,
+        }
+    }
+    Some(ranges)
+}
@@ -3,20 +3,23 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::ascii::StrAsciiExt;
+use extra::url::Url;
 use cssparser::parse_rule_list;
 use cssparser::ast::*;
 
-use errors::{ErrorLoggerIterator, log_css_error};
+use errors::{ErrorLoggerIterator, InvalidAtRuleError, log_css_error};
 use stylesheets::{CSSRule, CSSMediaRule, parse_style_rule, parse_nested_at_rule};
 use namespaces::NamespaceMap;
 
 
+#[deriving(Clone)]
 pub struct MediaRule {
     media_queries: MediaQueryList,
     rules: ~[CSSRule],
 }
 
 
+#[deriving(Clone)]
 pub struct MediaQueryList {
     // "not all" is omitted from the list.
     // An empty list never matches.
@@ -24,18 +27,20 @@ pub struct MediaQueryList {
 }
 
 // For now, this is a "Level 2 MQ", ie. a media type.
+#[deriving(Clone)]
 struct MediaQuery {
     media_type: MediaQueryType,
     // TODO: Level 3 MQ expressions
 }
 
 
+#[deriving(Clone)]
 enum MediaQueryType {
     All,  // Always true
     MediaType(MediaType),
 }
 
-#[deriving(Eq)]
+#[deriving(Eq, Clone)]
 pub enum MediaType {
     Screen,
     Print,
@@ -48,21 +53,21 @@ pub struct Device {
 
 
 pub fn parse_media_rule(rule: AtRule, parent_rules: &mut ~[CSSRule],
-                        namespaces: &NamespaceMap) {
+                        namespaces: &NamespaceMap, base_url: &Url) {
     let media_queries = parse_media_query_list(rule.prelude);
     let block = match rule.block {
         Some(block) => block,
         None => {
-            log_css_error(rule.location, "Invalid @media rule");
+            log_css_error(rule.location, InvalidAtRuleError, "@media", "Invalid @media rule");
             return
         }
     };
     let mut rules = ~[];
     for rule in ErrorLoggerIterator(parse_rule_list(block.move_iter())) {
         match rule {
-            QualifiedRule(rule) => parse_style_rule(rule, &mut rules, namespaces),
+            QualifiedRule(rule) => parse_style_rule(rule, &mut rules, namespaces, base_url),
             AtRule(rule) => parse_nested_at_rule(
-                rule.name.to_ascii_lower(), rule, &mut rules, namespaces),
+                rule.name.to_ascii_lower(), rule, &mut rules, namespaces, base_url),
         }
     }
     parent_rules.push(CSSMediaRule(MediaRule {
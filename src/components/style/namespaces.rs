@@ -4,8 +4,9 @@
 
 use std::hashmap::HashMap;
 use cssparser::ast::*;
-use errors::log_css_error;
+use errors::{InvalidAtRuleError, log_css_error};
 
+#[deriving(Clone)]
 pub struct NamespaceMap {
     default: Option<~str>,  // Optional URL
     prefix_map: HashMap<~str, ~str>,  // prefix -> URL
@@ -23,7 +24,7 @@ pub fn parse_namespace_rule(rule: AtRule, namespaces: &mut NamespaceMap) {
     let location = rule.location;
     macro_rules! syntax_error(
         () => {{
-            log_css_error(location, "Invalid @namespace rule");
+            log_css_error(location, InvalidAtRuleError, "@namespace", "Invalid @namespace rule");
             return
         }};
     );
@@ -49,12 +50,14 @@ pub fn parse_namespace_rule(rule: AtRule, namespaces: &mut NamespaceMap) {
     match (prefix, url) {
         (Some(prefix), Some(url)) => {
             if namespaces.prefix_map.swap(prefix, url).is_some() {
-                log_css_error(location, "Duplicate @namespace rule");
+                log_css_error(location, InvalidAtRuleError, "@namespace",
+                              "Duplicate @namespace rule");
             }
         },
         (None, Some(url)) => {
             if namespaces.default.is_some() {
-                log_css_error(location, "Duplicate @namespace rule");
+                log_css_error(location, InvalidAtRuleError, "@namespace",
+                              "Duplicate @namespace rule");
             }
             namespaces.default = Some(url);
         },
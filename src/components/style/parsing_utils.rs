@@ -4,7 +4,7 @@
 
 
 use std::ascii::StrAsciiExt;
-use cssparser::ast::{ComponentValue, Ident, SkipWhitespaceIterable};
+use cssparser::ast::{ComponentValue, Ident, Comma, SkipWhitespaceIterable};
 
 
 pub fn one_component_value<'a>(input: &'a [ComponentValue]) -> Option<&'a ComponentValue> {
@@ -19,3 +19,37 @@ pub fn get_ident_lower(component_value: &ComponentValue) -> Option<~str> {
         _ => None,
     }
 }
+
+
+/// Splits a list of component values at the commas, as in the arguments of a function.
+pub fn split_on_commas<'a>(input: &'a [ComponentValue]) -> ~[&'a [ComponentValue]] {
+    let mut groups = ~[];
+    let mut start = 0;
+    for (i, component_value) in input.iter().enumerate() {
+        match component_value {
+            &Comma => {
+                groups.push(input.slice(start, i));
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    groups.push(input.slice_from(start));
+    groups
+}
+
+
+/// Parses a comma-separated list, as in the value of a list-valued property, with `parse_one` for
+/// each item. Returns `None` if any item is invalid.
+pub fn parse_comma_separated<T>(input: &[ComponentValue],
+                                parse_one: &fn(&[ComponentValue]) -> Option<T>)
+                                -> Option<~[T]> {
+    let mut values = ~[];
+    for item in split_on_commas(input).iter() {
+        match parse_one(*item) {
+            Some(value) => values.push(value),
+            None => return None,
+        }
+    }
+    Some(values)
+}
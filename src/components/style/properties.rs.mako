@@ -5,11 +5,17 @@
 // This file is a Mako template: http://www.makotemplates.org/
 
 use std::ascii::StrAsciiExt;
+use std::cell::Cell;
 pub use extra::arc::Arc;
+pub use extra::url::Url;
 pub use cssparser::*;
 pub use cssparser::ast::*;
 
-use errors::{ErrorLoggerIterator, log_css_error};
+use errors::{ErrorLoggerIterator, InvalidValueError, UnsupportedAtRuleError};
+use errors::{UnsupportedPropertyError, log_css_error};
+use custom_properties;
+use custom_properties::{CustomProperties, DeclaredCustomValue};
+use servo_util::geometry::to_frac_px;
 pub use parsing_utils::*;
 pub use self::common_types::*;
 
@@ -43,6 +49,33 @@ LONGHANDS = []
 LONGHANDS_BY_NAME = {}
 SHORTHANDS = []
 
+# Longhands whose computed values are interpolated by transitions and animations. Other longhands
+# switch from one value to the other halfway through.
+ANIMATABLE = set(["margin-%s" % side for side in ["top", "right", "bottom", "left"]] +
+                 ["padding-%s" % side for side in ["top", "right", "bottom", "left"]] +
+                 ["border-%s-color" % side for side in ["top", "right", "bottom", "left"]] +
+                 ["border-%s-width" % side for side in ["top", "right", "bottom", "left"]] +
+                 ["top", "right", "bottom", "left", "width", "height", "min-width",
+                  "max-width", "background-color", "color", "font-size", "opacity",
+                  "transform"])
+
+# Animatable properties that take no negative values, which overshooting timing functions could
+# otherwise produce.
+NON_NEGATIVE_ANIMATABLE = set(["padding-%s" % side for side in ["top", "right", "bottom", "left"]] +
+                              ["border-%s-width" % side
+                               for side in ["top", "right", "bottom", "left"]] +
+                              ["width", "height", "min-width", "max-width", "font-size"])
+
+# Properties that apply to ::first-line (CSS 2.1 section 5.12.1).
+def applies_to_first_line(name):
+    return (name.startswith("font-") or name.startswith("background-") or
+            name in ["color", "line-height", "text-decoration", "vertical-align"])
+
+# Properties that apply to ::first-letter (CSS 2.1 section 5.12.2).
+def applies_to_first_letter(name):
+    return (applies_to_first_line(name) or name.startswith("margin-") or
+            name.startswith("padding-") or name.startswith("border-") or name == "float")
+
 def new_style_struct(name):
     longhands = []
     LONGHANDS_PER_STYLE_STRUCT.append((name, longhands))
@@ -71,13 +104,13 @@ pub mod longhands {
             % endif
             pub use self::computed_value::*;
             ${caller.body()}
-            pub fn parse_declared(input: &[ComponentValue])
+            pub fn parse_declared(input: &[ComponentValue], base_url: &Url)
                                -> Option<DeclaredValue<SpecifiedValue>> {
                 match CSSWideKeyword::parse(input) {
                     Some(Left(keyword)) => Some(CSSWideKeyword(keyword)),
                     Some(Right(Unset)) => Some(CSSWideKeyword(${
                         "Inherit" if inherited else "Initial"})),
-                    None => parse_specified(input),
+                    None => parse_specified(input, base_url),
                 }
             }
         }
@@ -86,7 +119,7 @@ pub mod longhands {
     <%def name="longhand(name, inherited=False, no_super=False)">
         <%self:raw_longhand name="${name}" inherited="${inherited}">
             ${caller.body()}
-            pub fn parse_specified(input: &[ComponentValue])
+            pub fn parse_specified(input: &[ComponentValue], _base_url: &Url)
                                -> Option<DeclaredValue<SpecifiedValue>> {
                 parse(input).map(super::SpecifiedValue)
             }
@@ -113,6 +146,15 @@ pub mod longhands {
                     % endfor
                 }
             }
+            impl ComputedValueToCss for computed_value::T {
+                fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                    match *self {
+                        % for value in values.split():
+                            ${to_rust_ident(value)} => ~"${value}",
+                        % endfor
+                    }
+                }
+            }
             pub type SpecifiedValue = computed_value::T;
             #[inline] pub fn get_initial_value() -> computed_value::T {
                 ${to_rust_ident(values.split()[0])}
@@ -151,6 +193,53 @@ pub mod longhands {
         </%self:single_component_value>
     </%def>
 
+    <%def name="keyword_list(name, values)">
+        <%self:longhand name="${name}">
+            pub use to_computed_value = super::computed_as_specified;
+            pub mod computed_value {
+                #[deriving(Eq, Clone)]
+                pub enum SingleComputedValue {
+                    % for value in values.split():
+                        ${to_rust_ident(value)},
+                    % endfor
+                }
+                /// One value per item of the list.
+                pub type T = ~[SingleComputedValue];
+            }
+            impl ComputedValueToCss for computed_value::T {
+                fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                    let values: ~[&'static str] = do self.iter().map |value| {
+                        match *value {
+                            % for value in values.split():
+                                ${to_rust_ident(value)} => "${value}",
+                            % endfor
+                        }
+                    }.collect();
+                    values.connect(", ")
+                }
+            }
+            pub type SpecifiedValue = computed_value::T;
+            #[inline] pub fn get_initial_value() -> computed_value::T {
+                ~[${to_rust_ident(values.split()[0])}]
+            }
+            pub fn from_keyword(keyword: &str) -> Option<SingleComputedValue> {
+                match keyword {
+                    % for value in values.split():
+                        "${value}" => Some(${to_rust_ident(value)}),
+                    % endfor
+                    _ => None,
+                }
+            }
+            pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+                do parse_comma_separated(input) |item| {
+                    do one_component_value(item).and_then(get_ident_lower).and_then |keyword| {
+                        from_keyword(keyword.as_slice())
+                    }
+                }
+            }
+        </%self:longhand>
+    </%def>
+
 
     // CSS 2.1, Section 8 - Box model
 
@@ -223,6 +312,75 @@ pub mod longhands {
         </%self:longhand>
     % endfor
 
+    // CSS Backgrounds and Borders 3, Section 5 - Rounded corners
+
+    <%self:longhand name="border-top-left-radius">
+        #[deriving(Clone)]
+        pub struct SpecifiedValue {
+            horizontal: specified::LengthOrPercentage,
+            vertical: specified::LengthOrPercentage,
+        }
+        pub mod computed_value {
+            use super::super::computed::LengthOrPercentage;
+            /// The horizontal and vertical radii of a corner.
+            #[deriving(Eq, Clone)]
+            pub struct T {
+                horizontal: LengthOrPercentage,
+                vertical: LengthOrPercentage,
+            }
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                if self.horizontal == self.vertical {
+                    self.horizontal.computed_value_to_css(style)
+                } else {
+                    format!("{:s} {:s}", self.horizontal.computed_value_to_css(style),
+                            self.vertical.computed_value_to_css(style))
+                }
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::LP_Length(Au(0)),
+                vertical: computed::LP_Length(Au(0)),
+            }
+        }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::compute_LengthOrPercentage(value.horizontal, context),
+                vertical: computed::compute_LengthOrPercentage(value.vertical, context),
+            }
+        }
+        /// [ <length> | <percentage> ]{1,2}
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            let mut iter = input.skip_whitespace()
+                                .map(specified::LengthOrPercentage::parse_non_negative);
+            let horizontal = match iter.next() {
+                Some(Some(horizontal)) => horizontal,
+                _ => return None,
+            };
+            let vertical = match iter.next() {
+                Some(Some(vertical)) => vertical,
+                Some(None) => return None,
+                None => horizontal.clone(),
+            };
+            if iter.next().is_some() {
+                return None
+            }
+            Some(SpecifiedValue { horizontal: horizontal, vertical: vertical })
+        }
+    </%self:longhand>
+    % for corner in ["top-right", "bottom-right", "bottom-left"]:
+        <%self:longhand name="border-${corner}-radius", no_super="True">
+            pub use super::border_top_left_radius::*;
+            pub type SpecifiedValue = super::border_top_left_radius::SpecifiedValue;
+            pub mod computed_value {
+                pub type T = super::super::border_top_left_radius::computed_value::T;
+            }
+        </%self:longhand>
+    % endfor
+
     ${new_style_struct("PositionOffsets")}
 
     % for side in ["top", "right", "bottom", "left"]:
@@ -270,6 +428,374 @@ pub mod longhands {
     ${single_keyword("float", "none left right")}
     ${single_keyword("clear", "none left right both")}
 
+    // CSS 2.1, Section 9.10 - Text direction
+
+    ${single_keyword("direction", "ltr rtl", inherited=True)}
+    ${single_keyword("unicode-bidi", "normal embed bidi-override")}
+
+    // CSS Color 3, Section 3.2 - Transparency
+
+    <%self:single_component_value name="opacity">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = CSSFloat;
+        pub mod computed_value {
+            use super::super::CSSFloat;
+            pub type T = CSSFloat;
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { 1. }
+        /// <number>, clamped to [0, 1]
+        pub fn from_component_value(input: &ComponentValue) -> Option<SpecifiedValue> {
+            match input {
+                &ast::Number(ref value) => Some(value.value.max(&0.).min(&1.)),
+                _ => None,
+            }
+        }
+    </%self:single_component_value>
+
+    // CSS Transforms, Section 6 - The transform property
+
+    <%self:longhand name="transform">
+        #[deriving(Clone)]
+        pub enum SpecifiedOperation {
+            SpecifiedMatrix(CSSFloat, CSSFloat, CSSFloat, CSSFloat, CSSFloat, CSSFloat),
+            SpecifiedTranslate(specified::LengthOrPercentage, specified::LengthOrPercentage),
+            SpecifiedScale(CSSFloat, CSSFloat),
+            SpecifiedRotate(CSSFloat),
+            SpecifiedSkew(CSSFloat, CSSFloat),
+        }
+        pub type SpecifiedValue = ~[SpecifiedOperation];
+        pub mod computed_value {
+            use super::super::{computed, CSSFloat};
+            /// One transform function. Angles are in radians and the translations of a matrix
+            /// are in pixels.
+            #[deriving(Eq, Clone)]
+            pub enum TransformOperation {
+                TransformMatrix(CSSFloat, CSSFloat, CSSFloat, CSSFloat, CSSFloat, CSSFloat),
+                TransformTranslate(computed::LengthOrPercentage, computed::LengthOrPercentage),
+                TransformScale(CSSFloat, CSSFloat),
+                TransformRotate(CSSFloat),
+                TransformSkew(CSSFloat, CSSFloat),
+            }
+            /// The transform functions in order, or none for `none`.
+            pub type T = ~[TransformOperation];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                if self.is_empty() {
+                    return ~"none"
+                }
+                let operations: ~[~str] = do self.iter().map |operation| {
+                    match *operation {
+                        TransformMatrix(a, b, c, d, e, f)
+                        => format!("matrix({}, {}, {}, {}, {}, {})", a, b, c, d, e, f),
+                        TransformTranslate(x, y)
+                        => format!("translate({:s}, {:s})", x.computed_value_to_css(style),
+                                   y.computed_value_to_css(style)),
+                        TransformScale(x, y) => format!("scale({}, {})", x, y),
+                        TransformRotate(angle) => format!("rotate({}rad)", angle),
+                        TransformSkew(x, y) => format!("skew({}rad, {}rad)", x, y),
+                    }
+                }.collect();
+                operations.connect(" ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[] }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            do value.move_iter().map |operation| {
+                match operation {
+                    SpecifiedMatrix(a, b, c, d, e, f) => TransformMatrix(a, b, c, d, e, f),
+                    SpecifiedTranslate(x, y) => TransformTranslate(
+                        computed::compute_LengthOrPercentage(x, context),
+                        computed::compute_LengthOrPercentage(y, context)),
+                    SpecifiedScale(x, y) => TransformScale(x, y),
+                    SpecifiedRotate(angle) => TransformRotate(angle),
+                    SpecifiedSkew(x, y) => TransformSkew(x, y),
+                }
+            }.collect()
+        }
+
+        fn parse_number(input: &ComponentValue) -> Option<CSSFloat> {
+            match input {
+                &Number(ref value) => Some(value.value),
+                _ => None,
+            }
+        }
+        /// Parses each of the comma-separated arguments of a function, which must be single
+        /// component values.
+        fn parse_arguments<V>(arguments: &[ComponentValue],
+                              parse_one: &fn(&ComponentValue) -> Option<V>)
+                              -> Option<~[V]> {
+            let mut values = ~[];
+            for argument in split_on_commas(arguments).iter() {
+                match one_component_value(*argument).and_then(|value| parse_one(value)) {
+                    Some(value) => values.push(value),
+                    None => return None,
+                }
+            }
+            Some(values)
+        }
+        fn parse_operation(name: &str, arguments: &[ComponentValue])
+                           -> Option<SpecifiedOperation> {
+            let zero = specified::LP_Length(specified::Au_(Au(0)));
+            match name {
+                "matrix" => match parse_arguments(arguments, parse_number) {
+                    Some(ref m) if m.len() == 6
+                    => Some(SpecifiedMatrix(m[0], m[1], m[2], m[3], m[4], m[5])),
+                    _ => None,
+                },
+                "translate" => match parse_arguments(arguments,
+                                                     specified::LengthOrPercentage::parse) {
+                    Some(ref t) if t.len() == 1 => Some(SpecifiedTranslate(t[0].clone(), zero)),
+                    Some(ref t) if t.len() == 2
+                    => Some(SpecifiedTranslate(t[0].clone(), t[1].clone())),
+                    _ => None,
+                },
+                "translatex" => match parse_arguments(arguments,
+                                                      specified::LengthOrPercentage::parse) {
+                    Some(ref t) if t.len() == 1 => Some(SpecifiedTranslate(t[0].clone(), zero)),
+                    _ => None,
+                },
+                "translatey" => match parse_arguments(arguments,
+                                                      specified::LengthOrPercentage::parse) {
+                    Some(ref t) if t.len() == 1 => Some(SpecifiedTranslate(zero, t[0].clone())),
+                    _ => None,
+                },
+                "scale" => match parse_arguments(arguments, parse_number) {
+                    Some(ref s) if s.len() == 1 => Some(SpecifiedScale(s[0], s[0])),
+                    Some(ref s) if s.len() == 2 => Some(SpecifiedScale(s[0], s[1])),
+                    _ => None,
+                },
+                "scalex" => match parse_arguments(arguments, parse_number) {
+                    Some(ref s) if s.len() == 1 => Some(SpecifiedScale(s[0], 1.)),
+                    _ => None,
+                },
+                "scaley" => match parse_arguments(arguments, parse_number) {
+                    Some(ref s) if s.len() == 1 => Some(SpecifiedScale(1., s[0])),
+                    _ => None,
+                },
+                "rotate" => match parse_arguments(arguments, specified::parse_angle) {
+                    Some(ref a) if a.len() == 1 => Some(SpecifiedRotate(a[0])),
+                    _ => None,
+                },
+                "skew" => match parse_arguments(arguments, specified::parse_angle) {
+                    Some(ref a) if a.len() == 1 => Some(SpecifiedSkew(a[0], 0.)),
+                    Some(ref a) if a.len() == 2 => Some(SpecifiedSkew(a[0], a[1])),
+                    _ => None,
+                },
+                "skewx" => match parse_arguments(arguments, specified::parse_angle) {
+                    Some(ref a) if a.len() == 1 => Some(SpecifiedSkew(a[0], 0.)),
+                    _ => None,
+                },
+                "skewy" => match parse_arguments(arguments, specified::parse_angle) {
+                    Some(ref a) if a.len() == 1 => Some(SpecifiedSkew(0., a[0])),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        /// none | <transform-function>+
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            match one_component_value(input).and_then(get_ident_lower) {
+                Some(ref keyword) if keyword.as_slice() == "none" => return Some(~[]),
+                _ => {}
+            }
+            let mut operations = ~[];
+            for component_value in input.skip_whitespace() {
+                match component_value {
+                    &Function(ref name, ref arguments) => {
+                        // FIXME: Workaround for https://github.com/mozilla/rust/issues/10683
+                        let name_lower = name.to_ascii_lower();
+                        match parse_operation(name_lower.as_slice(), arguments.as_slice()) {
+                            Some(operation) => operations.push(operation),
+                            None => return None,
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            if operations.is_empty() { None } else { Some(operations) }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="transform-origin">
+        pub use to_computed_value = super::background_position::to_computed_value;
+        pub use parse = super::background_position::parse;
+        pub type SpecifiedValue = super::background_position::SpecifiedValue;
+        pub mod computed_value {
+            pub type T = super::super::background_position::computed_value::T;
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            super::background_position::computed_value::T {
+                horizontal: computed::LP_Percentage(0.5),
+                vertical: computed::LP_Percentage(0.5),
+            }
+        }
+    </%self:longhand>
+
+    // CSS Transitions, Section 2 - Transitions
+
+    <%self:longhand name="transition-property">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = computed_value::T;
+        pub mod computed_value {
+            /// The lowercase names of the properties, or `none` or `all`.
+            pub type T = ~[~str];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                self.connect(", ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[~"all"] }
+        /// none | [ all | <IDENT> ]#
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            match one_component_value(input).and_then(get_ident_lower) {
+                Some(ref keyword) if keyword.as_slice() == "none" => return Some(~[~"none"]),
+                _ => {}
+            }
+            do parse_comma_separated(input) |item| {
+                one_component_value(item).and_then(get_ident_lower)
+                                         .filtered(|name| name.as_slice() != "none")
+            }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="transition-duration">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = computed_value::T;
+        pub mod computed_value {
+            use super::super::CSSFloat;
+            /// In seconds.
+            pub type T = ~[CSSFloat];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                let times: ~[~str] = self.iter().map(|time| format!("{}s", *time)).collect();
+                times.connect(", ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[0.] }
+        /// <time>#, which must not be negative
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            do parse_comma_separated(input) |item| {
+                one_component_value(item).and_then(specified::parse_time)
+                                         .filtered(|time| *time >= 0.)
+            }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="transition-timing-function">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = computed_value::T;
+        pub mod computed_value {
+            use super::super::TimingFunction;
+            pub type T = ~[TimingFunction];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                let functions: ~[~str] = do self.iter().map |function| {
+                    function.computed_value_to_css(style)
+                }.collect();
+                functions.connect(", ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[TimingFunction::ease()] }
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            do parse_comma_separated(input) |item| {
+                one_component_value(item).and_then(specified::parse_timing_function)
+            }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="transition-delay">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = super::transition_duration::SpecifiedValue;
+        pub mod computed_value {
+            pub type T = super::super::transition_duration::computed_value::T;
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[0.] }
+        /// <time>#
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            do parse_comma_separated(input) |item| {
+                one_component_value(item).and_then(specified::parse_time)
+            }
+        }
+    </%self:longhand>
+
+    // CSS Animations, Section 4 - Animations
+
+    <%self:longhand name="animation-name">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = super::transition_property::SpecifiedValue;
+        pub mod computed_value {
+            /// The names of the `@keyframes` rules, or `none`.
+            pub type T = super::super::transition_property::computed_value::T;
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[~"none"] }
+        /// [ none | <IDENT> ]#
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            do parse_comma_separated(input) |item| {
+                match one_component_value(item) {
+                    Some(&Ident(ref name)) => Some(name.to_owned()),
+                    _ => None,
+                }
+            }
+        }
+    </%self:longhand>
+    % for property in ["duration", "timing-function", "delay"]:
+        <%self:longhand name="animation-${property}", no_super="True">
+            pub use super::transition_${to_rust_ident(property)}::*;
+            pub type SpecifiedValue = super::transition_${to_rust_ident(property)}::SpecifiedValue;
+            pub mod computed_value {
+                pub type T = super::super::transition_${to_rust_ident(property)}::computed_value::T;
+            }
+        </%self:longhand>
+    % endfor
+
+    <%self:longhand name="animation-iteration-count">
+        pub use to_computed_value = super::computed_as_specified;
+        pub type SpecifiedValue = computed_value::T;
+        pub mod computed_value {
+            use super::super::CSSFloat;
+            #[deriving(Eq, Clone)]
+            pub enum IterationCount {
+                Count(CSSFloat),
+                Infinite,
+            }
+            pub type T = ~[IterationCount];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                let counts: ~[~str] = do self.iter().map |count| {
+                    match *count {
+                        Count(count) => format!("{}", count),
+                        Infinite => ~"infinite",
+                    }
+                }.collect();
+                counts.connect(", ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[Count(1.)] }
+        pub fn parse_one(input: &ComponentValue) -> Option<IterationCount> {
+            match input {
+                &Number(ref value) if value.value >= 0. => Some(Count(value.value)),
+                &Ident(ref value) if value.eq_ignore_ascii_case("infinite") => Some(Infinite),
+                _ => None,
+            }
+        }
+        /// [ infinite | <number> ]#
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            do parse_comma_separated(input) |item| {
+                one_component_value(item).and_then(parse_one)
+            }
+        }
+    </%self:longhand>
+
+    ${keyword_list("animation-direction", "normal reverse alternate alternate-reverse")}
+    ${keyword_list("animation-fill-mode", "none forwards backwards both")}
+    ${keyword_list("animation-play-state", "running paused")}
+
     // CSS 2.1, Section 10 - Visual formatting model details
 
     ${predefined_type("width", "LengthOrPercentageOrAuto",
@@ -319,6 +845,15 @@ pub mod longhands {
                 Number(CSSFloat),
             }
         }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                match *self {
+                    Normal => ~"normal",
+                    Length(length) => length.computed_value_to_css(style),
+                    Number(number) => format!("{}", number),
+                }
+            }
+        }
         #[inline] pub fn get_initial_value() -> computed_value::T { Normal }
         pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
                               -> computed_value::T {
@@ -360,6 +895,7 @@ pub mod longhands {
         }
         pub mod computed_value {
             use super::super::{Au, CSSFloat};
+            use super::super::computed::CalcLengthOrPercentage;
             #[deriving(Eq, Clone)]
             pub enum T {
                 % for keyword in vertical_align_keywords:
@@ -367,6 +903,19 @@ pub mod longhands {
                 % endfor
                 Length(Au),
                 Percentage(CSSFloat),
+                Calc(CalcLengthOrPercentage),
+            }
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                match *self {
+                    % for keyword in vertical_align_keywords:
+                        ${to_rust_ident(keyword)} => ~"${keyword}",
+                    % endfor
+                    Length(length) => length.computed_value_to_css(style),
+                    Percentage(percentage) => serialize_percentage(percentage),
+                    Calc(calc) => calc.computed_value_to_css(style),
+                }
             }
         }
         #[inline] pub fn get_initial_value() -> computed_value::T { baseline }
@@ -379,7 +928,8 @@ pub mod longhands {
                 SpecifiedLengthOrPercentage(value)
                 => match computed::compute_LengthOrPercentage(value, context) {
                     computed::LP_Length(value) => Length(value),
-                    computed::LP_Percentage(value) => Percentage(value)
+                    computed::LP_Percentage(value) => Percentage(value),
+                    computed::LP_Calc(value) => Calc(value),
                 }
             }
         }
@@ -394,12 +944,22 @@ pub mod longhands {
 
     // CSS 2.1, Section 12 - Generated content, automatic numbering, and lists
 
-    <%self:longhand name="content" inherited="False">
+    <%self:raw_longhand name="content" inherited="False">
+            use servo_util::url::make_url;
             pub use to_computed_value = super::computed_as_specified;
             pub mod computed_value {
+                use extra::url::Url;
+                use super::super::list_style_type::computed_value as list_style_type;
+
                 #[deriving(Eq, Clone)]
                 pub enum Content {
                     StringContent(~str),
+                    AttrContent(~str),
+                    UrlContent(Url),
+                    /// `counter(name, style)`
+                    CounterContent(~str, list_style_type::T),
+                    /// `counters(name, separator, style)`
+                    CountersContent(~str, ~str, list_style_type::T),
                 }
                 #[deriving(Eq, Clone)]
                 pub enum T {
@@ -408,12 +968,44 @@ pub mod longhands {
                     Content(~[Content]),
                 }
             }
+            impl ComputedValueToCss for computed_value::T {
+                fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                    match *self {
+                        normal => ~"normal",
+                        none => ~"none",
+                        Content(ref content) => {
+                            let items = do content.map |item| {
+                                match *item {
+                                    StringContent(ref value) => {
+                                        serialize_string(value.as_slice())
+                                    }
+                                    AttrContent(ref name) => format!("attr({:s})", *name),
+                                    UrlContent(ref url) => {
+                                        let url = url.to_str();
+                                        format!("url({:s})", serialize_string(url))
+                                    }
+                                    CounterContent(ref name, list_style) => {
+                                        format!("counter({:s}, {:s})", *name,
+                                                list_style.computed_value_to_css(style))
+                                    }
+                                    CountersContent(ref name, ref separator, list_style) => {
+                                        format!("counters({:s}, {:s}, {:s})", *name,
+                                                serialize_string(separator.as_slice()),
+                                                list_style.computed_value_to_css(style))
+                                    }
+                                }
+                            };
+                            items.connect(" ")
+                        }
+                    }
+                }
+            }
             pub type SpecifiedValue = computed_value::T;
             #[inline] pub fn get_initial_value() -> computed_value::T  { normal }
 
-            // normal | none | [ <string> ]+
-            // TODO: <uri>, <counter>, attr(<identifier>), open-quote, close-quote, no-open-quote, no-close-quote
-            pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            // normal | none | [ <string> | <uri> | <counter> | attr(<identifier>) ]+
+            // TODO: open-quote, close-quote, no-open-quote, no-close-quote
+            pub fn parse(input: &[ComponentValue], base_url: &Url) -> Option<SpecifiedValue> {
                 match one_component_value(input) {
                     Some(&Ident(ref keyword)) => match keyword.to_ascii_lower().as_slice() {
                         "normal" => return Some(normal),
@@ -427,13 +1019,189 @@ pub mod longhands {
                     match component_value {
                         &String(ref value)
                         => content.push(StringContent(value.to_owned())),
+                        &URL(ref value)
+                        => content.push(UrlContent(make_url(value.to_owned(),
+                                                            Some(base_url.clone())))),
+                        &Function(ref name, ref arguments)
+                        if name.eq_ignore_ascii_case("attr") => {
+                            match one_component_value(arguments.as_slice()) {
+                                Some(&Ident(ref attribute))
+                                => content.push(AttrContent(attribute.to_owned())),
+                                _ => return None
+                            }
+                        }
+                        &Function(ref name, ref arguments)
+                        if name.eq_ignore_ascii_case("counter") => {
+                            let arguments = split_on_commas(arguments.as_slice());
+                            match parse_counter(arguments.as_slice(), false) {
+                                Some(item) => content.push(item),
+                                None => return None
+                            }
+                        }
+                        &Function(ref name, ref arguments)
+                        if name.eq_ignore_ascii_case("counters") => {
+                            let arguments = split_on_commas(arguments.as_slice());
+                            match parse_counter(arguments.as_slice(), true) {
+                                Some(item) => content.push(item),
+                                None => return None
+                            }
+                        }
                         _ => return None  // invalid/unsupported value
                     }
                 }
                 Some(Content(content))
             }
-    </%self:longhand>
-    // CSS 2.1, Section 13 - Paged media
+            // counter(<identifier>, <list-style-type>?)
+            // counters(<identifier>, <string>, <list-style-type>?)
+            fn parse_counter(arguments: &[&[ComponentValue]], nested: bool) -> Option<Content> {
+                let style_index = if nested { 2 } else { 1 };
+                if arguments.len() < style_index || arguments.len() > style_index + 1 {
+                    return None
+                }
+                let name = match one_component_value(arguments[0]) {
+                    Some(&Ident(ref name)) if !name.eq_ignore_ascii_case("none") => name.to_owned(),
+                    _ => return None
+                };
+                let style = if arguments.len() > style_index {
+                    match one_component_value(arguments[style_index])
+                            .and_then(list_style_type::from_component_value) {
+                        Some(style) => style,
+                        None => return None
+                    }
+                } else {
+                    list_style_type::decimal
+                };
+                if nested {
+                    match one_component_value(arguments[1]) {
+                        Some(&String(ref separator))
+                        => Some(CountersContent(name, separator.to_owned(), style)),
+                        _ => None
+                    }
+                } else {
+                    Some(CounterContent(name, style))
+                }
+            }
+            pub fn parse_specified(input: &[ComponentValue], base_url: &Url)
+                               -> Option<DeclaredValue<SpecifiedValue>> {
+                parse(input, base_url).map(super::SpecifiedValue)
+            }
+    </%self:raw_longhand>
+
+    <%self:longhand name="counter-reset" inherited="False">
+        pub use to_computed_value = super::computed_as_specified;
+        pub mod computed_value {
+            /// The counter names with their values, in declaration order.
+            pub type T = ~[(~str, int)];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                if self.is_empty() {
+                    return ~"none"
+                }
+                let items = do self.map |&(ref name, value)| {
+                    format!("{:s} {:d}", *name, value)
+                };
+                items.connect(" ")
+            }
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[] }
+
+        /// none | [ <identifier> <integer>? ]+
+        ///
+        /// Counters without an explicit value get `default_value`.
+        pub fn parse_counters(input: &[ComponentValue], default_value: int)
+                              -> Option<SpecifiedValue> {
+            match one_component_value(input) {
+                Some(&Ident(ref keyword)) if keyword.eq_ignore_ascii_case("none")
+                => return Some(~[]),
+                _ => ()
+            }
+            let mut counters = ~[];
+            let mut iter = input.skip_whitespace();
+            loop {
+                let name = match iter.next() {
+                    Some(&Ident(ref name)) => name.to_owned(),
+                    Some(_) => return None,
+                    None => break,
+                };
+                if name.eq_ignore_ascii_case("none") || name.eq_ignore_ascii_case("inherit") ||
+                        name.eq_ignore_ascii_case("initial") {
+                    return None
+                }
+                let mut copied_iter = iter.clone();
+                let value = match copied_iter.next() {
+                    Some(&Number(ref value)) => match value.int_value {
+                        Some(integer) => { iter = copied_iter; integer as int }
+                        None => return None,
+                    },
+                    _ => default_value,
+                };
+                counters.push((name, value))
+            }
+            if counters.is_empty() { None } else { Some(counters) }
+        }
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            parse_counters(input, 0)
+        }
+    </%self:longhand>
+
+    <%self:longhand name="counter-increment" inherited="False">
+        pub type SpecifiedValue = super::counter_reset::SpecifiedValue;
+        pub mod computed_value {
+            pub type T = super::super::counter_reset::computed_value::T;
+        }
+        pub use to_computed_value = super::computed_as_specified;
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[] }
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            super::counter_reset::parse_counters(input, 1)
+        }
+    </%self:longhand>
+
+    ${new_style_struct("List")}
+
+    ${single_keyword("list-style-type", "disc circle square decimal lower-roman upper-roman "
+                                        "lower-alpha upper-alpha lower-latin upper-latin "
+                                        "lower-greek none", inherited=True)}
+
+    ${single_keyword("list-style-position", "outside inside", inherited=True)}
+
+    <%self:raw_longhand name="list-style-image" inherited="True">
+        use servo_util::url::make_url;
+        pub use to_computed_value = super::computed_as_specified;
+        pub mod computed_value {
+            use extra::url::Url;
+            pub type T = Option<Url>;
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                match *self {
+                    Some(ref url) => format!("url({:s})", serialize_string(url.to_str().as_slice())),
+                    None => ~"none",
+                }
+            }
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T { None }
+        /// none | <uri>
+        pub fn from_component_value(input: &ComponentValue, base_url: &Url)
+                                    -> Option<SpecifiedValue> {
+            match input {
+                &Ident(ref value) if value.eq_ignore_ascii_case("none") => Some(None),
+                &URL(ref value) => Some(Some(make_url(value.to_owned(), Some(base_url.clone())))),
+                _ => None,
+            }
+        }
+        pub fn parse_specified(input: &[ComponentValue], base_url: &Url)
+                               -> Option<DeclaredValue<SpecifiedValue>> {
+            match one_component_value(input) {
+                Some(value) => from_component_value(value, base_url).map(super::SpecifiedValue),
+                None => None,
+            }
+        }
+    </%self:raw_longhand>
+
+    // CSS 2.1, Section 13 - Paged media
 
     // CSS 2.1, Section 14 - Colors and Backgrounds
 
@@ -442,6 +1210,375 @@ pub mod longhands {
     ${predefined_type("background-color", "CSSColor",
                       "RGBA(RGBA { red: 0., green: 0., blue: 0., alpha: 0. }) /* transparent */")}
 
+    <%self:raw_longhand name="background-image">
+        pub mod computed_value {
+            use super::super::computed;
+            pub type T = Option<computed::Image>;
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                match *self {
+                    Some(ref image) => image.computed_value_to_css(style),
+                    None => ~"none",
+                }
+            }
+        }
+        pub type SpecifiedValue = Option<specified::Image>;
+        #[inline] pub fn get_initial_value() -> computed_value::T { None }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            value.map(|image| computed::compute_Image(image, context))
+        }
+        /// none | <image>
+        pub fn from_component_value(input: &ComponentValue, base_url: &Url)
+                                    -> Option<SpecifiedValue> {
+            match input {
+                &Ident(ref value) if value.eq_ignore_ascii_case("none") => Some(None),
+                _ => specified::Image::parse(input, base_url).map(Some),
+            }
+        }
+        pub fn parse_specified(input: &[ComponentValue], base_url: &Url)
+                               -> Option<DeclaredValue<SpecifiedValue>> {
+            match one_component_value(input) {
+                Some(value) => from_component_value(value, base_url).map(super::SpecifiedValue),
+                None => None,
+            }
+        }
+    </%self:raw_longhand>
+
+    ${single_keyword("background-repeat", "repeat repeat-x repeat-y no-repeat")}
+
+    ${single_keyword("background-attachment", "scroll fixed")}
+
+    <%self:longhand name="background-position">
+        #[deriving(Clone)]
+        pub struct SpecifiedValue {
+            horizontal: specified::LengthOrPercentage,
+            vertical: specified::LengthOrPercentage,
+        }
+        pub mod computed_value {
+            use super::super::computed::LengthOrPercentage;
+            #[deriving(Eq, Clone)]
+            pub struct T {
+                horizontal: LengthOrPercentage,
+                vertical: LengthOrPercentage,
+            }
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                format!("{:s} {:s}", self.horizontal.computed_value_to_css(style),
+                        self.vertical.computed_value_to_css(style))
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::LP_Percentage(0.),
+                vertical: computed::LP_Percentage(0.),
+            }
+        }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            computed_value::T {
+                horizontal: computed::compute_LengthOrPercentage(value.horizontal, context),
+                vertical: computed::compute_LengthOrPercentage(value.vertical, context),
+            }
+        }
+
+        /// One of the values of `background-position`.
+        #[deriving(Clone)]
+        pub enum PositionComponent {
+            PositionLength(specified::LengthOrPercentage),
+            PositionCenter,
+            PositionLeft,
+            PositionRight,
+            PositionTop,
+            PositionBottom,
+        }
+        pub fn parse_component(input: &ComponentValue) -> Option<PositionComponent> {
+            match get_ident_lower(input) {
+                Some(keyword) => match keyword.as_slice() {
+                    "center" => Some(PositionCenter),
+                    "left" => Some(PositionLeft),
+                    "right" => Some(PositionRight),
+                    "top" => Some(PositionTop),
+                    "bottom" => Some(PositionBottom),
+                    _ => None,
+                },
+                None => specified::LengthOrPercentage::parse(input).map(PositionLength),
+            }
+        }
+        fn to_length(component: PositionComponent) -> specified::LengthOrPercentage {
+            match component {
+                PositionLength(value) => value,
+                PositionLeft | PositionTop => specified::LP_Percentage(0.),
+                PositionCenter => specified::LP_Percentage(0.5),
+                PositionRight | PositionBottom => specified::LP_Percentage(1.),
+            }
+        }
+        /// Makes a value from one or two components, or returns `None` if they are inconsistent.
+        pub fn from_components(first: PositionComponent, second: Option<PositionComponent>)
+                               -> Option<SpecifiedValue> {
+            // A single vertical keyword, or keywords given vertical first.
+            let vertical_first = match (&first, &second) {
+                (&PositionTop, &None) | (&PositionBottom, &None) => true,
+                (_, &None) => false,
+                (&PositionTop, &Some(PositionLeft)) | (&PositionTop, &Some(PositionRight)) |
+                (&PositionTop, &Some(PositionCenter)) | (&PositionBottom, &Some(PositionLeft)) |
+                (&PositionBottom, &Some(PositionRight)) | (&PositionBottom, &Some(PositionCenter)) |
+                (&PositionCenter, &Some(PositionLeft)) | (&PositionCenter, &Some(PositionRight))
+                => true,
+                _ => false,
+            };
+            let second = second.unwrap_or(PositionCenter);
+            let (horizontal, vertical) = if vertical_first {
+                (second, first)
+            } else {
+                (first, second)
+            };
+            match (&horizontal, &vertical) {
+                (&PositionTop, _) | (&PositionBottom, _) | (_, &PositionLeft) |
+                (_, &PositionRight) => return None,
+                _ => (),
+            }
+            Some(SpecifiedValue {
+                horizontal: to_length(horizontal),
+                vertical: to_length(vertical),
+            })
+        }
+        /// [ <percentage> | <length> | left | center | right ]
+        /// [ <percentage> | <length> | top | center | bottom ]? | [ center | top | bottom ] ||
+        /// [ center | left | right ]
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            let mut iter = input.skip_whitespace().map(parse_component);
+            let first = iter.next().unwrap_or(None);
+            let second = iter.next();
+            if first.is_none() || iter.next().is_some() {
+                return None
+            }
+            match second {
+                None => from_components(first.unwrap(), None),
+                Some(None) => None,
+                Some(second) => from_components(first.unwrap(), second),
+            }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="background-size">
+        #[deriving(Clone)]
+        pub enum SpecifiedValue {
+            SpecifiedCover,
+            SpecifiedContain,
+            SpecifiedExplicitSize(specified::LengthOrPercentageOrAuto,
+                                  specified::LengthOrPercentageOrAuto),
+        }
+        pub mod computed_value {
+            use super::super::computed::LengthOrPercentageOrAuto;
+            #[deriving(Eq, Clone)]
+            pub enum T {
+                Cover,
+                Contain,
+                /// The width and the height.
+                ExplicitSize(LengthOrPercentageOrAuto, LengthOrPercentageOrAuto),
+            }
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                match *self {
+                    Cover => ~"cover",
+                    Contain => ~"contain",
+                    ExplicitSize(ref width, ref height) => {
+                        format!("{:s} {:s}", width.computed_value_to_css(style),
+                                height.computed_value_to_css(style))
+                    }
+                }
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            ExplicitSize(computed::LPA_Auto, computed::LPA_Auto)
+        }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            match value {
+                SpecifiedCover => Cover,
+                SpecifiedContain => Contain,
+                SpecifiedExplicitSize(width, height) => ExplicitSize(
+                    computed::compute_LengthOrPercentageOrAuto(width, context),
+                    computed::compute_LengthOrPercentageOrAuto(height, context)),
+            }
+        }
+        /// cover | contain | [ <length> | <percentage> | auto ]{1,2}
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            let mut iter = input.skip_whitespace();
+            let first = match iter.next() {
+                Some(value) => value,
+                None => return None,
+            };
+            match get_ident_lower(first) {
+                Some(ref keyword) if keyword.as_slice() == "cover" => {
+                    return if iter.next().is_none() { Some(SpecifiedCover) } else { None }
+                }
+                Some(ref keyword) if keyword.as_slice() == "contain" => {
+                    return if iter.next().is_none() { Some(SpecifiedContain) } else { None }
+                }
+                _ => (),
+            }
+            let width = specified::LengthOrPercentageOrAuto::parse_non_negative(first);
+            // A single value sets the width, and the height is `auto`.
+            let height = match iter.next() {
+                Some(value) => specified::LengthOrPercentageOrAuto::parse_non_negative(value),
+                None => Some(specified::LPA_Auto),
+            };
+            if iter.next().is_some() {
+                return None
+            }
+            match (width, height) {
+                (Some(width), Some(height)) => Some(SpecifiedExplicitSize(width, height)),
+                _ => None,
+            }
+        }
+    </%self:longhand>
+
+
+    /// A shadow of `box-shadow` or `text-shadow`. Text shadows have no spread radius and are not
+    /// inset.
+    #[deriving(Clone)]
+    pub struct SpecifiedShadow {
+        offset_x: specified::Length,
+        offset_y: specified::Length,
+        blur_radius: specified::Length,
+        spread_radius: specified::Length,
+        /// `None` for the value of the `color` property.
+        color: Option<specified::CSSColor>,
+        inset: bool,
+    }
+
+    /// none | <shadow> [ , <shadow> ]*
+    pub fn parse_shadows(input: &[ComponentValue], box_shadow: bool)
+                         -> Option<~[SpecifiedShadow]> {
+        match one_component_value(input) {
+            Some(&Ident(ref value)) if value.eq_ignore_ascii_case("none") => return Some(~[]),
+            _ => (),
+        }
+        let mut shadows = ~[];
+        for group in split_on_commas(input).iter() {
+            match parse_shadow(*group, box_shadow) {
+                Some(shadow) => shadows.push(shadow),
+                None => return None,
+            }
+        }
+        Some(shadows)
+    }
+
+    /// inset? && <length>{2,4} && <color>? for box shadows, or <length>{2,3} && <color>? for
+    /// text shadows. The blur radius, third, cannot be negative.
+    fn parse_shadow(input: &[ComponentValue], box_shadow: bool) -> Option<SpecifiedShadow> {
+        let mut lengths = ~[];
+        let mut lengths_closed = false;
+        let mut color = None;
+        let mut inset = false;
+        for component_value in input.skip_whitespace() {
+            if !lengths_closed {
+                let length = if lengths.len() == 2 {
+                    specified::Length::parse_non_negative(component_value)
+                } else {
+                    specified::Length::parse(component_value)
+                };
+                match length {
+                    Some(length) => { lengths.push(length); continue },
+                    // The lengths must be consecutive.
+                    None => lengths_closed = !lengths.is_empty(),
+                }
+            }
+            match component_value {
+                &Ident(ref value) if box_shadow && !inset && value.eq_ignore_ascii_case("inset")
+                => { inset = true; continue },
+                _ => (),
+            }
+            if color.is_none() {
+                match specified::CSSColor::parse(component_value) {
+                    Some(c) => { color = Some(c); continue },
+                    None => (),
+                }
+            }
+            return None
+        }
+        let max_lengths = if box_shadow { 4 } else { 3 };
+        if lengths.len() < 2 || lengths.len() > max_lengths {
+            return None
+        }
+        let zero = specified::Au_(Au(0));
+        Some(SpecifiedShadow {
+            offset_x: lengths[0].clone(),
+            offset_y: lengths[1].clone(),
+            blur_radius: if lengths.len() > 2 { lengths[2].clone() } else { zero.clone() },
+            spread_radius: if lengths.len() > 3 { lengths[3].clone() } else { zero },
+            color: color,
+            inset: inset,
+        })
+    }
+
+    /// Serializes the parts of a computed shadow.
+    pub fn serialize_shadow(color: computed::CSSColor, lengths: &[Au], inset: bool,
+                            style: &ComputedValues) -> ~str {
+        let mut parts = ~[color.computed_value_to_css(style)];
+        for length in lengths.iter() {
+            parts.push(length.computed_value_to_css(style))
+        }
+        if inset {
+            parts.push(~"inset")
+        }
+        parts.connect(" ")
+    }
+
+    // CSS Backgrounds and Borders 3, Section 7 - Drop shadows
+
+    <%self:longhand name="box-shadow">
+        pub type SpecifiedValue = ~[SpecifiedShadow];
+        pub mod computed_value {
+            use super::super::{Au, computed};
+            #[deriving(Eq, Clone)]
+            pub struct BoxShadow {
+                offset_x: Au,
+                offset_y: Au,
+                blur_radius: Au,
+                spread_radius: Au,
+                color: computed::CSSColor,
+                inset: bool,
+            }
+            pub type T = ~[BoxShadow];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                if self.is_empty() {
+                    return ~"none"
+                }
+                let shadows: ~[~str] = do self.iter().map |shadow| {
+                    serialize_shadow(shadow.color, [shadow.offset_x, shadow.offset_y,
+                                                    shadow.blur_radius, shadow.spread_radius],
+                                     shadow.inset, style)
+                }.collect();
+                shadows.connect(", ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[] }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            do value.move_iter().map |shadow| {
+                BoxShadow {
+                    offset_x: computed::compute_Au(shadow.offset_x, context),
+                    offset_y: computed::compute_Au(shadow.offset_y, context),
+                    blur_radius: computed::compute_Au(shadow.blur_radius, context),
+                    spread_radius: computed::compute_Au(shadow.spread_radius, context),
+                    color: shadow.color.unwrap_or(CurrentColor),
+                    inset: shadow.inset,
+                }
+            }.collect()
+        }
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            parse_shadows(input, /* box_shadow = */ true)
+        }
+    </%self:longhand>
+
 
     ${new_style_struct("Color")}
 
@@ -454,7 +1591,8 @@ pub mod longhands {
         #[inline] pub fn get_initial_value() -> computed_value::T {
             RGBA { red: 0., green: 0., blue: 0., alpha: 1. }  /* black */
         }
-        pub fn parse_specified(input: &[ComponentValue]) -> Option<DeclaredValue<SpecifiedValue>> {
+        pub fn parse_specified(input: &[ComponentValue], _base_url: &Url)
+                               -> Option<DeclaredValue<SpecifiedValue>> {
             match one_component_value(input).and_then(Color::parse) {
                 Some(RGBA(rgba)) => Some(SpecifiedValue(rgba)),
                 Some(CurrentColor) => Some(CSSWideKeyword(Inherit)),
@@ -482,6 +1620,16 @@ pub mod longhands {
             }
             pub type T = ~[FontFamily];
         }
+        impl ComputedValueToCss for ~[FontFamily] {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                let names = do self.map |family| {
+                    match *family {
+                        FamilyName(ref name) => name.clone(),
+                    }
+                };
+                names.connect(", ")
+            }
+        }
         pub type SpecifiedValue = computed_value::T;
         #[inline] pub fn get_initial_value() -> computed_value::T { ~[FamilyName(~"serif")] }
         /// <familiy-name>#
@@ -546,7 +1694,7 @@ pub mod longhands {
 
 
     ${single_keyword("font-style", "normal italic oblique", inherited=True)}
-    ${single_keyword("font-variant", "normal", inherited=True)}  // Add small-caps when supported
+    ${single_keyword("font-variant", "normal small-caps", inherited=True)}
 
     <%self:single_component_value name="font-weight" inherited="True">
         #[deriving(Clone)]
@@ -602,6 +1750,15 @@ pub mod longhands {
                 }
             }
         }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                match *self {
+                    % for weight in range(100, 901, 100):
+                        Weight${weight} => ~"${weight}",
+                    % endfor
+                }
+            }
+        }
         #[inline] pub fn get_initial_value() -> computed_value::T { Weight400 }  // normal
         pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
                               -> computed_value::T {
@@ -635,6 +1792,10 @@ pub mod longhands {
         }
     </%self:single_component_value>
 
+    ${single_keyword("font-stretch", "normal ultra-condensed extra-condensed condensed "
+                                     "semi-condensed semi-expanded expanded extra-expanded "
+                                     "ultra-expanded", inherited=True)}
+
     <%self:single_component_value name="font-size" inherited="True">
         pub use to_computed_value = super::super::common_types::computed::compute_Au;
         pub type SpecifiedValue = specified::Length;  // Percentages are the same as em.
@@ -646,12 +1807,13 @@ pub mod longhands {
             Au::from_px(16)  // medium
         }
         /// <length> | <percentage>
-        /// TODO: support <absolute-size> and <relative-size>
+        /// TODO: support <absolute-size> and <relative-size>, and calc() with percentages.
         pub fn from_component_value(input: &ComponentValue) -> Option<SpecifiedValue> {
-            do specified::LengthOrPercentage::parse_non_negative(input).map |value| {
+            do specified::LengthOrPercentage::parse_non_negative(input).and_then |value| {
                 match value {
-                    specified::LP_Length(value) => value,
-                    specified::LP_Percentage(value) => specified::Em(value),
+                    specified::LP_Length(value) => Some(value),
+                    specified::LP_Percentage(value) => Some(specified::Em(value)),
+                    specified::LP_Calc(_) => None,
                 }
             }
         }
@@ -661,8 +1823,98 @@ pub mod longhands {
 
     ${new_style_struct("Text")}
 
-    // TODO: initial value should be 'start' (CSS Text Level 3, direction-dependent.)
-    ${single_keyword("text-align", "left right center justify", inherited=True)}
+    // CSS Text Level 3: 'start' and 'end' depend on the direction of the block.
+    ${single_keyword("text-align", "start end left right center justify", inherited=True)}
+
+    <%self:single_component_value name="letter-spacing" inherited="True">
+        #[deriving(Clone)]
+        pub enum SpecifiedValue {
+            SpecifiedNormal,
+            SpecifiedLength(specified::Length),
+        }
+        pub mod computed_value {
+            use super::super::Au;
+            /// `None` for `normal`.
+            pub type T = Option<Au>;
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                match *self {
+                    None => ~"normal",
+                    Some(length) => length.computed_value_to_css(style),
+                }
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { None }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            match value {
+                SpecifiedNormal => None,
+                SpecifiedLength(value) => Some(computed::compute_Au(value, context)),
+            }
+        }
+        /// normal | <length>
+        pub fn from_component_value(input: &ComponentValue) -> Option<SpecifiedValue> {
+            match input {
+                &Ident(ref value) if value.eq_ignore_ascii_case("normal")
+                => Some(SpecifiedNormal),
+                _ => specified::Length::parse(input).map(SpecifiedLength),
+            }
+        }
+    </%self:single_component_value>
+
+    <%self:longhand name="word-spacing" inherited="True" no_super="True">
+        pub use super::letter_spacing::*;
+        pub type SpecifiedValue = super::letter_spacing::SpecifiedValue;
+        pub mod computed_value {
+            pub type T = super::super::letter_spacing::computed_value::T;
+        }
+    </%self:longhand>
+
+    ${single_keyword("text-transform", "none capitalize uppercase lowercase", inherited=True)}
+
+    ${single_keyword("white-space", "normal pre nowrap pre-wrap pre-line", inherited=True)}
+
+    <%self:single_component_value name="tab-size" inherited="True">
+        #[deriving(Clone)]
+        pub enum SpecifiedValue {
+            SpecifiedLength(specified::Length),
+            SpecifiedNumber(CSSFloat),
+        }
+        /// <number> | <length>
+        pub fn from_component_value(input: &ComponentValue) -> Option<SpecifiedValue> {
+            match input {
+                &ast::Number(ref value) if value.value >= 0.
+                => Some(SpecifiedNumber(value.value)),
+                _ => specified::Length::parse_non_negative(input).map(SpecifiedLength),
+            }
+        }
+        pub mod computed_value {
+            use super::super::{Au, CSSFloat};
+            /// A number is a multiple of the advance of the space character.
+            #[deriving(Eq, Clone)]
+            pub enum T {
+                Length(Au),
+                Number(CSSFloat),
+            }
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                match *self {
+                    Length(length) => length.computed_value_to_css(style),
+                    Number(number) => format!("{}", number),
+                }
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { Number(8.) }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            match value {
+                SpecifiedLength(value) => Length(computed::compute_Au(value, context)),
+                SpecifiedNumber(value) => Number(value),
+            }
+        }
+    </%self:single_component_value>
 
     <%self:longhand name="text-decoration">
         pub use to_computed_value = super::computed_as_specified;
@@ -678,6 +1930,15 @@ pub mod longhands {
             pub type T = super::SpecifiedValue;
             pub static none: T = super::SpecifiedValue { underline: false, overline: false, line_through: false };
         }
+        impl ComputedValueToCss for SpecifiedValue {
+            fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+                let mut keywords = ~[];
+                if self.underline { keywords.push("underline") }
+                if self.overline { keywords.push("overline") }
+                if self.line_through { keywords.push("line-through") }
+                if keywords.is_empty() { ~"none" } else { keywords.connect(" ") }
+            }
+        }
         #[inline] pub fn get_initial_value() -> computed_value::T {
             none
         }
@@ -709,15 +1970,61 @@ pub mod longhands {
         }
     </%self:longhand>
 
-    // CSS 2.1, Section 17 - Tables
-
-    // CSS 2.1, Section 18 - User interface
-}
+    // CSS Text Decoration 3, Section 4 - Text shadows
+
+    <%self:longhand name="text-shadow" inherited="True">
+        pub type SpecifiedValue = ~[SpecifiedShadow];
+        pub mod computed_value {
+            use super::super::{Au, computed};
+            #[deriving(Eq, Clone)]
+            pub struct TextShadow {
+                offset_x: Au,
+                offset_y: Au,
+                blur_radius: Au,
+                color: computed::CSSColor,
+            }
+            pub type T = ~[TextShadow];
+        }
+        impl ComputedValueToCss for computed_value::T {
+            fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+                if self.is_empty() {
+                    return ~"none"
+                }
+                let shadows: ~[~str] = do self.iter().map |shadow| {
+                    serialize_shadow(shadow.color,
+                                     [shadow.offset_x, shadow.offset_y, shadow.blur_radius],
+                                     false, style)
+                }.collect();
+                shadows.connect(", ")
+            }
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T { ~[] }
+        pub fn to_computed_value(value: SpecifiedValue, context: &computed::Context)
+                              -> computed_value::T {
+            do value.move_iter().map |shadow| {
+                TextShadow {
+                    offset_x: computed::compute_Au(shadow.offset_x, context),
+                    offset_y: computed::compute_Au(shadow.offset_y, context),
+                    blur_radius: computed::compute_Au(shadow.blur_radius, context),
+                    color: shadow.color.unwrap_or(CurrentColor),
+                }
+            }.collect()
+        }
+        pub fn parse(input: &[ComponentValue]) -> Option<SpecifiedValue> {
+            parse_shadows(input, /* box_shadow = */ false)
+        }
+    </%self:longhand>
+
+    // CSS 2.1, Section 17 - Tables
+
+    // CSS 2.1, Section 18 - User interface
+}
 
 
 pub mod shorthands {
     pub use super::*;
     pub use super::longhands::*;
+    use std::vec;
 
     <%def name="shorthand(name, sub_properties)">
     <%
@@ -731,7 +2038,8 @@ pub mod shorthands {
                     ${sub_property.ident}: Option<${sub_property.ident}::SpecifiedValue>,
                 % endfor
             }
-            pub fn parse(input: &[ComponentValue]) -> Option<Longhands> {
+            #[allow(unused_variable)]
+            pub fn parse(input: &[ComponentValue], base_url: &Url) -> Option<Longhands> {
                 ${caller.body()}
             }
         }
@@ -766,10 +2074,100 @@ pub mod shorthands {
 
 
     // TODO: other background-* properties
-    <%self:shorthand name="background" sub_properties="background-color">
-        do one_component_value(input).and_then(specified::CSSColor::parse).map |color| {
-            Longhands { background_color: Some(color) }
+    <%self:shorthand name="background" sub_properties="background-color background-image
+            background-repeat background-attachment background-position background-size">
+        let mut color = None;
+        let mut image = None;
+        let mut repeat = None;
+        let mut attachment = None;
+        let mut position_components = ~[];
+        let mut position_closed = false;
+        let mut size = None;
+        let mut any = false;
+        let mut iter = input.skip_whitespace();
+        loop {
+            let component_value = match iter.next() {
+                Some(component_value) => component_value,
+                None => break,
+            };
+            any = true;
+            // The components of the position must be consecutive.
+            if !position_closed && position_components.len() < 2 {
+                match background_position::parse_component(component_value) {
+                    Some(p) => { position_components.push(p); continue },
+                    None => ()
+                }
+            }
+            position_closed = !position_components.is_empty();
+            if color.is_none() {
+                match specified::CSSColor::parse(component_value) {
+                    Some(c) => { color = Some(c); continue },
+                    None => ()
+                }
+            }
+            if image.is_none() {
+                match background_image::from_component_value(component_value, base_url) {
+                    Some(i) => { image = Some(i); continue },
+                    None => ()
+                }
+            }
+            if repeat.is_none() {
+                match background_repeat::from_component_value(component_value) {
+                    Some(r) => { repeat = Some(r); continue },
+                    None => ()
+                }
+            }
+            if attachment.is_none() {
+                match background_attachment::from_component_value(component_value) {
+                    Some(a) => { attachment = Some(a); continue },
+                    None => ()
+                }
+            }
+            // The size may only follow the position, after a slash.
+            match component_value {
+                &Delim('/') if !position_components.is_empty() && size.is_none() => {
+                    let mut size_values = ~[];
+                    loop {
+                        let mut copied_iter = iter.clone();
+                        match copied_iter.next() {
+                            Some(value) if size_values.len() < 2 &&
+                                    background_size::parse([value.clone()]).is_some() => {
+                                size_values.push(value.clone());
+                                iter = copied_iter;
+                            }
+                            _ => break,
+                        }
+                    }
+                    size = background_size::parse(size_values);
+                    if size.is_none() { return None }
+                }
+                _ => return None,
+            }
         }
+        if !any {
+            return None
+        }
+        let position = match position_components.len() {
+            0 => None,
+            1 => match background_position::from_components(position_components[0].clone(),
+                                                            None) {
+                Some(position) => Some(position),
+                None => return None,
+            },
+            _ => match background_position::from_components(position_components[0].clone(),
+                                                            Some(position_components[1].clone())) {
+                Some(position) => Some(position),
+                None => return None,
+            },
+        };
+        Some(Longhands {
+            background_color: color,
+            background_image: image,
+            background_repeat: repeat,
+            background_attachment: attachment,
+            background_position: position,
+            background_size: size,
+        })
     </%self:shorthand>
 
     ${four_sides_shorthand("margin", "margin-%s", "margin_top::from_component_value")}
@@ -844,18 +2242,115 @@ pub mod shorthands {
         }
     </%self:shorthand>
 
+    <%self:shorthand name="border-radius" sub_properties="${' '.join(
+        'border-%s-radius' % corner
+        for corner in ['top-left', 'top-right', 'bottom-right', 'bottom-left']
+    )}">
+        // The horizontal radii, then the vertical radii after a slash.
+        let mut lists = ~[~[], ~[]];
+        let mut list = 0;
+        for component_value in input.skip_whitespace() {
+            match component_value {
+                &Delim('/') if list == 0 => list = 1,
+                _ => match specified::LengthOrPercentage::parse_non_negative(component_value) {
+                    Some(radius) if lists[list].len() < 4 => lists[list].push(radius),
+                    _ => return None,
+                },
+            }
+        }
+        if lists[0].is_empty() || (list == 1 && lists[1].is_empty()) {
+            return None
+        }
+        if list == 0 {
+            lists[1] = lists[0].clone()
+        }
+        // Missing values are copied from the opposite corner, as with the four sides of margin.
+        let corners: ~[~[specified::LengthOrPercentage]] = do lists.iter().map |radii| {
+            let top_left = radii[0].clone();
+            let top_right = if radii.len() > 1 { radii[1].clone() } else { top_left.clone() };
+            let bottom_right = if radii.len() > 2 { radii[2].clone() } else { top_left.clone() };
+            let bottom_left = if radii.len() > 3 { radii[3].clone() } else { top_right.clone() };
+            ~[top_left, top_right, bottom_right, bottom_left]
+        }.collect();
+        Some(Longhands {
+            % for i, corner in enumerate(["top_left", "top_right", "bottom_right", "bottom_left"]):
+                border_${corner}_radius: Some(border_top_left_radius::SpecifiedValue {
+                    horizontal: corners[0][${i}].clone(),
+                    vertical: corners[1][${i}].clone(),
+                }),
+            % endfor
+        })
+    </%self:shorthand>
+
+    <%self:shorthand name="list-style" sub_properties="list-style-type list-style-position
+                                                       list-style-image">
+        let mut nb_nones = 0u;
+        let mut style_type = None;
+        let mut position = None;
+        let mut image = None;
+        let mut any = false;
+        for component_value in input.skip_whitespace() {
+            any = true;
+            // Special-case 'none' because it is valid in both list-style-type and
+            // list-style-image. The values are assigned once all components are seen.
+            if get_ident_lower(component_value).filtered(
+                    |v| v.eq_ignore_ascii_case("none")).is_some() {
+                nb_nones += 1;
+                continue;
+            }
+            if style_type.is_none() {
+                match list_style_type::from_component_value(component_value) {
+                    Some(t) => { style_type = Some(t); continue },
+                    None => ()
+                }
+            }
+            if position.is_none() {
+                match list_style_position::from_component_value(component_value) {
+                    Some(p) => { position = Some(p); continue },
+                    None => ()
+                }
+            }
+            if image.is_none() {
+                match list_style_image::from_component_value(component_value, base_url) {
+                    Some(i) => { image = Some(i); continue },
+                    None => ()
+                }
+            }
+            return None
+        }
+        if !any {
+            return None
+        }
+        match nb_nones {
+            0 => (),
+            1 if style_type.is_none() => style_type = Some(list_style_type::none),
+            1 if image.is_none() => image = Some(None),
+            2 if style_type.is_none() && image.is_none() => {
+                style_type = Some(list_style_type::none);
+                image = Some(None);
+            }
+            _ => return None,
+        }
+        Some(Longhands {
+            list_style_type: style_type,
+            list_style_position: position,
+            list_style_image: image,
+        })
+    </%self:shorthand>
+
     <%self:shorthand name="font" sub_properties="font-style font-variant font-weight
-                                                 font-size line-height font-family">
+                                                 font-stretch font-size line-height font-family">
         let mut iter = input.skip_whitespace();
         let mut nb_normals = 0u;
         let mut style = None;
         let mut variant = None;
         let mut weight = None;
+        let mut stretch = None;
         let mut size = None;
         let mut line_height = None;
         for component_value in iter {
             // Special-case 'normal' because it is valid in each of
-            // font-style, font-weight and font-variant.
+            // font-style, font-weight, font-variant and font-stretch.
             // Leaves the values to None, 'normal' is the initial value for each of them.
             if get_ident_lower(component_value).filtered(
                     |v| v.eq_ignore_ascii_case("normal")).is_some() {
@@ -880,6 +2375,12 @@ pub mod shorthands {
                     None => ()
                 }
             }
+            if stretch.is_none() {
+                match font_stretch::from_component_value(component_value) {
+                    Some(s) => { stretch = Some(s); continue },
+                    None => ()
+                }
+            }
             match font_size::from_component_value(component_value) {
                 Some(s) => { size = Some(s); break },
                 None => return None
@@ -892,7 +2393,8 @@ pub mod shorthands {
                 &None => 0,
             }
         }
-        if size.is_none() || (count(&style) + count(&weight) + count(&variant) + nb_normals) > 3 {
+        if size.is_none() || (count(&style) + count(&weight) + count(&variant) + count(&stretch) +
+                              nb_normals) > 4 {
             return None
         }
         let mut copied_iter = iter.clone();
@@ -913,42 +2415,589 @@ pub mod shorthands {
             font_style: style,
             font_variant: variant,
             font_weight: weight,
+            font_stretch: stretch,
             font_size: size,
             line_height: line_height,
             font_family: family
         })
     </%self:shorthand>
 
+    <%self:shorthand name="transition" sub_properties="transition-property transition-duration
+                                                       transition-timing-function transition-delay">
+        let mut properties = ~[];
+        let mut durations = ~[];
+        let mut timing_functions = ~[];
+        let mut delays = ~[];
+        for item in split_on_commas(input).move_iter() {
+            let mut property = None;
+            let mut duration = None;
+            let mut timing_function = None;
+            let mut delay = None;
+            for component_value in item.skip_whitespace() {
+                // The first time is the duration, the second one is the delay.
+                match specified::parse_time(component_value) {
+                    Some(time) => {
+                        if duration.is_none() && time >= 0. { duration = Some(time); continue }
+                        if delay.is_none() { delay = Some(time); continue }
+                        return None
+                    }
+                    None => ()
+                }
+                if timing_function.is_none() {
+                    match specified::parse_timing_function(component_value) {
+                        Some(function) => { timing_function = Some(function); continue }
+                        None => ()
+                    }
+                }
+                if property.is_none() {
+                    match get_ident_lower(component_value) {
+                        Some(name) => { property = Some(name); continue }
+                        None => ()
+                    }
+                }
+                return None
+            }
+            properties.push(property.unwrap_or(~"all"));
+            durations.push(duration.unwrap_or(0.));
+            timing_functions.push(timing_function.unwrap_or(TimingFunction::ease()));
+            delays.push(delay.unwrap_or(0.));
+        }
+        // `none` is only valid as the single item.
+        if properties.len() > 1 && properties.iter().any(|name| name.as_slice() == "none") {
+            return None
+        }
+        Some(Longhands {
+            transition_property: Some(properties),
+            transition_duration: Some(durations),
+            transition_timing_function: Some(timing_functions),
+            transition_delay: Some(delays),
+        })
+    </%self:shorthand>
+
+    <%self:shorthand name="animation" sub_properties="animation-name animation-duration
+                                                      animation-timing-function animation-delay
+                                                      animation-iteration-count
+                                                      animation-direction animation-fill-mode
+                                                      animation-play-state">
+        let mut names = ~[];
+        let mut durations = ~[];
+        let mut timing_functions = ~[];
+        let mut delays = ~[];
+        let mut iteration_counts = ~[];
+        let mut directions = ~[];
+        let mut fill_modes = ~[];
+        let mut play_states = ~[];
+        for item in split_on_commas(input).move_iter() {
+            let mut name = None;
+            let mut duration = None;
+            let mut timing_function = None;
+            let mut delay = None;
+            let mut iteration_count = None;
+            let mut direction = None;
+            let mut fill_mode = None;
+            let mut play_state = None;
+            for component_value in item.skip_whitespace() {
+                // The first time is the duration, the second one is the delay.
+                match specified::parse_time(component_value) {
+                    Some(time) => {
+                        if duration.is_none() && time >= 0. { duration = Some(time); continue }
+                        if delay.is_none() { delay = Some(time); continue }
+                        return None
+                    }
+                    None => ()
+                }
+                if timing_function.is_none() {
+                    match specified::parse_timing_function(component_value) {
+                        Some(function) => { timing_function = Some(function); continue }
+                        None => ()
+                    }
+                }
+                if iteration_count.is_none() {
+                    match animation_iteration_count::parse_one(component_value) {
+                        Some(count) => { iteration_count = Some(count); continue }
+                        None => ()
+                    }
+                }
+                // Keywords take precedence over the name, so that a name matching one of them
+                // must come after it.
+                match get_ident_lower(component_value) {
+                    Some(keyword) => {
+                        if direction.is_none() {
+                            match animation_direction::from_keyword(keyword.as_slice()) {
+                                Some(value) => { direction = Some(value); continue }
+                                None => ()
+                            }
+                        }
+                        if fill_mode.is_none() {
+                            match animation_fill_mode::from_keyword(keyword.as_slice()) {
+                                Some(value) => { fill_mode = Some(value); continue }
+                                None => ()
+                            }
+                        }
+                        if play_state.is_none() {
+                            match animation_play_state::from_keyword(keyword.as_slice()) {
+                                Some(value) => { play_state = Some(value); continue }
+                                None => ()
+                            }
+                        }
+                    }
+                    None => ()
+                }
+                if name.is_none() {
+                    match component_value {
+                        &Ident(ref value) => { name = Some(value.to_owned()); continue }
+                        _ => ()
+                    }
+                }
+                return None
+            }
+            names.push(name.unwrap_or(~"none"));
+            durations.push(duration.unwrap_or(0.));
+            timing_functions.push(timing_function.unwrap_or(TimingFunction::ease()));
+            delays.push(delay.unwrap_or(0.));
+            iteration_counts.push(
+                iteration_count.unwrap_or(animation_iteration_count::Count(1.)));
+            directions.push(direction.unwrap_or(animation_direction::normal));
+            fill_modes.push(fill_mode.unwrap_or(animation_fill_mode::none));
+            play_states.push(play_state.unwrap_or(animation_play_state::running));
+        }
+        Some(Longhands {
+            animation_name: Some(names),
+            animation_duration: Some(durations),
+            animation_timing_function: Some(timing_functions),
+            animation_delay: Some(delays),
+            animation_iteration_count: Some(iteration_counts),
+            animation_direction: Some(directions),
+            animation_fill_mode: Some(fill_modes),
+            animation_play_state: Some(play_states),
+        })
+    </%self:shorthand>
+
+    // The values that a shorthand declaration gives to each of its longhands, as written, for the
+    // CSSOM. The components of the value are assigned as the parsers above do; `None` stands for
+    // a longhand that the value omits.
+
+    /// Returns the values of the longhands of the given shorthand, in the order of its
+    /// sub-properties, for a valid value without `var()` functions or CSS-wide keywords.
+    pub fn longhand_values(shorthand: &str, input: &[ComponentValue], base_url: &Url)
+                           -> ~[Option<~str>] {
+        match shorthand {
+            "margin" | "padding" | "border-color" | "border-style" | "border-width" => {
+                four_sides_values(input)
+            }
+            "border-top" | "border-right" | "border-bottom" | "border-left" => {
+                border_values(input)
+            }
+            // Each side gets the same color, style and width.
+            "border" => {
+                let values = border_values(input);
+                vec::from_fn(12, |i| values[i % 3].clone())
+            }
+            "border-radius" => border_radius_values(input),
+            "background" => background_values(input, base_url),
+            "list-style" => list_style_values(input, base_url),
+            "font" => font_values(input),
+            "transition" => transition_values(input),
+            "animation" => animation_values(input),
+            _ => ~[],
+        }
+    }
+
+    fn serialize_components(components: &[&ComponentValue]) -> ~str {
+        components.iter().map(|component| *component).to_css().trim().to_owned()
+    }
+
+    fn four_sides_values(input: &[ComponentValue]) -> ~[Option<~str>] {
+        let values: ~[~str] = input.skip_whitespace().map(|value| {
+            serialize_components([value])
+        }).collect();
+        let top = values[0].clone();
+        let right = if values.len() > 1 { values[1].clone() } else { top.clone() };
+        let bottom = if values.len() > 2 { values[2].clone() } else { top.clone() };
+        let left = if values.len() > 3 { values[3].clone() } else { right.clone() };
+        ~[Some(top), Some(right), Some(bottom), Some(left)]
+    }
+
+    fn border_values(input: &[ComponentValue]) -> ~[Option<~str>] {
+        let (mut color, mut style, mut width) = (None, None, None);
+        for component_value in input.skip_whitespace() {
+            if color.is_none() && specified::CSSColor::parse(component_value).is_some() {
+                color = Some(serialize_components([component_value]))
+            } else if style.is_none() &&
+                    border_top_style::from_component_value(component_value).is_some() {
+                style = Some(serialize_components([component_value]))
+            } else if width.is_none() {
+                width = Some(serialize_components([component_value]))
+            }
+        }
+        ~[color, style, width]
+    }
+
+    fn border_radius_values(input: &[ComponentValue]) -> ~[Option<~str>] {
+        let mut lists = ~[~[], ~[]];
+        let mut list = 0;
+        for component_value in input.skip_whitespace() {
+            match component_value {
+                &Delim('/') => list = 1,
+                _ => lists[list].push(serialize_components([component_value])),
+            }
+        }
+        if list == 0 {
+            lists[1] = lists[0].clone()
+        }
+        let corners: ~[~[~str]] = do lists.iter().map |radii| {
+            let top_left = radii[0].clone();
+            let top_right = if radii.len() > 1 { radii[1].clone() } else { top_left.clone() };
+            let bottom_right = if radii.len() > 2 { radii[2].clone() } else { top_left.clone() };
+            let bottom_left = if radii.len() > 3 { radii[3].clone() } else { top_right.clone() };
+            ~[top_left, top_right, bottom_right, bottom_left]
+        }.collect();
+        do vec::from_fn(4) |i| {
+            if corners[0][i] == corners[1][i] {
+                Some(corners[0][i].clone())
+            } else {
+                Some(format!("{:s} {:s}", corners[0][i], corners[1][i]))
+            }
+        }
+    }
+
+    fn background_values(input: &[ComponentValue], base_url: &Url) -> ~[Option<~str>] {
+        let (mut color, mut image, mut repeat, mut attachment) = (None, None, None, None);
+        let mut position = ~[];
+        let mut position_closed = false;
+        let mut size = ~[];
+        let mut iter = input.skip_whitespace();
+        loop {
+            let component_value = match iter.next() {
+                Some(component_value) => component_value,
+                None => break,
+            };
+            if !position_closed && position.len() < 2 &&
+                    background_position::parse_component(component_value).is_some() {
+                position.push(component_value);
+                continue
+            }
+            position_closed = !position.is_empty();
+            if color.is_none() && specified::CSSColor::parse(component_value).is_some() {
+                color = Some(serialize_components([component_value]))
+            } else if image.is_none() &&
+                    background_image::from_component_value(component_value,
+                                                           base_url).is_some() {
+                image = Some(serialize_components([component_value]))
+            } else if repeat.is_none() &&
+                    background_repeat::from_component_value(component_value).is_some() {
+                repeat = Some(serialize_components([component_value]))
+            } else if attachment.is_none() &&
+                    background_attachment::from_component_value(component_value).is_some() {
+                attachment = Some(serialize_components([component_value]))
+            } else {
+                // The slash before the size.
+                loop {
+                    let mut copied_iter = iter.clone();
+                    match copied_iter.next() {
+                        Some(value) if size.len() < 2 &&
+                                background_size::parse([value.clone()]).is_some() => {
+                            size.push(value);
+                            iter = copied_iter;
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+        let position = if position.is_empty() {
+            None
+        } else {
+            Some(serialize_components(position))
+        };
+        let size = if size.is_empty() { None } else { Some(serialize_components(size)) };
+        ~[color, image, repeat, attachment, position, size]
+    }
+
+    fn list_style_values(input: &[ComponentValue], base_url: &Url) -> ~[Option<~str>] {
+        let mut nb_nones = 0u;
+        let (mut style_type, mut position, mut image) = (None, None, None);
+        for component_value in input.skip_whitespace() {
+            if get_ident_lower(component_value).filtered(
+                    |v| v.eq_ignore_ascii_case("none")).is_some() {
+                nb_nones += 1
+            } else if style_type.is_none() &&
+                    list_style_type::from_component_value(component_value).is_some() {
+                style_type = Some(serialize_components([component_value]))
+            } else if position.is_none() &&
+                    list_style_position::from_component_value(component_value).is_some() {
+                position = Some(serialize_components([component_value]))
+            } else if image.is_none() {
+                image = Some(serialize_components([component_value]))
+            }
+        }
+        if nb_nones > 0 && style_type.is_none() {
+            style_type = Some(~"none");
+            nb_nones -= 1
+        }
+        if nb_nones > 0 && image.is_none() {
+            image = Some(~"none")
+        }
+        ~[style_type, position, image]
+    }
+
+    fn font_values(input: &[ComponentValue]) -> ~[Option<~str>] {
+        let (mut style, mut variant, mut weight, mut stretch) = (None, None, None, None);
+        let mut size = None;
+        // Components are indexed rather than iterated over, since the family keeps the whitespace
+        // between its components.
+        let mut i = 0;
+        while size.is_none() {
+            i = skip_whitespace_from(input, i);
+            let component_value = &input[i];
+            i += 1;
+            // `normal` leaves the value to the initial one.
+            let value = Some(serialize_components([component_value]));
+            if get_ident_lower(component_value).filtered(
+                    |v| v.eq_ignore_ascii_case("normal")).is_some() {
+                continue
+            } else if style.is_none() &&
+                    font_style::from_component_value(component_value).is_some() {
+                style = value
+            } else if weight.is_none() &&
+                    font_weight::from_component_value(component_value).is_some() {
+                weight = value
+            } else if variant.is_none() &&
+                    font_variant::from_component_value(component_value).is_some() {
+                variant = value
+            } else if stretch.is_none() &&
+                    font_stretch::from_component_value(component_value).is_some() {
+                stretch = value
+            } else {
+                size = value
+            }
+        }
+        let mut line_height = None;
+        let slash = skip_whitespace_from(input, i);
+        if slash < input.len() {
+            match input[slash] {
+                Delim('/') => {
+                    let j = skip_whitespace_from(input, slash + 1);
+                    line_height = Some(serialize_components([&input[j]]));
+                    i = j + 1
+                }
+                _ => (),
+            }
+        }
+        let family = input.slice_from(i).iter().to_css().trim().to_owned();
+        ~[style, variant, weight, stretch, size, line_height, Some(family)]
+    }
+
+    fn skip_whitespace_from(input: &[ComponentValue], mut i: uint) -> uint {
+        while i < input.len() {
+            match input[i] {
+                WhiteSpace => i += 1,
+                _ => break,
+            }
+        }
+        i
+    }
+
+    /// Joins the values of a longhand in each item of a list-valued shorthand, with the given
+    /// default for the items that omit it.
+    fn join_items(items: &[~[Option<~str>]], index: uint, default: &str) -> Option<~str> {
+        let values: ~[~str] = do items.iter().map |item| {
+            item[index].clone().unwrap_or(default.to_owned())
+        }.collect();
+        Some(values.connect(", "))
+    }
+
+    fn transition_values(input: &[ComponentValue]) -> ~[Option<~str>] {
+        let items: ~[~[Option<~str>]] = do split_on_commas(input).move_iter().map |item| {
+            let (mut property, mut duration, mut timing_function, mut delay) =
+                (None, None, None, None);
+            for component_value in item.skip_whitespace() {
+                let value = Some(serialize_components([component_value]));
+                match specified::parse_time(component_value) {
+                    Some(time) if duration.is_none() && time >= 0. => duration = value,
+                    Some(_) => delay = value,
+                    None if timing_function.is_none() &&
+                            specified::parse_timing_function(component_value).is_some() => {
+                        timing_function = value
+                    }
+                    None => property = value,
+                }
+            }
+            ~[property, duration, timing_function, delay]
+        }.collect();
+        ~[join_items(items, 0, "all"), join_items(items, 1, "0s"), join_items(items, 2, "ease"),
+          join_items(items, 3, "0s")]
+    }
+
+    fn animation_values(input: &[ComponentValue]) -> ~[Option<~str>] {
+        let items: ~[~[Option<~str>]] = do split_on_commas(input).move_iter().map |item| {
+            let mut values = ~[None, None, None, None, None, None, None, None];
+            for component_value in item.skip_whitespace() {
+                let value = Some(serialize_components([component_value]));
+                let keyword = get_ident_lower(component_value).unwrap_or(~"");
+                let index = match specified::parse_time(component_value) {
+                    Some(time) if values[1].is_none() && time >= 0. => 1,
+                    Some(_) => 3,
+                    None if values[2].is_none() &&
+                            specified::parse_timing_function(component_value).is_some() => 2,
+                    None if values[4].is_none() &&
+                            animation_iteration_count::parse_one(component_value).is_some() => 4,
+                    None if values[5].is_none() &&
+                            animation_direction::from_keyword(keyword.as_slice()).is_some() => 5,
+                    None if values[6].is_none() &&
+                            animation_fill_mode::from_keyword(keyword.as_slice()).is_some() => 6,
+                    None if values[7].is_none() &&
+                            animation_play_state::from_keyword(keyword.as_slice()).is_some() => 7,
+                    None => 0,
+                };
+                values[index] = value
+            }
+            values
+        }.collect();
+        ~[join_items(items, 0, "none"), join_items(items, 1, "0s"), join_items(items, 2, "ease"),
+          join_items(items, 3, "0s"), join_items(items, 4, "1"), join_items(items, 5, "normal"),
+          join_items(items, 6, "none"), join_items(items, 7, "running")]
+    }
+
 }
 
 
+#[deriving(Clone)]
 pub struct PropertyDeclarationBlock {
     important: Arc<~[PropertyDeclaration]>,
     normal: Arc<~[PropertyDeclaration]>,
+    /// The valid declarations of the block as written, for the CSSOM.
+    source: ~[SourceDeclaration],
+}
+
+/// A valid declaration as written in a stylesheet or style attribute. For a given property name
+/// only the last one is kept.
+#[deriving(Clone, Eq)]
+pub struct SourceDeclaration {
+    /// The property name, in lower case.
+    name: ~str,
+    /// The serialized value, without `!important`.
+    value: ~str,
+    important: bool,
+}
+
+impl PropertyDeclarationBlock {
+    /// Builds a block from declarations as written, such as those modified through the CSSOM.
+    pub fn from_source(source: ~[SourceDeclaration], base_url: &Url) -> PropertyDeclarationBlock {
+        parse_property_declaration_list(tokenize(serialize_declarations(source)), base_url)
+    }
+}
+
+/// Serializes the given declarations into the syntax of a style attribute.
+pub fn serialize_declarations(source: &[SourceDeclaration]) -> ~str {
+    let mut result = ~"";
+    for declaration in source.iter() {
+        if !result.is_empty() {
+            result.push_char(' ')
+        }
+        result.push_str(format!("{:s}: {:s}{:s};",
+                                declaration.name,
+                                declaration.value,
+                                if declaration.important { " !important" } else { "" }))
+    }
+    result
+}
+
+/// Returns true if the given name is a property that this engine supports: a custom property, or
+/// another property in lower case.
+pub fn is_supported_property(name: &str) -> bool {
+    if custom_properties::is_custom_property_name(name) {
+        return true
+    }
+    match name {
+        % for property in LONGHANDS + SHORTHANDS:
+            "${property.name}" => true,
+        % endfor
+        _ => false,
+    }
+}
+
+/// Returns the names of the longhands that the given lower case shorthand sets, or `None` if it is
+/// not a shorthand.
+pub fn longhands_for_shorthand(name: &str) -> Option<~[&'static str]> {
+    match name {
+        % for shorthand in SHORTHANDS:
+            "${shorthand.name}" => Some(~[
+                % for sub_property in shorthand.sub_properties:
+                    "${sub_property.name}",
+                % endfor
+            ]),
+        % endfor
+        _ => None,
+    }
+}
+
+/// Returns the value that a declaration of the given shorthand gives to one of its longhands, as
+/// written, or "" if it is not one of its longhands. Longhands that the value omits are reset to
+/// `initial`. A value with `var()` functions is only split when it is substituted, so its
+/// longhands have no value yet.
+pub fn longhand_value_in_shorthand(shorthand: &str, longhand: &str, value: &str, base_url: &Url)
+                                   -> ~str {
+    let index = match longhands_for_shorthand(shorthand) {
+        Some(longhands) => match longhands.iter().position(|name| *name == longhand) {
+            Some(index) => index,
+            None => return ~"",
+        },
+        None => return ~"",
+    };
+    let input: ~[ComponentValue] = tokenize(value).map(|(value, _)| value).collect();
+    if custom_properties::find_var_functions(input) != Some(false) {
+        return ~""
+    }
+    match CSSWideKeyword::parse(input) {
+        Some(_) => return value.trim().to_ascii_lower(),
+        None => (),
+    }
+    match shorthands::longhand_values(shorthand, input, base_url)[index] {
+        Some(ref value) => value.clone(),
+        None => ~"initial",
+    }
 }
 
 
-pub fn parse_style_attribute(input: &str) -> PropertyDeclarationBlock {
-    parse_property_declaration_list(tokenize(input))
+pub fn parse_style_attribute(input: &str, base_url: &Url) -> PropertyDeclarationBlock {
+    parse_property_declaration_list(tokenize(input), base_url)
 }
 
 
-pub fn parse_property_declaration_list<I: Iterator<Node>>(input: I) -> PropertyDeclarationBlock {
+pub fn parse_property_declaration_list<I: Iterator<Node>>(input: I, base_url: &Url)
+                                                          -> PropertyDeclarationBlock {
     let mut important = ~[];
     let mut normal = ~[];
+    let mut source = ~[];
     for item in ErrorLoggerIterator(parse_declaration_list(input)) {
         match item {
             Decl_AtRule(rule) => log_css_error(
-                rule.location, format!("Unsupported at-rule in declaration list: @{:s}", rule.name)),
+                rule.location, UnsupportedAtRuleError, format!("@{:s}", rule.name),
+                "Unsupported at-rule in declaration list"),
             Declaration(Declaration{ location: l, name: n, value: v, important: i}) => {
                 // TODO: only keep the last valid declaration for a given name.
                 let list = if i { &mut important } else { &mut normal };
-                match PropertyDeclaration::parse(n, v, list) {
-                    UnknownProperty => log_css_error(l, format!(
-                        "Unsupported property: {}:{}", n, v.iter().to_css())),
-                    InvalidValue => log_css_error(l, format!(
-                        "Invalid value: {}:{}", n, v.iter().to_css())),
-                    ValidDeclaration => (),
+                match PropertyDeclaration::parse(n, v, list, base_url) {
+                    UnknownProperty => log_css_error(
+                        l, UnsupportedPropertyError, format!("{}:{}", n, v.iter().to_css()),
+                        "Unsupported property"),
+                    InvalidValue => log_css_error(
+                        l, InvalidValueError, format!("{}:{}", n, v.iter().to_css()),
+                        "Invalid value"),
+                    ValidDeclaration => {
+                        let name = if custom_properties::is_custom_property_name(n) {
+                            n.to_owned()
+                        } else {
+                            n.to_ascii_lower()
+                        };
+                        source.retain(|declaration: &SourceDeclaration| declaration.name != name);
+                        source.push(SourceDeclaration {
+                            name: name,
+                            value: v.iter().to_css().trim().to_owned(),
+                            important: i,
+                        })
+                    }
                 }
             }
         }
@@ -956,6 +3005,7 @@ pub fn parse_property_declaration_list<I: Iterator<Node>>(input: I) -> PropertyD
     PropertyDeclarationBlock {
         important: Arc::new(important),
         normal: Arc::new(normal),
+        source: source,
     }
 }
 
@@ -986,6 +3036,17 @@ impl CSSWideKeyword {
 pub enum DeclaredValue<T> {
     SpecifiedValue(T),
     CSSWideKeyword(CSSWideKeyword),
+    /// A value with `var()` functions, which is parsed when they are substituted at cascade time.
+    WithVariables(UnparsedValue),
+}
+
+/// The value of a declaration that references custom properties.
+#[deriving(Clone)]
+pub struct UnparsedValue {
+    tokens: Arc<~[ComponentValue]>,
+    base_url: Url,
+    /// The shorthand that was declared, if any. Its value is parsed once for each longhand.
+    shorthand: Option<&'static str>,
 }
 
 #[deriving(Clone)]
@@ -993,6 +3054,8 @@ pub enum PropertyDeclaration {
     % for property in LONGHANDS:
         ${property.ident}_declaration(DeclaredValue<longhands::${property.ident}::SpecifiedValue>),
     % endfor
+    /// A custom property, with its name as written.
+    CustomDeclaration(~str, DeclaredCustomValue),
 }
 
 
@@ -1004,13 +3067,27 @@ enum PropertyDeclarationParseResult {
 
 impl PropertyDeclaration {
     pub fn parse(name: &str, value: &[ComponentValue],
-                 result_list: &mut ~[PropertyDeclaration]) -> PropertyDeclarationParseResult {
+                 result_list: &mut ~[PropertyDeclaration],
+                 base_url: &Url) -> PropertyDeclarationParseResult {
+        if custom_properties::is_custom_property_name(name) {
+            match DeclaredCustomValue::parse(value) {
+                Some(value) => result_list.push(CustomDeclaration(name.to_owned(), value)),
+                None => return InvalidValue,
+            }
+            return ValidDeclaration
+        }
         // FIXME: local variable to work around Rust #10683
         let name_lower = name.to_ascii_lower();
+        match custom_properties::find_var_functions(value) {
+            Some(true) => return PropertyDeclaration::parse_with_variables(
+                name_lower, value, result_list, base_url),
+            Some(false) => (),
+            None => return InvalidValue,
+        }
         match name_lower.as_slice() {
             % for property in LONGHANDS:
                 "${property.name}" => result_list.push(${property.ident}_declaration(
-                    match longhands::${property.ident}::parse_declared(value) {
+                    match longhands::${property.ident}::parse_declared(value, base_url) {
                         Some(value) => value,
                         None => return InvalidValue,
                     }
@@ -1033,7 +3110,7 @@ impl PropertyDeclaration {
                             ));
                         % endfor
                     },
-                    None => match shorthands::${shorthand.ident}::parse(value) {
+                    None => match shorthands::${shorthand.ident}::parse(value, base_url) {
                         Some(result) => {
                             % for sub_property in shorthand.sub_properties:
                                 result_list.push(${sub_property.ident}_declaration(
@@ -1052,6 +3129,115 @@ impl PropertyDeclaration {
         }
         ValidDeclaration
     }
+
+    /// Keeps the tokens of a value that references custom properties, for each longhand that the
+    /// property sets, since the value can only be checked once they are substituted.
+    fn parse_with_variables(name_lower: &str, value: &[ComponentValue],
+                            result_list: &mut ~[PropertyDeclaration],
+                            base_url: &Url) -> PropertyDeclarationParseResult {
+        let tokens = Arc::new(value.to_owned());
+        match name_lower {
+            % for property in LONGHANDS:
+                "${property.name}" => result_list.push(${property.ident}_declaration(
+                    WithVariables(UnparsedValue {
+                        tokens: tokens,
+                        base_url: base_url.clone(),
+                        shorthand: None,
+                    })
+                )),
+            % endfor
+            % for shorthand in SHORTHANDS:
+                "${shorthand.name}" => {
+                    % for sub_property in shorthand.sub_properties:
+                        result_list.push(${sub_property.ident}_declaration(
+                            WithVariables(UnparsedValue {
+                                tokens: tokens.clone(),
+                                base_url: base_url.clone(),
+                                shorthand: Some("${shorthand.name}"),
+                            })
+                        ));
+                    % endfor
+                },
+            % endfor
+            _ => return UnknownProperty,
+        }
+        ValidDeclaration
+    }
+
+    /// Returns the name of the property that this declaration sets: that of a longhand in lower
+    /// case, or that of a custom property as written.
+    pub fn name<'a>(&'a self) -> &'a str {
+        match *self {
+            % for property in LONGHANDS:
+                ${property.ident}_declaration(_) => "${property.name}",
+            % endfor
+            CustomDeclaration(ref name, _) => name.as_slice(),
+        }
+    }
+
+    /// Returns true if this declaration can apply to a `::first-line` pseudo-element.
+    pub fn applies_to_first_line(&self) -> bool {
+        match *self {
+            % for property in LONGHANDS:
+                % if applies_to_first_line(property.name):
+                    ${property.ident}_declaration(_) => true,
+                % endif
+            % endfor
+            _ => false,
+        }
+    }
+
+    /// Returns true if this declaration can apply to a `::first-letter` pseudo-element.
+    pub fn applies_to_first_letter(&self) -> bool {
+        match *self {
+            % for property in LONGHANDS:
+                % if applies_to_first_letter(property.name):
+                    ${property.ident}_declaration(_) => true,
+                % endif
+            % endfor
+            _ => false,
+        }
+    }
+}
+
+
+/// Each method substitutes the custom properties that the value references, then parses it for
+/// the given longhand. A value that is invalid once substituted makes the longhand invalid at
+/// computed-value time, which behaves as `unset`.
+impl UnparsedValue {
+    % for property in LONGHANDS:
+        fn substitute_${property.ident}(&self, custom_properties: &CustomProperties)
+            -> DeclaredValue<longhands::${property.ident}::SpecifiedValue> {
+            let unset = CSSWideKeyword(${"Inherit" if property.is_inherited else "Initial"});
+            let tokens = match custom_properties::substitute(self.tokens.get().as_slice(),
+                                                             custom_properties) {
+                Some(tokens) => tokens,
+                None => return unset,
+            };
+            match self.shorthand {
+                None => match longhands::${property.ident}::parse_declared(tokens.as_slice(),
+                                                                           &self.base_url) {
+                    Some(value) => value,
+                    None => unset,
+                },
+                % for shorthand in SHORTHANDS:
+                    % if property in shorthand.sub_properties:
+                        Some("${shorthand.name}") => {
+                            match shorthands::${shorthand.ident}::parse(tokens.as_slice(),
+                                                                        &self.base_url) {
+                                Some(result) => match result.${property.ident} {
+                                    Some(value) => SpecifiedValue(value),
+                                    None => CSSWideKeyword(Initial),
+                                },
+                                None => unset,
+                            }
+                        }
+                    % endif
+                % endfor
+                Some(_) => unset,
+            }
+        }
+    % endfor
 }
 
 
@@ -1072,6 +3258,10 @@ pub struct ComputedValues {
     % for name, longhands in LONGHANDS_PER_STYLE_STRUCT:
         ${name}: style_structs::${name},
     % endfor
+    /// Whether some value of this style or of an ancestor's style depends on the size of the
+    /// viewport, in which case it must be cascaded again when the viewport is resized.
+    depends_on_viewport: bool,
+    custom_properties: CustomProperties,
 }
 
 impl ComputedValues {
@@ -1090,6 +3280,233 @@ impl ComputedValues {
     }
 }
 
+/// Serialization of computed values back to CSS text, as returned by `getComputedStyle()`.
+pub trait ComputedValueToCss {
+    /// Serializes this value. Colors are resolved against the given style.
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str;
+}
+
+impl ComputedValueToCss for Au {
+    fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+        format!("{}px", to_frac_px(*self))
+    }
+}
+
+impl ComputedValueToCss for CSSFloat {
+    fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+        format!("{}", *self)
+    }
+}
+
+impl ComputedValueToCss for RGBA {
+    fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+        let red = (self.red * 255.).round() as int;
+        let green = (self.green * 255.).round() as int;
+        let blue = (self.blue * 255.).round() as int;
+        if self.alpha == 1. {
+            format!("rgb({}, {}, {})", red, green, blue)
+        } else {
+            format!("rgba({}, {}, {}, {})", red, green, blue, self.alpha)
+        }
+    }
+}
+
+impl ComputedValueToCss for computed::CSSColor {
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+        style.resolve_color(*self).computed_value_to_css(style)
+    }
+}
+
+impl ComputedValueToCss for computed::CalcLengthOrPercentage {
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+        let sum = serialize_calc_sum(self.length, self.percentage, style);
+        match self.bound {
+            None => format!("calc({:s})", sum),
+            Some(bound) => {
+                format!("{:s}({:s}, {:s})",
+                        if bound.upper { "min" } else { "max" },
+                        sum,
+                        serialize_calc_sum(bound.length, bound.percentage, style))
+            }
+        }
+    }
+}
+
+fn serialize_calc_sum(length: Au, percentage: CSSFloat, style: &ComputedValues) -> ~str {
+    let percentage = serialize_percentage(percentage);
+    if length < Au(0) {
+        format!("{:s} - {:s}", percentage, (-length).computed_value_to_css(style))
+    } else {
+        format!("{:s} + {:s}", percentage, length.computed_value_to_css(style))
+    }
+}
+
+impl ComputedValueToCss for computed::LengthOrPercentage {
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+        match *self {
+            computed::LP_Length(length) => length.computed_value_to_css(style),
+            computed::LP_Percentage(percentage) => serialize_percentage(percentage),
+            computed::LP_Calc(calc) => calc.computed_value_to_css(style),
+        }
+    }
+}
+
+impl ComputedValueToCss for computed::LengthOrPercentageOrAuto {
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+        match *self {
+            computed::LPA_Length(length) => length.computed_value_to_css(style),
+            computed::LPA_Percentage(percentage) => serialize_percentage(percentage),
+            computed::LPA_Calc(calc) => calc.computed_value_to_css(style),
+            computed::LPA_Auto => ~"auto",
+        }
+    }
+}
+
+impl ComputedValueToCss for computed::LengthOrPercentageOrNone {
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+        match *self {
+            computed::LPN_Length(length) => length.computed_value_to_css(style),
+            computed::LPN_Percentage(percentage) => serialize_percentage(percentage),
+            computed::LPN_Calc(calc) => calc.computed_value_to_css(style),
+            computed::LPN_None => ~"none",
+        }
+    }
+}
+
+impl ComputedValueToCss for computed::Image {
+    fn computed_value_to_css(&self, style: &ComputedValues) -> ~str {
+        match *self {
+            computed::UrlImage(ref url) => {
+                let url = url.to_str();
+                format!("url({:s})", serialize_string(url))
+            }
+            computed::LinearGradientImage(ref gradient) => {
+                let direction = match gradient.direction {
+                    AngleDirection(angle) => format!("{}deg", angle * 180. / PI),
+                    CornerDirection(horizontal, vertical) => format!("to {:s} {:s}",
+                        match horizontal { SideLeft => "left", SideRight => "right" },
+                        match vertical { SideTop => "top", SideBottom => "bottom" }),
+                };
+                format!("{:s}linear-gradient({:s}, {:s})",
+                        if gradient.repeating { "repeating-" } else { "" }, direction,
+                        serialize_color_stops(gradient.stops, style))
+            }
+            computed::RadialGradientImage(ref gradient) => {
+                let shape = match gradient.shape {
+                    computed::CircleExtent(extent) => format!("circle {:s}", extent.name()),
+                    computed::EllipseExtent(extent) => format!("ellipse {:s}", extent.name()),
+                    computed::Circle(radius) => radius.computed_value_to_css(style),
+                    computed::Ellipse(ref horizontal, ref vertical) => {
+                        format!("{:s} {:s}", horizontal.computed_value_to_css(style),
+                                vertical.computed_value_to_css(style))
+                    }
+                };
+                format!("{:s}radial-gradient({:s} at {:s}, {:s})",
+                        if gradient.repeating { "repeating-" } else { "" }, shape,
+                        gradient.position.computed_value_to_css(style),
+                        serialize_color_stops(gradient.stops, style))
+            }
+        }
+    }
+}
+
+impl ComputedValueToCss for TimingFunction {
+    fn computed_value_to_css(&self, _style: &ComputedValues) -> ~str {
+        match *self {
+            CubicBezier(x1, y1, x2, y2) => format!("cubic-bezier({}, {}, {}, {})", x1, y1, x2, y2),
+            Steps(steps, at_start) => {
+                format!("steps({}, {:s})", steps, if at_start { "start" } else { "end" })
+            }
+        }
+    }
+}
+
+fn serialize_color_stops(stops: &[computed::ColorStop], style: &ComputedValues) -> ~str {
+    let stops: ~[~str] = do stops.iter().map |stop| {
+        match stop.position {
+            Some(ref position) => format!("{:s} {:s}", stop.color.computed_value_to_css(style),
+                                          position.computed_value_to_css(style)),
+            None => stop.color.computed_value_to_css(style),
+        }
+    }.collect();
+    stops.connect(", ")
+}
+
+/// Serializes a percentage stored as a fraction, where 1.0 is 100%.
+pub fn serialize_percentage(value: CSSFloat) -> ~str {
+    format!("{}%", value * 100.)
+}
+
+/// Serializes a string as a double-quoted CSS string.
+pub fn serialize_string(value: &str) -> ~str {
+    let mut result = ~"\"";
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\A "),
+            _ => result.push_char(c),
+        }
+    }
+    result.push_char('"');
+    result
+}
+
+/// Serializes every longhand of the given style, sorted by property name, then the custom
+/// properties that are set, as returned by `getComputedStyle()`.
+pub fn serialize_computed_values(style: &ComputedValues) -> ~[SourceDeclaration] {
+    <%
+        all_longhands = [(style_struct, longhand)
+                         for style_struct, longhands in LONGHANDS_PER_STYLE_STRUCT
+                         for longhand in longhands]
+        all_longhands.sort(key=lambda pair: pair[1].name)
+    %>
+    let mut declarations = ~[
+        % for style_struct, longhand in all_longhands:
+            SourceDeclaration {
+                name: ~"${longhand.name}",
+                value: style.${style_struct}.${longhand.ident}.computed_value_to_css(style),
+                important: false,
+            },
+        % endfor
+    ];
+    for name in style.custom_properties.names().move_iter() {
+        let value = style.custom_properties.get(name.as_slice()).unwrap().iter().to_css();
+        declarations.push(SourceDeclaration {
+            name: name,
+            value: value.trim().to_owned(),
+            important: false,
+        })
+    }
+    declarations
+}
+
+/// Returns the style of a box placed on the first line of a block container, given the box's own
+/// style, the style of the block container and the block container's `::first-line` style.
+///
+/// NB: Per CSS 2.1 section 5.12.1, the `::first-line` style should be inherited through a fictional
+/// tag sequence, which would require re-cascading every element on the line. Instead, a property
+/// is taken from the `::first-line` style only if the box has the same value as the block, i.e. if
+/// nothing in between overrode it. This is a best approximation.
+pub fn apply_first_line_style(style: &ComputedValues,
+                              block_style: &ComputedValues,
+                              first_line_style: &ComputedValues)
+                              -> ComputedValues {
+    let mut result = style.clone();
+    % for style_struct, longhands in LONGHANDS_PER_STYLE_STRUCT:
+        % for longhand in longhands:
+            % if applies_to_first_line(longhand.name):
+                if style.${style_struct}.${longhand.ident} ==
+                        block_style.${style_struct}.${longhand.ident} {
+                    result.${style_struct}.${longhand.ident} =
+                        first_line_style.${style_struct}.${longhand.ident}.clone()
+                }
+            % endif
+        % endfor
+    % endfor
+    result
+}
+
 #[inline]
 fn get_initial_values() -> ComputedValues {
     ComputedValues {
@@ -1100,13 +3517,44 @@ fn get_initial_values() -> ComputedValues {
                 % endfor
             },
         % endfor
+        depends_on_viewport: false,
+        custom_properties: CustomProperties::new(),
     }
 }
 
 
+/// Measures fonts for the `ch` unit. The style system has no access to fonts, so layout
+/// implements this.
+pub trait FontMetricsProvider {
+    /// Returns the advance of the "0" glyph in the first available font for the given font
+    /// properties, if any.
+    fn zero_advance(&mut self, font: &style_structs::Font) -> Option<Au>;
+}
+
+/// A `FontMetricsProvider` for styles that no `ch` unit can apply to.
+pub struct NoFontMetrics;
+
+impl FontMetricsProvider for NoFontMetrics {
+    fn zero_advance(&mut self, _font: &style_structs::Font) -> Option<Au> {
+        None
+    }
+}
+
+/// What computed values depend on apart from the declarations and the parent style.
+pub struct CascadeEnvironment<'self> {
+    /// The computed font size of the root element, for `rem` units outside of the root element.
+    root_font_size: Au,
+    /// The size of the viewport, for viewport-percentage units.
+    viewport_width: Au,
+    viewport_height: Au,
+    font_metrics: &'self mut FontMetricsProvider,
+}
+
+
 // Most specific/important declarations last
 pub fn cascade(applicable_declarations: &[Arc<~[PropertyDeclaration]>],
-               parent_style: Option< &ComputedValues>)
+               parent_style: Option< &ComputedValues>,
+               environment: &mut CascadeEnvironment)
             -> ComputedValues {
     let initial_keep_alive;
     let (parent_style, is_root_element) = match parent_style {
@@ -1127,16 +3575,33 @@ pub fn cascade(applicable_declarations: &[Arc<~[PropertyDeclaration]>],
                 "Inherit" if property.is_inherited else "Initial"}),
         % endfor
     };
+    // Custom properties are computed first, since other declarations can reference them.
+    let mut custom_declarations = ~[];
+    for sub_list in applicable_declarations.iter() {
+        for declaration in sub_list.get().iter() {
+            match declaration {
+                &CustomDeclaration(ref name, ref value) => custom_declarations.push((name, value)),
+                _ => (),
+            }
+        }
+    }
+    let custom_properties = custom_properties::cascade(custom_declarations,
+                                                       &parent_style.custom_properties);
     for sub_list in applicable_declarations.iter() {
         for declaration in sub_list.get().iter() {
             match declaration {
                 % for property in LONGHANDS:
+                    &${property.ident}_declaration(WithVariables(ref value)) => {
+                        specified.${property.ident} =
+                            value.substitute_${property.ident}(&custom_properties)
+                    }
                     &${property.ident}_declaration(ref value) => {
                         // Overwrite earlier declarations.
                         // TODO: can we avoid a copy?
                         specified.${property.ident} = (*value).clone()
                     }
                 % endfor
+                &CustomDeclaration(*) => (),
             }
         }
     }
@@ -1147,6 +3612,7 @@ pub fn cascade(applicable_declarations: &[Arc<~[PropertyDeclaration]>],
                 SpecifiedValue(value) => value,
                 CSSWideKeyword(Initial) => longhands::$property::get_initial_value(),
                 CSSWideKeyword(Inherit) => parent_style.$style_struct.$property.clone(),
+                WithVariables(_) => fail!("var() functions are substituted before computing"),
             }
         };
     )
@@ -1170,6 +3636,19 @@ pub fn cascade(applicable_declarations: &[Arc<~[PropertyDeclaration]>],
         has_border_right: has_border!(border_right_style),
         has_border_bottom: has_border!(border_bottom_style),
         has_border_left: has_border!(border_left_style),
+        // Per CSS Values and Units 5.1.1, `rem` in the font size of the root element refers to
+        // the initial font size.
+        root_font_size: if is_root_element {
+            longhands::font_size::get_initial_value()
+        } else {
+            environment.root_font_size
+        },
+        font: None,
+        font_metrics: Cell::new(&mut *environment.font_metrics),
+        zero_advance: Cell::new_empty(),
+        viewport_width: environment.viewport_width,
+        viewport_height: environment.viewport_height,
+        depends_on_viewport: Cell::new_empty(),
     };
     macro_rules! get_computed(
         ($style_struct: ident, $property: ident) => {
@@ -1179,24 +3658,63 @@ pub fn cascade(applicable_declarations: &[Arc<~[PropertyDeclaration]>],
                 => longhands::$property::to_computed_value(value.clone(), context),
                 CSSWideKeyword(Initial) => longhands::$property::get_initial_value(),
                 CSSWideKeyword(Inherit) => parent_style.$style_struct.$property.clone(),
+                WithVariables(_) => fail!("var() functions are substituted before computing"),
             }
         };
     )
     context.font_size = get_computed!(Font, font_size);
-    ComputedValues {
+    if is_root_element {
+        context.root_font_size = context.font_size
+    }
+    // The font is computed first, for `ch`. None of its properties accept `ch` themselves.
+    let font = style_structs::Font {
         % for style_struct, longhands in LONGHANDS_PER_STYLE_STRUCT:
-            ${style_struct}: style_structs::${style_struct} {
+            % if style_struct == "Font":
                 % for longhand in longhands:
                     ${longhand.ident}:
                     % if longhand.ident == 'font_size':
                         context.font_size,
                     % else:
-                        get_computed!(${style_struct}, ${longhand.ident}),
+                        get_computed!(Font, ${longhand.ident}),
                     % endif
                 % endfor
-            },
+            % endif
+        % endfor
+    };
+    context.font = Some(font);
+    // Fields are evaluated in order, so the font is only moved out once the other structs have
+    // been computed.
+    let mut result = ComputedValues {
+        % for style_struct, longhands in LONGHANDS_PER_STYLE_STRUCT:
+            % if style_struct != "Font":
+                ${style_struct}: style_structs::${style_struct} {
+                    % for longhand in longhands:
+                        ${longhand.ident}: get_computed!(${style_struct}, ${longhand.ident}),
+                    % endfor
+                },
+            % endif
         % endfor
+        Font: context.font.take_unwrap(),
+        depends_on_viewport: parent_style.depends_on_viewport,
+        custom_properties: custom_properties,
+    };
+    if !context.depends_on_viewport.is_empty() {
+        result.depends_on_viewport = true
     }
+    result
+}
+
+/// Returns the style of an anonymous box, which inherits everything it can from the given style.
+pub fn cascade_anonymous(parent_style: &ComputedValues) -> ComputedValues {
+    // No declaration applies, so the environment is never used.
+    let mut font_metrics = NoFontMetrics;
+    let mut environment = CascadeEnvironment {
+        root_font_size: parent_style.Font.font_size,
+        viewport_width: Au(0),
+        viewport_height: Au(0),
+        font_metrics: &mut font_metrics as &mut FontMetricsProvider,
+    };
+    cascade(&[Arc::new(~[])], Some(parent_style), &mut environment)
 }
 
 
@@ -1210,7 +3728,334 @@ pub mod computed_values {
 
     pub use cssparser::RGBA;
     pub use super::common_types::computed::{
-        LengthOrPercentage, LP_Length, LP_Percentage,
-        LengthOrPercentageOrAuto, LPA_Length, LPA_Percentage, LPA_Auto,
-        LengthOrPercentageOrNone, LPN_Length, LPN_Percentage, LPN_None};
+        LengthOrPercentage, LP_Length, LP_Percentage, LP_Calc,
+        LengthOrPercentageOrAuto, LPA_Length, LPA_Percentage, LPA_Calc, LPA_Auto,
+        LengthOrPercentageOrNone, LPN_Length, LPN_Percentage, LPN_Calc, LPN_None,
+        CalcLengthOrPercentage};
+    pub use super::common_types::computed::{
+        Image, UrlImage, LinearGradientImage, RadialGradientImage,
+        LinearGradient, RadialGradient, ColorStop,
+        EndingShape, CircleExtent, EllipseExtent, Circle, Ellipse};
+    pub use super::common_types::{
+        GradientDirection, AngleDirection, CornerDirection,
+        HorizontalSide, SideLeft, SideRight, VerticalSide, SideTop, SideBottom,
+        ShapeExtent, ClosestSide, ClosestCorner, FarthestSide, FarthestCorner};
+}
+
+
+/// Computed values that transitions and animations can interpolate.
+pub trait Interpolate {
+    /// Returns the value at `progress` between `self` (at 0) and `other` (at 1), or `None` if the
+    /// two values can not be interpolated, e.g. `auto` and a length. `progress` may be outside of
+    /// [0, 1] with some timing functions.
+    fn interpolate(&self, other: &Self, progress: CSSFloat) -> Option<Self>;
+}
+
+/// Clamps interpolated values at zero, for the properties that take no negative values.
+trait ClampToNonNegative {
+    fn clamp_to_non_negative(&self) -> Self;
+}
+
+impl ClampToNonNegative for Au {
+    fn clamp_to_non_negative(&self) -> Au {
+        Au::max(*self, Au(0))
+    }
+}
+
+impl ClampToNonNegative for computed::CalcLengthOrPercentage {
+    /// The percentage is only resolved at layout time, so the result is clamped then.
+    fn clamp_to_non_negative(&self) -> computed::CalcLengthOrPercentage {
+        computed::CalcLengthOrPercentage {
+            non_negative: true,
+            .. self.clone()
+        }
+    }
+}
+
+impl ClampToNonNegative for computed::LengthOrPercentage {
+    fn clamp_to_non_negative(&self) -> computed::LengthOrPercentage {
+        match *self {
+            computed::LP_Length(value) => computed::LP_Length(value.clamp_to_non_negative()),
+            computed::LP_Percentage(value) => computed::LP_Percentage(value.max(&0.)),
+            computed::LP_Calc(ref value) => computed::LP_Calc(value.clamp_to_non_negative()),
+        }
+    }
+}
+
+impl ClampToNonNegative for computed::LengthOrPercentageOrAuto {
+    fn clamp_to_non_negative(&self) -> computed::LengthOrPercentageOrAuto {
+        match *self {
+            computed::LPA_Length(value) => computed::LPA_Length(value.clamp_to_non_negative()),
+            computed::LPA_Percentage(value) => computed::LPA_Percentage(value.max(&0.)),
+            computed::LPA_Calc(ref value) => computed::LPA_Calc(value.clamp_to_non_negative()),
+            computed::LPA_Auto => computed::LPA_Auto,
+        }
+    }
+}
+
+impl ClampToNonNegative for computed::LengthOrPercentageOrNone {
+    fn clamp_to_non_negative(&self) -> computed::LengthOrPercentageOrNone {
+        match *self {
+            computed::LPN_Length(value) => computed::LPN_Length(value.clamp_to_non_negative()),
+            computed::LPN_Percentage(value) => computed::LPN_Percentage(value.max(&0.)),
+            computed::LPN_Calc(ref value) => computed::LPN_Calc(value.clamp_to_non_negative()),
+            computed::LPN_None => computed::LPN_None,
+        }
+    }
+}
+
+impl Interpolate for CSSFloat {
+    #[inline]
+    fn interpolate(&self, other: &CSSFloat, progress: CSSFloat) -> Option<CSSFloat> {
+        Some(*self + (*other - *self) * progress)
+    }
+}
+
+impl Interpolate for Au {
+    #[inline]
+    fn interpolate(&self, other: &Au, progress: CSSFloat) -> Option<Au> {
+        let (Au(from), Au(to)) = (*self, *other);
+        Some(Au((from as CSSFloat + ((to - from) as CSSFloat) * progress).round() as i32))
+    }
+}
+
+impl Interpolate for RGBA {
+    /// Interpolates in premultiplied space, so that a transparent color does not tint the result.
+    fn interpolate(&self, other: &RGBA, progress: CSSFloat) -> Option<RGBA> {
+        let lerp = |from: f32, to: f32| {
+            (from as CSSFloat + ((to - from) as CSSFloat) * progress) as f32
+        };
+        let alpha = lerp(self.alpha, other.alpha).max(&0.).min(&1.);
+        if alpha == 0. {
+            return Some(RGBA { red: 0., green: 0., blue: 0., alpha: 0. })
+        }
+        let component = |from: f32, to: f32| {
+            (lerp(from * self.alpha, to * other.alpha) / alpha).max(&0.).min(&1.)
+        };
+        Some(RGBA {
+            red: component(self.red, other.red),
+            green: component(self.green, other.green),
+            blue: component(self.blue, other.blue),
+            alpha: alpha,
+        })
+    }
+}
+
+impl Interpolate for computed::CSSColor {
+    /// FIXME: `currentColor` should be resolved first, so that it can be interpolated too.
+    fn interpolate(&self, other: &computed::CSSColor, progress: CSSFloat)
+                   -> Option<computed::CSSColor> {
+        match (*self, *other) {
+            (RGBA(ref from), RGBA(ref to)) => from.interpolate(to, progress).map(|rgba| RGBA(rgba)),
+            _ => None,
+        }
+    }
+}
+
+impl Interpolate for computed::CalcLengthOrPercentage {
+    fn interpolate(&self, other: &computed::CalcLengthOrPercentage, progress: CSSFloat)
+                   -> Option<computed::CalcLengthOrPercentage> {
+        // Bounded expressions are not linear, so they change at once.
+        if self.bound.is_some() || other.bound.is_some() {
+            return None
+        }
+        Some(computed::CalcLengthOrPercentage {
+            length: self.length.interpolate(&other.length, progress).unwrap(),
+            percentage: self.percentage.interpolate(&other.percentage, progress).unwrap(),
+            bound: None,
+            non_negative: self.non_negative || other.non_negative,
+        })
+    }
+}
+
+impl Interpolate for computed::LengthOrPercentage {
+    fn interpolate(&self, other: &computed::LengthOrPercentage, progress: CSSFloat)
+                   -> Option<computed::LengthOrPercentage> {
+        match (*self, *other) {
+            (computed::LP_Length(from), computed::LP_Length(to)) => {
+                from.interpolate(&to, progress).map(|value| computed::LP_Length(value))
+            }
+            (computed::LP_Percentage(from), computed::LP_Percentage(to)) => {
+                from.interpolate(&to, progress).map(|value| computed::LP_Percentage(value))
+            }
+            (from, to) => {
+                from.to_calc().interpolate(&to.to_calc(), progress)
+                              .map(|value| computed::LP_Calc(value))
+            }
+        }
+    }
+}
+
+impl Interpolate for computed::LengthOrPercentageOrAuto {
+    fn interpolate(&self, other: &computed::LengthOrPercentageOrAuto, progress: CSSFloat)
+                   -> Option<computed::LengthOrPercentageOrAuto> {
+        match (*self, *other) {
+            (computed::LPA_Length(from), computed::LPA_Length(to)) => {
+                from.interpolate(&to, progress).map(|value| computed::LPA_Length(value))
+            }
+            (computed::LPA_Percentage(from), computed::LPA_Percentage(to)) => {
+                from.interpolate(&to, progress).map(|value| computed::LPA_Percentage(value))
+            }
+            (from, to) => match (from.to_calc(), to.to_calc()) {
+                (Some(from), Some(to)) => {
+                    from.interpolate(&to, progress).map(|value| computed::LPA_Calc(value))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Interpolate for computed::LengthOrPercentageOrNone {
+    fn interpolate(&self, other: &computed::LengthOrPercentageOrNone, progress: CSSFloat)
+                   -> Option<computed::LengthOrPercentageOrNone> {
+        match (*self, *other) {
+            (computed::LPN_Length(from), computed::LPN_Length(to)) => {
+                from.interpolate(&to, progress).map(|value| computed::LPN_Length(value))
+            }
+            (computed::LPN_Percentage(from), computed::LPN_Percentage(to)) => {
+                from.interpolate(&to, progress).map(|value| computed::LPN_Percentage(value))
+            }
+            (from, to) => match (from.to_calc(), to.to_calc()) {
+                (Some(from), Some(to)) => {
+                    from.interpolate(&to, progress).map(|value| computed::LPN_Calc(value))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Interpolate for longhands::transform::TransformOperation {
+    fn interpolate(&self, other: &longhands::transform::TransformOperation, progress: CSSFloat)
+                   -> Option<longhands::transform::TransformOperation> {
+        use longhands::transform::*;
+        let lerp = |from: CSSFloat, to: CSSFloat| from + (to - from) * progress;
+        match (self, other) {
+            (&TransformMatrix(a1, b1, c1, d1, e1, f1), &TransformMatrix(a2, b2, c2, d2, e2, f2)) => {
+                Some(TransformMatrix(lerp(a1, a2), lerp(b1, b2), lerp(c1, c2), lerp(d1, d2),
+                                     lerp(e1, e2), lerp(f1, f2)))
+            }
+            (&TransformTranslate(ref x1, ref y1), &TransformTranslate(ref x2, ref y2)) => {
+                match (x1.interpolate(x2, progress), y1.interpolate(y2, progress)) {
+                    (Some(x), Some(y)) => Some(TransformTranslate(x, y)),
+                    _ => None,
+                }
+            }
+            (&TransformScale(x1, y1), &TransformScale(x2, y2)) => {
+                Some(TransformScale(lerp(x1, x2), lerp(y1, y2)))
+            }
+            (&TransformRotate(angle1), &TransformRotate(angle2)) => {
+                Some(TransformRotate(lerp(angle1, angle2)))
+            }
+            (&TransformSkew(x1, y1), &TransformSkew(x2, y2)) => {
+                Some(TransformSkew(lerp(x1, x2), lerp(y1, y2)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the identity transform function of the same kind as the given one, used to interpolate
+/// from or to `none`.
+fn identity_transform_operation(operation: &longhands::transform::TransformOperation)
+                                -> longhands::transform::TransformOperation {
+    use longhands::transform::*;
+    match *operation {
+        TransformMatrix(*) => TransformMatrix(1., 0., 0., 1., 0., 0.),
+        TransformTranslate(*) => TransformTranslate(computed::LP_Length(Au(0)),
+                                                    computed::LP_Length(Au(0))),
+        TransformScale(*) => TransformScale(1., 1.),
+        TransformRotate(*) => TransformRotate(0.),
+        TransformSkew(*) => TransformSkew(0., 0.),
+    }
+}
+
+impl Interpolate for longhands::transform::computed_value::T {
+    /// Interpolates the transform functions pairwise. Both lists must have the same functions in
+    /// the same order, or one of them must be `none`.
+    fn interpolate(&self, other: &longhands::transform::computed_value::T, progress: CSSFloat)
+                   -> Option<longhands::transform::computed_value::T> {
+        let from = if self.is_empty() {
+            other.iter().map(identity_transform_operation).collect()
+        } else {
+            self.clone()
+        };
+        let to = if other.is_empty() {
+            self.iter().map(identity_transform_operation).collect()
+        } else {
+            other.clone()
+        };
+        if from.len() != to.len() {
+            return None
+        }
+        let mut result = ~[];
+        for (from, to) in from.iter().zip(to.iter()) {
+            match from.interpolate(to, progress) {
+                Some(operation) => result.push(operation),
+                None => return None,
+            }
+        }
+        Some(result)
+    }
+}
+
+<%
+    animatable_longhands = [(style_struct, longhand)
+                            for style_struct, longhands in LONGHANDS_PER_STYLE_STRUCT
+                            for longhand in longhands
+                            if longhand.name in ANIMATABLE]
+%>
+
+/// Returns true if transitions and animations interpolate the given lower case longhand, rather
+/// than switching from one value to the other halfway through.
+pub fn is_animatable_longhand(name: &str) -> bool {
+    match name {
+        % for style_struct, longhand in animatable_longhands:
+            "${longhand.name}" => true,
+        % endfor
+        _ => false,
+    }
+}
+
+/// Returns the names of the animatable longhands whose values differ between the two styles.
+pub fn changed_animatable_longhands(old: &ComputedValues, new: &ComputedValues)
+                                    -> ~[&'static str] {
+    let mut result = ~[];
+    % for style_struct, longhand in animatable_longhands:
+        if old.${style_struct}.${longhand.ident} != new.${style_struct}.${longhand.ident} {
+            result.push("${longhand.name}")
+        }
+    % endfor
+    result
+}
+
+/// Sets the given longhand of `result` to its value at `progress` between the values of `from`
+/// and `to`. Values that can not be interpolated switch from one to the other halfway through.
+/// Returns false if the longhand is not animatable.
+pub fn interpolate_longhand(name: &str, from: &ComputedValues, to: &ComputedValues,
+                            progress: CSSFloat, result: &mut ComputedValues) -> bool {
+    match name {
+        % for style_struct, longhand in animatable_longhands:
+            "${longhand.name}" => {
+                let from_value = &from.${style_struct}.${longhand.ident};
+                let to_value = &to.${style_struct}.${longhand.ident};
+                result.${style_struct}.${longhand.ident} =
+                        match from_value.interpolate(to_value, progress) {
+                    Some(value) => value,
+                    None if progress < 0.5 => from_value.clone(),
+                    None => to_value.clone(),
+                };
+                % if longhand.name == "opacity":
+                    result.${style_struct}.${longhand.ident} =
+                        result.${style_struct}.${longhand.ident}.max(&0.).min(&1.);
+                % elif longhand.name in NON_NEGATIVE_ANIMATABLE:
+                    result.${style_struct}.${longhand.ident} =
+                        result.${style_struct}.${longhand.ident}.clamp_to_non_negative();
+                % endif
+                true
+            }
+        % endfor
+        _ => false,
+    }
 }
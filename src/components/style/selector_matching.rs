@@ -4,6 +4,7 @@
 
 use extra::arc::Arc;
 use extra::sort::tim_sort;
+use servo_util::bloom::{BloomFilter, hash_str};
 use std::ascii::StrAsciiExt;
 use std::hashmap::HashMap;
 use std::str;
@@ -12,7 +13,9 @@ use media_queries::{Device, Screen};
 use node::{TElement, TNode};
 use properties::{PropertyDeclaration, PropertyDeclarationBlock};
 use selectors::*;
-use stylesheets::{Stylesheet, iter_style_rules};
+use stylesheets::{Stylesheet, KeyframesRule, iter_style_rules, iter_keyframes_rules};
+use stylesheets::iter_font_face_rules;
+use font_face::FontFaceRule;
 
 pub enum StylesheetOrigin {
     UserAgentOrigin,
@@ -23,6 +26,26 @@ pub enum StylesheetOrigin {
 /// The definition of whitespace per CSS Selectors Level 3 § 4.
 static SELECTOR_WHITESPACE: &'static [char] = &'static [' ', '\t', '\n', '\r', '\x0C'];
 
+/// The maximum number of ancestor hashes kept for each rule.
+static NUM_ANCESTOR_HASHES: uint = 4;
+
+/// Counts of the rules considered during selector matching, reported to the profiler.
+pub struct SelectorMatchingStats {
+    /// The number of rules rejected by the ancestor bloom filter without walking the tree.
+    fast_rejected_rules: uint,
+    /// The number of rules that went through full selector matching.
+    fully_matched_rules: uint,
+}
+
+impl SelectorMatchingStats {
+    pub fn new() -> SelectorMatchingStats {
+        SelectorMatchingStats {
+            fast_rejected_rules: 0,
+            fully_matched_rules: 0,
+        }
+    }
+}
+
 /// Map node attributes to Rules whose last simple selector starts with them.
 ///
 /// e.g.,
@@ -66,10 +89,15 @@ impl SelectorMap {
     ///
     /// Extract matching rules as per node's ID, classes, tag name, etc..
     /// Sort the Rules at the end to maintain cascading order.
+    ///
+    /// `affected_by_siblings` is set if any of the rules looked at depends on siblings.
     fn get_all_matching_rules<E:TElement,
                               N:TNode<E>>(
                               &self,
                               node: &N,
+                              bloom_filter: &BloomFilter,
+                              stats: &mut SelectorMatchingStats,
+                              affected_by_siblings: &mut bool,
                               matching_rules_list: &mut ~[Rule]) {
         // At the end, we're going to sort the rules that we added, so remember where we began.
         let init_len = matching_rules_list.len();
@@ -77,6 +105,9 @@ impl SelectorMap {
             match element.get_attr(None, "id") {
                 Some(id) => {
                     SelectorMap::get_matching_rules_from_hash(node,
+                                                              bloom_filter,
+                                                              stats,
+                                                              affected_by_siblings,
                                                               &self.id_hash,
                                                               id,
                                                               matching_rules_list)
@@ -88,6 +119,9 @@ impl SelectorMap {
                 Some(ref class_attr) => {
                     for class in class_attr.split_iter(SELECTOR_WHITESPACE) {
                         SelectorMap::get_matching_rules_from_hash(node,
+                                                                  bloom_filter,
+                                                                  stats,
+                                                                  affected_by_siblings,
                                                                   &self.class_hash,
                                                                   class,
                                                                   matching_rules_list)
@@ -99,10 +133,16 @@ impl SelectorMap {
             // HTML elements in HTML documents must be matched case-insensitively.
             // TODO(pradeep): Case-sensitivity depends on the document type.
             SelectorMap::get_matching_rules_from_hash(node,
+                                                      bloom_filter,
+                                                      stats,
+                                                      affected_by_siblings,
                                                       &self.element_hash,
                                                       element.get_local_name().to_ascii_lower(),
                                                       matching_rules_list);
             SelectorMap::get_matching_rules(node,
+                                            bloom_filter,
+                                            stats,
+                                            affected_by_siblings,
                                             self.universal_rules,
                                             matching_rules_list);
         });
@@ -114,24 +154,49 @@ impl SelectorMap {
     fn get_matching_rules_from_hash<E:TElement,
                                     N:TNode<E>>(
                                     node: &N,
+                                    bloom_filter: &BloomFilter,
+                                    stats: &mut SelectorMatchingStats,
+                                    affected_by_siblings: &mut bool,
                                     hash: &HashMap<~str,~[Rule]>,
                                     key: &str,
                                     matching_rules: &mut ~[Rule]) {
         match hash.find(&key.to_str()) {
             Some(rules) => {
-                SelectorMap::get_matching_rules(node, *rules, matching_rules)
+                SelectorMap::get_matching_rules(node,
+                                                bloom_filter,
+                                                stats,
+                                                affected_by_siblings,
+                                                *rules,
+                                                matching_rules)
             }
             None => {}
         }
     }
 
     /// Adds rules in `rules` that match `node` to the `matching_rules` list.
+    ///
+    /// Rules that require an ancestor with an ID, class or local name that is not in the bloom
+    /// filter are rejected without walking the tree.
     fn get_matching_rules<E:TElement,
                           N:TNode<E>>(
                           node: &N,
+                          bloom_filter: &BloomFilter,
+                          stats: &mut SelectorMatchingStats,
+                          affected_by_siblings: &mut bool,
                           rules: &[Rule],
                           matching_rules: &mut ~[Rule]) {
         for rule in rules.iter() {
+            if rule.depends_on_siblings {
+                *affected_by_siblings = true
+            }
+            if !rule.ancestor_hashes.iter().all(|&hash| {
+                    hash == 0 || bloom_filter.might_contain_hash(hash)
+            }) {
+                stats.fast_rejected_rules += 1;
+                continue
+            }
+
+            stats.fully_matched_rules += 1;
             if matches_compound_selector(rule.selector.get(), node) {
                 // TODO(pradeep): Is the cloning inefficient?
                 matching_rules.push(rule.clone());
@@ -236,7 +301,29 @@ pub struct Stylist {
     priv element_map: PerPseudoElementSelectorMap,
     priv before_map: PerPseudoElementSelectorMap,
     priv after_map: PerPseudoElementSelectorMap,
+    priv first_line_map: PerPseudoElementSelectorMap,
+    priv first_letter_map: PerPseudoElementSelectorMap,
     priv rules_source_order: uint,
+    // The attributes, other than `id` and `class`, that appear in attribute selectors.
+    priv style_affecting_attributes: ~[AttrSelector],
+    // The `@keyframes` rules by name. A later rule replaces an earlier one with the same name.
+    priv keyframes: HashMap<~str, KeyframesRule>,
+    // The `@font-face` rules, in the order their stylesheets were added.
+    priv font_face_rules: ~[FontFaceRule],
+}
+
+/// The information about an element that selectors in a `Stylist` can observe, apart from its
+/// ancestors. Two elements with equal keys whose parents have the same style have the same style
+/// as well.
+#[deriving(Eq, Clone)]
+pub struct StyleSharingKey {
+    priv local_name: ~str,
+    priv namespace_url: ~str,
+    priv class: Option<~str>,
+    // `None` if the element is not a link; otherwise whether the link has been visited.
+    priv link_state: Option<bool>,
+    // The values of the style affecting attributes of the `Stylist`, in order.
+    priv attributes: ~[Option<~str>],
 }
 
 impl Stylist {
@@ -246,44 +333,75 @@ impl Stylist {
             element_map: PerPseudoElementSelectorMap::new(),
             before_map: PerPseudoElementSelectorMap::new(),
             after_map: PerPseudoElementSelectorMap::new(),
+            first_line_map: PerPseudoElementSelectorMap::new(),
+            first_letter_map: PerPseudoElementSelectorMap::new(),
             rules_source_order: 0u,
+            style_affecting_attributes: ~[],
+            keyframes: HashMap::new(),
+            font_face_rules: ~[],
         }
     }
 
     pub fn add_stylesheet(&mut self, stylesheet: Stylesheet, origin: StylesheetOrigin) {
-        let (mut element_map, mut before_map, mut after_map) = match origin {
+        let (mut element_map, mut before_map, mut after_map, mut first_line_map,
+             mut first_letter_map) = match origin {
             UserAgentOrigin => (
                 &mut self.element_map.user_agent,
                 &mut self.before_map.user_agent,
                 &mut self.after_map.user_agent,
+                &mut self.first_line_map.user_agent,
+                &mut self.first_letter_map.user_agent,
             ),
             AuthorOrigin => (
                 &mut self.element_map.author,
                 &mut self.before_map.author,
                 &mut self.after_map.author,
+                &mut self.first_line_map.author,
+                &mut self.first_letter_map.author,
             ),
             UserOrigin => (
                 &mut self.element_map.user,
                 &mut self.before_map.user,
                 &mut self.after_map.user,
+                &mut self.first_line_map.user,
+                &mut self.first_letter_map.user,
             ),
         };
 
         // Take apart the StyleRule into individual Rules and insert
         // them into the SelectorMap of that priority.
+        //
+        // ::first-line and ::first-letter only accept a subset of properties (CSS 2.1 § 5.12), so
+        // the other declarations are dropped here once rather than at every cascade.
         macro_rules! append(
             ($priority: ident) => {
                 if style_rule.declarations.$priority.get().len() > 0 {
                     for selector in style_rule.selectors.iter() {
-                        let map = match selector.pseudo_element {
-                            None => &mut element_map,
-                            Some(Before) => &mut before_map,
-                            Some(After) => &mut after_map,
+                        let (map, declarations) = match selector.pseudo_element {
+                            None => (&mut element_map,
+                                     style_rule.declarations.$priority.clone()),
+                            Some(Before) => (&mut before_map,
+                                             style_rule.declarations.$priority.clone()),
+                            Some(After) => (&mut after_map,
+                                            style_rule.declarations.$priority.clone()),
+                            Some(FirstLine) => (&mut first_line_map, filter_declarations(
+                                style_rule.declarations.$priority.get(),
+                                |declaration| declaration.applies_to_first_line())),
+                            Some(FirstLetter) => (&mut first_letter_map, filter_declarations(
+                                style_rule.declarations.$priority.get(),
+                                |declaration| declaration.applies_to_first_letter())),
                         };
+                        let mut depends_on_siblings = false;
+                        note_selector(selector.compound_selectors.get(),
+                                      &mut self.style_affecting_attributes,
+                                      &mut depends_on_siblings);
                         map.$priority.insert(Rule {
                                 selector: selector.compound_selectors.clone(),
+                                ancestor_hashes: compute_ancestor_hashes(
+                                    selector.compound_selectors.get()),
+                                depends_on_siblings: depends_on_siblings,
                                 specificity: selector.specificity,
-                                declarations: style_rule.declarations.$priority.clone(),
+                                declarations: declarations,
                                 source_order: self.rules_source_order,
                         });
                     }
@@ -297,16 +415,63 @@ impl Stylist {
             append!(important);
             self.rules_source_order += 1;
         }
+        do iter_keyframes_rules(stylesheet.rules.as_slice(), device) |keyframes_rule| {
+            self.keyframes.insert(keyframes_rule.name.clone(), keyframes_rule.clone());
+        }
+        do iter_font_face_rules(stylesheet.rules.as_slice(), device) |font_face_rule| {
+            self.font_face_rules.push(font_face_rule.clone());
+        }
+    }
+
+    /// Returns the `@keyframes` rule with the given name, if any.
+    pub fn keyframes<'a>(&'a self, name: &str) -> Option<&'a KeyframesRule> {
+        self.keyframes.find_equiv(&name)
+    }
+
+    /// Returns the `@font-face` rules of every stylesheet added so far.
+    pub fn font_face_rules<'a>(&'a self) -> &'a [FontFaceRule] {
+        self.font_face_rules.as_slice()
+    }
+
+    /// Returns the key under which the style of the given element can be shared with other
+    /// elements, or `None` if its style cannot be shared. Elements with an ID or a style
+    /// attribute never share.
+    ///
+    /// Elements with equal keys look at the same rules during selector matching. The key does
+    /// not cover siblings, so the results of an element are only shared if none of the rules it
+    /// looked at depends on siblings; see `get_applicable_declarations`.
+    pub fn style_sharing_key<E:TElement>(&self, element: &E) -> Option<StyleSharingKey> {
+        if element.get_attr(None, "id").is_some() ||
+                element.get_attr(None, "style").is_some() {
+            return None
+        }
+
+        Some(StyleSharingKey {
+            local_name: element.get_local_name().to_owned(),
+            namespace_url: element.get_namespace_url().to_owned(),
+            class: element.get_attr(None, "class"),
+            link_state: element.get_link().map(|url| url_is_visited(url)),
+            attributes: self.style_affecting_attributes.iter().map(|attr| {
+                element.get_attr(attr.namespace.clone(), attr.name)
+            }).collect(),
+        })
     }
 
     /// Returns the applicable CSS declarations for the given element. This corresponds to
     /// `ElementRuleCollector` in WebKit.
+    ///
+    /// The bloom filter must contain the hashes of every ancestor of the element, as given by
+    /// `each_element_hash`. `affected_by_siblings` is set if any rule that was looked at, matching
+    /// or not, depends on the siblings of the element or of its ancestors.
     pub fn get_applicable_declarations<E:TElement,
                                        N:TNode<E>>(
                                        &self,
                                        element: &N,
                                        style_attribute: Option<&PropertyDeclarationBlock>,
-                                       pseudo_element: Option<PseudoElement>)
+                                       pseudo_element: Option<PseudoElement>,
+                                       bloom_filter: &BloomFilter,
+                                       stats: &mut SelectorMatchingStats,
+                                       affected_by_siblings: &mut bool)
                                        -> ~[Arc<~[PropertyDeclaration]>] {
         assert!(element.is_element());
         assert!(style_attribute.is_none() || pseudo_element.is_none(),
@@ -316,6 +481,8 @@ impl Stylist {
             None => &self.element_map,
             Some(Before) => &self.before_map,
             Some(After) => &self.after_map,
+            Some(FirstLine) => &self.first_line_map,
+            Some(FirstLetter) => &self.first_letter_map,
         };
         // In cascading order:
         let rule_map_list = [
@@ -336,7 +503,11 @@ impl Stylist {
 
         for (i, rule_map) in rule_map_list.iter().enumerate() {
             rule_map_indices[i] = matching_rules_list.len();
-            rule_map.get_all_matching_rules(element, &mut matching_rules_list);
+            rule_map.get_all_matching_rules(element,
+                                            bloom_filter,
+                                            stats,
+                                            affected_by_siblings,
+                                            &mut matching_rules_list);
         }
 
         let count = matching_rules_list.len();
@@ -419,12 +590,134 @@ struct Rule {
     // that it matches. Selector contains an owned vector (through
     // CompoundSelector) and we want to avoid the allocation.
     selector: Arc<CompoundSelector>,
+    // Bloom filter hashes of the IDs, classes and local names that ancestors of a matching
+    // element must have. Unused entries are zero.
+    ancestor_hashes: [u32, ..NUM_ANCESTOR_HASHES],
+    // Whether the selector looks at the siblings of the element or of one of its ancestors.
+    depends_on_siblings: bool,
     declarations: Arc<~[PropertyDeclaration]>,
     // Precedence among rules of equal specificity
     source_order: uint,
     specificity: u32,
 }
 
+/// Returns a copy of `declarations` containing only the declarations accepted by `predicate`.
+fn filter_declarations(declarations: &[PropertyDeclaration],
+                       predicate: &fn(&PropertyDeclaration) -> bool)
+                       -> Arc<~[PropertyDeclaration]> {
+    Arc::new(declarations.iter().filter(|declaration| predicate(*declaration))
+                                .map(|declaration| declaration.clone())
+                                .collect())
+}
+
+/// Records what the given selector can observe about elements, other than their ancestors: the
+/// attributes it looks at, which `Stylist::style_sharing_key` relies on, and whether it depends on
+/// siblings.
+fn note_selector(selector: &CompoundSelector,
+                 style_affecting_attributes: &mut ~[AttrSelector],
+                 depends_on_siblings: &mut bool) {
+    let mut current = Some(selector);
+    while current.is_some() {
+        let compound = current.unwrap();
+        for simple_selector in compound.simple_selectors.iter() {
+            note_simple_selector(simple_selector, style_affecting_attributes, depends_on_siblings)
+        }
+        current = match compound.next {
+            None => None,
+            Some((ref next, combinator)) => {
+                match combinator {
+                    NextSibling | LaterSibling => *depends_on_siblings = true,
+                    Child | Descendant => {}
+                }
+                Some(&**next)
+            }
+        }
+    }
+}
+
+fn note_simple_selector(selector: &SimpleSelector,
+                        style_affecting_attributes: &mut ~[AttrSelector],
+                        depends_on_siblings: &mut bool) {
+    match *selector {
+        AttrExists(ref attr) | AttrEqual(ref attr, _) | AttrIncludes(ref attr, _)
+        | AttrDashMatch(ref attr, _, _) | AttrPrefixMatch(ref attr, _)
+        | AttrSubstringMatch(ref attr, _) | AttrSuffixMatch(ref attr, _) => {
+            if !style_affecting_attributes.contains(attr) {
+                style_affecting_attributes.push(attr.clone())
+            }
+        }
+        FirstChild | LastChild | OnlyChild | NthChild(*) | NthLastChild(*) | NthOfType(*)
+        | NthLastOfType(*) | FirstOfType | LastOfType | OnlyOfType => *depends_on_siblings = true,
+        Negation(ref negated) => {
+            for simple_selector in negated.iter() {
+                note_simple_selector(simple_selector,
+                                     style_affecting_attributes,
+                                     depends_on_siblings)
+            }
+        }
+        IDSelector(*) | ClassSelector(*) | LocalNameSelector(*) | NamespaceSelector(*)
+        | AnyLink | Link | Visited | Root => {}
+    }
+}
+
+/// Computes the ancestor hashes of a rule with the given selector. Only compound selectors
+/// followed by a child or descendant combinator describe ancestors; those followed by a sibling
+/// combinator describe siblings of the element or of one of its ancestors.
+fn compute_ancestor_hashes(selector: &CompoundSelector) -> [u32, ..NUM_ANCESTOR_HASHES] {
+    let mut hashes = [0u32, ..NUM_ANCESTOR_HASHES];
+    let mut count = 0;
+    let mut next = &selector.next;
+    loop {
+        match *next {
+            None => return hashes,
+            Some((ref next_selector, combinator)) => {
+                match combinator {
+                    Child | Descendant => {
+                        for simple_selector in next_selector.simple_selectors.iter() {
+                            let hash = match *simple_selector {
+                                IDSelector(ref id) => hash_str(*id),
+                                ClassSelector(ref class) => hash_str(*class),
+                                // HTML elements in HTML documents must be matched
+                                // case-insensitively.
+                                LocalNameSelector(ref name) => hash_str(name.to_ascii_lower()),
+                                _ => continue,
+                            };
+                            if count == NUM_ANCESTOR_HASHES {
+                                return hashes
+                            }
+                            hashes[count] = hash;
+                            count += 1
+                        }
+                    }
+                    NextSibling | LaterSibling => {}
+                }
+                next = &next_selector.next
+            }
+        }
+    }
+}
+
+/// Calls the given closure with the bloom filter hash of the ID, of each class and of the local
+/// name of the given element. Callers of `Stylist::get_applicable_declarations` insert these for
+/// every ancestor of the element being matched.
+pub fn each_element_hash<E:TElement>(element: &E, callback: &fn(u32)) {
+    match element.get_attr(None, "id") {
+        Some(id) => callback(hash_str(id)),
+        None => {}
+    }
+    match element.get_attr(None, "class") {
+        Some(ref class_attr) => {
+            for class in class_attr.split_iter(SELECTOR_WHITESPACE) {
+                if !class.is_empty() {
+                    callback(hash_str(class))
+                }
+            }
+        }
+        None => {}
+    }
+    callback(hash_str(element.get_local_name().to_ascii_lower()))
+}
+
 impl Ord for Rule {
     #[inline]
     fn lt(&self, other: &Rule) -> bool {
@@ -719,7 +1012,8 @@ fn match_attribute<E:TElement,
 #[cfg(test)]
 mod tests {
     use extra::arc::Arc;
-    use super::{Rule, SelectorMap};
+    use super::{Rule, SelectorMap, compute_ancestor_hashes, note_selector};
+    use servo_util::bloom::hash_str;
 
     /// Helper method to get some Rules from selector strings.
     /// Each sublist of the result contains the Rules for one StyleRule.
@@ -734,6 +1028,8 @@ mod tests {
             .unwrap().move_iter().map(|s| {
                 Rule {
                     specificity: s.specificity,
+                    ancestor_hashes: compute_ancestor_hashes(s.compound_selectors.get()),
+                    depends_on_siblings: false,
                     selector: s.compound_selectors,
                     declarations: Arc::new(~[]),
                     source_order: i,
@@ -783,4 +1079,35 @@ mod tests {
         assert_eq!(0, selector_map.class_hash.find(&~"intro").unwrap()[0].source_order);
         assert!(selector_map.class_hash.find(&~"foo").is_none());
     }
+
+    #[test]
+    fn test_ancestor_hashes(){
+        let rules_list = get_mock_rules(["div#top > P.intro em", "ul + li", "a b c d e f"]);
+        let hashes = rules_list[0][0].ancestor_hashes;
+        assert_eq!(hashes[0], hash_str("p"));
+        assert_eq!(hashes[1], hash_str("intro"));
+        assert_eq!(hashes[2], hash_str("div"));
+        assert_eq!(hashes[3], hash_str("top"));
+        assert_eq!(rules_list[1][0].ancestor_hashes, [0, 0, 0, 0]);
+        assert_eq!(rules_list[2][0].ancestor_hashes[3], hash_str("b"));
+    }
+
+    #[test]
+    fn test_note_selector(){
+        let rules_list = get_mock_rules(["a[href] > b", "li + li", "p:not(:first-child)"]);
+        let mut attributes = ~[];
+        let mut depends_on_siblings = false;
+        note_selector(rules_list[0][0].selector.get(), &mut attributes, &mut depends_on_siblings);
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name, ~"href");
+        assert!(!depends_on_siblings);
+
+        note_selector(rules_list[1][0].selector.get(), &mut attributes, &mut depends_on_siblings);
+        assert!(depends_on_siblings);
+
+        depends_on_siblings = false;
+        note_selector(rules_list[2][0].selector.get(), &mut attributes, &mut depends_on_siblings);
+        assert!(depends_on_siblings);
+        assert_eq!(attributes.len(), 1);
+    }
 }
@@ -34,8 +34,8 @@ pub static STYLE_ATTRIBUTE_SPECIFICITY: u32 = 1 << 31;
 pub enum PseudoElement {
     Before,
     After,
-//    FirstLine,
-//    FirstLetter,
+    FirstLine,
+    FirstLetter,
 }
 
 
@@ -327,8 +327,8 @@ fn parse_one_simple_selector(iter: &mut Iter, namespaces: &NamespaceMap, inside_
                             // ** Do not add to this list! **
                             "before" => PseudoElementResult(Before),
                             "after" => PseudoElementResult(After),
-//                            "first-line" => PseudoElementResult(FirstLine),
-//                            "first-letter" => PseudoElementResult(FirstLetter),
+                            "first-line" => PseudoElementResult(FirstLine),
+                            "first-letter" => PseudoElementResult(FirstLetter),
                             _ => InvalidSimpleSelector
                         }
                     },
@@ -501,8 +501,8 @@ fn parse_pseudo_element(name: ~str) -> Option<PseudoElement> {
         // All supported pseudo-elements
         "before" => Some(Before),
         "after" => Some(After),
-//        "first-line" => Some(FirstLine),
-//        "first-letter" => Some(FirstLetter),
+        "first-line" => Some(FirstLine),
+        "first-letter" => Some(FirstLetter),
         _ => None
     }
 }
@@ -624,5 +624,21 @@ mod tests {
             pseudo_element: None,
             specificity: specificity(1, 1, 1),
         }]))
+        assert_eq!(parse("p::first-line"), Some(~[Selector{
+            compound_selectors: Arc::new(CompoundSelector {
+                simple_selectors: ~[LocalNameSelector(~"p")],
+                next: None,
+            }),
+            pseudo_element: Some(FirstLine),
+            specificity: specificity(0, 0, 2),
+        }]))
+        assert_eq!(parse("p:first-letter"), Some(~[Selector{
+            compound_selectors: Arc::new(CompoundSelector {
+                simple_selectors: ~[LocalNameSelector(~"p")],
+                next: None,
+            }),
+            pseudo_element: Some(FirstLetter),
+            specificity: specificity(0, 0, 2),
+        }]))
     }
 }
@@ -8,14 +8,18 @@ use extra::url::Url;
 
 use encoding::EncodingRef;
 
-use cssparser::{decode_stylesheet_bytes, tokenize, parse_stylesheet_rules, ToCss};
+use cssparser::{decode_stylesheet_bytes, tokenize, parse_stylesheet_rules, parse_rule_list, ToCss};
 use cssparser::ast::*;
 use selectors;
 use properties;
-use errors::{ErrorLoggerIterator, log_css_error};
+use errors::{CSSError, ErrorLoggerIterator, InvalidAtRuleError, InvalidSelectorError};
+use parsing_utils::split_on_commas;
+use properties::CSSFloat;
+use errors::{UnsupportedAtRuleError, collect_css_errors, log_css_error};
 use namespaces::{NamespaceMap, parse_namespace_rule};
 use media_queries::{MediaRule, parse_media_rule};
 use media_queries;
+use font_face::{FontFaceRule, parse_font_face_rule};
 
 
 pub struct Stylesheet {
@@ -25,18 +29,57 @@ pub struct Stylesheet {
     namespaces: NamespaceMap,
     encoding: EncodingRef,
     base_url: Url,
+    /// The errors found while parsing, in source order. The offending constructs were ignored.
+    errors: ~[CSSError],
 }
 
 
+impl Clone for Stylesheet {
+    fn clone(&self) -> Stylesheet {
+        Stylesheet {
+            rules: self.rules.clone(),
+            namespaces: self.namespaces.clone(),
+            encoding: self.encoding,
+            base_url: self.base_url.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+
+#[deriving(Clone)]
 pub enum CSSRule {
     CSSStyleRule(StyleRule),
     CSSMediaRule(MediaRule),
+    CSSKeyframesRule(KeyframesRule),
+    CSSFontFaceRule(FontFaceRule),
 }
 
 
+#[deriving(Clone)]
 pub struct StyleRule {
     selectors: ~[selectors::Selector],
     declarations: properties::PropertyDeclarationBlock,
+    /// The selectors as written, for the CSSOM.
+    selector_text: ~str,
+}
+
+
+/// An `@keyframes` rule.
+#[deriving(Clone)]
+pub struct KeyframesRule {
+    name: ~str,
+    /// The keyframes in source order.
+    keyframes: ~[Keyframe],
+}
+
+
+#[deriving(Clone)]
+pub struct Keyframe {
+    /// The offsets of this keyframe in the animation, from 0 for `from` to 1 for `to`.
+    offsets: ~[CSSFloat],
+    /// `!important` declarations are ignored in keyframes.
+    declarations: properties::PropertyDeclarationBlock,
 }
 
 
@@ -61,92 +104,179 @@ impl Stylesheet {
     }
 
     pub fn from_str(css: &str, base_url: Url, encoding: EncodingRef) -> Stylesheet {
-        static STATE_CHARSET: uint = 1;
-        static STATE_IMPORTS: uint = 2;
-        static STATE_NAMESPACES: uint = 3;
-        static STATE_BODY: uint = 4;
-        let mut state: uint = STATE_CHARSET;
-
-        let mut rules = ~[];
-        let mut namespaces = NamespaceMap::new();
-
-        for rule in ErrorLoggerIterator(parse_stylesheet_rules(tokenize(css))) {
-            let next_state;  // Unitialized to force each branch to set it.
-            match rule {
-                QualifiedRule(rule) => {
-                    next_state = STATE_BODY;
-                    parse_style_rule(rule, &mut rules, &namespaces)
-                },
-                AtRule(rule) => {
-                    let lower_name = rule.name.to_ascii_lower();
-                    match lower_name.as_slice() {
-                        "charset" => {
-                            if state > STATE_CHARSET {
-                                log_css_error(rule.location, "@charset must be the first rule")
-                            }
-                            // Valid @charset rules are just ignored
+        let ((rules, namespaces), errors) = do collect_css_errors(base_url.to_str()) {
+            parse_stylesheet(css, &base_url)
+        };
+        Stylesheet {
+            rules: rules,
+            namespaces: namespaces,
+            encoding: encoding,
+            base_url: base_url,
+            errors: errors,
+        }
+    }
+}
+
+
+fn parse_stylesheet(css: &str, base_url: &Url) -> (~[CSSRule], NamespaceMap) {
+    static STATE_CHARSET: uint = 1;
+    static STATE_IMPORTS: uint = 2;
+    static STATE_NAMESPACES: uint = 3;
+    static STATE_BODY: uint = 4;
+    let mut state: uint = STATE_CHARSET;
+
+    let mut rules = ~[];
+    let mut namespaces = NamespaceMap::new();
+
+    for rule in ErrorLoggerIterator(parse_stylesheet_rules(tokenize(css))) {
+        let next_state;  // Unitialized to force each branch to set it.
+        match rule {
+            QualifiedRule(rule) => {
+                next_state = STATE_BODY;
+                parse_style_rule(rule, &mut rules, &namespaces, base_url)
+            },
+            AtRule(rule) => {
+                let lower_name = rule.name.to_ascii_lower();
+                match lower_name.as_slice() {
+                    "charset" => {
+                        if state > STATE_CHARSET {
+                            log_css_error(rule.location, InvalidAtRuleError, "@charset",
+                                          "@charset must be the first rule")
+                        }
+                        // Valid @charset rules are just ignored
+                        next_state = STATE_IMPORTS;
+                    },
+                    "import" => {
+                        if state > STATE_IMPORTS {
+                            next_state = state;
+                            log_css_error(rule.location, InvalidAtRuleError, "@import",
+                                          "@import must be before any rule but @charset")
+                        } else {
                             next_state = STATE_IMPORTS;
-                        },
-                        "import" => {
-                            if state > STATE_IMPORTS {
-                                next_state = state;
-                                log_css_error(rule.location,
-                                              "@import must be before any rule but @charset")
-                            } else {
-                                next_state = STATE_IMPORTS;
-                                // TODO: support @import
-                                log_css_error(rule.location, "@import is not supported yet")
-                            }
-                        },
-                        "namespace" => {
-                            if state > STATE_NAMESPACES {
-                                next_state = state;
-                                log_css_error(
-                                    rule.location,
-                                    "@namespace must be before any rule but @charset and @import"
-                                )
-                            } else {
-                                next_state = STATE_NAMESPACES;
-                                parse_namespace_rule(rule, &mut namespaces)
-                            }
-                        },
-                        _ => {
-                            next_state = STATE_BODY;
-                            parse_nested_at_rule(lower_name, rule, &mut rules, &namespaces)
-                        },
-                    }
-                },
-            }
-            state = next_state;
+                            // TODO: support @import
+                            log_css_error(rule.location, UnsupportedAtRuleError, "@import",
+                                          "@import is not supported yet")
+                        }
+                    },
+                    "namespace" => {
+                        if state > STATE_NAMESPACES {
+                            next_state = state;
+                            log_css_error(
+                                rule.location, InvalidAtRuleError, "@namespace",
+                                "@namespace must be before any rule but @charset and @import"
+                            )
+                        } else {
+                            next_state = STATE_NAMESPACES;
+                            parse_namespace_rule(rule, &mut namespaces)
+                        }
+                    },
+                    _ => {
+                        next_state = STATE_BODY;
+                        parse_nested_at_rule(lower_name, rule, &mut rules, &namespaces, base_url)
+                    },
+                }
+            },
         }
-        Stylesheet{ rules: rules, namespaces: namespaces, encoding: encoding, base_url: base_url }
+        state = next_state;
     }
+    (rules, namespaces)
 }
 
 
 pub fn parse_style_rule(rule: QualifiedRule, parent_rules: &mut ~[CSSRule],
-                        namespaces: &NamespaceMap) {
+                        namespaces: &NamespaceMap, base_url: &Url) {
     let QualifiedRule{location: location, prelude: prelude, block: block} = rule;
-    // FIXME: avoid doing this for valid selectors
     let serialized = prelude.iter().to_css();
     match selectors::parse_selector_list(prelude, namespaces) {
         Some(selectors) => parent_rules.push(CSSStyleRule(StyleRule{
             selectors: selectors,
-            declarations: properties::parse_property_declaration_list(block.move_iter())
+            declarations: properties::parse_property_declaration_list(block.move_iter(),
+                                                                      base_url),
+            selector_text: serialized.trim().to_owned(),
         })),
-        None => log_css_error(location, format!(
-            "Invalid/unsupported selector: {}", serialized)),
+        None => log_css_error(location, InvalidSelectorError, serialized.trim(),
+                              "Invalid/unsupported selector"),
     }
 }
 
 
 // lower_name is passed explicitly to avoid computing it twice.
 pub fn parse_nested_at_rule(lower_name: &str, rule: AtRule,
-                            parent_rules: &mut ~[CSSRule], namespaces: &NamespaceMap) {
+                            parent_rules: &mut ~[CSSRule], namespaces: &NamespaceMap,
+                            base_url: &Url) {
     match lower_name {
-        "media" => parse_media_rule(rule, parent_rules, namespaces),
-        _ => log_css_error(rule.location, format!("Unsupported at-rule: @{:s}", lower_name))
+        "media" => parse_media_rule(rule, parent_rules, namespaces, base_url),
+        "keyframes" => parse_keyframes_rule(rule, parent_rules, base_url),
+        "font-face" => parse_font_face_rule(rule, parent_rules, base_url),
+        _ => log_css_error(rule.location, UnsupportedAtRuleError, format!("@{:s}", lower_name),
+                           "Unsupported at-rule")
+    }
+}
+
+
+pub fn parse_keyframes_rule(rule: AtRule, parent_rules: &mut ~[CSSRule], base_url: &Url) {
+    let prelude: ~[&ComponentValue] = rule.prelude.skip_whitespace().collect();
+    let name = match prelude.as_slice() {
+        [&Ident(ref name)] => name.to_owned(),
+        [&String(ref name)] => name.to_owned(),
+        _ => {
+            log_css_error(rule.location, InvalidAtRuleError, "@keyframes",
+                          "Invalid @keyframes name");
+            return
+        }
+    };
+    let block = match rule.block {
+        Some(block) => block,
+        None => {
+            log_css_error(rule.location, InvalidAtRuleError, "@keyframes",
+                          "Invalid @keyframes rule");
+            return
+        }
+    };
+    let mut keyframes = ~[];
+    for rule in ErrorLoggerIterator(parse_rule_list(block.move_iter())) {
+        match rule {
+            QualifiedRule(rule) => {
+                let QualifiedRule{location: location, prelude: prelude, block: block} = rule;
+                match parse_keyframe_selector(prelude) {
+                    Some(offsets) => keyframes.push(Keyframe {
+                        offsets: offsets,
+                        declarations: properties::parse_property_declaration_list(
+                            block.move_iter(), base_url),
+                    }),
+                    None => log_css_error(location, InvalidSelectorError,
+                                          prelude.iter().to_css().trim(),
+                                          "Invalid keyframe selector"),
+                }
+            }
+            AtRule(rule) => log_css_error(rule.location, InvalidAtRuleError,
+                                          format!("@{:s}", rule.name),
+                                          "At-rules are not allowed in @keyframes"),
+        }
     }
+    parent_rules.push(CSSKeyframesRule(KeyframesRule {
+        name: name,
+        keyframes: keyframes,
+    }))
+}
+
+
+/// Parses `[ from | to | <percentage> ]#`.
+fn parse_keyframe_selector(input: &[ComponentValue]) -> Option<~[CSSFloat]> {
+    let mut offsets = ~[];
+    for item in split_on_commas(input).move_iter() {
+        let values: ~[&ComponentValue] = item.skip_whitespace().collect();
+        let offset = match values.as_slice() {
+            [&Ident(ref keyword)] if keyword.eq_ignore_ascii_case("from") => 0.,
+            [&Ident(ref keyword)] if keyword.eq_ignore_ascii_case("to") => 1.,
+            [&Percentage(ref value)] if value.value >= 0. && value.value <= 100. => {
+                value.value / 100.
+            }
+            _ => return None,
+        };
+        offsets.push(offset)
+    }
+    if offsets.is_empty() { None } else { Some(offsets) }
 }
 
 
@@ -157,7 +287,39 @@ pub fn iter_style_rules<'a>(rules: &[CSSRule], device: &media_queries::Device,
             CSSStyleRule(ref rule) => callback(rule),
             CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
                 iter_style_rules(rule.rules.as_slice(), device, |s| callback(s))
-            }
+            },
+            CSSKeyframesRule(_) | CSSFontFaceRule(_) => (),
+        }
+    }
+}
+
+
+/// Calls `callback` for each `@keyframes` rule that applies to the given device, in source order.
+pub fn iter_keyframes_rules(rules: &[CSSRule], device: &media_queries::Device,
+                            callback: &fn(&KeyframesRule)) {
+    for rule in rules.iter() {
+        match *rule {
+            CSSStyleRule(_) => (),
+            CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
+                iter_keyframes_rules(rule.rules.as_slice(), device, |k| callback(k))
+            },
+            CSSKeyframesRule(ref rule) => callback(rule),
+            CSSFontFaceRule(_) => (),
+        }
+    }
+}
+
+
+/// Calls `callback` for each `@font-face` rule that applies to the given device, in source order.
+pub fn iter_font_face_rules(rules: &[CSSRule], device: &media_queries::Device,
+                            callback: &fn(&FontFaceRule)) {
+    for rule in rules.iter() {
+        match *rule {
+            CSSStyleRule(_) | CSSKeyframesRule(_) => (),
+            CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
+                iter_font_face_rules(rule.rules.as_slice(), device, |f| callback(f))
+            },
+            CSSFontFaceRule(ref rule) => callback(rule),
         }
     }
 }
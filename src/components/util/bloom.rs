@@ -0,0 +1,106 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A counting bloom filter, used to quickly reject CSS selectors whose ancestor requirements
+//! cannot be satisfied.
+
+use std::hash::Hash;
+use std::vec;
+
+/// The number of counters in the filter. This must be a power of two.
+static BLOOM_SIZE: uint = 4096;
+static BLOOM_MASK: u32 = 4095;
+
+/// A bloom filter with saturating 8-bit counters in place of bits, so that values can be removed
+/// as well as inserted. False positives are possible; false negatives are not.
+pub struct BloomFilter {
+    priv counters: ~[u8],
+}
+
+impl BloomFilter {
+    /// Creates a new, empty bloom filter.
+    pub fn new() -> BloomFilter {
+        BloomFilter {
+            counters: vec::from_elem(BLOOM_SIZE, 0u8),
+        }
+    }
+
+    /// Inserts the given hash into the filter.
+    pub fn insert_hash(&mut self, hash: u32) {
+        let (first, second) = indices(hash);
+        increment(&mut self.counters[first]);
+        increment(&mut self.counters[second]);
+    }
+
+    /// Removes the given hash from the filter. The hash must have been inserted before.
+    pub fn remove_hash(&mut self, hash: u32) {
+        let (first, second) = indices(hash);
+        decrement(&mut self.counters[first]);
+        decrement(&mut self.counters[second]);
+    }
+
+    /// Returns true if the given hash may have been inserted into the filter and false if it
+    /// definitely was not.
+    #[inline]
+    pub fn might_contain_hash(&self, hash: u32) -> bool {
+        let (first, second) = indices(hash);
+        self.counters[first] != 0 && self.counters[second] != 0
+    }
+
+    /// Removes everything from the filter.
+    pub fn clear(&mut self) {
+        for counter in self.counters.mut_iter() {
+            *counter = 0
+        }
+    }
+}
+
+/// Hashes a string for insertion into a bloom filter.
+pub fn hash_str(string: &str) -> u32 {
+    let hash = string.hash();
+    (hash ^ (hash >> 32)) as u32
+}
+
+#[inline]
+fn indices(hash: u32) -> (uint, uint) {
+    ((hash & BLOOM_MASK) as uint, ((hash >> 16) & BLOOM_MASK) as uint)
+}
+
+/// Counters saturate: once a counter overflows, it is never decremented again, because it is no
+/// longer known how many values map onto it.
+#[inline]
+fn increment(counter: &mut u8) {
+    if *counter != 0xff {
+        *counter += 1
+    }
+}
+
+#[inline]
+fn decrement(counter: &mut u8) {
+    if *counter != 0xff {
+        assert!(*counter != 0, "removing a hash that was never inserted");
+        *counter -= 1
+    }
+}
+
+#[test]
+fn test_bloom_filter() {
+    let mut filter = BloomFilter::new();
+    let foo = hash_str("foo");
+    let bar = hash_str("bar");
+    assert!(!filter.might_contain_hash(foo));
+
+    filter.insert_hash(foo);
+    filter.insert_hash(foo);
+    filter.insert_hash(bar);
+    assert!(filter.might_contain_hash(foo));
+    assert!(filter.might_contain_hash(bar));
+
+    filter.remove_hash(foo);
+    assert!(filter.might_contain_hash(foo));
+    filter.remove_hash(foo);
+    filter.remove_hash(bar);
+    assert!(!filter.might_contain_hash(foo));
+    assert!(!filter.might_contain_hash(bar));
+}
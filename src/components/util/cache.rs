@@ -2,7 +2,9 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use extra::ringbuf::RingBuf;
 use std::hashmap::HashMap;
+use std::vec::VecIterator;
 
 pub trait Cache<K: Eq, V: Clone> {
     fn insert(&mut self, key: K, value: V);
@@ -132,6 +134,11 @@ impl<K: Clone + Eq, V: Clone> LRUCache<K,V> {
         }
         self.entries[last_index].second_ref().clone()
     }
+
+    /// Iterates over the entries, from the least recently used one, without touching them.
+    pub fn iter<'a>(&'a self) -> VecIterator<'a, (K, V)> {
+        self.entries.iter()
+    }
 }
 
 impl<K: Clone + Eq, V: Clone> Cache<K,V> for LRUCache<K,V> {
@@ -198,3 +205,179 @@ fn test_lru_cache() {
     assert!(cache.find(&3).is_none()); // (4, 1) (no change)
     assert!(cache.find(&4).is_some()); // (1, 4)
 }
+
+#[test]
+fn test_lru_cache_iter() {
+    let mut cache = LRUCache::new(2);
+    cache.insert(1, @"one");
+    cache.insert(2, @"two");
+    cache.insert(3, @"three");
+    assert!(cache.find(&2).is_some());
+
+    let keys: ~[int] = cache.iter().map(|&(key, _)| key).collect();
+    assert_eq!(keys, ~[3, 2]);
+}
+
+/// A least-recently-used cache that finds entries by hashing their keys, for caches too large to
+/// search linearly. Uses are recorded in a queue; the records of entries used again since, or
+/// evicted, are skipped when the queue is consumed.
+pub struct HashLRUCache<K, V> {
+    /// The entries, each with the time it was last used.
+    entries: HashMap<K, (V, uint)>,
+    uses: RingBuf<(K, uint)>,
+    time: uint,
+    cache_size: uint,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> HashLRUCache<K,V> {
+    pub fn new(size: uint) -> HashLRUCache<K, V> {
+        HashLRUCache {
+            entries: HashMap::new(),
+            uses: RingBuf::new(),
+            time: 0,
+            cache_size: size,
+        }
+    }
+
+    pub fn len(&self) -> uint {
+        self.entries.len()
+    }
+
+    /// Calls the given function with each entry, in no particular order. This doesn't count as a
+    /// use of the entries.
+    pub fn each_entry(&self, f: &fn(&K, &V)) {
+        for (key, &(ref value, _)) in self.entries.iter() {
+            f(key, value)
+        }
+    }
+
+    /// Inserts an entry, and returns the entry it replaces or the least recently used one,
+    /// which it evicts to stay within the size of the cache.
+    pub fn insert_and_evict(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let time = self.tick(&key);
+        match self.entries.swap(key.clone(), (value, time)) {
+            Some((old_value, _)) => return Some((key, old_value)),
+            None => (),
+        }
+        if self.entries.len() <= self.cache_size {
+            return None
+        }
+        loop {
+            let (key, time) = self.uses.pop_front().unwrap();
+            let current = match self.entries.find(&key) {
+                Some(&(_, last_use)) => last_use == time,
+                None => false,
+            };
+            if current {
+                let (value, _) = self.entries.pop(&key).unwrap();
+                return Some((key, value))
+            }
+        }
+    }
+
+    /// Records a use of the given key, and returns its time.
+    fn tick(&mut self, key: &K) -> uint {
+        self.time += 1;
+        self.uses.push_back((key.clone(), self.time));
+
+        // Entries found over and over again leave stale records behind. Drop them before they
+        // outnumber the entries.
+        if self.uses.len() > self.cache_size * 2 + 1 {
+            let mut uses = RingBuf::new();
+            for &(ref key, time) in self.uses.iter() {
+                match self.entries.find(key) {
+                    Some(&(_, last_use)) if last_use == time => uses.push_back((key.clone(), time)),
+                    _ => (),
+                }
+            }
+            uses.push_back((key.clone(), self.time));
+            self.uses = uses;
+        }
+        self.time
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Cache<K,V> for HashLRUCache<K,V> {
+    fn insert(&mut self, key: K, value: V) {
+        self.insert_and_evict(key, value);
+    }
+
+    fn find(&mut self, key: &K) -> Option<V> {
+        if !self.entries.contains_key(key) {
+            return None
+        }
+        let time = self.tick(key);
+        match self.entries.find_mut(key) {
+            Some(entry) => {
+                let (ref value, ref mut last_use) = *entry;
+                *last_use = time;
+                Some(value.clone())
+            }
+            None => None,
+        }
+    }
+
+    fn find_or_create(&mut self, key: &K, blk: &fn(&K) -> V) -> V {
+        match self.find(key) {
+            Some(value) => value,
+            None => {
+                let value = blk(key);
+                self.insert(key.clone(), value.clone());
+                value
+            }
+        }
+    }
+
+    fn evict_all(&mut self) {
+        self.entries.clear();
+        self.uses.clear();
+    }
+}
+
+#[test]
+fn test_hash_lru_cache() {
+    let mut cache = HashLRUCache::new(2);
+    assert!(cache.insert_and_evict(1, @"one").is_none());
+    assert!(cache.insert_and_evict(2, @"two").is_none());
+    assert!(cache.find(&1).is_some());
+
+    // 2 is the least recently used entry.
+    assert_eq!(cache.insert_and_evict(3, @"three").map(|(key, _)| key), Some(2));
+    assert!(cache.find(&2).is_none());
+    assert!(cache.find(&1).is_some());
+    assert!(cache.find(&3).is_some());
+
+    // Replacing an entry evicts nothing else.
+    assert_eq!(cache.insert_and_evict(3, @"drei").map(|(key, _)| key), Some(3));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.find(&3), Some(@"drei"));
+}
+
+#[test]
+fn test_hash_lru_cache_stale_uses() {
+    let mut cache = HashLRUCache::new(2);
+    cache.insert(1, @"one");
+    cache.insert(2, @"two");
+    for _ in range(0, 10) {
+        assert!(cache.find(&1).is_some());
+    }
+    assert!(cache.uses.len() <= 5);
+    assert_eq!(cache.insert_and_evict(3, @"three").map(|(key, _)| key), Some(2));
+    assert_eq!(cache.insert_and_evict(4, @"four").map(|(key, _)| key), Some(1));
+}
+
+#[test]
+fn test_hash_lru_cache_each_entry() {
+    let mut cache = HashLRUCache::new(2);
+    cache.insert(1, @"one");
+    cache.insert(2, @"two");
+    let mut keys = ~[];
+    do cache.each_entry |&key, _| {
+        keys.push(key)
+    }
+    assert_eq!(keys.len(), 2);
+    assert!(keys.contains(&1) && keys.contains(&2));
+
+    // Visiting the entries doesn't make 1 used more recently than 2.
+    assert_eq!(cache.insert_and_evict(3, @"three").map(|(key, _)| key), Some(1));
+}
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use geom::matrix2d::Matrix2D;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
@@ -233,3 +234,68 @@ pub fn to_pt(au: Au) -> f64 {
     (*au as f64) / 60f64 * 72f64 / 96f64
 }
 
+
+/// Returns the bounding rectangle of the given rectangle transformed by `matrix`, which maps
+/// pixels.
+pub fn transform_rect(matrix: &Matrix2D<f32>, rect: &Rect<Au>) -> Rect<Au> {
+    let (left, top) = (to_frac_px(rect.origin.x) as f32, to_frac_px(rect.origin.y) as f32);
+    let right = left + to_frac_px(rect.size.width) as f32;
+    let bottom = top + to_frac_px(rect.size.height) as f32;
+    let corners = [(left, top), (right, top), (left, bottom), (right, bottom)];
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0f32, 0f32, 0f32, 0f32);
+    for (i, &(x, y)) in corners.iter().enumerate() {
+        let x_out = x * matrix.m11 + y * matrix.m21 + matrix.m31;
+        let y_out = x * matrix.m12 + y * matrix.m22 + matrix.m32;
+        if i == 0 {
+            min_x = x_out; max_x = x_out;
+            min_y = y_out; max_y = y_out;
+        } else {
+            min_x = min_x.min(&x_out); max_x = max_x.max(&x_out);
+            min_y = min_y.min(&y_out); max_y = max_y.max(&y_out);
+        }
+    }
+    Rect(Point2D(from_frac_px(min_x as f64), from_frac_px(min_y as f64)),
+         Size2D(from_frac_px((max_x - min_x) as f64), from_frac_px((max_y - min_y) as f64)))
+}
+
+/// Maps the given point through the inverse of `matrix`, which maps pixels. Returns `None` if the
+/// matrix is not invertible, as when it scales by zero.
+pub fn inverse_transform_point(matrix: &Matrix2D<f32>, point: &Point2D<Au>)
+                               -> Option<Point2D<Au>> {
+    let determinant = matrix.m11 * matrix.m22 - matrix.m12 * matrix.m21;
+    if determinant == 0.0 {
+        return None
+    }
+    let x = to_frac_px(point.x) as f32 - matrix.m31;
+    let y = to_frac_px(point.y) as f32 - matrix.m32;
+    Some(Point2D(from_frac_px(((x * matrix.m22 - y * matrix.m21) / determinant) as f64),
+                 from_frac_px(((y * matrix.m11 - x * matrix.m12) / determinant) as f64)))
+}
+
+/// Returns the bounding rectangle of the given rectangle mapped through the inverse of `matrix`,
+/// which maps pixels. Returns `None` if the matrix is not invertible.
+pub fn inverse_transform_rect(matrix: &Matrix2D<f32>, rect: &Rect<Au>) -> Option<Rect<Au>> {
+    let (left, top) = (rect.origin.x, rect.origin.y);
+    let (right, bottom) = (left + rect.size.width, top + rect.size.height);
+    let corners = [Point2D(left, top), Point2D(right, top), Point2D(left, bottom),
+                   Point2D(right, bottom)];
+
+    let mut bounds: Option<(Point2D<Au>, Point2D<Au>)> = None;
+    for corner in corners.iter() {
+        let point = match inverse_transform_point(matrix, corner) {
+            Some(point) => point,
+            None => return None,
+        };
+        bounds = match bounds {
+            None => Some((point, point)),
+            Some((min_point, max_point)) => {
+                Some((Point2D(min(min_point.x, point.x), min(min_point.y, point.y)),
+                      Point2D(max(max_point.x, point.x), max(max_point.y, point.y))))
+            }
+        }
+    }
+    bounds.map(|(min_point, max_point)| {
+        Rect(min_point, Size2D(max_point.x - min_point.x, max_point.y - min_point.y))
+    })
+}
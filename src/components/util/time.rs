@@ -29,6 +29,10 @@ impl ProfilerChan {
 pub enum ProfilerMsg {
     // Normal message used for reporting time
     TimeMsg(ProfilerCategory, f64),
+    // Message used for reporting the number of times an event happened
+    CountMsg(ProfilerCounter, uint),
+    // Message used for reporting the current size of a memory pool, in bytes
+    MemoryMsg(ProfilerMemoryCategory, uint),
     // Message used to force print the profiling metrics
     PrintMsg,
 }
@@ -92,10 +96,38 @@ impl ProfilerCategory {
 
 type ProfilerBuckets = TreeMap<ProfilerCategory, ~[f64]>;
 
+#[deriving(Eq, Clone, TotalEq, TotalOrd)]
+pub enum ProfilerCounter {
+    SelectorFastRejectCounter,
+    SelectorFullMatchCounter,
+    StyleSharingHitCounter,
+    StyleSharingMissCounter,
+}
+
+impl ProfilerCounter {
+    pub fn format(self) -> ~str {
+        format!("{:?}", self)
+    }
+}
+
+#[deriving(Eq, Clone, TotalEq, TotalOrd)]
+pub enum ProfilerMemoryCategory {
+    FontCacheFileDataMemory,
+    FontCacheShapedTextMemory,
+}
+
+impl ProfilerMemoryCategory {
+    pub fn format(self) -> ~str {
+        format!("{:?}", self)
+    }
+}
+
 // back end of the profiler that handles data aggregation and performance metrics
 pub struct Profiler {
     port: Port<ProfilerMsg>,
     buckets: ProfilerBuckets,
+    counters: TreeMap<ProfilerCounter, uint>,
+    memory: TreeMap<ProfilerMemoryCategory, uint>,
     last_msg: Option<ProfilerMsg>,
 }
 
@@ -132,6 +164,8 @@ impl Profiler {
         Profiler {
             port: port,
             buckets: ProfilerCategory::empty_buckets(),
+            counters: TreeMap::new(),
+            memory: TreeMap::new(),
             last_msg: None,
         }
     }
@@ -149,9 +183,19 @@ impl Profiler {
     fn handle_msg(&mut self, msg: ProfilerMsg) {
         match msg {
             TimeMsg(category, t) => self.buckets.find_mut(&category).unwrap().push(t),
+            CountMsg(counter, n) => {
+                let total = match self.counters.find(&counter) {
+                    Some(&total) => total + n,
+                    None => n,
+                };
+                self.counters.insert(counter, total);
+            }
+            MemoryMsg(category, size) => {
+                self.memory.insert(category, size);
+            }
             PrintMsg => match self.last_msg {
                 // only print if more data has arrived since the last printout
-                Some(TimeMsg(*)) => self.print_buckets(),
+                Some(TimeMsg(*)) | Some(CountMsg(*)) | Some(MemoryMsg(*)) => self.print_buckets(),
                 _ => ()
             },
         };
@@ -178,6 +222,22 @@ impl Profiler {
             }
         }
         println("");
+
+        if !self.counters.is_empty() {
+            println(format!("{:31s} {:15s}", "_counter_", "_total_"));
+            for (counter, total) in self.counters.iter() {
+                println(format!("{:-30s}: {:15u}", counter.format(), *total));
+            }
+            println("");
+        }
+
+        if !self.memory.is_empty() {
+            println(format!("{:31s} {:15s}", "_memory_", "_bytes_"));
+            for (category, size) in self.memory.iter() {
+                println(format!("{:-30s}: {:15u}", category.format(), *size));
+            }
+            println("");
+        }
     }
 }
 
@@ -194,6 +254,10 @@ pub fn profile<T>(category: ProfilerCategory,
     return val;
 }
 
+pub fn count(counter: ProfilerCounter, n: uint, profiler_chan: ProfilerChan) {
+    profiler_chan.send_deferred(CountMsg(counter, n));
+}
+
 pub fn time<T>(msg: &str, callback: &fn() -> T) -> T{
     let start_time = precise_time_ns();
     let val = callback();
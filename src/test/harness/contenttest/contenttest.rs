@@ -93,7 +93,8 @@ fn run_test(file: ~str) {
 
     let config = ProcessConfig {
         program: "./servo",
-        args: [~"-z", ~"-f", infile.clone()],
+        // The CSS errors of the test are printed along with its results.
+        args: [~"-z", ~"-f", ~"--css-errors", infile.clone()],
         env: None,
         cwd: None,
         io: [Ignored, stdout, stderr]